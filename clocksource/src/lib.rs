@@ -16,6 +16,11 @@ pub mod precise;
 
 mod sys;
 
+#[cfg(not(feature = "portable-atomic"))]
+use core::sync::atomic::AtomicU64;
+#[cfg(feature = "portable-atomic")]
+use portable_atomic::AtomicU64;
+
 const MILLIS_PER_SEC: u64 = 1_000;
 const MICROS_PER_SEC: u64 = 1_000_000;
 const NANOS_PER_SEC: u64 = 1_000_000_000;
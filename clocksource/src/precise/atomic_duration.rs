@@ -1,4 +1,5 @@
-use core::sync::atomic::{AtomicU64, Ordering};
+use crate::AtomicU64;
+use core::sync::atomic::Ordering;
 
 use super::Duration;
 
@@ -0,0 +1,55 @@
+//! Clocks for running the tests under Miri, which cannot call into `libc` or
+//! `winapi` for the system clocks. The monotonic clock is read through
+//! `std::time`, which Miri provides as a virtual clock, and the realtime clock
+//! follows it from a fixed start since isolation hides the system time.
+
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// The realtime clock starts at 2023-11-14T22:13:20Z, which is an arbitrary
+/// but fixed point so that runs are reproducible.
+const REALTIME_START: core::time::Duration = core::time::Duration::from_secs(1_700_000_000);
+
+/// `std::time::Instant` is opaque, so the clocks are measured from the first
+/// time either of them is read.
+fn elapsed() -> core::time::Duration {
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    START.get_or_init(Instant::now).elapsed()
+}
+
+pub mod monotonic {
+    use super::*;
+
+    pub fn coarse() -> crate::coarse::Instant {
+        crate::coarse::Instant {
+            secs: elapsed().as_secs() as u32,
+        }
+    }
+
+    pub fn precise() -> crate::precise::Instant {
+        crate::precise::Instant {
+            ns: elapsed().as_nanos() as u64,
+        }
+    }
+}
+
+pub mod realtime {
+    use super::*;
+
+    fn since_epoch() -> core::time::Duration {
+        REALTIME_START + elapsed()
+    }
+
+    pub fn coarse() -> crate::coarse::UnixInstant {
+        crate::coarse::UnixInstant {
+            secs: since_epoch().as_secs() as u32,
+        }
+    }
+
+    pub fn precise() -> crate::precise::UnixInstant {
+        crate::precise::UnixInstant {
+            ns: since_epoch().as_nanos() as u64,
+        }
+    }
+}
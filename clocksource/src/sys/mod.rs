@@ -1,9 +1,19 @@
-#[cfg(not(target_os = "windows"))]
+#[cfg(miri)]
+mod miri;
+#[cfg(miri)]
+pub use miri::*;
+
+#[cfg(all(not(miri), feature = "wasm"))]
+mod wasm;
+#[cfg(all(not(miri), feature = "wasm"))]
+pub use wasm::*;
+
+#[cfg(all(not(miri), not(feature = "wasm"), not(target_os = "windows")))]
 mod unix;
-#[cfg(not(target_os = "windows"))]
+#[cfg(all(not(miri), not(feature = "wasm"), not(target_os = "windows")))]
 pub use unix::*;
 
-#[cfg(target_os = "windows")]
+#[cfg(all(not(miri), not(feature = "wasm"), target_os = "windows"))]
 mod windows;
-#[cfg(target_os = "windows")]
+#[cfg(all(not(miri), not(feature = "wasm"), target_os = "windows"))]
 pub use windows::*;
@@ -0,0 +1,52 @@
+//! Clocks for `wasm32` targets, such as browsers and worker runtimes, where the
+//! system clocks are read through `web-time`. On other targets, `web-time`
+//! reads the clocks from `std::time`.
+
+use std::sync::OnceLock;
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub mod monotonic {
+    use super::*;
+
+    /// `web_time::Instant` is opaque, so the monotonic clock is measured from
+    /// the first time it is read.
+    fn elapsed() -> core::time::Duration {
+        static START: OnceLock<Instant> = OnceLock::new();
+
+        START.get_or_init(Instant::now).elapsed()
+    }
+
+    pub fn coarse() -> crate::coarse::Instant {
+        crate::coarse::Instant {
+            secs: elapsed().as_secs() as u32,
+        }
+    }
+
+    pub fn precise() -> crate::precise::Instant {
+        crate::precise::Instant {
+            ns: elapsed().as_nanos() as u64,
+        }
+    }
+}
+
+pub mod realtime {
+    use super::*;
+
+    fn since_epoch() -> core::time::Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    pub fn coarse() -> crate::coarse::UnixInstant {
+        crate::coarse::UnixInstant {
+            secs: since_epoch().as_secs() as u32,
+        }
+    }
+
+    pub fn precise() -> crate::precise::UnixInstant {
+        crate::precise::UnixInstant {
+            ns: since_epoch().as_nanos() as u64,
+        }
+    }
+}
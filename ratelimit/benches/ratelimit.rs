@@ -0,0 +1,121 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ratelimit::{KeyedRatelimiter, Ratelimiter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+// A ratelimiter which effectively never runs out of tokens, so that the
+// benchmarks measure the cost of admission and not of rejection.
+fn unlimited() -> Ratelimiter {
+    Ratelimiter::builder(1_000_000, Duration::from_micros(1))
+        .max_tokens(1_000_000)
+        .initial_available(1_000_000)
+        .build()
+        .unwrap()
+}
+
+fn single(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ratelimiter");
+    group.throughput(Throughput::Elements(1));
+
+    let ratelimiter = unlimited();
+    group.bench_function("try_wait/admit", |b| b.iter(|| ratelimiter.try_wait()));
+
+    let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    group.bench_function("try_wait/reject", |b| b.iter(|| ratelimiter.try_wait()));
+
+    group.finish();
+}
+
+// Each iteration is one call to `try_wait` on every thread, so the reported
+// throughput is the aggregate across all threads.
+fn contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contention");
+
+    for threads in [2, 8, 32] {
+        let ratelimiter = unlimited();
+
+        group.throughput(Throughput::Elements(threads));
+        group.bench_function(format!("try_wait/{threads}"), |b| {
+            b.iter_custom(|iters| {
+                let barrier = Barrier::new(threads as usize + 1);
+
+                std::thread::scope(|scope| {
+                    for _ in 0..threads {
+                        scope.spawn(|| {
+                            barrier.wait();
+                            for _ in 0..iters {
+                                let _ = ratelimiter.try_wait();
+                            }
+                        });
+                    }
+
+                    barrier.wait();
+                    let start = Instant::now();
+                    // the scope joins all the threads before returning
+                    start
+                })
+                .elapsed()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn keyed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keyed");
+    group.throughput(Throughput::Elements(1));
+
+    let ratelimiter: KeyedRatelimiter<u64> = KeyedRatelimiter::new(|_| unlimited());
+    for key in 0..1024 {
+        let _ = ratelimiter.try_wait(&key);
+    }
+
+    let mut key = 0;
+    group.bench_function("try_wait/existing", |b| {
+        b.iter(|| {
+            key = (key + 1) % 1024;
+            ratelimiter.try_wait(&key)
+        })
+    });
+
+    group.finish();
+}
+
+// Measures the hot path while another thread continuously changes the
+// parameters, which takes the write side of the parameter lock.
+fn interference(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interference");
+    group.throughput(Throughput::Elements(1));
+
+    let ratelimiter = unlimited();
+    let stop = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut amount = 999_999;
+            while !stop.load(Ordering::Relaxed) {
+                amount = if amount == 999_999 {
+                    1_000_000
+                } else {
+                    999_999
+                };
+                let _ = ratelimiter.set_refill_amount(amount);
+            }
+        });
+
+        group.bench_function("try_wait/set_refill_amount", |b| {
+            b.iter(|| ratelimiter.try_wait())
+        });
+
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, single, contention, keyed, interference);
+criterion_main!(benches);
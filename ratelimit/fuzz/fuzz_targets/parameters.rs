@@ -0,0 +1,77 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ratelimit::Ratelimiter;
+use std::time::Duration;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    amount: u64,
+    interval: u64,
+    max_tokens: u64,
+    ops: Vec<Op>,
+    mutations: Vec<Mutation>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Refill(u64),
+    Acquire { n: u64, at: u64 },
+}
+
+#[derive(Arbitrary, Debug)]
+enum Mutation {
+    RefillAmount(u64),
+    RefillInterval(u64),
+    MaxTokens(u64),
+    Available(u64),
+    Rate(f64),
+    Scale(f64),
+    Return(u64),
+}
+
+fuzz_target!(|input: Input| {
+    let Ok(ratelimiter) = Ratelimiter::builder(input.amount, Duration::from_nanos(input.interval))
+        .max_tokens(input.max_tokens)
+        .build()
+    else {
+        return;
+    };
+
+    // the parameters are changed while another thread refills and acquires
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for mutation in &input.mutations {
+                let _ = match *mutation {
+                    Mutation::RefillAmount(amount) => ratelimiter.set_refill_amount(amount),
+                    Mutation::RefillInterval(ns) => {
+                        ratelimiter.set_refill_interval(Duration::from_nanos(ns))
+                    }
+                    Mutation::MaxTokens(tokens) => ratelimiter.set_max_tokens(tokens),
+                    Mutation::Available(tokens) => ratelimiter.set_available(tokens),
+                    Mutation::Rate(rate) => ratelimiter.set_rate(rate),
+                    Mutation::Scale(scale) => ratelimiter.set_scale(scale),
+                    Mutation::Return(n) => {
+                        ratelimiter.return_n(n);
+                        Ok(())
+                    }
+                };
+            }
+        });
+
+        for op in &input.ops {
+            match *op {
+                Op::Refill(at) => {
+                    let _ = ratelimiter.fuzz_refill(at);
+                }
+                Op::Acquire { n, at } => {
+                    let _ = ratelimiter.fuzz_try_acquire_n(n, at);
+                }
+            }
+        }
+    });
+
+    assert!(ratelimiter.available() <= ratelimiter.max_tokens());
+    assert!(ratelimiter.refill_amount() <= ratelimiter.max_tokens());
+});
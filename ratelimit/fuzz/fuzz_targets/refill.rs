@@ -0,0 +1,50 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use ratelimit::Ratelimiter;
+use std::time::Duration;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    amount: u64,
+    interval: u64,
+    max_tokens: u64,
+    initial: u64,
+    smooth: bool,
+    ops: Vec<Op>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    // times are in nanoseconds since the ratelimiter was created, and may
+    // move backwards
+    Refill(u64),
+    Acquire { n: u64, at: u64 },
+    Return(u64),
+}
+
+fuzz_target!(|input: Input| {
+    let Ok(ratelimiter) = Ratelimiter::builder(input.amount, Duration::from_nanos(input.interval))
+        .max_tokens(input.max_tokens)
+        .initial_available(input.initial)
+        .smooth(input.smooth)
+        .build()
+    else {
+        return;
+    };
+
+    for op in input.ops {
+        match op {
+            Op::Refill(at) => {
+                let _ = ratelimiter.fuzz_refill(at);
+            }
+            Op::Acquire { n, at } => {
+                let _ = ratelimiter.fuzz_try_acquire_n(n, at);
+            }
+            Op::Return(n) => ratelimiter.return_n(n),
+        }
+
+        assert!(ratelimiter.available() <= ratelimiter.max_tokens());
+    }
+});
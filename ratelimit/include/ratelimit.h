@@ -0,0 +1,69 @@
+#ifndef RATELIMIT_H
+#define RATELIMIT_H
+
+/* Generated with cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The tokens were acquired.
+#define RATELIMIT_OK 0
+
+// There were insufficient tokens. The time until the next refill is written
+// to `wait_ns`.
+#define RATELIMIT_INSUFFICIENT 1
+
+// The handle was null or the ratelimiter panicked.
+#define RATELIMIT_ERROR -1
+
+typedef struct Ratelimiter Ratelimiter;
+
+// Create a ratelimiter which adds `amount` tokens every `interval_ns`
+// nanoseconds, up to `max_tokens`, and starts with `initial_available`
+// tokens. Returns null if the parameters are invalid.
+//
+// The handle must be freed with `ratelimit_destroy`.
+struct Ratelimiter *ratelimit_create(uint64_t amount,
+                                     uint64_t interval_ns,
+                                     uint64_t max_tokens,
+                                     uint64_t initial_available);
+
+// Try to acquire `n` tokens without blocking. Returns `RATELIMIT_OK` if the
+// tokens were acquired, or `RATELIMIT_INSUFFICIENT` with the time until the
+// next refill written to `wait_ns`, if `wait_ns` is not null.
+//
+// # Safety
+//
+// `ratelimiter` must be null or a handle from `ratelimit_create` which has
+// not been destroyed. `wait_ns` must be null or valid for writes.
+int32_t ratelimit_try_wait_n(const struct Ratelimiter *ratelimiter, uint64_t n, uint64_t *wait_ns);
+
+// Try to acquire a single token without blocking. See
+// `ratelimit_try_wait_n`.
+//
+// # Safety
+//
+// See `ratelimit_try_wait_n`.
+int32_t ratelimit_try_wait(const struct Ratelimiter *ratelimiter, uint64_t *wait_ns);
+
+// Returns the number of tokens currently available, or zero if the handle is
+// null.
+//
+// # Safety
+//
+// `ratelimiter` must be null or a handle from `ratelimit_create` which has
+// not been destroyed.
+uint64_t ratelimit_available(const struct Ratelimiter *ratelimiter);
+
+// Free a ratelimiter. Does nothing if the handle is null.
+//
+// # Safety
+//
+// `ratelimiter` must be null or a handle from `ratelimit_create` which has
+// not already been destroyed. The handle must not be used afterwards.
+void ratelimit_destroy(struct Ratelimiter *ratelimiter);
+
+#endif  /* RATELIMIT_H */
@@ -0,0 +1,350 @@
+//! Middleware which limits the requests to an [`actix-web`](actix_web)
+//! application.
+//!
+//! The ratelimiter for each request is chosen by a selector, which allows for
+//! a single limit, a limit for each client with a [`KeyedRatelimiter`], or a
+//! different limit for each route. Requests which are over the limit receive a
+//! `429 Too Many Requests` response with a `Retry-After` header, unless a
+//! custom denial response is provided. Either response carries the
+//! `RateLimit-*` headers for the ratelimiter which denied the request. Each
+//! request costs a single token unless a cost extractor is provided with
+//! [`RateLimit::cost_with`].
+//!
+//! ```no_run
+//! use actix_web::{web, App, HttpServer};
+//! use ratelimit::actix::RateLimit;
+//! use ratelimit::{KeyedRatelimiter, Ratelimiter, RatelimiterConfig};
+//! use std::sync::Arc;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = RatelimiterConfig::new("10/s".parse()?).initial_available(10);
+//! let clients = Arc::new(KeyedRatelimiter::from_config(config)?);
+//! let login = Arc::new(Ratelimiter::builder(1, std::time::Duration::from_secs(1)).build()?);
+//!
+//! HttpServer::new(move || {
+//!     App::new()
+//!         // each client is limited independently
+//!         .wrap(RateLimit::keyed(clients.clone(), |req| req.peer_addr().map(|a| a.ip())))
+//!         // the login route has an additional global limit
+//!         .service(
+//!             web::resource("/login")
+//!                 .wrap(RateLimit::new(login.clone()))
+//!                 .to(|| async { "ok" }),
+//!         )
+//! })
+//! .bind("0.0.0.0:8080")?
+//! .run()
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{CostExtractor, KeyedRatelimiter, Ratelimiter, RetryAfter, UnitCost};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::HttpResponse;
+use core::future::{ready, Future, Ready};
+use core::hash::Hash;
+use core::pin::Pin;
+use core::time::Duration;
+use std::rc::Rc;
+use std::sync::Arc;
+
+type Select = dyn Fn(&ServiceRequest, &mut dyn FnMut(&Ratelimiter) -> bool);
+type Deny = dyn Fn(&ServiceRequest, Duration) -> HttpResponse;
+
+/// A middleware factory which charges a ratelimiter for each request. Each
+/// request costs a single token unless a cost extractor is provided.
+#[derive(Clone)]
+pub struct RateLimit {
+    cost: Rc<dyn CostExtractor<ServiceRequest>>,
+    deny: Rc<Deny>,
+    select: Rc<Select>,
+}
+
+impl RateLimit {
+    /// Limit all requests with the provided ratelimiter.
+    pub fn new(ratelimiter: Arc<Ratelimiter>) -> Self {
+        Self::select(move |_| Some(ratelimiter.clone()))
+    }
+
+    /// Limit each request by the key returned by the provided function.
+    /// Requests without a key, or whose key is exempt, are not limited. See
+    /// [`KeyedRatelimiter::exempt`].
+    pub fn keyed<K>(
+        ratelimiter: Arc<KeyedRatelimiter<K>>,
+        key: impl Fn(&ServiceRequest) -> Option<K> + 'static,
+    ) -> Self
+    where
+        K: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        Self::with_selector(move |req, check| {
+            let Some(key) = key(req).filter(|key| !ratelimiter.exempt_request(key)) else {
+                return;
+            };
+
+            let acquired = check(&ratelimiter.get(&key));
+            ratelimiter.record_hot_key(&key, acquired);
+        })
+    }
+
+    /// Limit each request with the ratelimiter returned by the provided
+    /// function, for example based on [`ServiceRequest::match_pattern`].
+    /// Requests without a ratelimiter are not limited.
+    pub fn select(select: impl Fn(&ServiceRequest) -> Option<Arc<Ratelimiter>> + 'static) -> Self {
+        Self::with_selector(move |req, check| {
+            if let Some(ratelimiter) = select(req) {
+                check(&ratelimiter);
+            }
+        })
+    }
+
+    /// Internal function to create the middleware factory with a selector. The
+    /// selector checks the ratelimiter for a request, if any, with the
+    /// provided function, which returns whether the request was allowed.
+    fn with_selector(
+        select: impl Fn(&ServiceRequest, &mut dyn FnMut(&Ratelimiter) -> bool) + 'static,
+    ) -> Self {
+        Self {
+            cost: Rc::new(UnitCost),
+            deny: Rc::new(too_many_requests),
+            select: Rc::new(select),
+        }
+    }
+
+    /// Use the provided function to build the response for requests which are
+    /// over the limit. The function is provided with the time until the tokens
+    /// for the request would be available.
+    pub fn deny_with(
+        mut self,
+        deny: impl Fn(&ServiceRequest, Duration) -> HttpResponse + 'static,
+    ) -> Self {
+        self.deny = Rc::new(deny);
+        self
+    }
+
+    /// Charge each request the number of tokens returned by the cost
+    /// extractor, for example to charge writes more than reads. Costs above
+    /// the maximum number of tokens are charged as the maximum.
+    pub fn cost_with(mut self, cost: impl CostExtractor<ServiceRequest> + 'static) -> Self {
+        self.cost = Rc::new(cost);
+        self
+    }
+}
+
+/// Internal function to build the default response for a request which is
+/// over the limit. The `Retry-After` header is rounded up to whole seconds.
+fn too_many_requests(_req: &ServiceRequest, retry_after: Duration) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, RetryAfter::new(retry_after).seconds()))
+        .finish()
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            config: self.clone(),
+            service,
+        }))
+    }
+}
+
+/// The middleware created by [`RateLimit`].
+pub struct RateLimitMiddleware<S> {
+    config: RateLimit,
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let mut denied = None;
+
+        (self.config.select)(&req, &mut |ratelimiter| {
+            let cost = ratelimiter.charge(self.config.cost.cost(&req));
+            let Err(hint) = ratelimiter.try_wait_n(cost) else {
+                return true;
+            };
+
+            // the hint is for the next refill, which may not cover the cost
+            let retry_after = hint.max(ratelimiter.retry_after(cost).delay());
+            let mut response = (self.config.deny)(&req, retry_after);
+
+            for (name, value) in ratelimiter.headers().standard() {
+                if let Ok(value) = HeaderValue::try_from(value) {
+                    response
+                        .headers_mut()
+                        .insert(HeaderName::from_static(name), value);
+                }
+            }
+
+            denied = Some(response);
+            false
+        });
+
+        if let Some(response) = denied {
+            let response = req.into_response(response).map_into_right_body();
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let future = self.service.call(req);
+        Box::pin(async move { future.await.map(|res| res.map_into_left_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+
+    fn ratelimiter() -> Arc<Ratelimiter> {
+        Arc::new(
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .initial_available(1)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn middleware() {
+        actix_web::rt::System::new().block_on(async {
+            let login = ratelimiter();
+            let app = test::init_service(
+                App::new()
+                    .wrap(RateLimit::select(move |req| {
+                        (req.match_pattern().as_deref() == Some("/login")).then(|| login.clone())
+                    }))
+                    .route("/login", web::get().to(|| async { "ok" }))
+                    .route("/", web::get().to(|| async { "ok" })),
+            )
+            .await;
+
+            let get = |uri| test::TestRequest::get().uri(uri).to_request();
+
+            assert_eq!(
+                test::call_service(&app, get("/login")).await.status(),
+                StatusCode::OK
+            );
+            let response = test::call_service(&app, get("/login")).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "60");
+            assert_eq!(response.headers().get("ratelimit-reset").unwrap(), "60");
+
+            // other routes are not limited
+            assert_eq!(
+                test::call_service(&app, get("/")).await.status(),
+                StatusCode::OK
+            );
+        });
+    }
+
+    #[test]
+    fn keyed() {
+        actix_web::rt::System::new().block_on(async {
+            let config =
+                crate::RatelimiterConfig::new("1/min".parse().unwrap()).initial_available(1);
+            let ratelimiter = Arc::new(KeyedRatelimiter::from_config(config).unwrap());
+
+            let app = test::init_service(
+                App::new()
+                    .wrap(
+                        RateLimit::keyed(ratelimiter, |req| {
+                            req.headers()
+                                .get("x-api-key")
+                                .and_then(|v| v.to_str().ok())
+                                .map(|v| v.to_string())
+                        })
+                        .deny_with(|_, _| HttpResponse::ServiceUnavailable().finish()),
+                    )
+                    .route("/", web::get().to(|| async { "ok" })),
+            )
+            .await;
+
+            let get = |key| {
+                test::TestRequest::get()
+                    .uri("/")
+                    .insert_header(("x-api-key", key))
+                    .to_request()
+            };
+
+            assert_eq!(
+                test::call_service(&app, get("alice")).await.status(),
+                StatusCode::OK
+            );
+            assert_eq!(
+                test::call_service(&app, get("alice")).await.status(),
+                StatusCode::SERVICE_UNAVAILABLE
+            );
+            assert_eq!(
+                test::call_service(&app, get("bob")).await.status(),
+                StatusCode::OK
+            );
+        });
+    }
+
+    #[test]
+    fn cost() {
+        actix_web::rt::System::new().block_on(async {
+            let ratelimiter = Arc::new(
+                Ratelimiter::builder(1, Duration::from_secs(60))
+                    .max_tokens(6)
+                    .initial_available(6)
+                    .build()
+                    .unwrap(),
+            );
+
+            // writes cost five tokens and reads cost one
+            let app = test::init_service(
+                App::new()
+                    .wrap(
+                        RateLimit::new(ratelimiter.clone()).cost_with(|req: &ServiceRequest| {
+                            if req.method() == actix_web::http::Method::POST {
+                                5
+                            } else {
+                                1
+                            }
+                        }),
+                    )
+                    .route("/", web::to(|| async { "ok" })),
+            )
+            .await;
+
+            let post = test::TestRequest::post().uri("/").to_request();
+            assert_eq!(
+                test::call_service(&app, post).await.status(),
+                StatusCode::OK
+            );
+            let post = test::TestRequest::post().uri("/").to_request();
+            assert_eq!(
+                test::call_service(&app, post).await.status(),
+                StatusCode::TOO_MANY_REQUESTS
+            );
+            let get = test::TestRequest::get().uri("/").to_request();
+            assert_eq!(test::call_service(&app, get).await.status(), StatusCode::OK);
+            assert_eq!(ratelimiter.available(), 0);
+        });
+    }
+}
@@ -0,0 +1,300 @@
+use crate::atomic::Ordering;
+use crate::{
+    advance_instant, system_now, Error, QuotaHeaders, Ratelimiter, StandardHeaders, TryAcquireError,
+};
+use clocksource::precise::{AtomicInstant, Instant};
+use core::borrow::Borrow;
+use parking_lot::Mutex;
+
+// the rate is never backed off below this fraction of the original rate
+const MIN_FRACTION: f64 = 0.001;
+
+/// A client-side ratelimiter which also follows the throttling signals of the
+/// server it is calling, such as `429 Too Many Requests` responses.
+///
+/// When told that the server throttled a request with
+/// [`Adaptive::throttled`], all acquisitions are suppressed until the server's
+/// `Retry-After` has passed. Optionally, the local rate is also backed off by
+/// a factor on each throttle and recovered gradually as requests succeed,
+/// which keeps the client below the server's limit instead of repeatedly
+/// hitting it.
+///
+/// ```
+/// use ratelimit::{Adaptive, Ratelimiter};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::per_second(100).initial_available(100).build().unwrap();
+/// let client = Adaptive::new(ratelimiter).backoff(0.5, 0.05).unwrap();
+///
+/// assert!(client.try_wait().is_ok());
+///
+/// // the server responded with `429 Too Many Requests` and `Retry-After: 2`
+/// client.throttled(Some(Duration::from_secs(2)));
+///
+/// assert!(client.try_wait().is_err());
+/// assert_eq!(client.ratelimiter().rate(), 50.0);
+/// ```
+pub struct Adaptive<L> {
+    backoff: Option<Backoff>,
+    base_scale: f64,
+    headers: Box<dyn QuotaHeaders>,
+    ratelimiter: L,
+    scaling: Mutex<()>,
+    suppressed_until: AtomicInstant,
+}
+
+/// Internal type which holds the parameters for backing off the rate.
+#[derive(Clone, Copy)]
+struct Backoff {
+    factor: f64,
+    recovery: f64,
+}
+
+impl<L: Borrow<Ratelimiter>> Adaptive<L> {
+    /// Wrap the provided ratelimiter. By default, throttling signals only
+    /// suppress acquisitions and the rate is not backed off.
+    pub fn new(ratelimiter: L) -> Self {
+        let base_scale = ratelimiter.borrow().scale();
+
+        Self {
+            backoff: None,
+            base_scale,
+            headers: Box::new(StandardHeaders),
+            ratelimiter,
+            scaling: Mutex::new(()),
+            suppressed_until: AtomicInstant::now(),
+        }
+    }
+
+    /// Back off the rate on each throttling signal by multiplying it by the
+    /// factor, which must be in the range `0.0..1.0`. Each successful request
+    /// reported with [`Adaptive::succeeded`] recovers the rate by adding the
+    /// recovery, as a fraction of the original rate, until the original rate
+    /// is reached.
+    ///
+    /// The rate is adjusted through [`Ratelimiter::set_scale`], so the scale
+    /// of the ratelimiter should not also be changed elsewhere.
+    pub fn backoff(mut self, factor: f64, recovery: f64) -> Result<Self, Error> {
+        if !(0.0..1.0).contains(&factor) || !(0.0..=1.0).contains(&recovery) {
+            return Err(Error::InvalidFraction);
+        }
+
+        self.backoff = Some(Backoff { factor, recovery });
+        Ok(self)
+    }
+
+    /// Use the provided parser for the quota headers of responses passed to
+    /// [`Adaptive::response`]. By default, these are the standard
+    /// `RateLimit-*` headers.
+    pub fn quota_headers(mut self, headers: impl QuotaHeaders + 'static) -> Self {
+        self.headers = Box::new(headers);
+        self
+    }
+
+    /// Report the status and headers of a response from the remote API. The
+    /// headers are looked up by their lowercase names and parsed by the
+    /// [`QuotaHeaders`] provided to [`Adaptive::quota_headers`].
+    ///
+    /// The bucket is reconciled with any remaining quota using
+    /// [`Ratelimiter::sync_remote`]. Then, the response is reported with
+    /// [`Adaptive::throttled`] or [`Adaptive::succeeded`]. A throttled response
+    /// without a `Retry-After` whose quota is exhausted suppresses
+    /// acquisitions until the quota resets.
+    ///
+    /// ```
+    /// use ratelimit::{Adaptive, GitHubHeaders, Ratelimiter};
+    /// use std::collections::HashMap;
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// let ratelimiter = Ratelimiter::per_second(10).initial_available(10).build().unwrap();
+    /// let client = Adaptive::new(ratelimiter).quota_headers(GitHubHeaders);
+    ///
+    /// let reset = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + Duration::from_secs(60);
+    /// let reset = reset.as_secs().to_string();
+    /// let headers = HashMap::from([
+    ///     ("x-ratelimit-remaining", "0"),
+    ///     ("x-ratelimit-reset", reset.as_str()),
+    /// ]);
+    ///
+    /// client.response(403, &|name| headers.get(name).copied());
+    ///
+    /// assert_eq!(client.ratelimiter().available(), 0);
+    /// assert!(client.suppressed_for().unwrap() > Duration::from_secs(50));
+    /// ```
+    pub fn response<'a>(&self, status: u16, header: &dyn Fn(&str) -> Option<&'a str>) {
+        let quota = self.headers.parse(header);
+
+        if let (Some(remaining), Some(reset_at)) = (quota.remaining, quota.reset_at) {
+            self.ratelimiter.borrow().sync_remote(remaining, reset_at);
+        }
+
+        if self.headers.is_throttled(status, &quota) {
+            let retry_after = quota.retry_after.or_else(|| {
+                let reset_at = quota.reset_at.filter(|_| quota.remaining == Some(0))?;
+                Some(reset_at.duration_since(system_now()).unwrap_or_default())
+            });
+
+            self.throttled(retry_after);
+        } else {
+            self.succeeded();
+        }
+    }
+
+    /// Returns a reference to the wrapped ratelimiter.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Report that the server throttled a request. Acquisitions are suppressed
+    /// for the provided delay, typically from the `Retry-After` header, or for
+    /// one refill interval if the server did not provide one. The rate is also
+    /// backed off if [`Adaptive::backoff`] was configured.
+    pub fn throttled(&self, retry_after: Option<core::time::Duration>) {
+        let ratelimiter = self.ratelimiter.borrow();
+        let delay = retry_after.unwrap_or_else(|| ratelimiter.scaled_interval());
+
+        // the delay is from the server, so the deadline saturates rather than
+        // overflowing
+        let nanos = delay.as_nanos().min(u64::MAX as u128) as u64;
+        self.suppressed_until
+            .fetch_max(advance_instant(Instant::now(), 1, nanos), Ordering::AcqRel);
+
+        if let Some(backoff) = self.backoff {
+            let _lock = self.scaling.lock();
+            let scale = (ratelimiter.scale() * backoff.factor).max(self.base_scale * MIN_FRACTION);
+            let _ = ratelimiter.set_scale(scale);
+        }
+    }
+
+    /// Report that a request succeeded, which recovers a backed off rate. See
+    /// [`Adaptive::backoff`].
+    pub fn succeeded(&self) {
+        let Some(backoff) = self.backoff else {
+            return;
+        };
+
+        let ratelimiter = self.ratelimiter.borrow();
+        if ratelimiter.scale() >= self.base_scale {
+            return;
+        }
+
+        let _lock = self.scaling.lock();
+        let scale = (ratelimiter.scale() + self.base_scale * backoff.recovery).min(self.base_scale);
+        let _ = ratelimiter.set_scale(scale);
+    }
+
+    /// Returns the time remaining until acquisitions are no longer suppressed,
+    /// or `None` if they are not suppressed.
+    pub fn suppressed_for(&self) -> Option<core::time::Duration> {
+        let until = self.suppressed_until.load(Ordering::Acquire);
+        let now = Instant::now();
+
+        (until > now).then(|| core::time::Duration::from_nanos((until - now).as_nanos()))
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens. While acquisitions are
+    /// suppressed, this fails with the time remaining. See
+    /// [`Ratelimiter::try_wait_n`].
+    pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        match self.suppressed_for() {
+            Some(remaining) => Err(remaining),
+            None => self.ratelimiter.borrow().try_wait_n(n),
+        }
+    }
+
+    /// Non-blocking function to "wait" for a single token. See
+    /// [`Adaptive::try_wait_n`].
+    pub fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1)
+    }
+
+    /// Non-blocking function to acquire `n` tokens. While acquisitions are
+    /// suppressed, this fails with [`TryAcquireError::Insufficient`] and the
+    /// time remaining. See [`Ratelimiter::try_acquire_n`].
+    pub fn try_acquire_n(&self, n: u64) -> Result<(), TryAcquireError> {
+        match self.suppressed_for() {
+            Some(remaining) => Err(TryAcquireError::Insufficient(remaining)),
+            None => self.ratelimiter.borrow().try_acquire_n(n),
+        }
+    }
+
+    /// Non-blocking function to acquire a single token. See
+    /// [`Adaptive::try_acquire_n`].
+    pub fn try_acquire(&self) -> Result<(), TryAcquireError> {
+        self.try_acquire_n(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn suppress() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+        let client = Adaptive::new(&rl);
+
+        client.try_acquire().unwrap();
+        client.throttled(Some(Duration::from_millis(20)));
+
+        assert!(matches!(
+            client.try_acquire(),
+            Err(TryAcquireError::Insufficient(_))
+        ));
+        assert!(client.suppressed_for().unwrap() <= Duration::from_millis(20));
+
+        // a shorter delay does not end the suppression early
+        client.throttled(Some(Duration::ZERO));
+        assert!(client.try_wait().is_err());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(client.suppressed_for(), None);
+        client.try_acquire().unwrap();
+
+        // the rate is unchanged without a backoff
+        assert_eq!(rl.scale(), 1.0);
+        assert_eq!(rl.available(), 8);
+    }
+
+    #[test]
+    fn suppress_overflow() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let client = Adaptive::new(&rl);
+
+        // a huge delay saturates instead of wrapping into the past
+        for delay in [Duration::from_secs(18_446_744_073), Duration::MAX] {
+            client.throttled(Some(delay));
+            assert!(client.suppressed_for().unwrap() > Duration::from_secs(100 * 365 * 86_400));
+            assert!(client.try_acquire().is_err());
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .build()
+            .unwrap();
+        assert!(Adaptive::new(&rl).backoff(1.0, 0.1).is_err());
+
+        let client = Adaptive::new(&rl).backoff(0.5, 0.25).unwrap();
+
+        client.throttled(Some(Duration::ZERO));
+        client.throttled(Some(Duration::ZERO));
+        assert_eq!(rl.scale(), 0.25);
+
+        client.succeeded();
+        assert_eq!(rl.scale(), 0.5);
+
+        for _ in 0..4 {
+            client.succeeded();
+        }
+        assert_eq!(rl.scale(), 1.0);
+    }
+}
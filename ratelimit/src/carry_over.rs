@@ -0,0 +1,137 @@
+use crate::atomic::Ordering;
+use crate::{Builder, Ratelimiter};
+
+/// The policy for tokens which are unused when a refill occurs. This is most
+/// useful for modeling quotas which reset on fixed windows, see
+/// [`Builder::align_to_wall_clock`], where any unused quota from one window may
+/// or may not roll into the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CarryOver {
+    /// Unused tokens are retained, limited only by the max tokens. This is the
+    /// standard token bucket behavior and is the default.
+    #[default]
+    Unlimited,
+    /// Unused tokens expire at each refill. After a refill, exactly the refill
+    /// amount is available.
+    None,
+    /// Up to the provided number of unused tokens are retained at each refill.
+    /// After a refill, the refill amount plus the retained tokens are available,
+    /// still limited by the max tokens. The max tokens should typically be set
+    /// to the refill amount plus this cap.
+    Capped(u64),
+}
+
+impl Builder {
+    /// Set the policy for tokens which are unused when a refill occurs. See
+    /// [`CarryOver`] for the available options.
+    pub fn carry_over(mut self, policy: CarryOver) -> Self {
+        self.carry_over = policy;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the policy for tokens which are unused when a refill occurs.
+    pub fn carry_over(&self) -> CarryOver {
+        self.extensions()
+            .map(|e| e.carry_over)
+            .unwrap_or(CarryOver::Unlimited)
+    }
+
+    /// Internal function to add tokens for `intervals` refills of `amount`
+    /// tokens each when the carry-over is limited. Returns the number of tokens
+    /// which expired because they were not carried over, and the number which
+    /// were dropped because the bucket was full.
+    pub(crate) fn refill_windowed(&self, intervals: u64, amount: u64, capacity: u64) -> (u64, u64) {
+        let cap = match self.carry_over() {
+            CarryOver::Unlimited => u64::MAX,
+            CarryOver::None => 0,
+            CarryOver::Capped(cap) => cap,
+        };
+
+        let issued = intervals.saturating_mul(amount);
+
+        // Each refill retains up to `cap` of the tokens that were available and
+        // adds `amount` tokens. After several refills this is bounded by the
+        // tokens retained from a full window plus the refill amount.
+        let next = |available: u64| {
+            available
+                .min(cap)
+                .saturating_add(issued)
+                .min(amount.saturating_add(cap))
+                .min(capacity)
+        };
+
+        let previous = self
+            .available
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |a| Some(next(a)))
+            .unwrap();
+
+        let expired = previous.saturating_sub(cap);
+        let dropped = (previous.saturating_add(issued)).saturating_sub(next(previous));
+
+        // the total saturates when a large amount is issued, in which case
+        // fewer tokens may appear dropped than expired
+        (expired, dropped.saturating_sub(expired))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clocksource::precise::Duration;
+
+    fn ratelimiter(policy: CarryOver) -> Ratelimiter {
+        Ratelimiter::builder(10, core::time::Duration::from_secs(1))
+            .max_tokens(15)
+            .carry_over(policy)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn unlimited() {
+        let rl = ratelimiter(CarryOver::Unlimited);
+        rl.refill(rl.created + Duration::from_secs(1)).unwrap();
+        assert_eq!(rl.available(), 10);
+        rl.refill(rl.created + Duration::from_secs(2)).unwrap();
+        assert_eq!(rl.available(), 15);
+    }
+
+    #[test]
+    fn none() {
+        let rl = ratelimiter(CarryOver::None);
+        rl.refill(rl.created + Duration::from_secs(1)).unwrap();
+        assert_eq!(rl.available(), 10);
+        rl.try_wait_n(3).unwrap();
+        rl.refill(rl.created + Duration::from_secs(2)).unwrap();
+        assert_eq!(rl.available(), 10);
+        assert_eq!(rl.dropped(), 7);
+    }
+
+    #[test]
+    fn capped() {
+        let rl = ratelimiter(CarryOver::Capped(2));
+        rl.refill(rl.created + Duration::from_secs(1)).unwrap();
+        assert_eq!(rl.available(), 10);
+        rl.try_wait_n(9).unwrap();
+        rl.refill(rl.created + Duration::from_secs(2)).unwrap();
+        assert_eq!(rl.available(), 11);
+        rl.refill(rl.created + Duration::from_secs(5)).unwrap();
+        assert_eq!(rl.available(), 12);
+    }
+
+    #[test]
+    fn saturated() {
+        let rl = Ratelimiter::builder(u64::MAX, core::time::Duration::from_secs(1))
+            .max_tokens(u64::MAX)
+            .initial_available(7)
+            .carry_over(CarryOver::Capped(2))
+            .build()
+            .unwrap();
+
+        rl.refill(rl.created + Duration::from_secs(1)).unwrap();
+        assert_eq!(rl.available(), u64::MAX);
+        assert_eq!(rl.dropped(), 5);
+    }
+}
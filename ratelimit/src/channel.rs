@@ -0,0 +1,260 @@
+//! Wrappers around tokio's [`mpsc`] channel which limit the rate at which
+//! items are sent or received.
+//!
+//! Ratelimiting the sender paces the producers, while ratelimiting the
+//! receiver paces the consumer so that a work queue is processed no faster
+//! than the rate of the ratelimiter.
+//!
+//! ```
+//! use ratelimit::channel::RatelimitedReceiver;
+//! use ratelimit::Ratelimiter;
+//! use std::time::Duration;
+//!
+//! # tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(async {
+//! let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+//!     .build()
+//!     .unwrap();
+//!
+//! let (tx, rx) = tokio::sync::mpsc::channel(16);
+//! let mut rx = RatelimitedReceiver::new(rx, &ratelimiter);
+//!
+//! tx.send("job").await.unwrap();
+//!
+//! // each item is delivered after a token has been acquired
+//! assert_eq!(rx.recv().await, Some("job"));
+//! # });
+//! ```
+
+use crate::sleep::retry_delay;
+use crate::{Ratelimiter, TryAcquireError};
+use core::borrow::Borrow;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// The error returned by [`RatelimitedSender::send`]. The item which could not
+/// be sent is returned with the error.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SendError<T> {
+    /// The ratelimiter has been closed, so no more items can be sent.
+    #[error("the ratelimiter is closed")]
+    Closed(T),
+    /// The receiver has been dropped.
+    #[error("the channel is closed")]
+    Disconnected(T),
+}
+
+impl<T> SendError<T> {
+    /// Consumes the error, returning the item which could not be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            Self::Closed(item) | Self::Disconnected(item) => item,
+        }
+    }
+}
+
+/// A channel sender where each send waits for a single token from the
+/// ratelimiter. Cloning the sender shares the ratelimiter when `L` is a
+/// reference or an `Arc`.
+#[derive(Clone, Debug)]
+pub struct RatelimitedSender<T, L> {
+    ratelimiter: L,
+    sender: mpsc::Sender<T>,
+}
+
+impl<T, L: Borrow<Ratelimiter>> RatelimitedSender<T, L> {
+    /// Wrap the sender so that each item sent costs a single token.
+    pub fn new(sender: mpsc::Sender<T>, ratelimiter: L) -> Self {
+        Self {
+            ratelimiter,
+            sender,
+        }
+    }
+
+    /// Send an item once there is capacity in the channel and a token has been
+    /// acquired. The token is acquired after the capacity is reserved so that
+    /// tokens are not spent on items which cannot be sent.
+    pub async fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let Ok(permit) = self.sender.reserve().await else {
+            return Err(SendError::Disconnected(item));
+        };
+
+        if !acquire(self.ratelimiter.borrow()).await {
+            return Err(SendError::Closed(item));
+        }
+
+        permit.send(item);
+
+        Ok(())
+    }
+
+    /// Returns the ratelimiter which is charged for each item.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Returns a reference to the inner sender.
+    pub fn get_ref(&self) -> &mpsc::Sender<T> {
+        &self.sender
+    }
+
+    /// Consumes this wrapper, returning the inner sender.
+    pub fn into_inner(self) -> mpsc::Sender<T> {
+        self.sender
+    }
+}
+
+/// A channel receiver which delivers items no faster than the rate of the
+/// ratelimiter, charging a single token for each item.
+///
+/// Items are left in the channel while waiting for a token, so producers see
+/// backpressure once the channel is full. The receiver stops delivering items
+/// if the ratelimiter is closed.
+#[derive(Debug)]
+pub struct RatelimitedReceiver<T, L> {
+    ratelimiter: L,
+    receiver: mpsc::Receiver<T>,
+}
+
+impl<T, L: Borrow<Ratelimiter>> RatelimitedReceiver<T, L> {
+    /// Wrap the receiver so that each item received costs a single token.
+    pub fn new(receiver: mpsc::Receiver<T>, ratelimiter: L) -> Self {
+        Self {
+            ratelimiter,
+            receiver,
+        }
+    }
+
+    /// Receive the next item once a token has been acquired. Returns `None`
+    /// if the channel is closed and empty, or if the ratelimiter is closed.
+    pub async fn recv(&mut self) -> Option<T> {
+        let ratelimiter = self.ratelimiter.borrow();
+
+        if !acquire(ratelimiter).await {
+            return None;
+        }
+
+        let item = self.receiver.recv().await;
+
+        // the token is not needed if the channel is closed
+        if item.is_none() {
+            ratelimiter.return_n(1);
+        }
+
+        item
+    }
+
+    /// Returns the ratelimiter which is charged for each item.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Returns a reference to the inner receiver.
+    pub fn get_ref(&self) -> &mpsc::Receiver<T> {
+        &self.receiver
+    }
+
+    /// Returns a mutable reference to the inner receiver.
+    pub fn get_mut(&mut self) -> &mut mpsc::Receiver<T> {
+        &mut self.receiver
+    }
+
+    /// Consumes this wrapper, returning the inner receiver.
+    pub fn into_inner(self) -> mpsc::Receiver<T> {
+        self.receiver
+    }
+}
+
+/// Internal function to wait until a single token has been acquired. Returns
+/// `false` if the ratelimiter is closed.
+async fn acquire(ratelimiter: &Ratelimiter) -> bool {
+    let mut ticket = None;
+
+    loop {
+        match ratelimiter.try_acquire_queued(1, &mut ticket) {
+            Ok(()) => return true,
+            Err(TryAcquireError::Closed) => return false,
+            Err(e) => {
+                // a ratelimiter which is denying requests may resume, so we
+                // wait as if it were paused
+                let delay =
+                    retry_delay(ratelimiter, &e).unwrap_or_else(|| ratelimiter.scaled_interval());
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn sender() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+
+        runtime().block_on(async {
+            let (tx, mut rx) = mpsc::channel(8);
+            let tx = RatelimitedSender::new(tx, ratelimiter.clone());
+
+            let start = Instant::now();
+            for i in 0..3 {
+                tx.clone().send(i).await.unwrap();
+            }
+            assert!(start.elapsed() >= Duration::from_millis(20));
+            assert_eq!(rx.recv().await, Some(0));
+
+            ratelimiter.close();
+            assert_eq!(tx.send(3).await, Err(SendError::Closed(3)));
+
+            // no token is spent when the receiver has been dropped
+            drop(rx);
+            assert_eq!(tx.send(4).await, Err(SendError::Disconnected(4)));
+        });
+    }
+
+    #[test]
+    fn receiver() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
+            .max_tokens(2)
+            .initial_available(1)
+            .build()
+            .unwrap();
+
+        runtime().block_on(async {
+            let (tx, rx) = mpsc::channel(8);
+            let mut rx = RatelimitedReceiver::new(rx, &ratelimiter);
+
+            for i in 0..3 {
+                tx.send(i).await.unwrap();
+            }
+            drop(tx);
+
+            let start = Instant::now();
+            let mut items = Vec::new();
+            while let Some(item) = rx.recv().await {
+                items.push(item);
+            }
+            assert_eq!(items, vec![0, 1, 2]);
+            assert!(start.elapsed() >= Duration::from_millis(20));
+
+            // the token acquired for the end of the channel is returned
+            assert!(ratelimiter.available() >= 1);
+
+            ratelimiter.close();
+            assert_eq!(rx.recv().await, None);
+        });
+    }
+}
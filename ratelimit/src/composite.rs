@@ -0,0 +1,125 @@
+use crate::multi::charge_all;
+use crate::{Ratelimiter, TryAcquireError};
+use core::borrow::Borrow;
+
+/// A limiter which admits a request only if every one of its members admits
+/// it, for example to apply a per-user limit and a global limit together.
+///
+/// The members are charged in order. If a member denies the request, the
+/// members which were already charged are refunded, so a denied request does
+/// not use up any of the limits.
+///
+/// ```
+/// use ratelimit::{Composite, KeyedRatelimiter, Ratelimiter, RatelimiterConfig};
+///
+/// let config = RatelimiterConfig::new("10/s".parse().unwrap()).initial_available(10);
+/// let users = KeyedRatelimiter::from_config(config).unwrap();
+/// let global = Ratelimiter::per_second(100).initial_available(100).build().unwrap();
+///
+/// let alice = users.get("alice");
+/// let limiter = Composite::new(vec![&*alice, &global]);
+///
+/// if limiter.try_wait().is_ok() {
+///     // both the limit for alice and the global limit have been charged
+/// }
+/// ```
+pub struct Composite<L> {
+    members: Vec<L>,
+}
+
+impl<L: Borrow<Ratelimiter>> Composite<L> {
+    /// Create a limiter from the provided members. A limiter with no members
+    /// admits every request.
+    pub fn new(members: Vec<L>) -> Self {
+        Self { members }
+    }
+
+    /// Returns an iterator across the members.
+    pub fn members(&self) -> impl Iterator<Item = &Ratelimiter> {
+        self.members.iter().map(|member| member.borrow())
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens from every member. On
+    /// failure, nothing has been charged and the `Duration` is the hint from
+    /// the member which denied the request. See [`Ratelimiter::try_wait_n`].
+    pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        charge_all(self.charges(n)).map_err(|(index, e)| {
+            let member = self.members[index].borrow();
+
+            match e {
+                TryAcquireError::Insufficient(duration) => duration,
+                _ => member.scaled_interval(),
+            }
+        })
+    }
+
+    /// Non-blocking function to "wait" for a single token from every member.
+    /// See [`Composite::try_wait_n`].
+    pub fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1)
+    }
+
+    /// Non-blocking function to acquire `n` tokens from every member. On
+    /// failure, nothing has been charged and the error is the error from the
+    /// member which denied the request. See [`Ratelimiter::try_acquire_n`].
+    pub fn try_acquire_n(&self, n: u64) -> Result<(), TryAcquireError> {
+        charge_all(self.charges(n)).map_err(|(_, e)| e)
+    }
+
+    /// Non-blocking function to acquire a single token from every member. See
+    /// [`Composite::try_acquire_n`].
+    pub fn try_acquire(&self) -> Result<(), TryAcquireError> {
+        self.try_acquire_n(1)
+    }
+
+    /// Internal function which returns the charge of `n` tokens for each
+    /// member.
+    fn charges(&self, n: u64) -> impl Iterator<Item = (&Ratelimiter, u64)> + Clone {
+        self.members.iter().map(move |member| (member.borrow(), n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn ratelimiter(tokens: u64) -> Arc<Ratelimiter> {
+        Arc::new(
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(tokens)
+                .initial_available(tokens)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn refund() {
+        let global = ratelimiter(3);
+        let alice = Composite::new(vec![ratelimiter(2), global.clone()]);
+        let bob = Composite::new(vec![ratelimiter(2), global.clone()]);
+
+        alice.try_wait_n(2).unwrap();
+
+        // the global limit denies bob, so the limit for bob is refunded
+        assert!(bob.try_wait_n(2).is_err());
+        assert_eq!(
+            bob.members().map(|m| m.available()).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+
+        // alice is denied by the limit for alice, so the global limit is untouched
+        assert!(matches!(
+            alice.try_acquire(),
+            Err(TryAcquireError::Insufficient(_))
+        ));
+        assert_eq!(global.available(), 1);
+
+        bob.try_acquire().unwrap();
+        global.close();
+        assert_eq!(bob.try_acquire(), Err(TryAcquireError::Closed));
+        assert_eq!(bob.members().next().unwrap().available(), 1);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{Builder, Error, Rate};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A declarative configuration for a `Ratelimiter`. With the `serde` feature
+/// enabled this can be deserialized from formats such as JSON, TOML, or YAML
+/// alongside the rest of a service configuration. The rate is represented as
+/// a human-readable string, see [`Rate`] for the accepted format.
+///
+/// ```
+/// use ratelimit::RatelimiterConfig;
+///
+/// let config = RatelimiterConfig::new("1000/h".parse().unwrap())
+///     .max_tokens(100)
+///     .initial_available(100);
+///
+/// let ratelimiter = config.builder().unwrap().build().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RatelimiterConfig {
+    /// The rate at which tokens are added to the bucket.
+    pub rate: Rate,
+    /// The max tokens that can be held in the bucket. When not provided, this
+    /// defaults to the number of tokens in the rate's window.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub max_tokens: Option<u64>,
+    /// The number of tokens initially available. When not provided, no tokens
+    /// are initially available.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub initial_available: Option<u64>,
+}
+
+impl RatelimiterConfig {
+    /// Create a new config with the provided rate and default values for all
+    /// other fields.
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            max_tokens: None,
+            initial_available: None,
+        }
+    }
+
+    /// Set the max tokens that can be held in the bucket.
+    pub fn max_tokens(mut self, tokens: u64) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    /// Set the number of tokens initially available.
+    pub fn initial_available(mut self, tokens: u64) -> Self {
+        self.initial_available = Some(tokens);
+        self
+    }
+
+    /// Load a config from environment variables with the provided prefix. The
+    /// rate is read from `<PREFIX>_RATE` and is required. The max tokens and
+    /// initial available tokens are optionally read from `<PREFIX>_BURST` and
+    /// `<PREFIX>_INITIAL` respectively.
+    ///
+    /// For example, with a prefix of `MYAPP`, setting `MYAPP_RATE=100/s` and
+    /// `MYAPP_BURST=10` would configure a ratelimiter that allows 100 tokens/s
+    /// with bursts of up to 10 tokens.
+    pub fn from_env(prefix: &str) -> Result<Self, Error> {
+        Self::from_lookup(prefix, |name| std::env::var(name))
+    }
+
+    /// Internal function to load a config using the provided function to look
+    /// up each variable. See [`RatelimiterConfig::from_env`] for details.
+    fn from_lookup(
+        prefix: &str,
+        lookup: impl Fn(&str) -> Result<String, std::env::VarError>,
+    ) -> Result<Self, Error> {
+        let var = |name: String| -> Result<Option<String>, Error> {
+            match lookup(&name) {
+                Ok(value) => Ok(Some(value)),
+                Err(std::env::VarError::NotPresent) => Ok(None),
+                Err(std::env::VarError::NotUnicode(_)) => Err(Error::InvalidEnvVar(name)),
+            }
+        };
+
+        fn parse<T: core::str::FromStr>(
+            var: &impl Fn(String) -> Result<Option<String>, Error>,
+            name: String,
+        ) -> Result<Option<T>, Error> {
+            match var(name.clone())? {
+                Some(value) => value
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| Error::InvalidEnvVar(name)),
+                None => Ok(None),
+            }
+        }
+
+        let rate = format!("{prefix}_RATE");
+        let rate = parse(&var, rate.clone())?.ok_or(Error::MissingEnvVar(rate))?;
+
+        Ok(Self {
+            rate,
+            max_tokens: parse(&var, format!("{prefix}_BURST"))?,
+            initial_available: parse(&var, format!("{prefix}_INITIAL"))?,
+        })
+    }
+
+    /// Initialize a `Builder` from this config.
+    pub fn builder(&self) -> Result<Builder, Error> {
+        let mut builder = self.rate.builder()?;
+
+        if let Some(tokens) = self.max_tokens {
+            builder = builder.max_tokens(tokens);
+        }
+
+        if let Some(tokens) = self.initial_available {
+            builder = builder.initial_available(tokens);
+        }
+
+        Ok(builder)
+    }
+}
+
+impl Builder {
+    /// Initialize a builder from environment variables with the provided
+    /// prefix. See [`RatelimiterConfig::from_env`] for the variables that are
+    /// read.
+    pub fn from_env(prefix: &str) -> Result<Builder, Error> {
+        RatelimiterConfig::from_env(prefix)?.builder()
+    }
+}
+
+impl TryFrom<RatelimiterConfig> for Builder {
+    type Error = Error;
+
+    fn try_from(config: RatelimiterConfig) -> Result<Self, Self::Error> {
+        config.builder()
+    }
+}
+
+impl TryFrom<&RatelimiterConfig> for Builder {
+    type Error = Error;
+
+    fn try_from(config: &RatelimiterConfig) -> Result<Self, Self::Error> {
+        config.builder()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+
+    #[test]
+    fn builder() {
+        let rl = RatelimiterConfig::new("10/s".parse().unwrap())
+            .max_tokens(20)
+            .initial_available(5)
+            .builder()
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(rl.max_tokens(), 20);
+        assert_eq!(rl.available(), 5);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn from_env() {
+        fn lookup<'a>(
+            vars: &'a [(&'a str, &'a str)],
+        ) -> impl Fn(&str) -> Result<String, std::env::VarError> + 'a {
+            move |name| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+                    .ok_or(std::env::VarError::NotPresent)
+            }
+        }
+
+        let config = RatelimiterConfig::from_lookup(
+            "TEST",
+            lookup(&[
+                ("TEST_RATE", "100/s"),
+                ("TEST_BURST", "10"),
+                ("TEST_INITIAL", "5"),
+            ]),
+        )
+        .unwrap();
+
+        let rl = config.builder().unwrap().build().unwrap();
+        assert_eq!(rl.max_tokens(), 10);
+        assert_eq!(rl.available(), 5);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(10));
+
+        assert_eq!(
+            RatelimiterConfig::from_lookup("TEST", lookup(&[])).err(),
+            Some(Error::MissingEnvVar("TEST_RATE".to_string()))
+        );
+
+        assert_eq!(
+            RatelimiterConfig::from_lookup(
+                "TEST",
+                lookup(&[("TEST_RATE", "100/s"), ("TEST_BURST", "lots")])
+            )
+            .err(),
+            Some(Error::InvalidEnvVar("TEST_BURST".to_string()))
+        );
+
+        assert_eq!(
+            RatelimiterConfig::from_lookup("TEST", |_| Err(std::env::VarError::NotUnicode(
+                Default::default()
+            )))
+            .err(),
+            Some(Error::InvalidEnvVar("TEST_RATE".to_string()))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let config: RatelimiterConfig =
+            serde_json::from_str(r#"{ "rate": "1.5k/s", "max_tokens": 100 }"#).unwrap();
+
+        assert_eq!(config.rate.tokens_per_second(), 1500.0);
+        assert_eq!(config.max_tokens, Some(100));
+        assert_eq!(config.initial_available, None);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(json, r#"{"rate":"1500/s","max_tokens":100}"#);
+
+        assert!(serde_json::from_str::<RatelimiterConfig>(r#"{ "rate": "fast" }"#).is_err());
+    }
+}
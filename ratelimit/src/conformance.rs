@@ -0,0 +1,310 @@
+//! Helpers to verify that a ratelimiter conforms to its configured rate. A
+//! synthetic workload is replayed against a ratelimiter built from the config
+//! under virtual time, see [`Simulation`], and the tokens admitted are checked
+//! against the configured rate over every window of the run.
+//!
+//! A token bucket admits bursts, so the bound for a window is the burst plus
+//! the configured rate over the length of the window. The burst is the max
+//! tokens of the ratelimiter plus a single refill, since a refill may land
+//! just after a full bucket has been drained. Over long windows, the admitted
+//! rate converges on the configured rate.
+//!
+//! These are intended for use in tests, where a failure to conform should
+//! fail the test, and to produce evidence that a limit is enforced.
+//!
+//! ```
+//! use ratelimit::conformance::{assert_conforms, Workload};
+//! use ratelimit::RatelimiterConfig;
+//! use std::time::Duration;
+//!
+//! let config = RatelimiterConfig::new("100/s".parse().unwrap()).max_tokens(10);
+//!
+//! // a day of demand at ten times the rate
+//! let workload = Workload::new(Duration::from_secs(86_400), |_| 100)
+//!     .step(Duration::from_millis(100));
+//!
+//! let conformance = assert_conforms(&config, workload, 0.0);
+//! assert!(conformance.admitted <= 100 * 86_400 + 10);
+//! ```
+
+use crate::{Error, Ratelimiter, RatelimiterConfig, Simulation};
+use core::time::Duration;
+
+// slack for the floating point error in the bound for a window
+const EPSILON: f64 = 1e-6;
+
+/// A synthetic workload to replay against a ratelimiter. The demand function
+/// returns the number of tokens requested in each step, given the virtual time
+/// elapsed. Requests which are not admitted are retried in later steps.
+pub struct Workload<F> {
+    duration: Duration,
+    step: Option<Duration>,
+    demand: F,
+}
+
+impl<F: FnMut(Duration) -> u64> Workload<F> {
+    /// Create a workload which runs for `duration` of virtual time.
+    pub fn new(duration: Duration, demand: F) -> Self {
+        Self {
+            duration,
+            step: None,
+            demand,
+        }
+    }
+
+    /// Set the length of each step of virtual time. The default is the refill
+    /// interval of the ratelimiter. See [`Simulation::step`].
+    pub fn step(mut self, step: Duration) -> Self {
+        self.step = Some(step.max(Duration::from_nanos(1)));
+        self
+    }
+}
+
+/// A window of the run, with the tokens admitted and the bound on the tokens
+/// which may be admitted within it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    /// The virtual time at the start of the window.
+    pub start: Duration,
+    /// The virtual time at the end of the window.
+    pub end: Duration,
+    /// The number of tokens admitted within the window.
+    pub admitted: u64,
+    /// The most tokens which may be admitted within the window, including
+    /// the tolerance.
+    pub allowed: f64,
+}
+
+/// The results of verifying a ratelimiter against its configured rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conformance {
+    /// The configured rate in tokens/s.
+    pub rate: f64,
+    /// The burst allowed on top of the configured rate in any window.
+    pub burst: u64,
+    /// The relative tolerance which was applied to the bound.
+    pub tolerance: f64,
+    /// The virtual time which was simulated.
+    pub elapsed: Duration,
+    /// The number of tokens which were requested.
+    pub requested: u64,
+    /// The number of tokens which were admitted.
+    pub admitted: u64,
+    /// The window in which the admitted tokens came closest to, or furthest
+    /// beyond, the bound.
+    pub worst: Window,
+}
+
+impl Conformance {
+    /// Returns `true` if the tokens admitted were within the bound in every
+    /// window of the run.
+    pub fn conforms(&self) -> bool {
+        self.worst.admitted as f64 <= self.worst.allowed + EPSILON
+    }
+}
+
+/// Replay the workload against a ratelimiter built from the config, and check
+/// the tokens admitted over every window of the run. The bound for each
+/// window is relaxed by the relative `tolerance`, for example `0.01` allows
+/// 1% more tokens than the bound. Returns an error if the ratelimiter cannot
+/// be built from the config.
+///
+/// Since tokens are only admitted at the steps of the simulation, checking the
+/// windows which start and end on a step covers every window of the run.
+pub fn verify<F: FnMut(Duration) -> u64>(
+    config: &RatelimiterConfig,
+    workload: Workload<F>,
+    tolerance: f64,
+) -> Result<Conformance, Error> {
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        return Err(Error::InvalidTolerance);
+    }
+
+    let ratelimiter = config.builder()?.build()?;
+
+    Ok(check(
+        ratelimiter,
+        config.rate.tokens_per_second(),
+        workload,
+        tolerance,
+    ))
+}
+
+/// Internal function to replay the workload against the ratelimiter, and to
+/// check the tokens admitted against the rate over every window.
+fn check<F: FnMut(Duration) -> u64>(
+    ratelimiter: Ratelimiter,
+    rate: f64,
+    workload: Workload<F>,
+    tolerance: f64,
+) -> Conformance {
+    let interval = ratelimiter.refill_interval();
+    let burst = ratelimiter
+        .max_tokens()
+        .saturating_add((rate * interval.as_secs_f64()).ceil() as u64);
+
+    let step = workload.step.unwrap_or(interval);
+    let step_ns = step.as_nanos().clamp(1, u64::MAX as u128) as u64;
+    let steps = workload
+        .duration
+        .as_nanos()
+        .div_ceil(step_ns as u128)
+        .min(u64::MAX as u128) as u64;
+
+    let mut simulation = Simulation::new(ratelimiter, workload.demand).step(step);
+
+    // the bound for the window from step `i` to step `j` is
+    // `burst + rate * (t_j - t_i)`, so the window is within the bound when
+    // `admitted_j - rate * t_j <= burst + admitted_i - rate * t_i`, where
+    // `admitted_i` excludes the tokens admitted at step `i` itself
+    let bound_rate = rate * (1.0 + tolerance);
+    let allowance = burst as f64 * (1.0 + tolerance);
+
+    let mut requested: u64 = 0;
+    let mut admitted: u64 = 0;
+
+    // the start of the window with the lowest `admitted_i - rate * t_i`
+    let mut lowest = (f64::INFINITY, 0, 0);
+    let mut worst: Option<(f64, Window)> = None;
+
+    for index in 0..steps {
+        let time = index.saturating_mul(step_ns);
+
+        let value = admitted as f64 - bound_rate * time as f64 / 1e9;
+        if value < lowest.0 {
+            lowest = (value, time, admitted);
+        }
+
+        let report = simulation.run(step);
+        requested = requested.saturating_add(report.requested);
+        admitted = admitted.saturating_add(report.admitted);
+
+        let (_, start, before) = lowest;
+        let allowed = allowance + bound_rate * (time - start) as f64 / 1e9;
+        let window_admitted = admitted - before;
+        let excess = window_admitted as f64 - allowed;
+
+        if worst.is_none_or(|(worst, _)| excess > worst) {
+            worst = Some((
+                excess,
+                Window {
+                    start: Duration::from_nanos(start),
+                    end: Duration::from_nanos(time),
+                    admitted: window_admitted,
+                    allowed,
+                },
+            ));
+        }
+    }
+
+    let worst = worst.map(|(_, window)| window).unwrap_or(Window {
+        start: Duration::ZERO,
+        end: Duration::ZERO,
+        admitted: 0,
+        allowed: allowance,
+    });
+
+    Conformance {
+        rate,
+        burst,
+        tolerance,
+        elapsed: Duration::from_nanos(steps.saturating_mul(step_ns)),
+        requested,
+        admitted,
+        worst,
+    }
+}
+
+/// Replay the workload against a ratelimiter built from the config, and panic
+/// if the tokens admitted exceed the configured rate, relaxed by the relative
+/// `tolerance`, over any window. See [`verify`].
+///
+/// # Panics
+///
+/// Panics if the ratelimiter does not conform, or cannot be built from the
+/// config.
+#[track_caller]
+pub fn assert_conforms<F: FnMut(Duration) -> u64>(
+    config: &RatelimiterConfig,
+    workload: Workload<F>,
+    tolerance: f64,
+) -> Conformance {
+    let conformance = match verify(config, workload, tolerance) {
+        Ok(conformance) => conformance,
+        Err(e) => panic!("failed to verify conformance: {e}"),
+    };
+
+    if !conformance.conforms() {
+        let worst = conformance.worst;
+        panic!(
+            "ratelimiter admitted {} tokens from {:?} to {:?}, exceeding the bound of {:.3} tokens for {} tokens/s with a burst of {}",
+            worst.admitted,
+            worst.start,
+            worst.end,
+            worst.allowed,
+            conformance.rate,
+            conformance.burst,
+        );
+    }
+
+    conformance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // an hour in 10ms steps takes too long to interpret
+    #[cfg_attr(miri, ignore)]
+    fn conforms() {
+        let config = RatelimiterConfig::new("1.5k/s".parse().unwrap())
+            .max_tokens(100)
+            .initial_available(100);
+
+        // bursty demand, idle for most of each minute
+        let workload = Workload::new(Duration::from_secs(3600), |elapsed: Duration| {
+            if elapsed.as_secs() % 60 < 5 {
+                5_000
+            } else {
+                0
+            }
+        })
+        .step(Duration::from_millis(10));
+
+        let conformance = assert_conforms(&config, workload, 0.0);
+        assert_eq!(conformance.rate, 1500.0);
+        assert_eq!(conformance.elapsed, Duration::from_secs(3600));
+        assert!(conformance.admitted < conformance.requested);
+
+        // draining a full bucket comes within a refill of the bound
+        assert_eq!(conformance.burst, 102);
+        assert_eq!(conformance.worst.admitted, 100);
+    }
+
+    #[test]
+    fn violation() {
+        // a ratelimiter which admits twice the rate that it is checked against
+        let ratelimiter = Ratelimiter::per_second(200).max_tokens(10).build().unwrap();
+        let workload = Workload::new(Duration::from_secs(10), |_| 1_000);
+
+        let conformance = check(ratelimiter, 100.0, workload, 0.5);
+        assert!(!conformance.conforms());
+        assert!(conformance.worst.admitted as f64 > conformance.worst.allowed);
+        assert!(conformance.worst.end > conformance.worst.start);
+
+        let config = RatelimiterConfig::new("100/s".parse().unwrap());
+        let workload = Workload::new(Duration::from_secs(1), |_| 1);
+        assert_eq!(
+            verify(&config, workload, -1.0),
+            Err(Error::InvalidTolerance)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid() {
+        let config = RatelimiterConfig::new("100/s".parse().unwrap()).max_tokens(0);
+        assert_conforms(&config, Workload::new(Duration::from_secs(1), |_| 1), 0.0);
+    }
+}
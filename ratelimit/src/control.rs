@@ -0,0 +1,223 @@
+use crate::atomic::Ordering;
+use crate::{Ratelimiter, TryAcquireError};
+use clocksource::precise::Instant;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// The administrative state is packed into a single byte so that the hot path
+// only needs a single atomic load. The low bits hold the mode and the higher
+// bits hold flags.
+pub(crate) const ENFORCE: u8 = Mode::Enforce as u8;
+const MODE_MASK: u8 = 0b0011;
+const PAUSED: u8 = 0b0100;
+const CLOSED: u8 = 0b1000;
+// set while a caller which is pausing the ratelimiter records when the pause
+// began, before it sets the paused flag
+const PAUSING: u8 = 0b1_0000;
+
+/// The administrative mode of a ratelimiter. This allows operators to open or
+/// close a ratelimiter instantly, for instance from an admin endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum Mode {
+    /// Tokens are acquired from the bucket as normal. This is the default.
+    #[default]
+    Enforce = 0,
+    /// All attempts to acquire tokens succeed without consuming any tokens.
+    AllowAll = 1,
+    /// All attempts to acquire tokens fail with
+    /// [`TryAcquireError::Denied`].
+    DenyAll = 2,
+}
+
+/// Internal function to determine the outcome of an acquisition when the
+/// ratelimiter is not simply enforcing. Returns `None` if the acquisition
+/// should proceed as normal.
+pub(crate) fn check(state: u8) -> Option<Result<(), TryAcquireError>> {
+    if state & CLOSED != 0 {
+        return Some(Err(TryAcquireError::Closed));
+    }
+
+    if state & PAUSED != 0 {
+        return Some(Err(TryAcquireError::Paused));
+    }
+
+    match state & MODE_MASK {
+        m if m == Mode::AllowAll as u8 => Some(Ok(())),
+        m if m == Mode::DenyAll as u8 => Some(Err(TryAcquireError::Denied)),
+        _ => None,
+    }
+}
+
+impl Ratelimiter {
+    /// Pause the ratelimiter. While paused, all attempts to acquire tokens fail
+    /// with [`TryAcquireError::Paused`] and no refills occur. The tokens
+    /// available and the time remaining until the next refill are preserved so
+    /// that the ratelimiter continues where it left off when resumed.
+    ///
+    /// This is intended to be used for freezing traffic during deploys or
+    /// incident response without tearing down the ratelimiter.
+    pub fn pause(&self) {
+        // only the caller which starts the pause records when it began, so
+        // that a concurrent pause can't overwrite the start of this one
+        let claimed = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state & (PAUSED | PAUSING) == 0).then_some(state | PAUSING)
+            });
+
+        if claimed.is_ok() {
+            // the start of the pause is stored before the flag is set, so that
+            // a concurrent resume never reads the start of an earlier pause
+            self.paused_at.store(Instant::now(), Ordering::Release);
+            self.state.fetch_xor(PAUSING | PAUSED, Ordering::AcqRel);
+        }
+    }
+
+    /// Resume a paused ratelimiter. The next refill is pushed back by the
+    /// length of the pause so that no tokens are issued for the time spent
+    /// paused.
+    pub fn resume(&self) {
+        if self.state.fetch_and(!PAUSED, Ordering::AcqRel) & PAUSED != 0 {
+            let paused_for = Instant::now() - self.paused_at.load(Ordering::Acquire);
+            self.refill_at.fetch_add(paused_for, Ordering::AcqRel);
+            self.wakers.wake_all();
+        }
+    }
+
+    /// Returns true if the ratelimiter is paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & PAUSED != 0
+    }
+
+    /// Close the ratelimiter. All subsequent attempts to acquire tokens fail
+    /// fast with [`TryAcquireError::Closed`], which takes precedence over
+    /// pausing and the administrative mode. Closing is permanent and is
+    /// intended to unblock callers during a clean shutdown.
+    ///
+    /// Note that [`Ratelimiter::try_wait`] cannot distinguish a closed
+    /// ratelimiter and will continue to return the refill interval. Callers
+    /// which need to observe the closure should use
+    /// [`Ratelimiter::try_acquire`].
+    pub fn close(&self) {
+        self.state.fetch_or(CLOSED, Ordering::AcqRel);
+        self.wakers.wake_all();
+    }
+
+    /// Returns true if the ratelimiter has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & CLOSED != 0
+    }
+
+    /// Returns the current administrative mode.
+    pub fn mode(&self) -> Mode {
+        match self.state.load(Ordering::Relaxed) & MODE_MASK {
+            m if m == Mode::AllowAll as u8 => Mode::AllowAll,
+            m if m == Mode::DenyAll as u8 => Mode::DenyAll,
+            _ => Mode::Enforce,
+        }
+    }
+
+    /// Change the administrative mode. This takes effect immediately for all
+    /// subsequent attempts to acquire tokens. See [`Mode`] for details.
+    pub fn set_mode(&self, mode: Mode) {
+        let _ = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                Some((state & !MODE_MASK) | mode as u8)
+            });
+        self.wakers.wake_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn pause() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(10))
+            .max_tokens(10)
+            .initial_available(2)
+            .build()
+            .unwrap();
+
+        rl.pause();
+        assert!(rl.is_paused());
+        assert_eq!(rl.try_acquire(), Err(TryAcquireError::Paused));
+        assert!(rl.try_wait().is_err());
+
+        // no tokens are added while paused
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(rl.available(), 2);
+        let refill_at = rl.next_refill();
+
+        rl.resume();
+        assert!(!rl.is_paused());
+        assert!(rl.next_refill() >= refill_at + Duration::from_millis(50));
+        assert_eq!(rl.try_acquire(), Ok(()));
+        assert_eq!(rl.try_acquire(), Ok(()));
+        assert!(matches!(
+            rl.try_acquire(),
+            Err(TryAcquireError::Insufficient(_))
+        ));
+    }
+
+    #[test]
+    fn mode() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(10))
+            .initial_available(1)
+            .build()
+            .unwrap();
+        assert_eq!(rl.mode(), Mode::Enforce);
+
+        rl.set_mode(Mode::AllowAll);
+        assert_eq!(rl.mode(), Mode::AllowAll);
+        for _ in 0..10 {
+            assert_eq!(rl.try_acquire(), Ok(()));
+        }
+        assert_eq!(rl.available(), 1);
+
+        rl.set_mode(Mode::DenyAll);
+        assert_eq!(rl.try_acquire(), Err(TryAcquireError::Denied));
+        assert_eq!(rl.available(), 1);
+
+        // pausing takes precedence over the mode and retains it
+        rl.set_mode(Mode::AllowAll);
+        rl.pause();
+        assert_eq!(rl.try_acquire(), Err(TryAcquireError::Paused));
+        rl.resume();
+        assert_eq!(rl.mode(), Mode::AllowAll);
+
+        rl.set_mode(Mode::Enforce);
+        assert_eq!(rl.try_acquire(), Ok(()));
+        assert!(rl.try_acquire().is_err());
+    }
+
+    #[test]
+    fn close() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+        assert!(!rl.is_closed());
+
+        rl.set_mode(Mode::AllowAll);
+        rl.pause();
+        rl.close();
+        assert!(rl.is_closed());
+        assert_eq!(rl.try_acquire(), Err(TryAcquireError::Closed));
+        assert!(rl.try_wait().is_err());
+
+        // closing is permanent
+        rl.resume();
+        rl.set_mode(Mode::Enforce);
+        assert!(rl.is_closed());
+        assert_eq!(rl.try_acquire_n(1), Err(TryAcquireError::Closed));
+        assert_eq!(rl.available(), 10);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{Ratelimiter, TryAcquireError};
+
+/// The number of tokens which an item costs, so that different kinds of
+/// requests can be charged different amounts.
+///
+/// ```
+/// use ratelimit::{Cost, Ratelimiter};
+/// use std::time::Duration;
+///
+/// enum Op {
+///     Read,
+///     Write,
+/// }
+///
+/// impl Cost for Op {
+///     fn cost(&self) -> u64 {
+///         match self {
+///             Op::Read => 1,
+///             Op::Write => 5,
+///         }
+///     }
+/// }
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .max_tokens(6)
+///     .initial_available(6)
+///     .build()
+///     .unwrap();
+///
+/// assert!(ratelimiter.try_wait_for_item(&Op::Write).is_ok());
+/// assert!(ratelimiter.try_wait_for_item(&Op::Read).is_ok());
+/// assert!(ratelimiter.try_wait_for_item(&Op::Read).is_err());
+/// ```
+pub trait Cost {
+    /// Returns the number of tokens which this item costs.
+    fn cost(&self) -> u64;
+}
+
+impl<T: Cost + ?Sized> Cost for &T {
+    fn cost(&self) -> u64 {
+        (**self).cost()
+    }
+}
+
+/// Determines the number of tokens which a request costs. This is used by the
+/// middleware integrations, which charge a single token for each request
+/// unless a cost extractor is provided.
+///
+/// This is implemented for closures which take a request and return its cost.
+pub trait CostExtractor<R: ?Sized>: Send + Sync {
+    /// Returns the number of tokens which the request costs.
+    fn cost(&self, request: &R) -> u64;
+}
+
+impl<F, R: ?Sized> CostExtractor<R> for F
+where
+    F: Fn(&R) -> u64 + Send + Sync,
+{
+    fn cost(&self, request: &R) -> u64 {
+        self(request)
+    }
+}
+
+/// Charges a single token for each request.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnitCost;
+
+impl<R: ?Sized> CostExtractor<R> for UnitCost {
+    fn cost(&self, _request: &R) -> u64 {
+        1
+    }
+}
+
+/// Charges each request the cost from its implementation of [`Cost`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ItemCost;
+
+impl<R: Cost + ?Sized> CostExtractor<R> for ItemCost {
+    fn cost(&self, request: &R) -> u64 {
+        request.cost()
+    }
+}
+
+impl Ratelimiter {
+    /// Non-blocking function to "wait" for the tokens which an item costs. See
+    /// [`Ratelimiter::try_wait_n`] for details.
+    ///
+    /// Note: costs above the maximum number of tokens are charged as the
+    /// maximum, so that the item can eventually be admitted.
+    pub fn try_wait_for_item<T: Cost + ?Sized>(
+        &self,
+        item: &T,
+    ) -> Result<(), core::time::Duration> {
+        self.try_wait_n(self.charge(item.cost()))
+    }
+
+    /// Non-blocking function to acquire the tokens which an item costs. See
+    /// [`Ratelimiter::try_acquire_n`] and [`Ratelimiter::try_wait_for_item`]
+    /// for details.
+    pub fn try_acquire_for_item<T: Cost + ?Sized>(&self, item: &T) -> Result<(), TryAcquireError> {
+        self.try_acquire_n(self.charge(item.cost()))
+    }
+
+    /// Internal function to limit a cost to the maximum number of tokens.
+    pub(crate) fn charge(&self, cost: u64) -> u64 {
+        cost.min(self.max_tokens())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    struct Bytes(u64);
+
+    impl Cost for Bytes {
+        fn cost(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn try_wait_for_item() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        rl.try_wait_for_item(&Bytes(4)).unwrap();
+        assert_eq!(rl.available(), 6);
+        assert!(rl.try_acquire_for_item(&Bytes(7)).is_err());
+        rl.try_acquire_for_item(&&Bytes(0)).unwrap();
+        assert_eq!(rl.available(), 6);
+
+        // costs above the max tokens are charged as the max tokens
+        rl.set_available(10).unwrap();
+        rl.try_wait_for_item(&Bytes(100)).unwrap();
+        assert_eq!(rl.available(), 0);
+    }
+
+    #[test]
+    fn extractors() {
+        assert_eq!(UnitCost.cost(&Bytes(5)), 1);
+        assert_eq!(ItemCost.cost(&Bytes(5)), 5);
+        assert_eq!((|s: &str| s.len() as u64).cost("hello"), 5);
+    }
+}
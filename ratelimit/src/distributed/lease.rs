@@ -0,0 +1,210 @@
+use super::{DistributedBackend, DistributedError, DistributedRatelimiter};
+use crate::atomic::{AtomicU64, Ordering};
+use parking_lot::Mutex;
+
+/// A distributed ratelimiter which leases tokens from the shared bucket in
+/// chunks and hands them out locally. This avoids a round trip to the backend
+/// on each acquisition, which is required for high rates.
+///
+/// Tokens which are leased but unused are returned to the shared bucket by
+/// [`LeasedRatelimiter::release`] or when the ratelimiter is dropped. The
+/// tradeoff is that up to one lease worth of tokens per process may be held
+/// back from other processes at any time.
+///
+/// ```
+/// use ratelimit::{DistributedRatelimiter, MemoryBackend, Ratelimiter};
+///
+/// let ratelimiter = DistributedRatelimiter::new(
+///     MemoryBackend::new(),
+///     "api",
+///     Ratelimiter::per_second(100_000).initial_available(100_000),
+/// )
+/// .unwrap()
+/// .leased(1000);
+///
+/// // the first acquisition leases 1000 tokens from the backend
+/// assert!(ratelimiter.try_wait().is_ok());
+/// assert_eq!(ratelimiter.leased(), 999);
+/// ```
+pub struct LeasedRatelimiter<B: DistributedBackend> {
+    lease_size: u64,
+    leased: AtomicU64,
+    leasing: Mutex<()>,
+    remote: DistributedRatelimiter<B>,
+}
+
+impl<B: DistributedBackend> DistributedRatelimiter<B> {
+    /// Convert into a ratelimiter which leases tokens from the shared bucket
+    /// in chunks of `lease_size`. See [`LeasedRatelimiter`] for details.
+    pub fn leased(self, lease_size: u64) -> LeasedRatelimiter<B> {
+        LeasedRatelimiter {
+            lease_size: lease_size.max(1),
+            leased: AtomicU64::new(0),
+            leasing: Mutex::new(()),
+            remote: self,
+        }
+    }
+}
+
+impl<B: DistributedBackend> LeasedRatelimiter<B> {
+    /// Returns the number of tokens in each lease.
+    pub fn lease_size(&self) -> u64 {
+        self.lease_size
+    }
+
+    /// Returns the number of leased tokens which are available locally.
+    pub fn leased(&self) -> u64 {
+        self.leased.load(Ordering::Relaxed)
+    }
+
+    /// Returns a reference to the underlying distributed ratelimiter.
+    pub fn remote(&self) -> &DistributedRatelimiter<B> {
+        &self.remote
+    }
+
+    /// Internal function to take `n` tokens from the local lease.
+    fn take(&self, n: u64) -> bool {
+        self.leased
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |leased| {
+                leased.checked_sub(n)
+            })
+            .is_ok()
+    }
+
+    /// Non-blocking function to acquire `n` tokens. Tokens are taken from the
+    /// local lease when possible, otherwise a new lease is taken from the
+    /// shared bucket. If a full lease is not available, only the `n` tokens
+    /// requested are taken from the shared bucket. If the backend returns an
+    /// error, the outcome is determined by the [`crate::FailurePolicy`].
+    pub fn try_acquire_n(&self, n: u64) -> Result<(), DistributedError> {
+        if self.take(n) {
+            return Ok(());
+        }
+
+        let _leasing = self.leasing.lock();
+
+        // another thread may have taken a new lease while we waited
+        if self.take(n) {
+            return Ok(());
+        }
+
+        let lease = self.lease_size.max(n);
+
+        // The failure policy is applied to the `n` tokens requested rather
+        // than the lease so that no tokens are credited locally when the
+        // backend is unavailable.
+        let result = match self.remote.try_acquire_remote(lease) {
+            Ok(()) => {
+                self.leased.fetch_add(lease - n, Ordering::AcqRel);
+                return Ok(());
+            }
+            Err(DistributedError::Insufficient(_)) if lease > n => {
+                self.remote.try_acquire_remote(n)
+            }
+            Err(e) => Err(e),
+        };
+
+        self.remote.fallback.apply(n, result)
+    }
+
+    /// Non-blocking function to acquire a single token. See
+    /// [`LeasedRatelimiter::try_acquire_n`] for details.
+    pub fn try_acquire(&self) -> Result<(), DistributedError> {
+        self.try_acquire_n(1)
+    }
+
+    /// Non-blocking function to wait for `n` tokens, matching
+    /// [`crate::Ratelimiter::try_wait_n`]. If the acquisition fails due to a
+    /// backend error, the refill interval is returned.
+    pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        self.try_acquire_n(n).map_err(|e| match e {
+            DistributedError::Insufficient(duration) => duration,
+            _ => self.remote.bucket.interval(),
+        })
+    }
+
+    /// Non-blocking function to wait for a single token. See
+    /// [`LeasedRatelimiter::try_wait_n`] for details.
+    pub fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1)
+    }
+
+    /// Return any unused leased tokens to the shared bucket. If this fails,
+    /// the tokens are kept in the local lease.
+    pub fn release(&self) -> Result<(), DistributedError> {
+        let _leasing = self.leasing.lock();
+
+        let leased = self.leased.swap(0, Ordering::AcqRel);
+
+        if leased == 0 {
+            return Ok(());
+        }
+
+        self.remote.return_n(leased).inspect_err(|_| {
+            self.leased.fetch_add(leased, Ordering::AcqRel);
+        })
+    }
+}
+
+impl<B: DistributedBackend> Drop for LeasedRatelimiter<B> {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn leased(
+        backend: &Arc<MemoryBackend>,
+        lease_size: u64,
+    ) -> LeasedRatelimiter<Arc<MemoryBackend>> {
+        DistributedRatelimiter::new(
+            backend.clone(),
+            "leased",
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(100)
+                .initial_available(25),
+        )
+        .unwrap()
+        .leased(lease_size)
+    }
+
+    #[test]
+    fn lease() {
+        let backend = Arc::new(MemoryBackend::new());
+
+        let a = leased(&backend, 10);
+        let b = leased(&backend, 10);
+
+        assert!(a.try_wait().is_ok());
+        assert_eq!(a.leased(), 9);
+        assert_eq!(backend.load("leased").unwrap().unwrap().available, 15);
+
+        // only one round trip per lease
+        for _ in 0..9 {
+            assert!(a.try_wait().is_ok());
+        }
+        assert_eq!(a.leased(), 0);
+        assert_eq!(backend.load("leased").unwrap().unwrap().available, 15);
+
+        assert!(b.try_wait_n(2).is_ok());
+        assert_eq!(b.leased(), 8);
+
+        // a full lease is no longer available, so only the tokens needed are
+        // taken
+        assert!(a.try_wait_n(3).is_ok());
+        assert_eq!(a.leased(), 0);
+        assert_eq!(backend.load("leased").unwrap().unwrap().available, 2);
+
+        // unused tokens are returned when dropped
+        drop(b);
+        assert_eq!(backend.load("leased").unwrap().unwrap().available, 10);
+
+        a.release().unwrap();
+        assert_eq!(backend.load("leased").unwrap().unwrap().available, 10);
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{advance, Builder};
+use clocksource::precise::UnixInstant;
+use parking_lot::Mutex;
+use policy::Fallback;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+mod lease;
+mod policy;
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::{AsyncRedisRatelimiter, RedisRatelimiter};
+pub use lease::LeasedRatelimiter;
+pub use policy::FailurePolicy;
+
+/// The number of times an acquisition is retried when it races with another
+/// process updating the same bucket.
+const MAX_ATTEMPTS: usize = 8;
+
+/// The reasons that an attempt to acquire tokens from a distributed
+/// ratelimiter may fail.
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum DistributedError {
+    #[error("insufficient tokens, next refill in {0:?}")]
+    Insufficient(core::time::Duration),
+    #[error("the bucket is being updated by too many processes at once")]
+    Contended,
+    #[error("backend error: {0}")]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// The state of a token bucket as stored in a [`DistributedBackend`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BucketState {
+    /// The number of tokens available.
+    pub available: u64,
+    /// The time of the next refill in microseconds since the unix epoch.
+    pub refill_at: u64,
+}
+
+/// A store which holds the state of token buckets shared between processes.
+/// Implementing this trait allows the bucket to be kept in any store which
+/// supports an atomic compare-and-set, such as DynamoDB or Postgres.
+///
+/// The refill logic runs within each process using the system clock, so the
+/// clocks of the hosts sharing a bucket should be kept in sync.
+pub trait DistributedBackend: Send + Sync {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Load the state of the bucket with the provided key. Returns `None` if
+    /// the bucket does not exist.
+    fn load(&self, key: &str) -> Result<Option<BucketState>, Self::Error>;
+
+    /// Atomically replace the state of the bucket with `new` if the current
+    /// state matches `current`, where `None` means the bucket does not exist.
+    /// Returns `true` if the state was replaced.
+    fn compare_and_set(
+        &self,
+        key: &str,
+        current: Option<BucketState>,
+        new: BucketState,
+    ) -> Result<bool, Self::Error>;
+}
+
+impl<T: DistributedBackend + ?Sized> DistributedBackend for std::sync::Arc<T> {
+    type Error = T::Error;
+
+    fn load(&self, key: &str) -> Result<Option<BucketState>, Self::Error> {
+        (**self).load(key)
+    }
+
+    fn compare_and_set(
+        &self,
+        key: &str,
+        current: Option<BucketState>,
+        new: BucketState,
+    ) -> Result<bool, Self::Error> {
+        (**self).compare_and_set(key, current, new)
+    }
+}
+
+/// A backend which keeps buckets in memory. This is mostly useful for testing
+/// and as a reference when implementing a [`DistributedBackend`].
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    buckets: Mutex<BTreeMap<String, BucketState>>,
+}
+
+impl MemoryBackend {
+    /// Create a new, empty, backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DistributedBackend for MemoryBackend {
+    type Error = core::convert::Infallible;
+
+    fn load(&self, key: &str) -> Result<Option<BucketState>, Self::Error> {
+        Ok(self.buckets.lock().get(key).copied())
+    }
+
+    fn compare_and_set(
+        &self,
+        key: &str,
+        current: Option<BucketState>,
+        new: BucketState,
+    ) -> Result<bool, Self::Error> {
+        let mut buckets = self.buckets.lock();
+
+        if buckets.get(key).copied() != current {
+            return Ok(false);
+        }
+
+        buckets.insert(key.to_string(), new);
+
+        Ok(true)
+    }
+}
+
+/// The parameters of a token bucket which is shared between processes.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    capacity: u64,
+    refill_amount: u64,
+    refill_interval_us: u64,
+    initial_available: u64,
+}
+
+impl Bucket {
+    fn new(builder: &Builder) -> Result<Self, crate::Error> {
+        if builder.max_tokens < builder.refill_amount {
+            return Err(crate::Error::MaxTokensTooLow);
+        }
+
+        if builder.refill_interval.as_micros() > u64::MAX as u128 {
+            return Err(crate::Error::RefillIntervalTooLong);
+        }
+
+        let refill_interval_us = builder.refill_interval.as_micros() as u64;
+
+        if refill_interval_us == 0 {
+            return Err(crate::Error::RefillIntervalTooShort);
+        }
+
+        Ok(Self {
+            capacity: builder.max_tokens,
+            refill_amount: builder.refill_amount.max(1),
+            refill_interval_us,
+            initial_available: builder.initial_available.min(builder.max_tokens),
+        })
+    }
+
+    /// Refill the bucket up to the provided time, in microseconds since the
+    /// unix epoch, and attempt to take `n` tokens. On success, returns the new
+    /// state. Otherwise returns the time until enough tokens are available.
+    fn acquire(
+        &self,
+        state: Option<BucketState>,
+        now: u64,
+        n: u64,
+    ) -> Result<BucketState, core::time::Duration> {
+        let mut state = state.unwrap_or(BucketState {
+            available: self.initial_available,
+            refill_at: now.saturating_add(self.refill_interval_us),
+        });
+
+        if now >= state.refill_at {
+            let intervals = (now - state.refill_at) / self.refill_interval_us + 1;
+
+            state.available = state
+                .available
+                .saturating_add(intervals.saturating_mul(self.refill_amount))
+                .min(self.capacity);
+            state.refill_at = advance(state.refill_at, intervals, self.refill_interval_us);
+        }
+
+        match state.available.checked_sub(n) {
+            Some(available) => {
+                state.available = available;
+                Ok(state)
+            }
+            None => {
+                let short = n - state.available;
+                let wait = (state.refill_at - now)
+                    + ((short - 1) / self.refill_amount).saturating_mul(self.refill_interval_us);
+
+                Err(core::time::Duration::from_micros(wait.max(1)))
+            }
+        }
+    }
+
+    fn interval(&self) -> core::time::Duration {
+        core::time::Duration::from_micros(self.refill_interval_us)
+    }
+}
+
+/// Returns the current time in microseconds since the unix epoch.
+fn now() -> u64 {
+    UnixInstant::now()
+        .duration_since(UnixInstant::EPOCH)
+        .as_nanos()
+        / 1_000
+}
+
+/// A token bucket which is shared between processes through a
+/// [`DistributedBackend`]. The last known state of the bucket is cached
+/// locally so that an acquisition normally takes a single compare-and-set.
+/// The cache is refreshed from the backend when the compare-and-set fails.
+///
+/// Since other processes only remove tokens, except when returning them, the
+/// cached state does not underestimate the tokens available. An acquisition
+/// which fails against the cached state does not need to contact the backend
+/// at all.
+///
+/// The bucket is configured with the same [`Builder`] as a local ratelimiter.
+/// Only the refill amount, refill interval, max tokens, and initial available
+/// are used. The refill interval must be at least one microsecond.
+///
+/// ```
+/// use ratelimit::{DistributedRatelimiter, MemoryBackend, Ratelimiter};
+///
+/// let ratelimiter = DistributedRatelimiter::new(
+///     MemoryBackend::new(),
+///     "api",
+///     Ratelimiter::per_second(1000).initial_available(1000),
+/// )
+/// .unwrap();
+///
+/// assert!(ratelimiter.try_wait().is_ok());
+/// ```
+pub struct DistributedRatelimiter<B> {
+    backend: B,
+    bucket: Bucket,
+    cached: Mutex<Option<BucketState>>,
+    fallback: Fallback,
+    key: String,
+}
+
+impl<B: DistributedBackend> DistributedRatelimiter<B> {
+    /// Create a new ratelimiter which stores the bucket under the provided key.
+    /// The bucket is created in the backend on first use.
+    pub fn new(backend: B, key: impl Into<String>, builder: Builder) -> Result<Self, crate::Error> {
+        Ok(Self {
+            backend,
+            bucket: Bucket::new(&builder)?,
+            cached: Mutex::new(None),
+            fallback: Fallback::default(),
+            key: key.into(),
+        })
+    }
+
+    /// Set the policy which is applied when the backend returns an error. See
+    /// [`FailurePolicy`] for the available options.
+    ///
+    /// Returns an error if a local fallback is selected with a fraction which
+    /// is not in the range `0.0..=1.0` or is zero.
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Result<Self, crate::Error> {
+        self.fallback = Fallback::new(policy, &self.bucket)?;
+        Ok(self)
+    }
+
+    /// Returns a reference to the backend.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns the key the bucket is stored under.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Non-blocking function to acquire `n` tokens. If the backend returns an
+    /// error, the outcome is determined by the [`FailurePolicy`].
+    pub fn try_acquire_n(&self, n: u64) -> Result<(), DistributedError> {
+        self.fallback.apply(n, self.try_acquire_remote(n))
+    }
+
+    /// Internal function to acquire `n` tokens from the backend without
+    /// applying the failure policy.
+    fn try_acquire_remote(&self, n: u64) -> Result<(), DistributedError> {
+        let mut cached = self.cached.lock();
+
+        // the bucket may have been created by another process
+        if cached.is_none() {
+            *cached = self
+                .backend
+                .load(&self.key)
+                .map_err(|e| DistributedError::Backend(Box::new(e)))?;
+        }
+
+        for _ in 0..MAX_ATTEMPTS {
+            let new = self
+                .bucket
+                .acquire(*cached, now(), n)
+                .map_err(DistributedError::Insufficient)?;
+
+            match self.backend.compare_and_set(&self.key, *cached, new) {
+                Ok(true) => {
+                    *cached = Some(new);
+                    return Ok(());
+                }
+                Ok(false) => {
+                    *cached = self
+                        .backend
+                        .load(&self.key)
+                        .map_err(|e| DistributedError::Backend(Box::new(e)))?;
+                }
+                Err(e) => return Err(DistributedError::Backend(Box::new(e))),
+            }
+        }
+
+        Err(DistributedError::Contended)
+    }
+
+    /// Return `n` tokens to the shared bucket. The tokens available will not
+    /// exceed the max tokens.
+    pub fn return_n(&self, n: u64) -> Result<(), DistributedError> {
+        let mut cached = self.cached.lock();
+
+        for _ in 0..MAX_ATTEMPTS {
+            let current = self
+                .backend
+                .load(&self.key)
+                .map_err(|e| DistributedError::Backend(Box::new(e)))?;
+
+            let Some(mut new) = current else {
+                // there is nothing to return tokens to
+                return Ok(());
+            };
+
+            new.available = new.available.saturating_add(n).min(self.bucket.capacity);
+
+            match self.backend.compare_and_set(&self.key, current, new) {
+                Ok(true) => {
+                    *cached = Some(new);
+                    return Ok(());
+                }
+                Ok(false) => continue,
+                Err(e) => return Err(DistributedError::Backend(Box::new(e))),
+            }
+        }
+
+        Err(DistributedError::Contended)
+    }
+
+    /// Non-blocking function to acquire a single token. See
+    /// [`DistributedRatelimiter::try_acquire_n`] for details.
+    pub fn try_acquire(&self) -> Result<(), DistributedError> {
+        self.try_acquire_n(1)
+    }
+
+    /// Non-blocking function to wait for `n` tokens, matching
+    /// [`crate::Ratelimiter::try_wait_n`]. If the acquisition fails due to a
+    /// backend error, the refill interval is returned.
+    pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        self.try_acquire_n(n).map_err(|e| match e {
+            DistributedError::Insufficient(duration) => duration,
+            _ => self.bucket.interval(),
+        })
+    }
+
+    /// Non-blocking function to wait for a single token. See
+    /// [`DistributedRatelimiter::try_wait_n`] for details.
+    pub fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ratelimiter;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn bucket() {
+        let bucket = Bucket::new(&Ratelimiter::per_second(1000).initial_available(2000)).unwrap();
+        assert_eq!(bucket.capacity, 1000);
+        assert_eq!(bucket.refill_amount, 1);
+        assert_eq!(bucket.refill_interval_us, 1000);
+        assert_eq!(bucket.initial_available, 1000);
+
+        assert_eq!(
+            Bucket::new(&Ratelimiter::builder(1, Duration::from_nanos(100))).unwrap_err(),
+            crate::Error::RefillIntervalTooShort
+        );
+        assert_eq!(
+            Bucket::new(&Ratelimiter::builder(2, Duration::from_secs(1))).unwrap_err(),
+            crate::Error::MaxTokensTooLow
+        );
+    }
+
+    #[test]
+    fn acquire() {
+        let bucket = Bucket::new(
+            &Ratelimiter::builder(2, Duration::from_millis(1))
+                .max_tokens(10)
+                .initial_available(3),
+        )
+        .unwrap();
+
+        let state = bucket.acquire(None, 1_000_000, 3).unwrap();
+        assert_eq!(
+            state,
+            BucketState {
+                available: 0,
+                refill_at: 1_001_000
+            }
+        );
+
+        // need two refills for three tokens
+        assert_eq!(
+            bucket.acquire(Some(state), 1_000_500, 3),
+            Err(Duration::from_micros(1_500))
+        );
+
+        // refills are capped at the max tokens
+        let state = bucket.acquire(Some(state), 1_100_000, 1).unwrap();
+        assert_eq!(
+            state,
+            BucketState {
+                available: 9,
+                refill_at: 1_101_000
+            }
+        );
+    }
+
+    #[test]
+    fn shared() {
+        let backend = Arc::new(MemoryBackend::new());
+
+        let builder = || {
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(10)
+                .initial_available(10)
+        };
+
+        let a = DistributedRatelimiter::new(backend.clone(), "shared", builder()).unwrap();
+        let b = DistributedRatelimiter::new(backend.clone(), "shared", builder()).unwrap();
+
+        // the two ratelimiters share a single budget
+        for _ in 0..5 {
+            assert!(a.try_wait().is_ok());
+            assert!(b.try_wait().is_ok());
+        }
+
+        assert!(a.try_wait().is_err());
+        assert!(matches!(
+            b.try_acquire(),
+            Err(DistributedError::Insufficient(_))
+        ));
+        assert_eq!(backend.load("shared").unwrap().unwrap().available, 0);
+    }
+}
@@ -0,0 +1,153 @@
+use super::{Bucket, DistributedError};
+use crate::Ratelimiter;
+use std::sync::Arc;
+
+/// Determines the outcome of an acquisition when the distributed backend
+/// returns an error, for instance because it is unreachable or times out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FailurePolicy {
+    /// Acquisitions fail with the backend error. This is the default.
+    #[default]
+    Closed,
+    /// Acquisitions succeed without limiting.
+    Open,
+    /// Acquisitions fall back to a standalone bucket within this process which
+    /// refills at the provided fraction of the shared rate. For example, with
+    /// ten replicas sharing a budget, a fraction of `0.1` keeps the combined
+    /// rate close to the shared rate during an outage.
+    Local(f64),
+}
+
+/// Internal type which applies the failure policy.
+#[derive(Clone, Default)]
+pub(super) struct Fallback {
+    policy: FailurePolicy,
+    local: Option<Arc<Ratelimiter>>,
+}
+
+impl Fallback {
+    pub(super) fn new(policy: FailurePolicy, bucket: &Bucket) -> Result<Self, crate::Error> {
+        let local = match policy {
+            FailurePolicy::Local(fraction) => {
+                if !fraction.is_finite() || fraction <= 0.0 || fraction > 1.0 {
+                    return Err(crate::Error::InvalidRate);
+                }
+
+                let rate = fraction * bucket.refill_amount as f64 * 1_000_000.0
+                    / bucket.refill_interval_us as f64;
+                let burst = ((bucket.capacity as f64 * fraction).ceil() as u64).max(1);
+
+                let builder = Ratelimiter::from_rate(rate, burst)?;
+                let burst = builder.max_tokens;
+
+                Some(Arc::new(builder.initial_available(burst).build()?))
+            }
+            _ => None,
+        };
+
+        Ok(Self { policy, local })
+    }
+
+    /// Apply the policy to the result of an acquisition from the backend.
+    pub(super) fn apply(
+        &self,
+        n: u64,
+        result: Result<(), DistributedError>,
+    ) -> Result<(), DistributedError> {
+        match result {
+            Ok(()) | Err(DistributedError::Insufficient(_)) => result,
+            Err(e) => match (&self.policy, &self.local) {
+                (FailurePolicy::Open, _) => Ok(()),
+                (FailurePolicy::Local(_), Some(local)) => {
+                    local.try_wait_n(n).map_err(DistributedError::Insufficient)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    /// A backend which is always unavailable.
+    struct Unavailable;
+
+    impl DistributedBackend for Unavailable {
+        type Error = std::io::Error;
+
+        fn load(&self, _key: &str) -> Result<Option<BucketState>, Self::Error> {
+            Err(std::io::Error::other("unavailable"))
+        }
+
+        fn compare_and_set(
+            &self,
+            _key: &str,
+            _current: Option<BucketState>,
+            _new: BucketState,
+        ) -> Result<bool, Self::Error> {
+            Err(std::io::Error::other("unavailable"))
+        }
+    }
+
+    fn ratelimiter(policy: FailurePolicy) -> DistributedRatelimiter<Unavailable> {
+        DistributedRatelimiter::new(
+            Unavailable,
+            "unavailable",
+            Ratelimiter::builder(1, Duration::from_millis(10)).max_tokens(100),
+        )
+        .unwrap()
+        .failure_policy(policy)
+        .unwrap()
+    }
+
+    #[test]
+    fn closed() {
+        let rl = ratelimiter(FailurePolicy::Closed);
+        assert!(matches!(
+            rl.try_acquire(),
+            Err(DistributedError::Backend(_))
+        ));
+        assert_eq!(rl.try_wait(), Err(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn open() {
+        let rl = ratelimiter(FailurePolicy::Open);
+        for _ in 0..1000 {
+            assert!(rl.try_acquire().is_ok());
+        }
+
+        // leased tokens are not credited when failing open
+        let rl = rl.leased(10);
+        assert!(rl.try_acquire().is_ok());
+        assert_eq!(rl.leased(), 0);
+    }
+
+    #[test]
+    fn local() {
+        // the local bucket holds a tenth of the burst at a tenth of the rate
+        let rl = ratelimiter(FailurePolicy::Local(0.1));
+        for _ in 0..10 {
+            assert!(rl.try_acquire().is_ok());
+        }
+        assert!(matches!(
+            rl.try_acquire(),
+            Err(DistributedError::Insufficient(_))
+        ));
+
+        let rl = rl.leased(5);
+        assert!(rl.try_acquire().is_err());
+
+        assert!(DistributedRatelimiter::new(
+            Unavailable,
+            "unavailable",
+            Ratelimiter::per_second(10),
+        )
+        .unwrap()
+        .failure_policy(FailurePolicy::Local(1.5))
+        .is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use super::{Bucket, DistributedError, FailurePolicy, Fallback};
+use crate::Builder;
+use parking_lot::Mutex;
+
+/// A Lua script implementing an atomic refill and acquire. The bucket is kept
+/// in a hash with the tokens available and the time of the next refill. The
+/// server clock is used so that all replicas agree on the current time.
+///
+/// The hash expires once the bucket would have refilled to its max tokens, so
+/// that idle buckets don't accumulate in Redis. An expired bucket is recreated
+/// with the initial available tokens, which is never more than a full bucket.
+///
+/// Calling `TIME` before a write requires effects replication, which is
+/// enabled explicitly for servers older than Redis 5 where it is not the
+/// default.
+///
+/// Returns zero if the tokens were acquired, otherwise the number of
+/// microseconds until enough tokens would be available.
+const SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local amount = tonumber(ARGV[2])
+local interval = tonumber(ARGV[3])
+local initial = tonumber(ARGV[4])
+local n = tonumber(ARGV[5])
+
+redis.replicate_commands()
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000000 + tonumber(time[2])
+
+local state = redis.call('HMGET', KEYS[1], 'available', 'refill_at')
+local available = tonumber(state[1])
+local refill_at = tonumber(state[2])
+
+if available == nil or refill_at == nil then
+    available = initial
+    refill_at = now + interval
+end
+
+if now >= refill_at then
+    local intervals = math.floor((now - refill_at) / interval) + 1
+    available = math.min(capacity, available + intervals * amount)
+    refill_at = refill_at + intervals * interval
+end
+
+local wait = 0
+
+if available >= n then
+    available = available - n
+else
+    local short = n - available
+    wait = (refill_at - now) + math.floor((short - 1) / amount) * interval
+    wait = math.max(wait, 1)
+end
+
+-- numbers are formatted explicitly since the default conversion to a string
+-- loses precision for large values
+redis.call('HSET', KEYS[1],
+    'available', string.format('%d', available),
+    'refill_at', string.format('%d', refill_at))
+
+-- expire the bucket once it would be full, but not before the next refill
+local refills = math.ceil((capacity - available) / amount)
+local full = (refill_at - now) + math.max(refills - 1, 0) * interval
+redis.call('PEXPIRE', KEYS[1], string.format('%d', math.ceil(full / 1000) + 1))
+
+return wait
+";
+
+impl Bucket {
+    fn invocation<'a>(
+        &self,
+        script: &'a redis::Script,
+        key: &str,
+        n: u64,
+    ) -> redis::ScriptInvocation<'a> {
+        let mut invocation = script.key(key);
+        invocation
+            .arg(self.capacity)
+            .arg(self.refill_amount)
+            .arg(self.refill_interval_us)
+            .arg(self.initial_available)
+            .arg(n);
+        invocation
+    }
+
+    fn result(wait: redis::RedisResult<u64>) -> Result<(), DistributedError> {
+        match wait {
+            Ok(0) => Ok(()),
+            Ok(wait) => Err(DistributedError::Insufficient(
+                core::time::Duration::from_micros(wait),
+            )),
+            Err(e) => Err(DistributedError::Backend(Box::new(e))),
+        }
+    }
+}
+
+/// A token bucket which is stored in Redis so that multiple replicas of a
+/// service share a single budget. Each acquisition is a single round trip
+/// which atomically refills the bucket and takes the tokens.
+///
+/// The bucket is configured with the same [`Builder`] as a local ratelimiter.
+/// Only the refill amount, refill interval, max tokens, and initial available
+/// are used. The refill interval must be at least one microsecond.
+///
+/// This uses a blocking connection, see [`AsyncRedisRatelimiter`] for use
+/// with tokio.
+///
+/// ```no_run
+/// use ratelimit::{Ratelimiter, RedisRatelimiter};
+///
+/// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// let ratelimiter =
+///     RedisRatelimiter::new(client, "api", Ratelimiter::per_second(1000)).unwrap();
+///
+/// if ratelimiter.try_wait().is_ok() {
+///     // do some ratelimited action here
+/// }
+/// ```
+pub struct RedisRatelimiter {
+    bucket: Bucket,
+    client: redis::Client,
+    connection: Mutex<Option<redis::Connection>>,
+    fallback: Fallback,
+    key: String,
+    script: redis::Script,
+}
+
+impl RedisRatelimiter {
+    /// Create a new ratelimiter which stores the bucket under the provided key.
+    /// The bucket is created in Redis on first use.
+    pub fn new(
+        client: redis::Client,
+        key: impl Into<String>,
+        builder: Builder,
+    ) -> Result<Self, crate::Error> {
+        Ok(Self {
+            bucket: Bucket::new(&builder)?,
+            client,
+            connection: Mutex::new(None),
+            fallback: Fallback::default(),
+            key: key.into(),
+            script: redis::Script::new(SCRIPT),
+        })
+    }
+
+    /// Set the policy which is applied when the backend returns an error. See
+    /// [`FailurePolicy`] for the available options.
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Result<Self, crate::Error> {
+        self.fallback = Fallback::new(policy, &self.bucket)?;
+        Ok(self)
+    }
+
+    /// Returns the key the bucket is stored under.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Non-blocking function to acquire `n` tokens. The connection is
+    /// re-established on the next attempt if the backend returns an error, and
+    /// the outcome of this attempt is determined by the [`FailurePolicy`].
+    pub fn try_acquire_n(&self, n: u64) -> Result<(), DistributedError> {
+        let mut connection = self.connection.lock();
+
+        let result = match connection.as_mut() {
+            Some(connection) => self
+                .bucket
+                .invocation(&self.script, &self.key, n)
+                .invoke(connection),
+            None => self.client.get_connection().and_then(|mut new| {
+                let result = self
+                    .bucket
+                    .invocation(&self.script, &self.key, n)
+                    .invoke(&mut new);
+                *connection = Some(new);
+                result
+            }),
+        };
+
+        if result.is_err() {
+            *connection = None;
+        }
+
+        self.fallback.apply(n, Bucket::result(result))
+    }
+
+    /// Non-blocking function to acquire a single token. See
+    /// [`RedisRatelimiter::try_acquire_n`] for details.
+    pub fn try_acquire(&self) -> Result<(), DistributedError> {
+        self.try_acquire_n(1)
+    }
+
+    /// Non-blocking function to wait for `n` tokens, matching
+    /// [`crate::Ratelimiter::try_wait_n`]. If the acquisition fails due to a
+    /// backend error, the refill interval is returned.
+    pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        self.try_acquire_n(n).map_err(|e| match e {
+            DistributedError::Insufficient(duration) => duration,
+            _ => self.bucket.interval(),
+        })
+    }
+
+    /// Non-blocking function to wait for a single token. See
+    /// [`RedisRatelimiter::try_wait_n`] for details.
+    pub fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1)
+    }
+}
+
+/// The async counterpart to [`RedisRatelimiter`] which uses a multiplexed
+/// tokio connection. It is cheap to clone and clones share the connection.
+#[derive(Clone)]
+pub struct AsyncRedisRatelimiter {
+    bucket: Bucket,
+    connection: redis::aio::MultiplexedConnection,
+    fallback: Fallback,
+    key: String,
+    script: redis::Script,
+}
+
+impl AsyncRedisRatelimiter {
+    /// Create a new ratelimiter which stores the bucket under the provided key
+    /// using an established connection. The bucket is created in Redis on
+    /// first use.
+    pub fn new(
+        connection: redis::aio::MultiplexedConnection,
+        key: impl Into<String>,
+        builder: Builder,
+    ) -> Result<Self, crate::Error> {
+        Ok(Self {
+            bucket: Bucket::new(&builder)?,
+            connection,
+            fallback: Fallback::default(),
+            key: key.into(),
+            script: redis::Script::new(SCRIPT),
+        })
+    }
+
+    /// Set the policy which is applied when the backend returns an error. See
+    /// [`FailurePolicy`] for the available options.
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Result<Self, crate::Error> {
+        self.fallback = Fallback::new(policy, &self.bucket)?;
+        Ok(self)
+    }
+
+    /// Returns the key the bucket is stored under.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Acquire `n` tokens without waiting for them to become available. If the
+    /// backend returns an error, the outcome is determined by the
+    /// [`FailurePolicy`].
+    pub async fn try_acquire_n(&self, n: u64) -> Result<(), DistributedError> {
+        let mut connection = self.connection.clone();
+
+        let wait = self
+            .bucket
+            .invocation(&self.script, &self.key, n)
+            .invoke_async(&mut connection)
+            .await;
+
+        self.fallback.apply(n, Bucket::result(wait))
+    }
+
+    /// Acquire a single token without waiting for it to become available.
+    pub async fn try_acquire(&self) -> Result<(), DistributedError> {
+        self.try_acquire_n(1).await
+    }
+
+    /// Matches [`crate::Ratelimiter::try_wait_n`]. If the acquisition fails due
+    /// to a backend error, the refill interval is returned.
+    pub async fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        self.try_acquire_n(n).await.map_err(|e| match e {
+            DistributedError::Insufficient(duration) => duration,
+            _ => self.bucket.interval(),
+        })
+    }
+
+    /// Matches [`crate::Ratelimiter::try_wait`]. See
+    /// [`AsyncRedisRatelimiter::try_wait_n`] for details.
+    pub async fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ratelimiter;
+    use std::time::Duration;
+
+    // requires a Redis server, set `REDIS_URL` and run with `--ignored`
+    #[test]
+    #[ignore]
+    fn redis() {
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL is not set");
+
+        let key = format!("ratelimit-test-{}", std::process::id());
+        let client = redis::Client::open(url).unwrap();
+
+        let rl = RedisRatelimiter::new(
+            client.clone(),
+            key.clone(),
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(10)
+                .initial_available(3),
+        )
+        .unwrap();
+
+        assert!(rl.try_acquire_n(2).is_ok());
+        assert!(rl.try_wait().is_ok());
+        assert!(matches!(
+            rl.try_acquire(),
+            Err(DistributedError::Insufficient(_))
+        ));
+
+        // the bucket expires once it would have refilled to the max tokens
+        let ttl: i64 = redis::cmd("PTTL")
+            .arg(&key)
+            .query(&mut client.get_connection().unwrap())
+            .unwrap();
+        assert!(ttl > 540_000 && ttl <= 600_001, "{ttl}");
+
+        redis::cmd("DEL")
+            .arg(&key)
+            .query::<()>(&mut client.get_connection().unwrap())
+            .unwrap();
+    }
+}
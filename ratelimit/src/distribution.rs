@@ -0,0 +1,208 @@
+use crate::{advance_instant, Builder, Ratelimiter};
+use clocksource::precise::{Duration, Instant};
+
+/// The distribution of the time between refills.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Distribution {
+    /// Refills happen at exactly the refill interval. This is the default.
+    #[default]
+    Uniform,
+    /// The time between refills is exponentially distributed with a mean of
+    /// the refill interval, which results in refills forming a Poisson process.
+    /// This models the arrivals of independent clients and exposes queueing
+    /// effects that perfectly paced traffic would hide. The long-run average
+    /// rate is unchanged.
+    Poisson,
+}
+
+/// The most refills we will generate individually when catching up after the
+/// ratelimiter has been idle. Beyond this, refills are spaced uniformly which
+/// keeps the cost of a refill bounded.
+const MAX_CATCHUP: u64 = 1024;
+
+impl Builder {
+    /// Set the distribution of the time between refills. See [`Distribution`]
+    /// for the available options.
+    pub fn distribution(mut self, distribution: Distribution) -> Self {
+        self.distribution = distribution;
+        self
+    }
+
+    /// Add random jitter to the time between refills. The jitter is provided as
+    /// a fraction of the refill interval, for example `0.1` allows each refill
+    /// to happen up to 10% earlier or later than it otherwise would. Since the
+    /// jitter is uniformly distributed around the refill interval, the
+    /// long-run average rate is unchanged.
+    ///
+    /// This prevents ratelimiters which are created at the same moment, for
+    /// instance across many instances of a service, from issuing tokens in
+    /// lock-step. The jitter must be in the range `0.0..=1.0` and does not
+    /// apply to the Poisson distribution, which is already randomized.
+    ///
+    /// The default is no jitter.
+    pub fn jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the distribution of the time between refills.
+    pub fn distribution(&self) -> Distribution {
+        self.extensions()
+            .map(|e| e.distribution)
+            .unwrap_or_default()
+    }
+
+    /// Returns the jitter applied to the time between refills as a fraction of
+    /// the refill interval.
+    pub fn jitter(&self) -> f64 {
+        self.extensions().map(|e| e.jitter).unwrap_or(0.0)
+    }
+
+    /// Internal function to apply jitter to a refill interval.
+    pub(crate) fn jittered(&self, interval: u64) -> u64 {
+        let Some(extensions) = self.extensions().filter(|e| e.jitter != 0.0) else {
+            return interval;
+        };
+
+        let offset = extensions.jitter * (2.0 * extensions.random.next_f64() - 1.0);
+
+        ((interval as f64 * (1.0 + offset)).round() as u64).max(1)
+    }
+
+    /// Internal function to determine the number of refills which are due at
+    /// the provided time and when the following refill should be scheduled.
+    pub(crate) fn refills_due(
+        &self,
+        refill_at: Instant,
+        time: Instant,
+        interval: Duration,
+    ) -> (u64, Instant) {
+        let interval = interval.as_nanos();
+
+        let poisson = self
+            .extensions()
+            .filter(|e| e.distribution == Distribution::Poisson);
+
+        match poisson {
+            None => {
+                let intervals = (time - refill_at).as_nanos() / interval + 1;
+
+                let next = advance_instant(refill_at, intervals - 1, interval);
+
+                (intervals, advance_instant(next, 1, self.jittered(interval)))
+            }
+            Some(extensions) => {
+                let mut intervals = 0;
+                let mut next = refill_at;
+
+                while next <= time {
+                    intervals += 1;
+
+                    if intervals > MAX_CATCHUP {
+                        let remaining = (time - next).as_nanos() / interval + 1;
+                        intervals += remaining - 1;
+                        next = advance_instant(next, remaining, interval);
+                        break;
+                    }
+
+                    let gap = (interval as f64 * extensions.random.exponential()).round() as u64;
+                    next = advance_instant(next, 1, gap.max(1));
+                }
+
+                (intervals, next)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gaps(distribution: Distribution) -> (f64, f64) {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+            .distribution(distribution)
+            .build()
+            .unwrap();
+
+        let mut gaps = Vec::new();
+        let mut time = rl.next_refill();
+
+        for _ in 0..10_000 {
+            rl.refill(time).unwrap();
+            let next = rl.next_refill();
+            gaps.push((next - time).as_nanos() as f64);
+            time = next;
+        }
+
+        let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+        let stddev =
+            (gaps.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / gaps.len() as f64).sqrt();
+
+        (mean, stddev)
+    }
+
+    #[test]
+    fn uniform() {
+        let (mean, stddev) = gaps(Distribution::Uniform);
+        assert_eq!(mean, 1_000_000.0);
+        assert_eq!(stddev, 0.0);
+    }
+
+    #[test]
+    fn jitter() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+            .jitter(0.5)
+            .build()
+            .unwrap();
+
+        let mut gaps = Vec::new();
+        let mut time = rl.next_refill();
+
+        for _ in 0..10_000 {
+            rl.refill(time).unwrap();
+            let next = rl.next_refill();
+            gaps.push((next - time).as_nanos());
+            time = next;
+        }
+
+        let mean = gaps.iter().sum::<u64>() as f64 / gaps.len() as f64;
+        assert!((980_000.0..1_020_000.0).contains(&mean), "{mean}");
+        assert!(gaps.iter().all(|g| (500_000..=1_500_000).contains(g)));
+        assert!(gaps.iter().any(|g| *g < 750_000));
+        assert!(gaps.iter().any(|g| *g > 1_250_000));
+
+        assert!(
+            Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                .jitter(1.5)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn poisson() {
+        // for an exponential distribution, the stddev is equal to the mean
+        let (mean, stddev) = gaps(Distribution::Poisson);
+        assert!((950_000.0..1_050_000.0).contains(&mean), "{mean}");
+        assert!((900_000.0..1_100_000.0).contains(&stddev), "{stddev}");
+    }
+
+    #[test]
+    fn catchup() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+            .max_tokens(u64::MAX)
+            .distribution(Distribution::Poisson)
+            .build()
+            .unwrap();
+
+        // after a long idle period, the number of refills should be close to
+        // the number of elapsed intervals
+        let time = rl.next_refill() + Duration::from_secs(10);
+        rl.refill(time).unwrap();
+        assert!((9_500..10_500).contains(&rl.available()));
+        assert!(rl.next_refill() > time);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{Ratelimiter, TryAcquireError};
+use core::borrow::Borrow;
+use std::collections::VecDeque;
+
+/// Internal type which holds the queue and deficit for a consumer.
+struct Consumer<T> {
+    deficit: u64,
+    items: VecDeque<(T, u64)>,
+    quantum: u64,
+}
+
+/// A dispatcher which paces several queues from a single ratelimiter using
+/// deficit round-robin scheduling.
+///
+/// Each consumer is registered with a quantum, which is the number of tokens
+/// it may spend each time it is visited. The queues are visited in turn, so
+/// over time each busy consumer receives tokens in proportion to its quantum,
+/// and no queue can monopolize the budget. A consumer whose queue is empty
+/// does not keep its deficit, so it can't build up credit while idle.
+///
+/// ```
+/// use ratelimit::{DeficitRoundRobin, Ratelimiter};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+///     .max_tokens(10)
+///     .initial_available(10)
+///     .build()
+///     .unwrap();
+///
+/// let mut drr = DeficitRoundRobin::new(&ratelimiter);
+/// let bulk = drr.register(1);
+/// let interactive = drr.register(2);
+///
+/// drr.push(bulk, "compact", 1);
+/// drr.push(interactive, "query", 1);
+///
+/// while let Ok(Some((consumer, item))) = drr.try_next() {
+///     println!("dispatching {item} for {consumer}");
+/// }
+/// ```
+pub struct DeficitRoundRobin<T, L> {
+    consumers: Vec<Consumer<T>>,
+    // the consumer being visited
+    current: usize,
+    // true if the quantum has been added for the current visit
+    granted: bool,
+    ratelimiter: L,
+}
+
+impl<T, L: Borrow<Ratelimiter>> DeficitRoundRobin<T, L> {
+    /// Create a dispatcher with no consumers.
+    pub fn new(ratelimiter: L) -> Self {
+        Self {
+            consumers: Vec::new(),
+            current: 0,
+            granted: false,
+            ratelimiter,
+        }
+    }
+
+    /// Register a consumer with the provided quantum, returning the id of the
+    /// consumer.
+    ///
+    /// Note: a quantum of zero is treated as one.
+    pub fn register(&mut self, quantum: u64) -> usize {
+        self.consumers.push(Consumer {
+            deficit: 0,
+            items: VecDeque::new(),
+            quantum: quantum.max(1),
+        });
+
+        self.consumers.len() - 1
+    }
+
+    /// Add an item which costs the provided number of tokens to the queue of a
+    /// consumer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the consumer has not been registered.
+    pub fn push(&mut self, consumer: usize, item: T, cost: u64) {
+        self.consumers[consumer].items.push_back((item, cost));
+    }
+
+    /// Returns the ratelimiter which is charged for each item.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Returns the number of items queued for a consumer.
+    pub fn queued(&self, consumer: usize) -> usize {
+        self.consumers
+            .get(consumer)
+            .map(|consumer| consumer.items.len())
+            .unwrap_or(0)
+    }
+
+    /// Returns the total number of items queued for all consumers.
+    pub fn len(&self) -> usize {
+        self.consumers.iter().map(|c| c.items.len()).sum()
+    }
+
+    /// Returns `true` if no items are queued.
+    pub fn is_empty(&self) -> bool {
+        self.consumers.iter().all(|c| c.items.is_empty())
+    }
+
+    /// Returns the next item to dispatch along with the id of its consumer,
+    /// once the tokens for it have been acquired. Returns `None` if no items
+    /// are queued.
+    ///
+    /// Returns an error if the tokens for the next item are not available. The
+    /// same item is chosen on the next attempt, so that the order of dispatch
+    /// is not affected by the ratelimiter. Costs above the maximum number of
+    /// tokens in the ratelimiter are charged as the maximum.
+    pub fn try_next(&mut self) -> Result<Option<(usize, T)>, TryAcquireError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+
+        loop {
+            let consumer = &mut self.consumers[self.current];
+
+            if consumer.items.is_empty() {
+                consumer.deficit = 0;
+                self.advance();
+                continue;
+            }
+
+            if !self.granted {
+                consumer.deficit = consumer.deficit.saturating_add(consumer.quantum);
+                self.granted = true;
+            }
+
+            let cost = consumer.items.front().map(|(_, cost)| *cost).unwrap_or(0);
+
+            if cost > consumer.deficit {
+                self.advance();
+                continue;
+            }
+
+            let ratelimiter = self.ratelimiter.borrow();
+            if cost > 0 {
+                ratelimiter.try_acquire_n(cost.min(ratelimiter.max_tokens()))?;
+            }
+
+            consumer.deficit -= cost;
+            let item = consumer
+                .items
+                .pop_front()
+                .map(|(item, _)| (self.current, item));
+
+            return Ok(item);
+        }
+    }
+
+    /// Internal function to move on to the next consumer.
+    fn advance(&mut self) {
+        self.current = (self.current + 1) % self.consumers.len();
+        self.granted = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn quantum() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(100)
+            .initial_available(100)
+            .build()
+            .unwrap();
+
+        let mut drr = DeficitRoundRobin::new(&ratelimiter);
+        let a = drr.register(1);
+        let b = drr.register(3);
+
+        for i in 0..8 {
+            drr.push(a, i, 1);
+            drr.push(b, i, 1);
+        }
+        assert_eq!(drr.len(), 16);
+
+        // each round dispatches up to the quantum of each consumer
+        let order: Vec<usize> = (0..8).map(|_| drr.try_next().unwrap().unwrap().0).collect();
+        assert_eq!(order, vec![a, b, b, b, a, b, b, b]);
+        assert_eq!(drr.queued(a), 6);
+        assert_eq!(drr.queued(b), 2);
+        assert_eq!(ratelimiter.available(), 92);
+    }
+
+    #[test]
+    fn cost() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(5)
+            .build()
+            .unwrap();
+
+        let mut drr = DeficitRoundRobin::new(&ratelimiter);
+        let a = drr.register(2);
+        let b = drr.register(2);
+
+        // an item which costs more than the quantum waits for its deficit to
+        // build up over several rounds
+        drr.push(a, "large", 3);
+        drr.push(b, "small", 1);
+        drr.push(b, "small", 1);
+        drr.push(b, "small", 1);
+
+        assert_eq!(drr.try_next(), Ok(Some((b, "small"))));
+        assert_eq!(drr.try_next(), Ok(Some((b, "small"))));
+        assert_eq!(drr.try_next(), Ok(Some((a, "large"))));
+
+        // without tokens, the same item is chosen on the next attempt
+        assert!(drr.try_next().is_err());
+        ratelimiter.return_n(1);
+        assert_eq!(drr.try_next(), Ok(Some((b, "small"))));
+        assert_eq!(drr.try_next(), Ok(None));
+    }
+}
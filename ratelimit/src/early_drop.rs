@@ -0,0 +1,139 @@
+use crate::atomic::Ordering;
+use crate::{Builder, Ratelimiter};
+
+/// The configuration for probabilistic early drop, see
+/// [`Builder::early_drop`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct EarlyDrop {
+    max_probability: f64,
+    threshold: f64,
+}
+
+impl EarlyDrop {
+    /// Returns `true` if the configuration is valid.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.threshold > 0.0
+            && self.threshold <= 1.0
+            && self.max_probability > 0.0
+            && self.max_probability <= 1.0
+    }
+
+    /// Returns the probability of dropping an acquisition which leaves the
+    /// provided fraction of the capacity in the bucket.
+    fn probability(&self, remaining: f64) -> f64 {
+        if remaining >= self.threshold {
+            0.0
+        } else {
+            self.max_probability * (self.threshold - remaining) / self.threshold
+        }
+    }
+}
+
+impl Builder {
+    /// Reject acquisitions probabilistically as the bucket runs low, rather
+    /// than only once it is empty. This smooths the transition into overload
+    /// for load-shedding deployments, in the style of random early detection.
+    ///
+    /// Once an acquisition would leave less than the `threshold` fraction of
+    /// the max tokens in the bucket, it is rejected with a probability which
+    /// rises linearly from zero at the threshold to `max_probability` when the
+    /// bucket is empty. Both must be in the range `0.0..=1.0` and greater than
+    /// zero.
+    ///
+    /// Rejections are reported as [`TryAcquireError::Insufficient`] and are
+    /// counted by [`Ratelimiter::early_dropped`]. This only applies to
+    /// [`Ratelimiter::try_acquire_n`] and the functions built on it.
+    ///
+    /// [`TryAcquireError::Insufficient`]: crate::TryAcquireError::Insufficient
+    pub fn early_drop(mut self, threshold: f64, max_probability: f64) -> Self {
+        self.early_drop = Some(EarlyDrop {
+            max_probability,
+            threshold,
+        });
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the number of acquisitions which have been rejected by
+    /// probabilistic early drop. See [`Builder::early_drop`].
+    pub fn early_dropped(&self) -> u64 {
+        self.extensions()
+            .map(|e| e.early_dropped.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Internal function to decide whether an acquisition which has taken its
+    /// tokens should be dropped. If so, the tokens are returned.
+    pub(crate) fn early_drop(&self, n: u64) -> bool {
+        let Some(extensions) = self.extensions() else {
+            return false;
+        };
+        let Some(early_drop) = &extensions.early_drop else {
+            return false;
+        };
+
+        let remaining = self.available() as f64 / self.max_tokens() as f64;
+        let probability = early_drop.probability(remaining);
+
+        if probability > 0.0 && extensions.random.next_f64() < probability {
+            extensions.early_dropped.fetch_add(1, Ordering::Relaxed);
+            self.refund(n);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn early_drop() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10_000)
+            .initial_available(10_000)
+            .early_drop(0.5, 1.0)
+            .build()
+            .unwrap();
+
+        // nothing is dropped while the bucket is above the threshold
+        for _ in 0..5_000 {
+            rl.try_acquire().unwrap();
+        }
+        assert_eq!(rl.early_dropped(), 0);
+
+        // below the threshold, drops become more likely as the bucket empties
+        let mut admitted = 0;
+        for _ in 0..1_000 {
+            if rl.try_acquire().is_ok() {
+                admitted += 1;
+            }
+        }
+        assert_eq!(rl.early_dropped(), 1_000 - admitted);
+        assert!((850..1_000).contains(&admitted), "{admitted}");
+
+        rl.set_available(100).unwrap();
+        let dropped = rl.early_dropped();
+        for _ in 0..50 {
+            let _ = rl.try_acquire();
+        }
+        assert!(rl.early_dropped() - dropped > 40);
+    }
+
+    #[test]
+    fn invalid() {
+        for (threshold, probability) in [(0.0, 0.5), (1.5, 0.5), (0.5, 0.0), (0.5, f64::NAN)] {
+            assert_eq!(
+                Ratelimiter::builder(1, Duration::from_secs(1))
+                    .early_drop(threshold, probability)
+                    .build()
+                    .err(),
+                Some(Error::InvalidEarlyDrop)
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{Builder, Ratelimiter};
+use std::sync::mpsc::SyncSender;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A change in the state of a `Ratelimiter`, sent to the channel provided to
+/// [`Builder::events`].
+///
+/// Successful acquisitions are not sent, since they are frequent enough to
+/// fill the channel. They are counted by [`Ratelimiter::stats`] instead.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Event {
+    /// An attempt to acquire `tokens` failed. The shortfall is the number of
+    /// tokens which were missing when it failed, which is zero if it failed
+    /// for another reason such as the ratelimiter being paused.
+    Denied { tokens: u64, shortfall: u64 },
+    /// The bucket was refilled with `amount` tokens, including any which were
+    /// then dropped.
+    Refilled { amount: u64 },
+    /// `tokens` were dropped because the bucket was full.
+    Dropped { tokens: u64 },
+    /// The parameters of the ratelimiter changed.
+    ParametersChanged {
+        max_tokens: u64,
+        refill_amount: u64,
+        refill_interval: core::time::Duration,
+        rate: f64,
+    },
+    /// An [`crate::Experiment`] started the stage with the index `stage`,
+    /// which has a rate of `rate` tokens/s.
+    StageStarted { stage: usize, rate: f64 },
+    /// An [`crate::Experiment`] finished its last stage.
+    ExperimentFinished,
+}
+
+impl Builder {
+    /// Send an [`Event`] to the provided channel whenever the state of the
+    /// ratelimiter changes, for example to stream its activity into an audit
+    /// log.
+    ///
+    /// The channel is bounded, and events are discarded rather than blocking
+    /// the ratelimiter when it is full or the receiver has been dropped.
+    ///
+    /// ```
+    /// use ratelimit::{Event, Ratelimiter};
+    /// use std::sync::mpsc::sync_channel;
+    /// use std::time::Duration;
+    ///
+    /// let (sender, events) = sync_channel(1024);
+    ///
+    /// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+    ///     .events(sender)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert!(ratelimiter.try_acquire_n(1).is_err());
+    /// assert_eq!(
+    ///     events.try_recv(),
+    ///     Ok(Event::Denied {
+    ///         tokens: 1,
+    ///         shortfall: 1
+    ///     })
+    /// );
+    /// ```
+    pub fn events(mut self, sender: SyncSender<Event>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to send an event, if a channel was provided. The
+    /// event is only constructed if it will be sent.
+    pub(crate) fn send_event(&self, event: impl FnOnce() -> Event) {
+        if let Some(sender) = self.extensions().and_then(|e| e.events.as_ref()) {
+            let _ = sender.try_send(event());
+        }
+    }
+
+    /// Internal function to describe the current parameters as an event. Must
+    /// be called without holding the parameters lock.
+    pub(crate) fn parameters_event(&self) -> Event {
+        Event::ParametersChanged {
+            max_tokens: self.max_tokens(),
+            refill_amount: self.refill_amount(),
+            refill_interval: self.refill_interval(),
+            rate: self.rate(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::mpsc::sync_channel;
+    use std::time::Duration;
+
+    #[test]
+    fn events() {
+        let (sender, events) = sync_channel(4);
+
+        let rl = Ratelimiter::builder(2, Duration::from_millis(50))
+            .max_tokens(3)
+            .initial_available(2)
+            .events(sender)
+            .build()
+            .unwrap();
+
+        assert!(rl.try_acquire_n(3).is_err());
+
+        std::thread::sleep(Duration::from_millis(50));
+        rl.try_acquire().unwrap();
+
+        rl.set_refill_amount(1).unwrap();
+
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                Event::Denied {
+                    tokens: 3,
+                    shortfall: 1
+                },
+                Event::Refilled { amount: 2 },
+                Event::Dropped { tokens: 1 },
+                Event::ParametersChanged {
+                    max_tokens: 3,
+                    refill_amount: 1,
+                    refill_interval: Duration::from_millis(50),
+                    rate: 20.0,
+                },
+            ]
+        );
+
+        // events are discarded while the channel is full
+        for _ in 0..8 {
+            let _ = rl.try_acquire_n(4);
+        }
+        assert_eq!(events.try_iter().count(), 4);
+    }
+}
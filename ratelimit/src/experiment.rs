@@ -0,0 +1,320 @@
+use crate::{advance_instant, Error, Event, Ratelimiter};
+use clocksource::precise::Instant;
+use core::borrow::Borrow;
+use parking_lot::Mutex;
+
+/// A stage of an [`Experiment`], which holds the ratelimiter at a rate for a
+/// duration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    name: String,
+    rate: f64,
+    duration: core::time::Duration,
+}
+
+impl Stage {
+    /// Create a stage which holds the ratelimiter at `rate` tokens/s for the
+    /// `duration`. The name is for reporting, such as `"warmup"` or `"spike"`.
+    pub fn new(name: impl Into<String>, rate: f64, duration: core::time::Duration) -> Self {
+        Self {
+            name: name.into(),
+            rate,
+            duration,
+        }
+    }
+
+    /// Returns the name of the stage.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the rate of the stage in tokens/s.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Returns how long the stage lasts.
+    pub fn duration(&self) -> core::time::Duration {
+        self.duration
+    }
+}
+
+/// Internal type which tracks how far an experiment has progressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Progress {
+    Idle,
+    Stage(usize),
+    Finished,
+}
+
+/// A controller which walks a ratelimiter through a sequence of stages, for
+/// example a warmup, a steady state, a spike, and a cooldown, as used by
+/// benchmarking and canary tooling.
+///
+/// The experiment starts with [`Experiment::start`] or the first call to
+/// [`Experiment::update`], and the rate is changed as the experiment moves
+/// into each stage. The rate of the last stage remains in effect once the
+/// experiment has finished. The experiment is driven either by calling
+/// [`Experiment::update`] periodically, or by [`Experiment::run`] which
+/// blocks until the experiment has finished.
+///
+/// An [`Event::StageStarted`] is sent as each stage starts, including stages
+/// which were passed over between updates, and an
+/// [`Event::ExperimentFinished`] is sent once the last stage ends. See
+/// [`crate::Builder::events`].
+///
+/// Since the refill amount cannot exceed the max tokens, the max tokens should
+/// be set high enough for the peak rate. Otherwise, a shorter refill interval
+/// is used.
+///
+/// ```
+/// use ratelimit::{Experiment, Ratelimiter, Stage};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::per_second(100)
+///     .max_tokens(1000)
+///     .build()
+///     .unwrap();
+///
+/// let experiment = Experiment::new(
+///     &ratelimiter,
+///     [
+///         Stage::new("warmup", 100.0, Duration::from_secs(60)),
+///         Stage::new("steady", 500.0, Duration::from_secs(600)),
+///         Stage::new("spike", 1000.0, Duration::from_secs(30)),
+///         Stage::new("cooldown", 100.0, Duration::from_secs(60)),
+///     ],
+/// )
+/// .unwrap();
+///
+/// assert_eq!(experiment.update().unwrap().name(), "warmup");
+/// ```
+pub struct Experiment<L> {
+    ratelimiter: L,
+    stages: Vec<Stage>,
+    state: Mutex<(Instant, Progress)>,
+}
+
+impl<L: Borrow<Ratelimiter>> Experiment<L> {
+    /// Create an experiment which walks the ratelimiter through the stages.
+    /// Returns an error if there are no stages or a stage has a rate which is
+    /// not a finite number of tokens/s greater than zero.
+    pub fn new(ratelimiter: L, stages: impl IntoIterator<Item = Stage>) -> Result<Self, Error> {
+        let stages: Vec<Stage> = stages.into_iter().collect();
+
+        if stages.is_empty()
+            || !stages
+                .iter()
+                .all(|stage| stage.rate.is_finite() && stage.rate > 0.0)
+        {
+            return Err(Error::InvalidRate);
+        }
+
+        Ok(Self {
+            ratelimiter,
+            stages,
+            state: Mutex::new((Instant::now(), Progress::Idle)),
+        })
+    }
+
+    /// Returns the ratelimiter which is being controlled.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Returns the stages of the experiment.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Start, or restart, the experiment from the first stage.
+    pub fn start(&self) {
+        let mut state = self.state.lock();
+        *state = (Instant::now(), Progress::Idle);
+        self.advance(&mut state, Instant::now());
+    }
+
+    /// Move the experiment into the stage for the current time, changing the
+    /// rate if the stage has changed. The experiment is started if it has not
+    /// been already. Returns the current stage, or `None` if the experiment
+    /// has finished.
+    pub fn update(&self) -> Option<&Stage> {
+        let mut state = self.state.lock();
+        self.advance(&mut state, Instant::now());
+        self.current(state.1)
+    }
+
+    /// Returns the current stage without changing it, or `None` if the
+    /// experiment has not started or has finished.
+    pub fn stage(&self) -> Option<&Stage> {
+        self.current(self.state.lock().1)
+    }
+
+    /// Returns `true` once the last stage has ended.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().1 == Progress::Finished
+    }
+
+    /// Blocking function which runs the experiment until it has finished,
+    /// starting it if it has not been already. The calling thread sleeps until
+    /// each stage ends.
+    pub fn run(&self) {
+        loop {
+            let next = {
+                let mut state = self.state.lock();
+                self.advance(&mut state, Instant::now())
+            };
+
+            match next {
+                Some(next) => {
+                    let now = Instant::now();
+                    if next > now {
+                        std::thread::sleep(core::time::Duration::from_nanos(
+                            (next - now).as_nanos(),
+                        ));
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Internal function to return the stage for the progress.
+    fn current(&self, progress: Progress) -> Option<&Stage> {
+        match progress {
+            Progress::Stage(index) => self.stages.get(index),
+            Progress::Idle | Progress::Finished => None,
+        }
+    }
+
+    /// Internal function to move the experiment into the stage for the
+    /// provided time. Returns when the current stage ends, or `None` if the
+    /// experiment has finished.
+    fn advance(&self, state: &mut (Instant, Progress), now: Instant) -> Option<Instant> {
+        let (started, progress) = state;
+        if *progress == Progress::Idle {
+            *started = now;
+        }
+
+        let elapsed = now
+            .checked_duration_since(*started)
+            .unwrap_or_default()
+            .as_nanos();
+
+        // find the stage which contains the elapsed time
+        let mut end: u64 = 0;
+        let mut target = Progress::Finished;
+        for (index, stage) in self.stages.iter().enumerate() {
+            end = end.saturating_add(stage.duration.as_nanos().min(u64::MAX as u128) as u64);
+            if elapsed < end {
+                target = Progress::Stage(index);
+                break;
+            }
+        }
+
+        if target != *progress {
+            let first = match *progress {
+                Progress::Idle => 0,
+                Progress::Stage(index) => index + 1,
+                Progress::Finished => self.stages.len(),
+            };
+            let last = match target {
+                Progress::Stage(index) => index + 1,
+                _ => self.stages.len(),
+            };
+
+            let ratelimiter = self.ratelimiter();
+            for (index, stage) in self.stages.iter().enumerate().take(last).skip(first) {
+                ratelimiter.send_event(|| Event::StageStarted {
+                    stage: index,
+                    rate: stage.rate,
+                });
+            }
+
+            match target {
+                Progress::Stage(index) => ratelimiter.apply_rate(self.stages[index].rate),
+                _ => {
+                    if let Some(stage) = self.stages.last() {
+                        ratelimiter.apply_rate(stage.rate);
+                    }
+                    ratelimiter.send_event(|| Event::ExperimentFinished);
+                }
+            }
+
+            *progress = target;
+        }
+
+        match target {
+            Progress::Stage(_) => Some(advance_instant(*started, 1, end)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::mpsc::sync_channel;
+    use std::time::Duration;
+
+    #[test]
+    fn stages() {
+        let (sender, events) = sync_channel(16);
+
+        let rl = Ratelimiter::per_second(10)
+            .max_tokens(1000)
+            .events(sender)
+            .build()
+            .unwrap();
+
+        let experiment = Experiment::new(
+            &rl,
+            [
+                Stage::new("warmup", 100.0, Duration::from_millis(20)),
+                Stage::new("spike", 1000.0, Duration::from_millis(20)),
+                Stage::new("cooldown", 50.0, Duration::from_millis(20)),
+            ],
+        )
+        .unwrap();
+
+        assert!(experiment.stage().is_none());
+        assert_eq!(experiment.update().unwrap().name(), "warmup");
+        assert_eq!(rl.rate(), 100.0);
+
+        experiment.run();
+        assert!(experiment.is_finished());
+        assert!(experiment.update().is_none());
+        assert_eq!(rl.rate(), 50.0);
+
+        let stages: Vec<Event> = events
+            .try_iter()
+            .filter(|e| !matches!(e, Event::ParametersChanged { .. }))
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                Event::StageStarted {
+                    stage: 0,
+                    rate: 100.0
+                },
+                Event::StageStarted {
+                    stage: 1,
+                    rate: 1000.0
+                },
+                Event::StageStarted {
+                    stage: 2,
+                    rate: 50.0
+                },
+                Event::ExperimentFinished,
+            ]
+        );
+
+        // the experiment may be restarted
+        experiment.start();
+        assert_eq!(experiment.stage().unwrap().name(), "warmup");
+        assert_eq!(rl.rate(), 100.0);
+
+        assert!(Experiment::new(&rl, []).is_err());
+        assert!(Experiment::new(&rl, [Stage::new("idle", 0.0, Duration::from_secs(1))]).is_err());
+    }
+}
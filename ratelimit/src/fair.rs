@@ -0,0 +1,272 @@
+use crate::atomic::{AtomicU64, AtomicUsize, Ordering};
+use crate::{Builder, Ratelimiter, TryAcquireError};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Internal type which holds the tickets of the callers waiting for tokens in
+/// the order they arrived. The count allows the hot path to skip the lock
+/// when nobody is waiting.
+#[derive(Default)]
+pub(crate) struct Queue {
+    next: AtomicU64,
+    tickets: Mutex<VecDeque<u64>>,
+    waiting: AtomicUsize,
+}
+
+impl Queue {
+    /// Returns `true` if nobody is waiting.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.waiting.load(Ordering::Acquire) == 0
+    }
+
+    /// Returns the number of bytes allocated to hold the tickets.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.tickets.lock().capacity() * core::mem::size_of::<u64>()
+    }
+
+    /// Join the back of the queue.
+    fn enqueue(self: &Arc<Self>) -> Ticket {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+
+        let mut tickets = self.tickets.lock();
+        tickets.push_back(id);
+        self.waiting.store(tickets.len(), Ordering::Release);
+
+        Ticket {
+            id,
+            queue: self.clone(),
+        }
+    }
+
+    /// Returns `true` if the ticket is at the front of the queue.
+    fn is_head(&self, id: u64) -> bool {
+        self.tickets.lock().front() == Some(&id)
+    }
+
+    /// Leave the queue, whether the tokens were acquired or the caller gave up.
+    fn remove(&self, id: u64) {
+        let mut tickets = self.tickets.lock();
+
+        if let Some(position) = tickets.iter().position(|t| *t == id) {
+            tickets.remove(position);
+            self.waiting.store(tickets.len(), Ordering::Release);
+        }
+    }
+}
+
+/// A place in the queue of a ratelimiter which has FIFO ordering enabled. The
+/// place is given up when the ticket is dropped.
+#[derive(Debug)]
+pub(crate) struct Ticket {
+    id: u64,
+    queue: Arc<Queue>,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.queue.remove(self.id);
+    }
+}
+
+impl core::fmt::Debug for Queue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Queue")
+            .field("waiting", &self.waiting.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl Builder {
+    /// Enable first-come-first-served ordering for callers which wait for
+    /// tokens. Without this, a caller which has been retrying for some time
+    /// can lose each refill to new arrivals and may be starved indefinitely.
+    ///
+    /// When enabled, callers which wait for tokens through the adapters in this
+    /// crate or [`Ratelimiter::poll_acquire`] join a queue, and only the caller
+    /// at the front of the queue may acquire tokens. While anyone is queued,
+    /// [`Ratelimiter::try_acquire_n`] fails for new arrivals.
+    ///
+    /// Note: a caller which stops retrying without dropping its waiter will
+    /// block the callers behind it.
+    pub fn fifo(mut self, enabled: bool) -> Self {
+        self.fifo = enabled;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns `true` if first-come-first-served ordering is enabled for
+    /// callers which wait for tokens. See [`Builder::fifo`].
+    pub fn is_fifo(&self) -> bool {
+        self.queue().is_some()
+    }
+
+    /// Returns the number of callers which are queued waiting for tokens. This
+    /// is always zero unless FIFO ordering is enabled.
+    pub fn queued(&self) -> usize {
+        self.queue()
+            .map(|queue| queue.waiting.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Internal function to return the queue, if FIFO ordering is enabled.
+    pub(crate) fn queue(&self) -> Option<&Arc<Queue>> {
+        self.extensions()?.queue.as_ref()
+    }
+
+    /// Internal function to acquire `n` tokens for a caller which retries
+    /// until it succeeds. When FIFO ordering is enabled, a caller which fails
+    /// for lack of tokens is given a ticket, and holders of a ticket may only
+    /// acquire tokens once they reach the front of the queue.
+    pub(crate) fn try_acquire_queued(
+        &self,
+        n: u64,
+        ticket: &mut Option<Ticket>,
+    ) -> Result<(), TryAcquireError> {
+        let result = self.attempt_queued(n, ticket);
+        self.record(n, result)
+    }
+
+    /// Internal function to attempt to acquire `n` tokens for a caller which
+    /// retries until it succeeds, without recording the outcome. See
+    /// [`Ratelimiter::try_acquire_queued`].
+    pub(crate) fn attempt_queued(
+        &self,
+        n: u64,
+        ticket: &mut Option<Ticket>,
+    ) -> Result<(), TryAcquireError> {
+        let Some(queue) = self.queue() else {
+            return self.attempt(n);
+        };
+
+        // a ticket for another queue is from a ratelimiter which has since
+        // been replaced, such as an evicted bucket of a keyed ratelimiter
+        if ticket
+            .as_ref()
+            .is_some_and(|t| !Arc::ptr_eq(&t.queue, queue))
+        {
+            *ticket = None;
+        }
+
+        let Some(id) = ticket.as_ref().map(|t| t.id) else {
+            let result = self.attempt(n);
+
+            if let Err(TryAcquireError::Insufficient(_)) = result {
+                *ticket = Some(queue.enqueue());
+            }
+
+            return result;
+        };
+
+        let result = match self.check_state().or_else(|| self.check_penalty()) {
+            Some(result) => result,
+            None if queue.is_head(id) => self.take_or_drop(n, 0),
+            None => Err(TryAcquireError::Insufficient(self.scaled_interval())),
+        };
+
+        if result.is_ok() {
+            // the next caller in the queue can now proceed
+            *ticket = None;
+            self.wakers.wake_all();
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn fifo() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(4)
+            .fifo(true)
+            .build()
+            .unwrap();
+        assert!(rl.is_fifo());
+
+        // callers which fail for lack of tokens join the queue in order
+        let mut first = None;
+        let mut second = None;
+        assert!(rl.try_acquire_queued(2, &mut first).is_err());
+        assert!(rl.try_acquire_queued(1, &mut second).is_err());
+        assert_eq!(rl.queued(), 2);
+
+        // new arrivals and callers behind the head can't take the tokens
+        rl.return_n(3);
+        assert!(rl.try_acquire().is_err());
+        assert!(rl.try_acquire_queued(1, &mut second).is_err());
+
+        assert!(rl.try_acquire_queued(2, &mut first).is_ok());
+        assert!(first.is_none());
+        assert!(rl.try_acquire_queued(1, &mut second).is_ok());
+        assert_eq!(rl.queued(), 0);
+
+        // giving up a ticket leaves the queue
+        let mut third = None;
+        assert!(rl.try_acquire_queued(1, &mut third).is_err());
+        assert_eq!(rl.queued(), 1);
+        drop(third);
+        assert_eq!(rl.queued(), 0);
+    }
+
+    // test that a queued caller can't leave the penalty box by holding a ticket
+    #[test]
+    fn penalty() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(4)
+            .fifo(true)
+            .penalty(1, Duration::from_secs(60), Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let mut ticket = None;
+        assert!(rl.try_acquire_queued(2, &mut ticket).is_err());
+        assert!(ticket.is_some());
+        assert!(rl.try_acquire_queued(2, &mut ticket).is_err());
+        assert!(rl.is_penalized());
+
+        rl.return_n(4);
+        assert!(rl.try_acquire_queued(2, &mut ticket).is_err());
+        assert_eq!(rl.available(), 4);
+    }
+
+    // test that early drop applies to callers holding a FIFO ticket
+    #[test]
+    fn early_drop() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .fifo(true)
+            .early_drop(0.5, 1.0)
+            .build()
+            .unwrap();
+
+        let mut ticket = None;
+        assert!(rl.try_acquire_queued(1, &mut ticket).is_err());
+        assert!(ticket.is_some());
+
+        // emptying the bucket is always dropped
+        rl.set_available(1).unwrap();
+        assert!(rl.try_acquire_queued(1, &mut ticket).is_err());
+        assert_eq!(rl.early_dropped(), 1);
+        assert_eq!(rl.available(), 1);
+    }
+
+    #[test]
+    fn unfair() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(4)
+            .build()
+            .unwrap();
+        assert!(!rl.is_fifo());
+
+        let mut ticket = None;
+        assert!(rl.try_acquire_queued(1, &mut ticket).is_err());
+        assert!(ticket.is_none());
+        assert_eq!(rl.queued(), 0);
+    }
+}
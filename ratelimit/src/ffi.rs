@@ -0,0 +1,179 @@
+//! A C interface to the ratelimiter, so that C and C++ services can embed the
+//! same limiter. The header is in `include/ratelimit.h` and is generated with
+//! `cbindgen`:
+//!
+//! ```sh
+//! cbindgen --config cbindgen.toml --output include/ratelimit.h
+//! ```
+//!
+//! To link the library, build it as a static or dynamic library:
+//!
+//! ```sh
+//! cargo rustc --release --features ffi --crate-type staticlib
+//! ```
+//!
+//! The ratelimiter is an opaque handle, which is created with
+//! `ratelimit_create` and freed with `ratelimit_destroy`. The handle may be
+//! shared between threads. No function panics across the boundary: a panic is
+//! caught and reported as `RATELIMIT_ERROR`.
+//!
+//! ```c
+//! Ratelimiter *ratelimiter = ratelimit_create(1, 1000000000, 10, 10);
+//!
+//! uint64_t wait_ns;
+//! if (ratelimit_try_wait(ratelimiter, &wait_ns) == RATELIMIT_INSUFFICIENT) {
+//!     // try again after `wait_ns` nanoseconds
+//! }
+//!
+//! ratelimit_destroy(ratelimiter);
+//! ```
+
+use crate::Ratelimiter;
+use core::time::Duration;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// The tokens were acquired.
+pub const RATELIMIT_OK: i32 = 0;
+
+/// There were insufficient tokens. The time until the next refill is written
+/// to `wait_ns`.
+pub const RATELIMIT_INSUFFICIENT: i32 = 1;
+
+/// The handle was null or the ratelimiter panicked.
+pub const RATELIMIT_ERROR: i32 = -1;
+
+/// Create a ratelimiter which adds `amount` tokens every `interval_ns`
+/// nanoseconds, up to `max_tokens`, and starts with `initial_available`
+/// tokens. Returns null if the parameters are invalid.
+///
+/// The handle must be freed with `ratelimit_destroy`.
+#[no_mangle]
+pub extern "C" fn ratelimit_create(
+    amount: u64,
+    interval_ns: u64,
+    max_tokens: u64,
+    initial_available: u64,
+) -> *mut Ratelimiter {
+    let ratelimiter = catch_unwind(|| {
+        Ratelimiter::builder(amount, Duration::from_nanos(interval_ns))
+            .max_tokens(max_tokens)
+            .initial_available(initial_available)
+            .build()
+    });
+
+    match ratelimiter {
+        Ok(Ok(ratelimiter)) => Box::into_raw(Box::new(ratelimiter)),
+        _ => core::ptr::null_mut(),
+    }
+}
+
+/// Try to acquire `n` tokens without blocking. Returns `RATELIMIT_OK` if the
+/// tokens were acquired, or `RATELIMIT_INSUFFICIENT` with the time until the
+/// next refill written to `wait_ns`, if `wait_ns` is not null.
+///
+/// # Safety
+///
+/// `ratelimiter` must be null or a handle from `ratelimit_create` which has
+/// not been destroyed. `wait_ns` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ratelimit_try_wait_n(
+    ratelimiter: *const Ratelimiter,
+    n: u64,
+    wait_ns: *mut u64,
+) -> i32 {
+    let Some(ratelimiter) = ratelimiter.as_ref() else {
+        return RATELIMIT_ERROR;
+    };
+
+    match catch_unwind(AssertUnwindSafe(|| ratelimiter.try_wait_n(n))) {
+        Ok(Ok(())) => RATELIMIT_OK,
+        Ok(Err(wait)) => {
+            if let Some(wait_ns) = wait_ns.as_mut() {
+                *wait_ns = wait.as_nanos().min(u64::MAX as u128) as u64;
+            }
+            RATELIMIT_INSUFFICIENT
+        }
+        Err(_) => RATELIMIT_ERROR,
+    }
+}
+
+/// Try to acquire a single token without blocking. See
+/// `ratelimit_try_wait_n`.
+///
+/// # Safety
+///
+/// See `ratelimit_try_wait_n`.
+#[no_mangle]
+pub unsafe extern "C" fn ratelimit_try_wait(
+    ratelimiter: *const Ratelimiter,
+    wait_ns: *mut u64,
+) -> i32 {
+    ratelimit_try_wait_n(ratelimiter, 1, wait_ns)
+}
+
+/// Returns the number of tokens currently available, or zero if the handle is
+/// null.
+///
+/// # Safety
+///
+/// `ratelimiter` must be null or a handle from `ratelimit_create` which has
+/// not been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn ratelimit_available(ratelimiter: *const Ratelimiter) -> u64 {
+    let Some(ratelimiter) = ratelimiter.as_ref() else {
+        return 0;
+    };
+
+    catch_unwind(AssertUnwindSafe(|| ratelimiter.available())).unwrap_or(0)
+}
+
+/// Free a ratelimiter. Does nothing if the handle is null.
+///
+/// # Safety
+///
+/// `ratelimiter` must be null or a handle from `ratelimit_create` which has
+/// not already been destroyed. The handle must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ratelimit_destroy(ratelimiter: *mut Ratelimiter) {
+    if !ratelimiter.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(ratelimiter))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi() {
+        // max tokens below the refill amount is invalid
+        assert!(ratelimit_create(10, 1_000_000_000, 1, 0).is_null());
+
+        let ratelimiter = ratelimit_create(1, 1_000_000_000, 2, 2);
+        assert!(!ratelimiter.is_null());
+
+        unsafe {
+            assert_eq!(ratelimit_available(ratelimiter), 2);
+            assert_eq!(
+                ratelimit_try_wait_n(ratelimiter, 2, core::ptr::null_mut()),
+                RATELIMIT_OK
+            );
+
+            let mut wait_ns = 0;
+            assert_eq!(
+                ratelimit_try_wait(ratelimiter, &mut wait_ns),
+                RATELIMIT_INSUFFICIENT
+            );
+            assert!(wait_ns > 0 && wait_ns <= 1_000_000_000);
+
+            assert_eq!(
+                ratelimit_try_wait(core::ptr::null(), &mut wait_ns),
+                RATELIMIT_ERROR
+            );
+            assert_eq!(ratelimit_available(core::ptr::null()), 0);
+
+            ratelimit_destroy(ratelimiter);
+            ratelimit_destroy(core::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{advance_instant, Distribution, Ratelimiter};
+use clocksource::precise::Instant;
+
+impl Ratelimiter {
+    /// Returns the next `n` ideal firing times for operations at the current
+    /// rate, following the distribution of the ratelimiter. This allows an
+    /// open-loop load generator to pre-compute when work should be started and
+    /// to dispatch it to worker threads, rather than having each worker
+    /// contend on the ratelimiter for every operation.
+    ///
+    /// The times start from the next refill, or from now if that refill is
+    /// overdue. Tokens are spaced evenly at the rate, even when each refill
+    /// adds more than one token. With the uniform distribution the times are
+    /// multiples of the spacing, with any jitter applied to each gap. With the
+    /// Poisson distribution the gaps are exponentially distributed with a mean
+    /// of the spacing.
+    ///
+    /// This does not acquire any tokens.
+    ///
+    /// ```
+    /// use ratelimit::Ratelimiter;
+    /// use std::time::Duration;
+    ///
+    /// let ratelimiter = Ratelimiter::per_second(1000).build().unwrap();
+    ///
+    /// let times: Vec<_> = ratelimiter.schedule(3).collect();
+    /// assert_eq!((times[1] - times[0]).as_nanos(), 1_000_000);
+    /// assert_eq!((times[2] - times[1]).as_nanos(), 1_000_000);
+    /// ```
+    pub fn schedule(&self, n: usize) -> impl Iterator<Item = Instant> + '_ {
+        let start = self.next_refill().max(Instant::now());
+
+        let rate = self.rate();
+        let spacing = if rate > 0.0 {
+            1_000_000_000.0 / rate
+        } else {
+            f64::INFINITY
+        };
+
+        let mut next = start;
+        let distribution = self.distribution();
+        let jitter = self.jitter();
+
+        (0..n).map(move |index| {
+            if distribution == Distribution::Uniform && jitter == 0.0 {
+                // multiples of the spacing don't accumulate rounding errors
+                return offset(start, index as f64 * spacing);
+            }
+
+            let time = next;
+            let gap = match self
+                .extensions()
+                .filter(|_| distribution == Distribution::Poisson)
+            {
+                Some(extensions) => (spacing * extensions.random.exponential())
+                    .round()
+                    .min(u64::MAX as f64) as u64,
+                None => self.jittered(spacing.round().min(u64::MAX as f64) as u64),
+            };
+            next = advance_instant(next, 1, gap);
+            time
+        })
+    }
+}
+
+/// Internal function to return the instant which is `nanos` after `start`.
+fn offset(start: Instant, nanos: f64) -> Instant {
+    advance_instant(start, 1, nanos.round().min(u64::MAX as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use clocksource::precise::Instant;
+
+    #[test]
+    fn uniform() {
+        // each refill adds 4 tokens, which are spaced evenly
+        let rl = Ratelimiter::builder(4, core::time::Duration::from_millis(1))
+            .max_tokens(4)
+            .build()
+            .unwrap();
+
+        let now = Instant::now();
+        let times: Vec<Instant> = rl.schedule(100).collect();
+        assert_eq!(times.len(), 100);
+        assert!(times[0] >= now);
+        assert!(times
+            .windows(2)
+            .all(|w| (w[1] - w[0]).as_nanos() == 250_000));
+
+        // scheduling does not acquire tokens
+        assert_eq!(rl.available(), 0);
+    }
+
+    #[test]
+    fn poisson() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+            .distribution(Distribution::Poisson)
+            .build()
+            .unwrap();
+
+        let times: Vec<Instant> = rl.schedule(10_001).collect();
+        let gaps: Vec<u64> = times.windows(2).map(|w| (w[1] - w[0]).as_nanos()).collect();
+
+        let mean = gaps.iter().sum::<u64>() as f64 / gaps.len() as f64;
+        assert!((950_000.0..1_050_000.0).contains(&mean), "{mean}");
+        assert!(gaps.iter().any(|g| *g < 500_000));
+        assert!(gaps.iter().any(|g| *g > 2_000_000));
+    }
+}
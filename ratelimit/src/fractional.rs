@@ -0,0 +1,209 @@
+use crate::{Error, Rate, Ratelimiter};
+
+/// The default precision of a [`FractionalRatelimiter`], which tracks tokens
+/// in thousandths (millitokens).
+pub const DEFAULT_PRECISION: u64 = 1_000;
+
+/// A builder for a [`FractionalRatelimiter`].
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+///
+/// // one request every 10 minutes, shared across 4 endpoints
+/// let ratelimiter = Ratelimiter::fractional("1/10min".parse().unwrap())
+///     .max_tokens(1.0)
+///     .initial_available(1.0)
+///     .build()
+///     .unwrap();
+///
+/// for _ in 0..4 {
+///     assert!(ratelimiter.try_wait(0.25).is_ok());
+/// }
+/// assert!(ratelimiter.try_wait(0.25).is_err());
+/// ```
+pub struct FractionalBuilder {
+    initial_available: f64,
+    max_tokens: Option<f64>,
+    precision: u64,
+    rate: Rate,
+}
+
+impl FractionalBuilder {
+    /// Initialize a new builder for a ratelimiter which allows the `rate`.
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            initial_available: 0.0,
+            max_tokens: None,
+            precision: DEFAULT_PRECISION,
+            rate,
+        }
+    }
+
+    /// Set the number of units which make up a single token. Tokens, costs,
+    /// and the rate are all tracked in these units.
+    ///
+    /// The default is [`DEFAULT_PRECISION`], which tracks millitokens.
+    pub fn precision(mut self, units: u64) -> Self {
+        self.precision = units;
+        self
+    }
+
+    /// Set the max tokens that can be held at any time. See
+    /// [`crate::Builder::max_tokens`].
+    ///
+    /// The default is the number of tokens in the window of the rate.
+    pub fn max_tokens(mut self, tokens: f64) -> Self {
+        self.max_tokens = Some(tokens);
+        self
+    }
+
+    /// Set the number of tokens that are initially available. See
+    /// [`crate::Builder::initial_available`].
+    ///
+    /// The default is that no tokens are initially available.
+    pub fn initial_available(mut self, tokens: f64) -> Self {
+        self.initial_available = tokens;
+        self
+    }
+
+    /// Consumes this builder and attempts to construct a
+    /// `FractionalRatelimiter`.
+    pub fn build(self) -> Result<FractionalRatelimiter, Error> {
+        if self.precision == 0 {
+            return Err(Error::InvalidPrecision);
+        }
+
+        let units = Rate::new(
+            self.rate.tokens() * self.precision as f64,
+            self.rate.window(),
+        )?;
+        if units.tokens() < 1.0 {
+            return Err(Error::InvalidRate);
+        }
+
+        let mut builder = units
+            .builder()?
+            .initial_available(to_units(self.initial_available, self.precision));
+        if let Some(tokens) = self.max_tokens {
+            builder = builder.max_tokens(to_units(tokens, self.precision));
+        }
+
+        Ok(FractionalRatelimiter {
+            precision: self.precision,
+            ratelimiter: builder.build()?,
+        })
+    }
+}
+
+/// A ratelimiter which tracks tokens as fixed-point numbers, so that very low
+/// rates and costs of a fraction of a token can be represented without
+/// rounding them to zero or one token.
+///
+/// Each token is made up of a number of units, which is the precision. Costs
+/// are rounded up to a whole number of units, so a non-zero cost is never
+/// free.
+pub struct FractionalRatelimiter {
+    precision: u64,
+    ratelimiter: Ratelimiter,
+}
+
+impl FractionalRatelimiter {
+    /// Returns the number of units which make up a single token.
+    pub fn precision(&self) -> u64 {
+        self.precision
+    }
+
+    /// Returns the underlying ratelimiter, whose tokens are units.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        &self.ratelimiter
+    }
+
+    /// Returns the rate in tokens/s.
+    pub fn rate(&self) -> f64 {
+        self.ratelimiter.rate() / self.precision as f64
+    }
+
+    /// Returns the number of tokens currently available.
+    pub fn available(&self) -> f64 {
+        self.ratelimiter.available() as f64 / self.precision as f64
+    }
+
+    /// Returns the max tokens that can be held at any time.
+    pub fn max_tokens(&self) -> f64 {
+        self.ratelimiter.max_tokens() as f64 / self.precision as f64
+    }
+
+    /// Non-blocking function to "wait" for `cost` tokens, which may be a
+    /// fraction of a token. See [`Ratelimiter::try_wait_n`].
+    pub fn try_wait(&self, cost: f64) -> Result<(), core::time::Duration> {
+        self.ratelimiter.try_wait_n(to_units(cost, self.precision))
+    }
+
+    /// Non-blocking function to "wait" for a cost in units rather than
+    /// tokens. See [`Ratelimiter::try_wait_n`].
+    pub fn try_wait_units(&self, units: u64) -> Result<(), core::time::Duration> {
+        self.ratelimiter.try_wait_n(units)
+    }
+
+    /// Returns `cost` tokens which were acquired but not used. See
+    /// [`Ratelimiter::return_n`].
+    pub fn return_tokens(&self, cost: f64) {
+        self.ratelimiter.return_n(to_units(cost, self.precision));
+    }
+}
+
+impl Ratelimiter {
+    /// Initialize a builder for a [`FractionalRatelimiter`], which allows the
+    /// `rate` and tracks tokens in fractions of a token.
+    pub fn fractional(rate: Rate) -> FractionalBuilder {
+        FractionalBuilder::new(rate)
+    }
+}
+
+/// Internal function to convert a number of tokens into units, rounding up so
+/// that a non-zero number of tokens is never zero units.
+fn to_units(tokens: f64, precision: u64) -> u64 {
+    // negative and NaN costs saturate to zero
+    (tokens * precision as f64).ceil() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use clocksource::precise::Duration;
+
+    #[test]
+    fn low_rate() {
+        // a single token an hour, which is a millitoken every 3.6s
+        let rl = Ratelimiter::fractional("1/h".parse().unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(rl.precision(), 1_000);
+        assert_eq!(rl.max_tokens(), 1.0);
+        assert!((rl.rate() - 1.0 / 3600.0).abs() < 1e-12);
+
+        let inner = rl.ratelimiter();
+        inner
+            .refill(inner.created + Duration::from_secs(36))
+            .unwrap();
+        assert_eq!(rl.available(), 0.01);
+
+        // tiny costs are rounded up to a whole unit
+        assert!(rl.try_wait(0.0001).is_ok());
+        assert!(rl.try_wait_units(9).is_ok());
+        assert!(rl.try_wait(0.0001).is_err());
+
+        rl.return_tokens(0.005);
+        assert_eq!(rl.available(), 0.005);
+    }
+
+    #[test]
+    fn invalid() {
+        let rate: Rate = "1/h".parse().unwrap();
+        assert!(Ratelimiter::fractional(rate).precision(0).build().is_err());
+
+        // a rate below a single unit per window cannot be represented
+        let rate: Rate = "0.0001/h".parse().unwrap();
+        assert!(Ratelimiter::fractional(rate).build().is_err());
+    }
+}
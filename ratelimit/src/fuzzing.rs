@@ -0,0 +1,30 @@
+//! Hooks for the fuzz targets in `fuzz/`, which are only built with
+//! `--cfg fuzzing` as set by `cargo fuzz`. These run the refill and acquire
+//! paths at virtual times, so that the targets can make huge jumps in time
+//! without waiting for them.
+
+use crate::{advance_instant, Ratelimiter, TryAcquireError};
+use clocksource::precise::Instant;
+
+impl Ratelimiter {
+    /// Refill the bucket at `ns` nanoseconds after the ratelimiter was
+    /// created. See [`Ratelimiter::try_wait_n`] for the result.
+    #[doc(hidden)]
+    pub fn fuzz_refill(&self, ns: u64) -> Result<(), core::time::Duration> {
+        self.refill(self.fuzz_time(ns))
+    }
+
+    /// Acquire `n` tokens at `ns` nanoseconds after the ratelimiter was
+    /// created.
+    #[doc(hidden)]
+    pub fn fuzz_try_acquire_n(&self, n: u64, ns: u64) -> Result<(), TryAcquireError> {
+        let time = self.fuzz_time(ns);
+        self.take_at(n, 0, || time)
+    }
+
+    /// Internal function to return the virtual time `ns` nanoseconds after
+    /// the ratelimiter was created, saturating at the latest instant.
+    fn fuzz_time(&self, ns: u64) -> Instant {
+        advance_instant(self.created, 1, ns)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::{Builder, Ratelimiter};
+use std::sync::Arc;
+
+// the smallest factor which is applied, so that the refill interval remains
+// representable
+const MIN_FACTOR: f64 = 1e-6;
+
+/// A source of a multiplier which is applied to the rate of a ratelimiter,
+/// for instance to shed load based on CPU utilization or queue depth. The
+/// factor is read lazily when the bucket is refilled, so no background thread
+/// is required. See [`Builder::gate`].
+///
+/// This is implemented for closures, which makes it easy to read the factor
+/// from a `tokio::sync::watch` channel or an existing metric:
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let (tx, rx) = tokio::sync::watch::channel(1.0);
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+///     .gate(move || *rx.borrow())
+///     .build()
+///     .unwrap();
+///
+/// // halve the rate while the service is overloaded
+/// tx.send(0.5).unwrap();
+/// ```
+pub trait Gate: Send + Sync {
+    /// Returns the multiplier to apply to the rate. A factor of `1.0` leaves
+    /// the rate unchanged, while a factor of zero effectively stops refills.
+    /// Factors below one in a million are treated as one in a million and
+    /// non-finite factors are ignored.
+    fn factor(&self) -> f64;
+}
+
+impl<F> Gate for F
+where
+    F: Fn() -> f64 + Send + Sync,
+{
+    fn factor(&self) -> f64 {
+        self()
+    }
+}
+
+/// A [`Gate`] with a factor which is set directly. Clones share the same
+/// factor, so one clone can be given to the ratelimiter while another is
+/// updated by whatever monitors the load.
+#[derive(Clone, Debug)]
+pub struct GateFactor {
+    // the bits of the factor as an `f64`
+    bits: Arc<AtomicU64>,
+}
+
+impl GateFactor {
+    /// Create a gate with the provided initial factor.
+    pub fn new(factor: f64) -> Self {
+        Self {
+            bits: Arc::new(AtomicU64::new(factor.to_bits())),
+        }
+    }
+
+    /// Returns the current factor.
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    /// Change the factor. This takes effect at the next refill.
+    pub fn set(&self, factor: f64) {
+        self.bits.store(factor.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Default for GateFactor {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl Gate for GateFactor {
+    fn factor(&self) -> f64 {
+        self.get()
+    }
+}
+
+impl Builder {
+    /// Multiply the rate by a factor supplied by a [`Gate`]. The gate is
+    /// applied in addition to any scale set with [`Ratelimiter::set_scale`].
+    pub fn gate(mut self, gate: impl Gate + 'static) -> Self {
+        self.gate = Some(Box::new(gate));
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the factor from the gate which is currently applied to the
+    /// rate. This is `1.0` if there is no gate.
+    pub fn gate_factor(&self) -> f64 {
+        self.parameters.read().gate
+    }
+
+    /// Internal function to read the factor from the gate, if any, and apply
+    /// it to the rate if it has changed. Called before each refill.
+    pub(crate) fn update_gate(&self) {
+        let Some(gate) = self.extensions().and_then(|e| e.gate.as_ref()) else {
+            return;
+        };
+
+        let factor = gate.factor();
+        if factor.is_nan() || factor == f64::INFINITY {
+            return;
+        }
+        let factor = factor.max(MIN_FACTOR);
+
+        if self.parameters.read().gate == factor {
+            return;
+        }
+
+        let mut parameters = self.parameters.write();
+        parameters.gate = factor;
+        parameters.rescale();
+        drop(parameters);
+
+        self.notify_parameters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn gate() {
+        let factor = GateFactor::default();
+
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .gate(factor.clone())
+            .build()
+            .unwrap();
+        rl.set_scale(2.0).unwrap();
+        assert_eq!(rl.gate_factor(), 1.0);
+
+        // the factor is applied lazily when the bucket is refilled, on top of
+        // the scale
+        factor.set(0.25);
+        assert_eq!(rl.gate_factor(), 1.0);
+        std::thread::sleep(Duration::from_millis(1));
+        let _ = rl.try_acquire();
+        assert_eq!(rl.gate_factor(), 0.25);
+        assert!((rl.rate() - 500.0).abs() < 1.0, "{}", rl.rate());
+
+        // invalid factors are ignored or clamped
+        factor.set(f64::NAN);
+        let _ = rl.try_acquire();
+        assert_eq!(rl.gate_factor(), 0.25);
+
+        factor.set(-1.0);
+        std::thread::sleep(Duration::from_millis(2));
+        let _ = rl.try_acquire();
+        assert_eq!(rl.gate_factor(), 1e-6);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::retry_after::ceil_seconds;
+use crate::{system_now, Ratelimiter};
+use core::time::Duration;
+use std::time::UNIX_EPOCH;
+
+// header names are lowercase so they can be used with `HeaderName::from_static`
+const RATELIMIT_LIMIT: &str = "ratelimit-limit";
+const RATELIMIT_REMAINING: &str = "ratelimit-remaining";
+const RATELIMIT_RESET: &str = "ratelimit-reset";
+
+/// The values for the headers which tell a client about its rate limit, from
+/// [`Ratelimiter::headers`]. These are the standard `RateLimit-Limit`,
+/// `RateLimit-Remaining`, and `RateLimit-Reset` headers, as well as the legacy
+/// `X-RateLimit-*` headers which some clients still expect.
+///
+/// For a [`KeyedRatelimiter`](crate::KeyedRatelimiter), use the headers of the
+/// bucket for the key of the request.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1500))
+///     .max_tokens(10)
+///     .initial_available(10)
+///     .build()
+///     .unwrap();
+///
+/// ratelimiter.try_acquire().unwrap();
+///
+/// let headers = ratelimiter.headers();
+/// assert_eq!(
+///     headers.standard(),
+///     [
+///         ("ratelimit-limit", "10".to_string()),
+///         ("ratelimit-remaining", "9".to_string()),
+///         ("ratelimit-reset", "2".to_string()),
+///     ]
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitHeaders {
+    /// The maximum number of tokens.
+    pub limit: u64,
+    /// The number of tokens available.
+    pub remaining: u64,
+    /// The time until the bucket is full again.
+    pub reset: Duration,
+}
+
+impl RateLimitHeaders {
+    /// Returns the time until the bucket is full in whole seconds. This is
+    /// rounded up, so that a client which waits this long is never early.
+    pub fn reset_seconds(&self) -> u64 {
+        ceil_seconds(self.reset)
+    }
+
+    /// Returns the names and values of the standard `RateLimit-Limit`,
+    /// `RateLimit-Remaining`, and `RateLimit-Reset` headers. The reset is the
+    /// number of seconds until the bucket is full.
+    pub fn standard(&self) -> [(&'static str, String); 3] {
+        [
+            (RATELIMIT_LIMIT, self.limit.to_string()),
+            (RATELIMIT_REMAINING, self.remaining.to_string()),
+            (RATELIMIT_RESET, self.reset_seconds().to_string()),
+        ]
+    }
+
+    /// Returns the names and values of the legacy `X-RateLimit-Limit`,
+    /// `X-RateLimit-Remaining`, and `X-RateLimit-Reset` headers. Following the
+    /// usual convention for these headers, the reset is the unix time in
+    /// seconds at which the bucket is full, rounded up.
+    pub fn legacy(&self) -> [(&'static str, String); 3] {
+        let reset = system_now().duration_since(UNIX_EPOCH).unwrap_or_default() + self.reset;
+
+        [
+            ("x-ratelimit-limit", self.limit.to_string()),
+            ("x-ratelimit-remaining", self.remaining.to_string()),
+            ("x-ratelimit-reset", ceil_seconds(reset).to_string()),
+        ]
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the values for the rate limit headers of a response. See
+    /// [`RateLimitHeaders`] for details.
+    ///
+    /// The reset is the time until enough refills have happened to fill the
+    /// bucket, assuming no further tokens are acquired.
+    pub fn headers(&self) -> RateLimitHeaders {
+        let limit = self.max_tokens();
+
+        RateLimitHeaders {
+            limit,
+            remaining: self.available().min(limit),
+            reset: self.time_until_available(limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn headers() {
+        let rl = Ratelimiter::builder(2, Duration::from_secs(10))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        let headers = rl.headers();
+        assert_eq!(headers.reset, Duration::ZERO);
+        assert_eq!(headers.standard()[2].1, "0");
+
+        // five tokens need three refills of two, the first of which is due in
+        // just under ten seconds
+        rl.try_acquire_n(5).unwrap();
+        let headers = rl.headers();
+        assert_eq!(headers.remaining, 5);
+        assert!(headers.reset > Duration::from_secs(29));
+        assert!(headers.reset <= Duration::from_secs(30));
+        assert_eq!(headers.reset_seconds(), 30);
+
+        let legacy = headers.legacy();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let reset: u64 = legacy[2].1.parse().unwrap();
+        assert!(reset >= now + 30 && reset <= now + 31);
+        assert_eq!(legacy[1], ("x-ratelimit-remaining", "5".to_string()));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::{system_now, Builder, LatencySnapshot, Ratelimiter};
+use clocksource::precise::Instant;
+use core::time::Duration;
+use histogram::AtomicHistogram;
+use std::time::SystemTime;
+
+// the histogram for each slice records nanoseconds with a relative error of
+// about 25%, which keeps long spans small
+const GROUPING_POWER: u8 = 2;
+const MAX_VALUE_POWER: u8 = 64;
+
+/// The configuration for the heatmap, see [`Builder::heatmap`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct HeatmapConfig {
+    resolution: Duration,
+    span: Duration,
+}
+
+impl HeatmapConfig {
+    /// Returns `true` if the configuration is valid.
+    pub(crate) fn is_valid(&self) -> bool {
+        !self.resolution.is_zero() && self.resolution <= self.span
+    }
+}
+
+/// Internal type which holds the counters for one slice of time.
+struct Slot {
+    acquired: AtomicU64,
+    denied: AtomicU64,
+    // the index of the slice which this slot currently holds, offset by one
+    // so that zero marks an unused slot
+    index: AtomicU64,
+    waits: AtomicHistogram,
+}
+
+/// Internal type which holds a ring of slots, each of which covers one slice
+/// of time. Slots are reused lazily when a later slice is recorded.
+pub(crate) struct Timeline {
+    created: Instant,
+    resolution: Duration,
+    slots: Box<[Slot]>,
+}
+
+impl Timeline {
+    pub(crate) fn new(config: HeatmapConfig, created: Instant) -> Self {
+        let len = config
+            .span
+            .as_nanos()
+            .div_ceil(config.resolution.as_nanos()) as usize;
+
+        let slots = (0..len)
+            .map(|_| Slot {
+                acquired: AtomicU64::new(0),
+                denied: AtomicU64::new(0),
+                index: AtomicU64::new(0),
+                waits: AtomicHistogram::new(GROUPING_POWER, MAX_VALUE_POWER).unwrap(),
+            })
+            .collect();
+
+        Self {
+            created,
+            resolution: config.resolution,
+            slots,
+        }
+    }
+
+    /// Returns the number of bytes allocated for the slots and their
+    /// histograms.
+    pub(crate) fn heap_size(&self) -> usize {
+        let buckets = crate::latency::histogram_buckets(GROUPING_POWER, MAX_VALUE_POWER);
+        self.slots.len() * (core::mem::size_of::<Slot>() + buckets * core::mem::size_of::<u64>())
+    }
+
+    /// Internal function to return the index of the slice for a time.
+    fn index(&self, time: Instant) -> u64 {
+        let elapsed = time
+            .checked_duration_since(self.created)
+            .unwrap_or_default()
+            .as_nanos();
+
+        elapsed / self.resolution.as_nanos() as u64 + 1
+    }
+
+    /// Record the outcome of an attempt to acquire tokens.
+    fn record(&self, time: Instant, wait: Option<Duration>) {
+        let index = self.index(time);
+        let slot = &self.slots[index as usize % self.slots.len()];
+
+        let previous = slot.index.load(Ordering::Acquire);
+        if previous < index
+            && slot
+                .index
+                .compare_exchange(previous, index, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            // the slot held an earlier slice, so it is cleared before use.
+            // Attempts recorded by other threads while the slot is cleared may
+            // be lost, which is acceptable for a heatmap.
+            slot.acquired.store(0, Ordering::Relaxed);
+            slot.denied.store(0, Ordering::Relaxed);
+            let _ = slot.waits.drain();
+        } else if previous > index {
+            // the slot has already moved on to a later slice
+            return;
+        }
+
+        match wait {
+            Some(wait) => {
+                if wait.is_zero() {
+                    slot.acquired.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    slot.denied.fetch_add(1, Ordering::Relaxed);
+                }
+
+                let _ = slot
+                    .waits
+                    .increment(wait.as_nanos().min(u64::MAX as u128) as u64);
+            }
+            None => {
+                slot.denied.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Capture the slices which are within the span, oldest first.
+    fn snapshot(&self) -> Heatmap {
+        let now = Instant::now();
+        let system_now = system_now();
+        let current = self.index(now);
+        let oldest = current.saturating_sub(self.slots.len() as u64 - 1);
+
+        let mut slots: Vec<(u64, &Slot)> = self
+            .slots
+            .iter()
+            .map(|slot| (slot.index.load(Ordering::Acquire), slot))
+            .filter(|(index, _)| *index >= oldest.max(1) && *index <= current)
+            .collect();
+        slots.sort_by_key(|(index, _)| *index);
+
+        let slices = slots
+            .into_iter()
+            .map(|(index, slot)| {
+                let offset = self.resolution.as_nanos() as u64 * (index - 1);
+                let age =
+                    Duration::from_nanos((now - self.created).as_nanos().saturating_sub(offset));
+
+                Slice {
+                    acquired: slot.acquired.load(Ordering::Relaxed),
+                    denied: slot.denied.load(Ordering::Relaxed),
+                    start: system_now.checked_sub(age).unwrap_or(system_now),
+                    waits: LatencySnapshot {
+                        histogram: slot.waits.load(),
+                    },
+                }
+            })
+            .collect();
+
+        Heatmap {
+            resolution: self.resolution,
+            slices,
+        }
+    }
+}
+
+/// The behavior of a `Ratelimiter` over a recent span of time, divided into
+/// slices. This can answer questions such as when throttling spiked, which a
+/// cumulative counter can't. See [`Builder::heatmap`].
+///
+/// Slices in which nothing was recorded are omitted.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .max_tokens(2)
+///     .initial_available(2)
+///     .heatmap(Duration::from_secs(300), Duration::from_secs(1))
+///     .build()
+///     .unwrap();
+///
+/// for _ in 0..3 {
+///     let _ = ratelimiter.try_wait();
+/// }
+///
+/// let heatmap = ratelimiter.heatmap().unwrap();
+/// let slice = &heatmap.slices()[0];
+/// assert_eq!((slice.acquired, slice.denied), (2, 1));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    resolution: Duration,
+    slices: Vec<Slice>,
+}
+
+impl Heatmap {
+    /// Returns the length of time covered by each slice.
+    pub fn resolution(&self) -> Duration {
+        self.resolution
+    }
+
+    /// Returns the slices in which something was recorded, oldest first.
+    pub fn slices(&self) -> &[Slice] {
+        &self.slices
+    }
+}
+
+/// The attempts to acquire tokens during one slice of a [`Heatmap`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slice {
+    /// The number of successful acquisitions.
+    pub acquired: u64,
+    /// The number of attempts to acquire tokens which failed.
+    pub denied: u64,
+    /// The time at which the slice started.
+    pub start: SystemTime,
+    /// How long the callers would have had to wait for tokens, see
+    /// [`Builder::latency_histogram`].
+    pub waits: LatencySnapshot,
+}
+
+impl Builder {
+    /// Keep a rolling record of acquisitions, denials, and wait times over the
+    /// last `span`, divided into slices of the provided resolution. The record
+    /// can be read with [`Ratelimiter::heatmap`].
+    ///
+    /// The resolution must be greater than zero and no longer than the span.
+    /// Memory is held for each slice, so spans with very many slices should be
+    /// avoided.
+    pub fn heatmap(mut self, span: Duration, resolution: Duration) -> Self {
+        self.heatmap = Some(HeatmapConfig { resolution, span });
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Capture the heatmap. Returns `None` unless it was enabled with
+    /// [`Builder::heatmap`].
+    pub fn heatmap(&self) -> Option<Heatmap> {
+        self.extensions()
+            .and_then(|e| e.heatmap.as_ref())
+            .map(|timeline| timeline.snapshot())
+    }
+
+    /// Internal function to record an attempt to acquire tokens in the
+    /// heatmap. The wait is `None` if the attempt failed for a reason other
+    /// than insufficient tokens.
+    pub(crate) fn record_heatmap(&self, wait: Option<Duration>) {
+        if let Some(timeline) = self.extensions().and_then(|e| e.heatmap.as_ref()) {
+            timeline.record(Instant::now(), wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn slices() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(2)
+            .initial_available(2)
+            .heatmap(Duration::from_millis(40), Duration::from_millis(10))
+            .build()
+            .unwrap();
+
+        let _ = rl.try_acquire();
+        let _ = rl.try_acquire();
+        std::thread::sleep(Duration::from_millis(10));
+        let _ = rl.try_acquire();
+        rl.close();
+        let _ = rl.try_acquire();
+
+        let heatmap = rl.heatmap().unwrap();
+        assert_eq!(heatmap.resolution(), Duration::from_millis(10));
+
+        let slices = heatmap.slices();
+        assert_eq!(slices.len(), 2);
+        assert_eq!((slices[0].acquired, slices[0].denied), (2, 0));
+        assert_eq!((slices[1].acquired, slices[1].denied), (0, 2));
+        assert!(slices[0].start < slices[1].start);
+        assert!(slices[1].waits.percentile(100.0).unwrap() > Duration::from_secs(30));
+
+        // slices older than the span are dropped
+        std::thread::sleep(Duration::from_millis(50));
+        let _ = rl.try_acquire();
+        assert_eq!(rl.heatmap().unwrap().slices().len(), 1);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            Ratelimiter::builder(1, Duration::from_secs(1))
+                .heatmap(Duration::from_secs(1), Duration::from_secs(2))
+                .build()
+                .err(),
+            Some(Error::InvalidHeatmap)
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::KeyedRatelimiter;
+use core::borrow::Borrow;
+use core::hash::Hash;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+
+/// A key which is among the most frequent, from [`KeyedRatelimiter::hot_keys`].
+///
+/// Counts are approximate once there are more distinct keys than are tracked.
+/// The count is then an upper bound, which overestimates the true count by at
+/// most the error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HotKey<K> {
+    /// The key.
+    pub key: K,
+    /// The number of acquisitions or denials for the key.
+    pub count: u64,
+    /// The most by which the count may overestimate the true count.
+    pub error: u64,
+}
+
+/// The most frequently acquired and denied keys of a `KeyedRatelimiter`, from
+/// [`KeyedRatelimiter::hot_keys`]. Each list is ordered from the most to the
+/// least frequent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HotKeys<K> {
+    /// The keys with the most successful acquisitions.
+    pub acquired: Vec<HotKey<K>>,
+    /// The keys with the most denied acquisitions.
+    pub denied: Vec<HotKey<K>>,
+}
+
+/// Internal type which tracks the most frequent keys in bounded memory using
+/// the space-saving algorithm. When a new key arrives and all slots are used,
+/// it replaces the least frequent key and inherits its count as its error.
+pub(crate) struct SpaceSaving<K> {
+    capacity: usize,
+    counts: HashMap<K, (u64, u64)>,
+}
+
+impl<K: Hash + Eq + Clone> SpaceSaving<K> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn record<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some((count, _)) = self.counts.get_mut(key) {
+            *count += 1;
+            return;
+        }
+
+        if self.counts.len() < self.capacity {
+            self.counts.insert(key.to_owned(), (1, 0));
+            return;
+        }
+
+        // evict the least frequent key, which is a linear scan since only a
+        // small number of keys are tracked
+        let Some((min_key, min_count)) = self
+            .counts
+            .iter()
+            .min_by_key(|(_, (count, _))| *count)
+            .map(|(key, (count, _))| (key.clone(), *count))
+        else {
+            return;
+        };
+
+        self.counts.remove::<K>(&min_key);
+        self.counts
+            .insert(key.to_owned(), (min_count + 1, min_count));
+    }
+
+    pub(crate) fn top(&self) -> Vec<HotKey<K>> {
+        let mut top: Vec<HotKey<K>> = self
+            .counts
+            .iter()
+            .map(|(key, (count, error))| HotKey {
+                key: key.clone(),
+                count: *count,
+                error: *error,
+            })
+            .collect();
+
+        top.sort_by(|a, b| b.count.cmp(&a.count).then(a.error.cmp(&b.error)));
+        top
+    }
+}
+
+/// Internal type which tracks the hot keys for acquisitions and denials.
+pub(crate) struct HotKeyTracker<K> {
+    acquired: SpaceSaving<K>,
+    denied: SpaceSaving<K>,
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync> KeyedRatelimiter<K> {
+    /// Track the `k` keys with the most acquisitions and the `k` keys with the
+    /// most denials, so that operators can see which clients are being
+    /// throttled. See [`KeyedRatelimiter::hot_keys`].
+    ///
+    /// Memory use is bounded by `k`, regardless of the number of keys, and
+    /// the counts outlive any eviction of idle buckets.
+    ///
+    /// ```
+    /// use ratelimit::{KeyedRatelimiter, RatelimiterConfig};
+    ///
+    /// let config = RatelimiterConfig::new("1/s".parse().unwrap()).initial_available(1);
+    /// let ratelimiter: KeyedRatelimiter = KeyedRatelimiter::from_config(config)
+    ///     .unwrap()
+    ///     .track_hot_keys(10);
+    ///
+    /// for _ in 0..5 {
+    ///     let _ = ratelimiter.try_wait("alice");
+    /// }
+    /// let _ = ratelimiter.try_wait("bob");
+    ///
+    /// let hot_keys = ratelimiter.hot_keys();
+    /// assert_eq!(hot_keys.denied[0].key, "alice");
+    /// assert_eq!(hot_keys.denied[0].count, 4);
+    /// assert_eq!(hot_keys.acquired.len(), 2);
+    /// ```
+    pub fn track_hot_keys(mut self, k: usize) -> Self {
+        self.hot_keys = Some(Mutex::new(HotKeyTracker {
+            acquired: SpaceSaving::new(k),
+            denied: SpaceSaving::new(k),
+        }));
+        self
+    }
+
+    /// Returns the most frequently acquired and denied keys. The lists are
+    /// empty unless tracking is enabled with
+    /// [`KeyedRatelimiter::track_hot_keys`].
+    ///
+    /// Acquisitions are counted for the `try_*` functions of the keyed
+    /// ratelimiter and for the middlewares built on it.
+    pub fn hot_keys(&self) -> HotKeys<K> {
+        match &self.hot_keys {
+            Some(tracker) => {
+                let tracker = tracker.lock();
+                HotKeys {
+                    acquired: tracker.acquired.top(),
+                    denied: tracker.denied.top(),
+                }
+            }
+            None => HotKeys {
+                acquired: Vec::new(),
+                denied: Vec::new(),
+            },
+        }
+    }
+
+    /// Internal function to count the outcome of an acquisition for a key, if
+    /// hot keys are tracked.
+    pub(crate) fn record_hot_key<Q>(&self, key: &Q, acquired: bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(tracker) = &self.hot_keys {
+            let mut tracker = tracker.lock();
+            if acquired {
+                tracker.acquired.record(key);
+            } else {
+                tracker.denied.record(key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn space_saving() {
+        let mut tracker = SpaceSaving::<u64>::new(2);
+
+        for key in [1, 1, 1, 2, 2, 3] {
+            tracker.record(&key);
+        }
+
+        // 3 replaced 2, the least frequent key, and inherited its count
+        assert_eq!(
+            tracker.top(),
+            vec![
+                HotKey {
+                    key: 1,
+                    count: 3,
+                    error: 0
+                },
+                HotKey {
+                    key: 3,
+                    count: 3,
+                    error: 2
+                },
+            ]
+        );
+
+        assert!(SpaceSaving::<u64>::new(0).top().is_empty());
+    }
+}
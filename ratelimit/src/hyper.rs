@@ -0,0 +1,289 @@
+//! Admission control for services built directly on [`hyper`](::hyper).
+//!
+//! [`RateLimit`] wraps a hyper [`Service`] and charges a ratelimiter with a
+//! single token before each request reaches the inner service. Requests which
+//! are over the limit are answered with `429 Too Many Requests` by default, or
+//! can be queued until a token is available, or answered with a custom
+//! response. Each request costs a single token unless a cost extractor is
+//! provided with [`RateLimit::cost_with`].
+//!
+//! ```
+//! use hyper::service::Service;
+//! use hyper::{Request, Response};
+//! use ratelimit::hyper::RateLimit;
+//! use ratelimit::Ratelimiter;
+//! use std::convert::Infallible;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! # #[derive(Clone)]
+//! # struct Hello;
+//! # impl Service<Request<String>> for Hello {
+//! #     type Response = Response<String>;
+//! #     type Error = Infallible;
+//! #     type Future = std::future::Ready<Result<Response<String>, Infallible>>;
+//! #     fn call(&self, _: Request<String>) -> Self::Future {
+//! #         std::future::ready(Ok(Response::new("hello".to_string())))
+//! #     }
+//! # }
+//! let ratelimiter = Arc::new(Ratelimiter::builder(1, Duration::from_millis(10)).build().unwrap());
+//!
+//! let service = RateLimit::new(Hello, ratelimiter)
+//!     .deny_with(|retry_after| {
+//!         Response::builder()
+//!             .status(503)
+//!             .body(format!("retry in {retry_after:?}"))
+//!             .unwrap()
+//!     });
+//! ```
+
+use crate::{CostExtractor, Ratelimiter, RetryAfter, TryAcquireError, UnitCost};
+use ::hyper::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use ::hyper::http::request::Parts;
+use ::hyper::service::Service;
+use ::hyper::{Request, Response, StatusCode};
+use core::future::Future;
+use core::pin::Pin;
+use core::time::Duration;
+use std::sync::Arc;
+
+type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+type Deny<B> = dyn Fn(Duration) -> Response<B> + Send + Sync;
+
+/// A service which performs admission control with a ratelimiter before
+/// passing requests to the inner service.
+pub struct RateLimit<S, B> {
+    cost: Arc<dyn CostExtractor<Parts>>,
+    deny: Arc<Deny<B>>,
+    inner: Arc<S>,
+    queue: bool,
+    ratelimiter: Arc<Ratelimiter>,
+}
+
+impl<S, B> Clone for RateLimit<S, B> {
+    fn clone(&self) -> Self {
+        Self {
+            cost: self.cost.clone(),
+            deny: self.deny.clone(),
+            inner: self.inner.clone(),
+            queue: self.queue,
+            ratelimiter: self.ratelimiter.clone(),
+        }
+    }
+}
+
+impl<S, B: Default + 'static> RateLimit<S, B> {
+    /// Wrap the inner service with the provided ratelimiter. Requests which
+    /// are over the limit are answered with `429 Too Many Requests` and a
+    /// `Retry-After` header. Denied requests, including those answered with a
+    /// custom response, also carry the `RateLimit-*` headers.
+    pub fn new(inner: S, ratelimiter: Arc<Ratelimiter>) -> Self {
+        Self {
+            cost: Arc::new(UnitCost),
+            deny: Arc::new(too_many_requests),
+            inner: Arc::new(inner),
+            queue: false,
+            ratelimiter,
+        }
+    }
+}
+
+impl<S, B> RateLimit<S, B> {
+    /// Use the provided function to build the response for requests which are
+    /// over the limit. The function is provided with the time until the tokens
+    /// for the request would be available.
+    pub fn deny_with(
+        mut self,
+        deny: impl Fn(Duration) -> Response<B> + Send + Sync + 'static,
+    ) -> Self {
+        self.deny = Arc::new(deny);
+        self
+    }
+
+    /// Charge each request the number of tokens returned by the cost
+    /// extractor, which is provided with the head of the request. Costs above
+    /// the maximum number of tokens are charged as the maximum.
+    pub fn cost_with(mut self, cost: impl CostExtractor<Parts> + 'static) -> Self {
+        self.cost = Arc::new(cost);
+        self
+    }
+
+    /// Queue requests which are over the limit until a token is available,
+    /// instead of answering them immediately. Requests are only denied if the
+    /// ratelimiter is closed or denying all requests.
+    ///
+    /// Note: the queue is unbounded, so this should be combined with a limit
+    /// on the number of concurrent requests.
+    pub fn queue(mut self) -> Self {
+        self.queue = true;
+        self
+    }
+
+    /// Returns the ratelimiter which is charged by this service.
+    pub fn ratelimiter(&self) -> &Arc<Ratelimiter> {
+        &self.ratelimiter
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Internal function to build the response for a request which is over
+    /// the limit, with the rate limit headers for the ratelimiter.
+    fn deny(&self, retry_after: Duration) -> Response<B> {
+        let mut response = (self.deny)(retry_after);
+
+        for (name, value) in self.ratelimiter.headers().standard() {
+            if let Ok(value) = HeaderValue::try_from(value) {
+                response
+                    .headers_mut()
+                    .insert(HeaderName::from_static(name), value);
+            }
+        }
+
+        response
+    }
+}
+
+/// Internal function to build the default response for a request which is
+/// over the limit. The `Retry-After` header is rounded up to whole seconds.
+fn too_many_requests<B: Default>(retry_after: Duration) -> Response<B> {
+    let seconds = RetryAfter::new(retry_after).seconds();
+
+    let mut response = Response::new(B::default());
+    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    response.headers_mut().insert(RETRY_AFTER, seconds.into());
+    response
+}
+
+impl<S, R, B> Service<Request<R>> for RateLimit<S, B>
+where
+    S: Service<Request<R>, Response = Response<B>> + Send + Sync + 'static,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+    R: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response<B>;
+    type Error = S::Error;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn call(&self, request: Request<R>) -> Self::Future {
+        let (parts, body) = request.into_parts();
+        let cost = self.ratelimiter.charge(self.cost.cost(&parts));
+        let request = Request::from_parts(parts, body);
+
+        let retry_after = match self.ratelimiter.try_acquire_n(cost) {
+            Ok(()) => {
+                let future = self.inner.call(request);
+                return Box::pin(future);
+            }
+            // the hint is for the next refill, which may not cover the cost
+            Err(TryAcquireError::Insufficient(hint)) => {
+                hint.max(self.ratelimiter.retry_after(cost).delay())
+            }
+            Err(_) => self.ratelimiter.scaled_interval(),
+        };
+
+        if !self.queue {
+            let response = self.deny(retry_after);
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let this = self.clone();
+
+        Box::pin(async move {
+            match crate::sleep::acquire_n(&this.ratelimiter, cost).await {
+                Ok(()) => this.inner.call(request).await,
+                Err(_) => Ok(this.deny(this.ratelimiter.scaled_interval())),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::time::Instant;
+
+    struct Echo;
+
+    impl Service<Request<String>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = core::future::Ready<Result<Response<String>, Infallible>>;
+
+        fn call(&self, request: Request<String>) -> Self::Future {
+            core::future::ready(Ok(Response::new(request.into_body())))
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    fn request() -> Request<String> {
+        Request::new("hello".to_string())
+    }
+
+    #[test]
+    fn deny() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .initial_available(1)
+                .build()
+                .unwrap(),
+        );
+        let service = RateLimit::new(Echo, ratelimiter.clone());
+
+        runtime().block_on(async {
+            let response = service.call(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body(), "hello");
+
+            let response = service.call(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers()[RETRY_AFTER], "60");
+            assert_eq!(response.headers()["ratelimit-limit"], "1");
+
+            let service = service.deny_with(|_| {
+                Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .body("busy".to_string())
+                    .unwrap()
+            });
+            let response = service.call(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(response.body(), "busy");
+        });
+    }
+
+    #[test]
+    fn queue() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+        let service = RateLimit::new(Echo, ratelimiter.clone()).queue();
+
+        runtime().block_on(async {
+            let start = Instant::now();
+            for _ in 0..3 {
+                let response = service.call(request()).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+            }
+            assert!(start.elapsed() >= Duration::from_millis(20));
+
+            // a closed ratelimiter denies queued requests
+            ratelimiter.close();
+            let response = service.call(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        });
+    }
+}
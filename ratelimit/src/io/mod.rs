@@ -0,0 +1,270 @@
+//! Wrappers which limit the bandwidth of readers and writers.
+//!
+//! Each byte costs a single token, so the rate of the ratelimiter is the
+//! bandwidth in bytes. Reads and writes are split into chunks of at most
+//! [`DEFAULT_CHUNK_SIZE`] bytes (or the configured chunk size) so that large
+//! buffers don't require an equally large burst. Chunks are also limited to the
+//! maximum number of tokens in the ratelimiter.
+//!
+//! With the `tokio-io` feature, `AsyncThrottledReader` and
+//! `AsyncThrottledWriter` provide the same limits for tokio's `AsyncRead`
+//! and `AsyncWrite`, waking the task once the tokens for the next chunk have
+//! been refilled.
+//!
+//! ```
+//! use ratelimit::io::ThrottledReader;
+//! use ratelimit::Ratelimiter;
+//! use std::io::Read;
+//! use std::time::Duration;
+//!
+//! // 1 MiB/s with a burst of 64 KiB
+//! let ratelimiter = Ratelimiter::builder(1024, Duration::from_millis(1))
+//!     .max_tokens(64 * 1024)
+//!     .initial_available(64 * 1024)
+//!     .build()
+//!     .unwrap();
+//!
+//! let mut reader = ThrottledReader::new(&[0_u8; 1024][..], &ratelimiter);
+//! let mut buf = Vec::new();
+//! reader.read_to_end(&mut buf).unwrap();
+//! assert_eq!(buf.len(), 1024);
+//! ```
+
+use crate::Ratelimiter;
+use core::borrow::Borrow;
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "tokio-io")]
+mod tokio;
+
+#[cfg(feature = "tokio-io")]
+pub use self::tokio::{AsyncThrottledReader, AsyncThrottledWriter};
+
+/// The default maximum number of bytes which are read or written at once.
+pub const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+/// A reader which limits the rate at which bytes are read from the inner
+/// reader.
+///
+/// If the ratelimiter is denying all requests or is closed, reads fail with an
+/// error which wraps a [`TryAcquireError`](crate::TryAcquireError).
+pub struct ThrottledReader<R, L> {
+    inner: R,
+    throttle: Throttle<L>,
+}
+
+/// A writer which limits the rate at which bytes are written to the inner
+/// writer.
+///
+/// If the ratelimiter is denying all requests or is closed, writes fail with
+/// an error which wraps a [`TryAcquireError`](crate::TryAcquireError).
+pub struct ThrottledWriter<W, L> {
+    inner: W,
+    throttle: Throttle<L>,
+}
+
+/// Internal type which charges the ratelimiter for each chunk of a transfer.
+struct Throttle<L> {
+    chunk_size: usize,
+    ratelimiter: L,
+}
+
+impl<L: Borrow<Ratelimiter>> Throttle<L> {
+    fn new(ratelimiter: L) -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            ratelimiter,
+        }
+    }
+
+    /// Returns the size of the next chunk of a buffer with the provided length.
+    fn chunk(&self, len: usize) -> usize {
+        let max = usize::try_from(self.ratelimiter.borrow().max_tokens()).unwrap_or(usize::MAX);
+        len.min(self.chunk_size).min(max)
+    }
+
+    /// Block until the tokens for the next chunk of a buffer with the provided
+    /// length are acquired. Returns the size of the chunk.
+    fn acquire(&self, len: usize) -> io::Result<usize> {
+        let chunk = self.chunk(len);
+
+        crate::sleep::wait_n(self.ratelimiter.borrow(), chunk as u64).map_err(io::Error::other)?;
+
+        Ok(chunk)
+    }
+
+    /// Return the tokens for the bytes of a chunk which were not transferred.
+    fn refund(&self, chunk: usize, result: &io::Result<usize>) {
+        let transferred = *result.as_ref().unwrap_or(&0);
+
+        if transferred < chunk {
+            self.ratelimiter
+                .borrow()
+                .return_n((chunk - transferred) as u64);
+        }
+    }
+}
+
+impl<R, L: Borrow<Ratelimiter>> ThrottledReader<R, L> {
+    /// Wrap the reader so that each byte read costs a single token.
+    pub fn new(inner: R, ratelimiter: L) -> Self {
+        Self {
+            inner,
+            throttle: Throttle::new(ratelimiter),
+        }
+    }
+
+    /// Set the maximum number of bytes which are read at once. The chunk size
+    /// is also limited by the maximum number of tokens in the ratelimiter.
+    ///
+    /// Note: a chunk size of zero is treated as one.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.throttle.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Returns the ratelimiter which is charged for each byte.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.throttle.ratelimiter.borrow()
+    }
+
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this wrapper, returning the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<W, L: Borrow<Ratelimiter>> ThrottledWriter<W, L> {
+    /// Wrap the writer so that each byte written costs a single token.
+    pub fn new(inner: W, ratelimiter: L) -> Self {
+        Self {
+            inner,
+            throttle: Throttle::new(ratelimiter),
+        }
+    }
+
+    /// Set the maximum number of bytes which are written at once. The chunk
+    /// size is also limited by the maximum number of tokens in the ratelimiter.
+    ///
+    /// Note: a chunk size of zero is treated as one.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.throttle.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Returns the ratelimiter which is charged for each byte.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.throttle.ratelimiter.borrow()
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Consumes this wrapper, returning the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<R: Read, L: Borrow<Ratelimiter>> Read for ThrottledReader<R, L> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.read(buf);
+        }
+
+        let chunk = self.throttle.acquire(buf.len())?;
+        let result = self.inner.read(&mut buf[..chunk]);
+        self.throttle.refund(chunk, &result);
+
+        result
+    }
+}
+
+impl<W: Write, L: Borrow<Ratelimiter>> Write for ThrottledWriter<W, L> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.inner.write(buf);
+        }
+
+        let chunk = self.throttle.acquire(buf.len())?;
+        let result = self.inner.write(&buf[..chunk]);
+        self.throttle.refund(chunk, &result);
+
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TryAcquireError;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn reader() {
+        let ratelimiter = Ratelimiter::builder(100, Duration::from_millis(10))
+            .max_tokens(100)
+            .initial_available(100)
+            .build()
+            .unwrap();
+
+        // reads are limited to the chunk size, and the tokens for bytes which
+        // were not read are returned
+        let mut reader = ThrottledReader::new(&[0_u8; 50][..], &ratelimiter).chunk_size(40);
+        let mut buf = [0; 64];
+        assert_eq!(reader.read(&mut buf).unwrap(), 40);
+        assert_eq!(reader.read(&mut buf).unwrap(), 10);
+        assert_eq!(ratelimiter.available(), 50);
+
+        // the transfer waits for refills once the burst is exhausted
+        let start = Instant::now();
+        let mut reader = ThrottledReader::new(&[0_u8; 250][..], &ratelimiter);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.len(), 250);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn writer() {
+        let ratelimiter = Ratelimiter::builder(10, Duration::from_millis(1))
+            .max_tokens(16)
+            .build()
+            .unwrap();
+
+        // chunks are limited to the maximum number of tokens
+        let mut writer = ThrottledWriter::new(Vec::new(), &ratelimiter);
+        assert_eq!(writer.write(&[1; 64]).unwrap(), 16);
+        writer.write_all(&[2; 64]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref().len(), 80);
+
+        ratelimiter.close();
+        let error = writer.write(&[3; 1]).unwrap_err();
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<TryAcquireError>(),
+            Some(&TryAcquireError::Closed)
+        );
+    }
+}
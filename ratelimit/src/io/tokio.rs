@@ -0,0 +1,362 @@
+use super::Throttle;
+use crate::fair::Ticket;
+use crate::sleep::retry_delay;
+use crate::Ratelimiter;
+use core::borrow::Borrow;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+pin_project_lite::pin_project! {
+    /// An asynchronous reader which limits the rate at which bytes are read
+    /// from the inner reader. The task is woken once the tokens for the next
+    /// chunk are available, so this requires a tokio runtime with the timer
+    /// enabled.
+    ///
+    /// Wrapping each connection of a proxy with a reference to a shared
+    /// ratelimiter enforces a global bandwidth cap, while giving each
+    /// connection its own ratelimiter enforces a per-connection cap.
+    ///
+    /// If the ratelimiter is denying all requests or is closed, reads fail
+    /// with an error which wraps a [`TryAcquireError`](crate::TryAcquireError).
+    pub struct AsyncThrottledReader<R, L> {
+        #[pin]
+        inner: R,
+        throttle: AsyncThrottle<L>,
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// An asynchronous writer which limits the rate at which bytes are written
+    /// to the inner writer. The task is woken once the tokens for the next
+    /// chunk are available, so this requires a tokio runtime with the timer
+    /// enabled.
+    ///
+    /// If the ratelimiter is denying all requests or is closed, writes fail
+    /// with an error which wraps a [`TryAcquireError`](crate::TryAcquireError).
+    pub struct AsyncThrottledWriter<W, L> {
+        #[pin]
+        inner: W,
+        throttle: AsyncThrottle<L>,
+    }
+}
+
+/// Internal type which tracks the tokens acquired for a chunk while the inner
+/// reader or writer is not ready, so that they are not charged twice.
+struct AsyncThrottle<L> {
+    delay: Option<Pin<Box<Sleep>>>,
+    granted: usize,
+    ticket: Option<Ticket>,
+    throttle: Throttle<L>,
+}
+
+impl<L: Borrow<Ratelimiter>> AsyncThrottle<L> {
+    fn new(ratelimiter: L) -> Self {
+        Self {
+            delay: None,
+            granted: 0,
+            ticket: None,
+            throttle: Throttle::new(ratelimiter),
+        }
+    }
+
+    /// Poll for the tokens for the next chunk of a buffer with the provided
+    /// length. Returns the size of the chunk.
+    fn poll_acquire(&mut self, cx: &mut Context<'_>, len: usize) -> Poll<io::Result<usize>> {
+        let ratelimiter = self.throttle.ratelimiter.borrow();
+
+        if self.granted > 0 {
+            // the buffer may be smaller than when the tokens were acquired
+            if self.granted > len {
+                ratelimiter.return_n((self.granted - len) as u64);
+                self.granted = len;
+            }
+            return Poll::Ready(Ok(self.granted));
+        }
+
+        let chunk = self.throttle.chunk(len);
+        let mut registered = false;
+
+        loop {
+            match ratelimiter.try_acquire_queued(chunk as u64, &mut self.ticket) {
+                Ok(()) => {
+                    self.delay = None;
+                    self.granted = chunk;
+                    return Poll::Ready(Ok(chunk));
+                }
+                Err(e) => {
+                    let Some(delay) = retry_delay(ratelimiter, &e) else {
+                        self.ticket = None;
+                        return Poll::Ready(Err(io::Error::other(e)));
+                    };
+
+                    // the task is woken early if tokens are added to the
+                    // bucket before the timer fires
+                    if !registered {
+                        ratelimiter.register_waker(cx.waker());
+                        registered = true;
+                        continue;
+                    }
+
+                    let delay = self
+                        .delay
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(delay)));
+                    ready!(delay.as_mut().poll(cx));
+                    self.delay = None;
+                }
+            }
+        }
+    }
+
+    /// Settle the tokens for the current chunk once the inner reader or writer
+    /// has completed the transfer.
+    fn settle(&mut self, result: &io::Result<usize>) {
+        let chunk = core::mem::take(&mut self.granted);
+        self.throttle.refund(chunk, result);
+    }
+}
+
+impl<R, L: Borrow<Ratelimiter>> AsyncThrottledReader<R, L> {
+    /// Wrap the reader so that each byte read costs a single token.
+    pub fn new(inner: R, ratelimiter: L) -> Self {
+        Self {
+            inner,
+            throttle: AsyncThrottle::new(ratelimiter),
+        }
+    }
+
+    /// Set the maximum number of bytes which are read at once. The chunk size
+    /// is also limited by the maximum number of tokens in the ratelimiter.
+    ///
+    /// Note: a chunk size of zero is treated as one.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.throttle.throttle.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Returns the ratelimiter which is charged for each byte.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.throttle.throttle.ratelimiter.borrow()
+    }
+
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns a pinned mutable reference to the inner reader.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut R> {
+        self.project().inner
+    }
+
+    /// Consumes this wrapper, returning the inner reader. Tokens which were
+    /// acquired for a read that has not completed are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<W, L: Borrow<Ratelimiter>> AsyncThrottledWriter<W, L> {
+    /// Wrap the writer so that each byte written costs a single token.
+    pub fn new(inner: W, ratelimiter: L) -> Self {
+        Self {
+            inner,
+            throttle: AsyncThrottle::new(ratelimiter),
+        }
+    }
+
+    /// Set the maximum number of bytes which are written at once. The chunk
+    /// size is also limited by the maximum number of tokens in the ratelimiter.
+    ///
+    /// Note: a chunk size of zero is treated as one.
+    pub fn chunk_size(mut self, bytes: usize) -> Self {
+        self.throttle.throttle.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Returns the ratelimiter which is charged for each byte.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.throttle.throttle.ratelimiter.borrow()
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns a pinned mutable reference to the inner writer.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut W> {
+        self.project().inner
+    }
+
+    /// Consumes this wrapper, returning the inner writer. Tokens which were
+    /// acquired for a write that has not completed are lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead, L: Borrow<Ratelimiter>> AsyncRead for AsyncThrottledReader<R, L> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+
+        if buf.remaining() == 0 {
+            return this.inner.poll_read(cx, buf);
+        }
+
+        let chunk = ready!(this.throttle.poll_acquire(cx, buf.remaining()))?;
+
+        let mut limited = buf.take(chunk);
+        let ptr = limited.filled().as_ptr();
+        let result = ready!(this.inner.poll_read(cx, &mut limited)).map(|()| {
+            assert_eq!(limited.filled().as_ptr(), ptr);
+            limited.filled().len()
+        });
+        this.throttle.settle(&result);
+
+        let n = result?;
+        // SAFETY: the inner reader initialized the first `n` bytes of the
+        // unfilled part of the buffer, which `limited` was borrowed from
+        unsafe {
+            buf.assume_init(n);
+        }
+        buf.advance(n);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite, L: Borrow<Ratelimiter>> AsyncWrite for AsyncThrottledWriter<W, L> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+
+        if buf.is_empty() {
+            return this.inner.poll_write(cx, buf);
+        }
+
+        let chunk = ready!(this.throttle.poll_acquire(cx, buf.len()))?;
+        let result = ready!(this.inner.poll_write(cx, &buf[..chunk]));
+        this.throttle.settle(&result);
+
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TryAcquireError;
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn reader() {
+        let ratelimiter = Ratelimiter::builder(100, Duration::from_millis(10))
+            .max_tokens(100)
+            .initial_available(100)
+            .build()
+            .unwrap();
+
+        runtime().block_on(async {
+            // reads are limited to the chunk size, and the tokens for bytes
+            // which were not read are returned
+            let mut reader =
+                AsyncThrottledReader::new(&[0_u8; 50][..], &ratelimiter).chunk_size(40);
+            let mut buf = [0; 64];
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 40);
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 10);
+            assert_eq!(ratelimiter.available(), 50);
+
+            // the transfer waits for refills once the burst is exhausted
+            let start = Instant::now();
+            let mut reader = AsyncThrottledReader::new(&[0_u8; 250][..], &ratelimiter);
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf.len(), 250);
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        });
+    }
+
+    #[test]
+    fn writer() {
+        let ratelimiter = Ratelimiter::builder(10, Duration::from_millis(1))
+            .max_tokens(16)
+            .build()
+            .unwrap();
+
+        runtime().block_on(async {
+            // chunks are limited to the maximum number of tokens
+            let mut writer = AsyncThrottledWriter::new(Vec::new(), &ratelimiter);
+            assert_eq!(writer.write(&[1; 64]).await.unwrap(), 16);
+            writer.write_all(&[2; 64]).await.unwrap();
+            writer.flush().await.unwrap();
+            assert_eq!(writer.get_ref().len(), 80);
+
+            ratelimiter.close();
+            let error = writer.write(&[3; 1]).await.unwrap_err();
+            assert_eq!(
+                error.get_ref().unwrap().downcast_ref::<TryAcquireError>(),
+                Some(&TryAcquireError::Closed)
+            );
+        });
+    }
+
+    #[test]
+    fn pending() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(8)
+            .initial_available(8)
+            .build()
+            .unwrap();
+
+        runtime().block_on(async {
+            // tokens are held while the inner reader is not ready, and are not
+            // charged again when the read is retried
+            let (mut tx, rx) = tokio::io::duplex(64);
+            let mut reader = AsyncThrottledReader::new(rx, &ratelimiter);
+            let mut buf = [0; 64];
+            assert!(futures::FutureExt::now_or_never(reader.read(&mut buf)).is_none());
+            assert_eq!(ratelimiter.available(), 0);
+
+            tx.write_all(&[1; 3]).await.unwrap();
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 3);
+            assert_eq!(ratelimiter.available(), 5);
+        });
+    }
+}
@@ -0,0 +1,174 @@
+//! An adapter which paces a blocking [`Iterator`] with a ratelimiter.
+//!
+//! ```
+//! use ratelimit::iter::RatelimitIteratorExt;
+//! use ratelimit::Ratelimiter;
+//! use std::time::Duration;
+//!
+//! let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+//!     .build()
+//!     .unwrap();
+//!
+//! // each item is yielded after a token has been acquired
+//! for path in ["a", "b", "c"].iter().ratelimited(&ratelimiter) {
+//!     println!("{path}");
+//! }
+//! ```
+
+use crate::sleep::{block, retry_delay};
+use crate::{Ratelimiter, TryAcquireError};
+use core::borrow::Borrow;
+
+/// The cost function used by [`RatelimitIteratorExt::ratelimited`] and the
+/// other adapters in this crate which charge a single token for each item.
+pub type UnitCost<T> = fn(&T) -> u64;
+
+/// An extension trait which adds ratelimiting adapters to any [`Iterator`].
+pub trait RatelimitIteratorExt: Iterator + Sized {
+    /// Yield items no faster than the rate of the ratelimiter, charging a
+    /// single token for each item. The calling thread waits until a token is
+    /// available.
+    fn ratelimited<L: Borrow<Ratelimiter>>(
+        self,
+        ratelimiter: L,
+    ) -> RatelimitedIter<Self, L, UnitCost<Self::Item>> {
+        self.ratelimited_with(ratelimiter, |_| 1)
+    }
+
+    /// Yield items no faster than the rate of the ratelimiter, charging the
+    /// number of tokens returned by the cost function for each item.
+    ///
+    /// Note: costs above the maximum number of tokens in the ratelimiter are
+    /// charged as the maximum, since they could never be acquired otherwise.
+    fn ratelimited_with<L, F>(self, ratelimiter: L, cost: F) -> RatelimitedIter<Self, L, F>
+    where
+        L: Borrow<Ratelimiter>,
+        F: FnMut(&Self::Item) -> u64,
+    {
+        RatelimitedIter {
+            cost,
+            iter: self,
+            ratelimiter,
+        }
+    }
+}
+
+impl<I: Iterator> RatelimitIteratorExt for I {}
+
+/// An iterator which yields the items of the inner iterator no faster than the
+/// rate of a ratelimiter. Created by [`RatelimitIteratorExt::ratelimited`] and
+/// [`RatelimitIteratorExt::ratelimited_with`].
+///
+/// The iterator ends early if the ratelimiter is closed.
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct RatelimitedIter<I, L, F> {
+    cost: F,
+    iter: I,
+    ratelimiter: L,
+}
+
+impl<I, L, F> RatelimitedIter<I, L, F> {
+    /// Returns a reference to the inner iterator.
+    pub fn get_ref(&self) -> &I {
+        &self.iter
+    }
+
+    /// Consumes this adapter, returning the inner iterator.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<I, L, F> Iterator for RatelimitedIter<I, L, F>
+where
+    I: Iterator,
+    L: Borrow<Ratelimiter>,
+    F: FnMut(&I::Item) -> u64,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        let cost = (self.cost)(&item);
+
+        if cost == 0 {
+            return Some(item);
+        }
+
+        wait(self.ratelimiter.borrow(), cost).then_some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Internal function to block the calling thread until `cost` tokens have
+/// been acquired. Costs above the maximum number of tokens are charged as the
+/// maximum. Returns `false` if the ratelimiter is closed.
+pub(crate) fn wait(ratelimiter: &Ratelimiter, cost: u64) -> bool {
+    let cost = cost.min(ratelimiter.max_tokens());
+    let mut ticket = None;
+
+    loop {
+        match ratelimiter.try_acquire_queued(cost, &mut ticket) {
+            Ok(()) => return true,
+            Err(TryAcquireError::Closed) => return false,
+            Err(e) => {
+                // a ratelimiter which is denying requests may resume, so we
+                // wait as if it were paused
+                let delay =
+                    retry_delay(ratelimiter, &e).unwrap_or_else(|| ratelimiter.scaled_interval());
+                block(ratelimiter, delay);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ratelimited() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+
+        let start = Instant::now();
+        let items: Vec<u64> = (0..3).ratelimited(ratelimiter.clone()).collect();
+        assert_eq!(items, vec![0, 1, 2]);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // the iterator ends when the ratelimiter is closed
+        ratelimiter.close();
+        assert_eq!((0..3).ratelimited(&*ratelimiter).next(), None);
+    }
+
+    #[test]
+    fn cost() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        let mut iter = [4, 0, 20]
+            .into_iter()
+            .ratelimited_with(&ratelimiter, |n| *n);
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+
+        assert_eq!(iter.next(), Some(4));
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(ratelimiter.available(), 6);
+
+        // costs above the burst are clamped
+        ratelimiter.return_n(4);
+        assert_eq!(iter.next(), Some(20));
+        assert_eq!(ratelimiter.available(), 0);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::KeyedRatelimiter;
+use core::hash::Hash;
+use core::time::Duration;
+use parking_lot::{Condvar, Mutex};
+use std::sync::{Arc, Weak};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+/// The counters of the background thread started by
+/// [`KeyedRatelimiter::spawn_janitor`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SweepStats {
+    /// The number of sweeps which have been made.
+    pub sweeps: u64,
+    /// The total number of buckets which have been evicted.
+    pub evicted: u64,
+    /// The time taken by the most recent sweep.
+    pub last_duration: Duration,
+}
+
+/// Internal type which holds the requests made of the background thread.
+#[derive(Default)]
+struct Control {
+    paused: bool,
+    stopped: bool,
+}
+
+/// Internal type which is shared between the handle and the background
+/// thread.
+#[derive(Default)]
+struct Shared {
+    condvar: Condvar,
+    control: Mutex<Control>,
+    evicted: AtomicU64,
+    last_duration: AtomicU64,
+    sweeps: AtomicU64,
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync + 'static> KeyedRatelimiter<K> {
+    /// Start a background thread which evicts idle buckets each time the
+    /// interval elapses. While the thread is running, buckets are no longer
+    /// evicted as new keys are added, which keeps the sweeps off the request
+    /// path. Requires an idle timeout, see [`KeyedRatelimiter::idle_timeout`].
+    ///
+    /// The thread holds a weak reference, so it also stops once the keyed
+    /// ratelimiter is dropped.
+    ///
+    /// ```
+    /// use ratelimit::{KeyedRatelimiter, RatelimiterConfig};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    ///
+    /// let config = RatelimiterConfig::new("10/s".parse().unwrap());
+    /// let ratelimiter: Arc<KeyedRatelimiter> = Arc::new(
+    ///     KeyedRatelimiter::from_config(config)
+    ///         .unwrap()
+    ///         .idle_timeout(Duration::from_secs(60)),
+    /// );
+    ///
+    /// let janitor = ratelimiter.spawn_janitor(Duration::from_secs(10));
+    ///
+    /// // sweeps can be paused, for example during a latency-sensitive period
+    /// janitor.pause();
+    /// janitor.resume();
+    ///
+    /// janitor.stop();
+    /// ```
+    pub fn spawn_janitor(self: &Arc<Self>, interval: Duration) -> Janitor {
+        let shared = Arc::new(Shared::default());
+        let ratelimiter = Arc::downgrade(self);
+
+        self.set_janitor(true);
+
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || sweep(ratelimiter, shared, interval))
+        };
+
+        let target: Weak<dyn JanitorTarget> = Arc::downgrade(self) as _;
+
+        Janitor {
+            ratelimiter: target,
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Internal function which runs the background sweeps until stopped.
+fn sweep<K: Hash + Eq + Clone + Send + Sync>(
+    ratelimiter: Weak<KeyedRatelimiter<K>>,
+    shared: Arc<Shared>,
+    interval: Duration,
+) {
+    loop {
+        let mut control = shared.control.lock();
+
+        if !control.stopped {
+            shared.condvar.wait_for(&mut control, interval);
+        }
+
+        // wait while paused, unless stopped in the meantime
+        while control.paused && !control.stopped {
+            shared.condvar.wait(&mut control);
+        }
+
+        if control.stopped {
+            return;
+        }
+
+        drop(control);
+
+        let Some(ratelimiter) = ratelimiter.upgrade() else {
+            return;
+        };
+
+        let start = Instant::now();
+        let evicted = ratelimiter.evict_idle();
+
+        shared
+            .last_duration
+            .store(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        shared.evicted.fetch_add(evicted as u64, Ordering::Relaxed);
+        shared.sweeps.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A handle to the background thread started by
+/// [`KeyedRatelimiter::spawn_janitor`]. The thread is stopped when the handle
+/// is dropped, and eviction returns to the request path.
+pub struct Janitor {
+    ratelimiter: Weak<dyn JanitorTarget>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Internal trait which erases the key type of the keyed ratelimiter, so that
+/// the handle can restore eviction on the request path when it is stopped.
+trait JanitorTarget: Send + Sync {
+    fn set_janitor(&self, running: bool);
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync> JanitorTarget for KeyedRatelimiter<K> {
+    fn set_janitor(&self, running: bool) {
+        KeyedRatelimiter::set_janitor(self, running)
+    }
+}
+
+impl Janitor {
+    /// Pause the sweeps. A sweep which is in progress is completed.
+    pub fn pause(&self) {
+        self.shared.control.lock().paused = true;
+    }
+
+    /// Resume the sweeps after a pause.
+    pub fn resume(&self) {
+        self.shared.control.lock().paused = false;
+        self.shared.condvar.notify_all();
+    }
+
+    /// Returns true if the sweeps are paused.
+    pub fn is_paused(&self) -> bool {
+        self.shared.control.lock().paused
+    }
+
+    /// Returns the counters of the sweeps. See [`SweepStats`].
+    pub fn stats(&self) -> SweepStats {
+        SweepStats {
+            sweeps: self.shared.sweeps.load(Ordering::Relaxed),
+            evicted: self.shared.evicted.load(Ordering::Relaxed),
+            last_duration: Duration::from_nanos(self.shared.last_duration.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Stop the background thread, waiting for a sweep in progress to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.shared.control.lock().stopped = true;
+        self.shared.condvar.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        if let Some(ratelimiter) = self.ratelimiter.upgrade() {
+            ratelimiter.set_janitor(false);
+        }
+    }
+}
+
+impl Drop for Janitor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn janitor() {
+        let config = RatelimiterConfig::new("10/s".parse().unwrap());
+        let rl: Arc<KeyedRatelimiter> = Arc::new(
+            KeyedRatelimiter::from_config(config)
+                .unwrap()
+                .idle_timeout(Duration::from_millis(10)),
+        );
+
+        let janitor = rl.spawn_janitor(Duration::from_millis(5));
+        janitor.pause();
+        assert!(janitor.is_paused());
+
+        rl.get("a");
+        rl.get("b");
+        std::thread::sleep(Duration::from_millis(30));
+
+        // eviction is left to the paused janitor rather than the request path
+        rl.get("c");
+        assert_eq!(rl.len(), 3);
+
+        janitor.resume();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(rl.len(), 0);
+
+        let stats = janitor.stats();
+        assert!(stats.sweeps > 0);
+        assert_eq!(stats.evicted, 3);
+
+        // stopping the janitor returns eviction to the request path
+        janitor.stop();
+        rl.get("d");
+        std::thread::sleep(Duration::from_millis(30));
+        rl.get("e");
+        assert_eq!(rl.len(), 1);
+    }
+}
@@ -0,0 +1,621 @@
+use crate::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::hot_keys::HotKeyTracker;
+use crate::slab::Slab;
+use crate::{Error, Ratelimiter, RatelimiterConfig, TryAcquireError};
+use clocksource::precise::{AtomicInstant, Duration, Instant};
+use core::borrow::Borrow;
+use core::hash::Hash;
+use core::ops::Deref;
+use parking_lot::{MappedRwLockReadGuard, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::{HashMap, HashSet};
+
+type Factory<K> = dyn Fn(&K) -> Ratelimiter + Send + Sync;
+type Normalizer<K> = dyn Fn(&K) -> K + Send + Sync;
+type Predicate<K> = dyn Fn(&K) -> bool + Send + Sync;
+
+struct Bucket {
+    limiter: Ratelimiter,
+    last_used: AtomicInstant,
+}
+
+/// Internal type which stores the buckets in a slab, with an index from each
+/// key to its slot. The ratelimiters are held in the slots rather than in
+/// separate allocations, and slots are reused as buckets are evicted, which
+/// avoids fragmenting memory when there are many keys.
+struct Buckets<K> {
+    index: HashMap<K, usize>,
+    slab: Slab<Bucket>,
+}
+
+impl<K: Hash + Eq> Buckets<K> {
+    fn get<Q>(&self, key: &Q) -> Option<&Bucket>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.slab.get(*self.index.get(key)?)
+    }
+
+    fn insert(&mut self, key: K, bucket: Bucket) -> usize {
+        let slot = self.slab.insert(bucket);
+        if let Some(previous) = self.index.insert(key, slot) {
+            self.slab.remove(previous);
+        }
+        slot
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<Bucket>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.slab.remove(self.index.remove(key)?)
+    }
+
+    fn retain(&mut self, mut keep: impl FnMut(&Bucket) -> bool) {
+        let slab = &mut self.slab;
+
+        self.index.retain(|_, slot| {
+            let keep = slab.get(*slot).is_some_and(&mut keep);
+            if !keep {
+                slab.remove(*slot);
+            }
+            keep
+        });
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&K, &Bucket)> {
+        self.index
+            .iter()
+            .filter_map(|(key, slot)| Some((key, self.slab.get(*slot)?)))
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+/// The memory used by the buckets of a `KeyedRatelimiter`, from
+/// [`KeyedRatelimiter::memory_usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The number of buckets.
+    pub buckets: usize,
+    /// The number of slots which hold a bucket or are free to be reused.
+    pub slots: usize,
+    /// The approximate number of bytes used by the slots, the memory the
+    /// ratelimiters in them have allocated, and the index of keys. This
+    /// excludes memory owned by the keys themselves, such as the contents of a
+    /// `String`, and by user provided types such as an observer beyond their
+    /// own size.
+    pub bytes: usize,
+}
+
+/// A reference to the ratelimiter for a key, from [`KeyedRatelimiter::get`].
+///
+/// The ratelimiters are stored together, and this holds a read lock on them.
+/// Buckets for new keys cannot be added or evicted until it is dropped, so it
+/// should not be held while waiting for tokens.
+pub struct KeyedRef<'a>(MappedRwLockReadGuard<'a, Ratelimiter>);
+
+impl Deref for KeyedRef<'_> {
+    type Target = Ratelimiter;
+
+    fn deref(&self) -> &Ratelimiter {
+        &self.0
+    }
+}
+
+/// A collection of ratelimiters which are created on demand for each key, for
+/// example to limit each client, user, or tenant independently.
+///
+/// Buckets can optionally be evicted after they have been idle for some time
+/// so that memory use is bounded by the number of active keys.
+///
+/// ```
+/// use ratelimit::{KeyedRatelimiter, RatelimiterConfig};
+///
+/// let config = RatelimiterConfig::new("10/s".parse().unwrap()).initial_available(10);
+/// let ratelimiter: KeyedRatelimiter = KeyedRatelimiter::from_config(config).unwrap();
+///
+/// // each key has an independent budget
+/// assert!(ratelimiter.try_wait_n("alice", 10).is_ok());
+/// assert!(ratelimiter.try_wait("alice").is_err());
+/// assert!(ratelimiter.try_wait("bob").is_ok());
+/// ```
+pub struct KeyedRatelimiter<K = String> {
+    buckets: RwLock<Buckets<K>>,
+    exempt: RwLock<HashSet<K>>,
+    exempt_if: Option<Box<Predicate<K>>>,
+    exempted: AtomicU64,
+    factory: Box<Factory<K>>,
+    pub(crate) hot_keys: Option<Mutex<HotKeyTracker<K>>>,
+    idle_timeout: Option<Duration>,
+    janitor: AtomicBool,
+    last_sweep: AtomicInstant,
+    normalize: Option<Box<Normalizer<K>>>,
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync> KeyedRatelimiter<K> {
+    /// Create a new keyed ratelimiter which uses the provided function to
+    /// construct the ratelimiter for each new key.
+    pub fn new(factory: impl Fn(&K) -> Ratelimiter + Send + Sync + 'static) -> Self {
+        Self {
+            buckets: RwLock::new(Buckets {
+                index: HashMap::new(),
+                slab: Slab::new(),
+            }),
+            exempt: RwLock::new(HashSet::new()),
+            exempt_if: None,
+            exempted: AtomicU64::new(0),
+            factory: Box::new(factory),
+            hot_keys: None,
+            idle_timeout: None,
+            janitor: AtomicBool::new(false),
+            last_sweep: AtomicInstant::now(),
+            normalize: None,
+        }
+    }
+
+    /// Create a new keyed ratelimiter where the ratelimiter for each key is
+    /// constructed from the same config. Returns an error if the config is
+    /// invalid.
+    pub fn from_config(config: RatelimiterConfig) -> Result<Self, Error> {
+        // validate the config once so that constructing each bucket can't fail
+        config.builder()?.build()?;
+
+        Ok(Self::new(move |_| {
+            config
+                .builder()
+                .and_then(|builder| builder.build())
+                .expect("config was validated")
+        }))
+    }
+
+    /// Evict buckets which have not been used for the provided duration. The
+    /// eviction happens as new keys are added, at most once per timeout. See
+    /// [`KeyedRatelimiter::evict_idle`] to evict explicitly, or
+    /// [`KeyedRatelimiter::spawn_janitor`] to evict from a background thread.
+    ///
+    /// Note: a bucket which is evicted is replaced by a new one the next time
+    /// the key is used, so the timeout should be long enough that an idle
+    /// bucket would have refilled completely.
+    pub fn idle_timeout(mut self, timeout: core::time::Duration) -> Self {
+        self.idle_timeout = Some(Duration::from_nanos(timeout.as_nanos() as u64));
+        self
+    }
+
+    /// Map each key with the provided function before looking up its bucket,
+    /// so that keys which map to the same key share a bucket. For example, to
+    /// ignore the case of a username. See [`KeyedRatelimiter::ip_prefix`] to
+    /// group client addresses by their network.
+    pub fn normalize_with(mut self, normalize: impl Fn(&K) -> K + Send + Sync + 'static) -> Self {
+        self.normalize = Some(Box::new(normalize));
+        self
+    }
+
+    /// Exempt keys which match the provided predicate from limiting, for
+    /// example health checks or internal callers. See
+    /// [`KeyedRatelimiter::exempt`] for details.
+    pub fn exempt_if(mut self, predicate: impl Fn(&K) -> bool + Send + Sync + 'static) -> Self {
+        self.exempt_if = Some(Box::new(predicate));
+        self
+    }
+
+    /// Exempt the provided key from limiting. Exemptions are checked before
+    /// the bucket lookup and any normalization of the key, so an exempt key
+    /// never has a bucket and does not consume the budget of the keys it
+    /// would share a bucket with. Returns false if the key was already exempt.
+    ///
+    /// Acquisitions for exempt keys always succeed and are counted by
+    /// [`KeyedRatelimiter::exempted`].
+    ///
+    /// ```
+    /// use ratelimit::{KeyedRatelimiter, RatelimiterConfig};
+    ///
+    /// let config = RatelimiterConfig::new("1/s".parse().unwrap());
+    /// let ratelimiter: KeyedRatelimiter = KeyedRatelimiter::from_config(config).unwrap();
+    ///
+    /// ratelimiter.exempt("healthcheck".to_string());
+    ///
+    /// assert!(ratelimiter.try_wait("alice").is_err());
+    /// assert!(ratelimiter.try_wait("healthcheck").is_ok());
+    /// assert_eq!(ratelimiter.exempted(), 1);
+    /// ```
+    pub fn exempt(&self, key: K) -> bool {
+        self.exempt.write().insert(key)
+    }
+
+    /// Remove the exemption for the provided key. Returns false if the key was
+    /// not exempt. This does not affect keys exempted by a predicate.
+    pub fn unexempt<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.exempt.write().remove(key)
+    }
+
+    /// Returns true if the provided key is exempt from limiting, either
+    /// explicitly or by the predicate.
+    pub fn is_exempt<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if self.exempt.read().contains(key) {
+            return true;
+        }
+
+        self.exempt_if
+            .as_ref()
+            .is_some_and(|predicate| predicate(&key.to_owned()))
+    }
+
+    /// Returns the number of requests which were allowed because their key
+    /// was exempt.
+    pub fn exempted(&self) -> u64 {
+        self.exempted.load(Ordering::Relaxed)
+    }
+
+    /// Internal function to check whether a request for the provided key is
+    /// exempt, counting it if so.
+    pub(crate) fn exempt_request<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let exempt = self.is_exempt(key);
+        if exempt {
+            self.exempted.fetch_add(1, Ordering::Relaxed);
+        }
+        exempt
+    }
+
+    /// Returns the ratelimiter for the provided key, creating it if needed.
+    /// See [`KeyedRef`] for the lock which is held by the reference.
+    pub fn get<Q>(&self, key: &Q) -> KeyedRef<'_>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.get_or_insert_with(key, |key| (self.factory)(key))
+    }
+
+    /// Returns the ratelimiter for the provided key. If there is no ratelimiter
+    /// for the key, one is created with the provided function instead of the
+    /// default factory.
+    pub fn get_or_insert_with<Q>(
+        &self,
+        key: &Q,
+        create: impl FnOnce(&K) -> Ratelimiter,
+    ) -> KeyedRef<'_>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        match &self.normalize {
+            Some(normalize) => self.bucket::<K>(&normalize(&key.to_owned()), create),
+            None => self.bucket(key, create),
+        }
+    }
+
+    /// Internal function to return the ratelimiter for a key which has already
+    /// been normalized, creating it with the provided function if needed.
+    fn bucket<Q>(&self, key: &Q, create: impl FnOnce(&K) -> Ratelimiter) -> KeyedRef<'_>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let now = Instant::now();
+
+        let buckets = RwLockReadGuard::try_map(self.buckets.read(), |buckets| {
+            let bucket = buckets.get(key)?;
+            bucket.last_used.store(now, Ordering::Relaxed);
+            Some(&bucket.limiter)
+        });
+
+        match buckets {
+            Ok(limiter) => return KeyedRef(limiter),
+            Err(buckets) => drop(buckets),
+        }
+
+        // buckets are evicted on the request path unless a janitor is running
+        if let Some(timeout) = self.idle_timeout {
+            if !self.janitor.load(Ordering::Relaxed)
+                && now - self.last_sweep.load(Ordering::Relaxed) >= timeout
+            {
+                self.evict_idle();
+            }
+        }
+
+        let mut buckets = self.buckets.write();
+
+        // we may have raced with another caller between the read and write
+        // lock, otherwise the bucket is created
+        let slot = match buckets.index.get(key) {
+            Some(slot) => *slot,
+            None => {
+                let key = key.to_owned();
+                let limiter = create(&key);
+
+                buckets.insert(
+                    key,
+                    Bucket {
+                        limiter,
+                        last_used: AtomicInstant::new(now),
+                    },
+                )
+            }
+        };
+
+        KeyedRef(RwLockReadGuard::map(
+            RwLockWriteGuard::downgrade(buckets),
+            |buckets| &buckets.slab.get(slot).expect("bucket is present").limiter,
+        ))
+    }
+
+    /// Remove the ratelimiter for the provided key, returning it if present.
+    pub fn remove<Q>(&self, key: &Q) -> Option<Ratelimiter>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let mut buckets = self.buckets.write();
+
+        match &self.normalize {
+            Some(normalize) => buckets.remove::<K>(&normalize(&key.to_owned())),
+            None => buckets.remove(key),
+        }
+        .map(|bucket| bucket.limiter)
+    }
+
+    /// Remove all buckets which have been idle for longer than the idle
+    /// timeout. Returns the number of buckets which were removed. Does nothing
+    /// if no idle timeout is configured.
+    pub fn evict_idle(&self) -> usize {
+        let Some(timeout) = self.idle_timeout else {
+            return 0;
+        };
+
+        let now = Instant::now();
+        self.last_sweep.store(now, Ordering::Relaxed);
+
+        let mut buckets = self.buckets.write();
+        let before = buckets.len();
+
+        buckets.retain(|bucket| now - bucket.last_used.load(Ordering::Relaxed) < timeout);
+
+        before - buckets.len()
+    }
+
+    /// Internal function to record whether a janitor is evicting buckets, see
+    /// [`KeyedRatelimiter::spawn_janitor`].
+    pub(crate) fn set_janitor(&self, running: bool) {
+        self.janitor.store(running, Ordering::Relaxed);
+    }
+
+    /// Internal function to return the keys of the buckets whose ratelimiter
+    /// matches the predicate.
+    pub(crate) fn keys_where(&self, mut predicate: impl FnMut(&Ratelimiter) -> bool) -> Vec<K> {
+        self.buckets
+            .read()
+            .iter()
+            .filter(|(_, bucket)| predicate(&bucket.limiter))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Returns the number of keys which currently have a bucket.
+    pub fn len(&self) -> usize {
+        self.buckets.read().len()
+    }
+
+    /// Returns true if there are no buckets.
+    pub fn is_empty(&self) -> bool {
+        self.buckets.read().len() == 0
+    }
+
+    /// Returns the memory used by the buckets. See [`MemoryUsage`].
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let buckets = self.buckets.read();
+
+        // the index holds a control byte for each entry
+        let index = buckets.index.capacity() * (core::mem::size_of::<(K, usize)>() + 1);
+        let limiters: usize = buckets
+            .iter()
+            .map(|(_, bucket)| bucket.limiter.heap_size())
+            .sum();
+
+        MemoryUsage {
+            buckets: buckets.len(),
+            slots: buckets.slab.slots(),
+            bytes: buckets.slab.bytes() + index + limiters,
+        }
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens for the provided key. See
+    /// [`Ratelimiter::try_wait_n`] for details.
+    pub fn try_wait_n<Q>(&self, key: &Q, n: u64) -> Result<(), core::time::Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if self.exempt_request(key) {
+            return Ok(());
+        }
+
+        let result = self.get(key).try_wait_n(n);
+        self.record_hot_key(key, result.is_ok());
+        result
+    }
+
+    /// Non-blocking function to "wait" for a single token for the provided key.
+    /// See [`Ratelimiter::try_wait_n`] for details.
+    pub fn try_wait<Q>(&self, key: &Q) -> Result<(), core::time::Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.try_wait_n(key, 1)
+    }
+
+    /// Non-blocking function to acquire `n` tokens for the provided key. See
+    /// [`Ratelimiter::try_acquire_n`] for details.
+    pub fn try_acquire_n<Q>(&self, key: &Q, n: u64) -> Result<(), TryAcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if self.exempt_request(key) {
+            return Ok(());
+        }
+
+        let result = self.get(key).try_acquire_n(n);
+        self.record_hot_key(key, result.is_ok());
+        result
+    }
+
+    /// Non-blocking function to acquire a single token for the provided key.
+    /// See [`Ratelimiter::try_acquire_n`] for details.
+    pub fn try_acquire<Q>(&self, key: &Q) -> Result<(), TryAcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.try_acquire_n(key, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn keyed() {
+        let rl: KeyedRatelimiter<u64> = KeyedRatelimiter::new(|key| {
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(10)
+                .initial_available(*key)
+                .build()
+                .unwrap()
+        });
+        assert!(rl.is_empty());
+
+        assert!(rl.try_wait_n(&3, 3).is_ok());
+        assert!(rl.try_wait(&3).is_err());
+        assert!(rl.try_acquire_n(&5, 5).is_ok());
+        assert_eq!(rl.len(), 2);
+
+        let limiter = core::ptr::from_ref::<Ratelimiter>(&rl.get(&3));
+        assert!(core::ptr::eq(limiter, &*rl.get(&3)));
+        assert!(rl.remove(&3).is_some());
+        assert_eq!(rl.get(&3).available(), 3);
+
+        // a custom constructor is only used for new keys
+        let limiter = rl.get_or_insert_with(&7, |_| {
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .build()
+                .unwrap()
+        });
+        assert_eq!(limiter.available(), 0);
+        drop(limiter);
+        assert_eq!(rl.get_or_insert_with(&3, |_| unreachable!()).available(), 3);
+
+        assert!(KeyedRatelimiter::<String>::from_config(RatelimiterConfig {
+            rate: "100M/s".parse().unwrap(),
+            max_tokens: Some(1),
+            initial_available: None,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn exempt() {
+        let rl: KeyedRatelimiter<u64> = KeyedRatelimiter::new(|_| {
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .build()
+                .unwrap()
+        })
+        .normalize_with(|key| key / 10)
+        .exempt_if(|key| *key >= 100);
+
+        assert!(rl.exempt(1));
+        assert!(!rl.exempt(1));
+
+        assert!(rl.try_acquire(&1).is_ok());
+        assert!(rl.try_wait(&123).is_ok());
+        assert!(rl.try_acquire(&2).is_err());
+        assert_eq!(rl.exempted(), 2);
+
+        // exempt keys never get a bucket
+        assert_eq!(rl.len(), 1);
+
+        assert!(rl.is_exempt(&1));
+        assert!(rl.unexempt(&1));
+        assert!(!rl.is_exempt(&1));
+        assert!(rl.try_acquire(&1).is_err());
+        assert_eq!(rl.exempted(), 2);
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let config = RatelimiterConfig::new("10/s".parse().unwrap());
+        let rl: KeyedRatelimiter = KeyedRatelimiter::from_config(config)
+            .unwrap()
+            .idle_timeout(Duration::from_millis(20));
+
+        rl.get("a");
+        rl.get("b");
+        assert_eq!(rl.evict_idle(), 0);
+
+        std::thread::sleep(Duration::from_millis(30));
+        rl.get("b");
+        assert_eq!(rl.evict_idle(), 1);
+        assert_eq!(rl.len(), 1);
+
+        // idle buckets are evicted as new keys are added
+        std::thread::sleep(Duration::from_millis(30));
+        rl.get("c");
+        assert_eq!(rl.len(), 1);
+
+        // the slots of evicted buckets are reused
+        let usage = rl.memory_usage();
+        assert_eq!(usage.buckets, 1);
+        assert_eq!(usage.slots, 2);
+        assert!(usage.bytes > std::mem::size_of::<Ratelimiter>());
+
+        rl.get("d");
+        assert_eq!(rl.memory_usage().slots, 2);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let plain = KeyedRatelimiter::new(|_: &u64| {
+            Ratelimiter::builder(1, Duration::from_secs(1))
+                .build()
+                .unwrap()
+        });
+        let counted = KeyedRatelimiter::new(|_: &u64| {
+            Ratelimiter::builder(1, Duration::from_secs(1))
+                .counters(true)
+                .build()
+                .unwrap()
+        });
+
+        for key in 0..100 {
+            plain.get(&key);
+            counted.get(&key);
+        }
+
+        // the ratelimiters are stored in the slots, and only the optional
+        // features they are configured with are allocated separately
+        let usage = plain.memory_usage();
+        assert!(usage.bytes >= 100 * std::mem::size_of::<Ratelimiter>());
+        assert_eq!(
+            counted.memory_usage().bytes - usage.bytes,
+            100 * std::mem::size_of::<crate::Extensions>()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{Builder, Ratelimiter};
+use core::time::Duration;
+use histogram::{AtomicHistogram, Config, Histogram};
+
+// the histogram records nanoseconds with a relative error of about 3%
+const GROUPING_POWER: u8 = 5;
+const MAX_VALUE_POWER: u8 = 64;
+
+/// A point-in-time capture of the wait-latency histogram of a `Ratelimiter`.
+/// See [`Builder::latency_histogram`].
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
+///     .max_tokens(2)
+///     .initial_available(2)
+///     .latency_histogram(true)
+///     .build()
+///     .unwrap();
+///
+/// for _ in 0..3 {
+///     let _ = ratelimiter.try_wait();
+/// }
+///
+/// let latency = ratelimiter.latency_snapshot().unwrap();
+/// assert_eq!(latency.count(), 3);
+/// assert_eq!(latency.percentile(50.0), Some(Duration::ZERO));
+/// assert!(latency.percentile(99.0).unwrap() > Duration::from_millis(5));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencySnapshot {
+    pub(crate) histogram: Histogram,
+}
+
+impl LatencySnapshot {
+    /// Returns the number of attempts which were recorded.
+    pub fn count(&self) -> u64 {
+        self.histogram.as_slice().iter().sum()
+    }
+
+    /// Returns the wait at the provided percentile, which must be in the range
+    /// `0.0..=100.0`. The wait is the upper bound of the bucket which holds the
+    /// percentile. Returns `None` if nothing has been recorded or the
+    /// percentile is invalid.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        self.histogram
+            .percentile(percentile)
+            .ok()
+            .flatten()
+            .map(|bucket| Duration::from_nanos(bucket.end()))
+    }
+
+    /// Returns the waits at each of the provided percentiles, sorted by the
+    /// percentile. See [`LatencySnapshot::percentile`].
+    pub fn percentiles(&self, percentiles: &[f64]) -> Vec<(f64, Duration)> {
+        self.histogram
+            .percentiles(percentiles)
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(percentile, bucket)| (percentile, Duration::from_nanos(bucket.end())))
+            .collect()
+    }
+
+    /// Returns the underlying histogram of waits in nanoseconds.
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+}
+
+impl Builder {
+    /// Record a histogram of how long callers had to wait for tokens, which
+    /// can be read with [`Ratelimiter::latency_snapshot`]. This helps to tune
+    /// the max tokens, since a bucket which is too small makes callers wait
+    /// during bursts.
+    ///
+    /// Each attempt to acquire tokens records how long the caller would have
+    /// to wait for them: zero if the tokens were acquired, or the time until
+    /// the next refill if there were insufficient tokens. Callers which wait
+    /// and retry record each attempt. Attempts which fail because the
+    /// ratelimiter is paused, denying all requests, or closed are not
+    /// recorded.
+    pub fn latency_histogram(mut self, enabled: bool) -> Self {
+        self.latency_histogram = enabled;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Capture the wait-latency histogram. Returns `None` unless it was
+    /// enabled with [`Builder::latency_histogram`].
+    pub fn latency_snapshot(&self) -> Option<LatencySnapshot> {
+        self.extensions()
+            .and_then(|e| e.latency.as_ref())
+            .map(|latency| LatencySnapshot {
+                histogram: latency.load(),
+            })
+    }
+
+    /// Internal function to record how long a caller would have to wait.
+    pub(crate) fn record_latency(&self, wait: Duration) {
+        if let Some(latency) = self.extensions().and_then(|e| e.latency.as_ref()) {
+            let _ = latency.increment(wait.as_nanos().min(u64::MAX as u128) as u64);
+        }
+    }
+}
+
+/// Internal function to create the histogram for a ratelimiter.
+pub(crate) fn histogram() -> AtomicHistogram {
+    AtomicHistogram::new(GROUPING_POWER, MAX_VALUE_POWER).unwrap()
+}
+
+/// Internal function to return the number of bytes allocated for the buckets
+/// of a histogram created by [`histogram`].
+pub(crate) fn histogram_size() -> usize {
+    histogram_buckets(GROUPING_POWER, MAX_VALUE_POWER) * core::mem::size_of::<u64>()
+}
+
+/// Internal function to return the number of buckets in a histogram with the
+/// provided configuration.
+pub(crate) fn histogram_buckets(grouping_power: u8, max_value_power: u8) -> usize {
+    Config::new(grouping_power, max_value_power)
+        .unwrap()
+        .total_buckets()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn latency() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(1))
+            .max_tokens(4)
+            .initial_available(4)
+            .build()
+            .unwrap();
+        assert!(rl.latency_snapshot().is_none());
+
+        let rl = Ratelimiter::builder(1, Duration::from_secs(1))
+            .max_tokens(4)
+            .initial_available(4)
+            .latency_histogram(true)
+            .build()
+            .unwrap();
+
+        for _ in 0..8 {
+            let _ = rl.try_acquire();
+        }
+        rl.close();
+        let _ = rl.try_acquire();
+
+        let latency = rl.latency_snapshot().unwrap();
+        assert_eq!(latency.count(), 8);
+
+        let percentiles = latency.percentiles(&[50.0, 90.0]);
+        assert_eq!(percentiles[0], (50.0, Duration::ZERO));
+        assert!(percentiles[1].1 > Duration::from_millis(900));
+        assert!(percentiles[1].1 < Duration::from_millis(1100));
+        assert_eq!(latency.percentile(101.0), None);
+    }
+}
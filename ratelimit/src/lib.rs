@@ -1,63 +1,385 @@
 //! This crate provides a simple implementation of a ratelimiter that can be
 //! shared between threads.
 //!
-//! ```
-//! use ratelimit::Ratelimiter;
-//! use std::time::Duration;
-//!
-//! // Constructs a ratelimiter that generates 1 tokens/s with no burst. This
-//! // can be used to produce a steady rate of requests. The ratelimiter starts
-//! // with no tokens available, which means across application restarts, we
-//! // cannot exceed the configured ratelimit.
-//! let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
-//!     .build()
-//!     .unwrap();
-//!
-//! // Another use case might be admission control, where we start with some
-//! // initial budget and replenish it periodically. In this example, our
-//! // ratelimiter allows 1000 tokens/hour. For every hour long sliding window,
-//! // no more than 1000 tokens can be acquired. But all tokens can be used in
-//! // a single burst. Additional calls to `try_wait()` will return an error
-//! // until the next token addition.
-//! //
-//! // This is popular approach with public API ratelimits.
-//! let ratelimiter = Ratelimiter::builder(1000, Duration::from_secs(3600))
-//!     .max_tokens(1000)
-//!     .initial_available(1000)
-//!     .build()
-//!     .unwrap();
+#![cfg_attr(
+    feature = "std",
+    doc = r#"
+```
+use ratelimit::Ratelimiter;
+use std::time::Duration;
+
+// Constructs a ratelimiter that generates 1 tokens/s with no burst. This
+// can be used to produce a steady rate of requests. The ratelimiter starts
+// with no tokens available, which means across application restarts, we
+// cannot exceed the configured ratelimit.
+let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+    .build()
+    .unwrap();
+
+// Another use case might be admission control, where we start with some
+// initial budget and replenish it periodically. In this example, our
+// ratelimiter allows 1000 tokens/hour. For every hour long sliding window,
+// no more than 1000 tokens can be acquired. But all tokens can be used in
+// a single burst. Additional calls to `try_wait()` will return an error
+// until the next token addition.
+//
+// This is popular approach with public API ratelimits.
+let ratelimiter = Ratelimiter::builder(1000, Duration::from_secs(3600))
+    .max_tokens(1000)
+    .initial_available(1000)
+    .build()
+    .unwrap();
+
+// For very high rates, we should avoid using too short of an interval due
+// to limits of system clock resolution. Instead, it's better to allow some
+// burst and add multiple tokens per interval. The resulting ratelimiter
+// here generates 50 million tokens/s and allows no more than 50 tokens to
+// be acquired in any 1 microsecond long window.
+let ratelimiter = Ratelimiter::builder(50, Duration::from_micros(1))
+    .max_tokens(50)
+    .build()
+    .unwrap();
+
+// Alternatively, the ratelimiter can be constructed directly from a rate
+// in tokens/s and a burst size. The refill amount and interval will be
+// chosen for us, taking the clock resolution into account. Here we allow
+// up to 50 million tokens/s with a burst of 100 tokens.
+let ratelimiter = Ratelimiter::from_rate(50_000_000.0, 100)
+    .unwrap()
+    .build()
+    .unwrap();
+
+// constructs a ratelimiter that generates 100 tokens/s with no burst
+let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
+    .build()
+    .unwrap();
+
+for _ in 0..10 {
+    // a simple sleep-wait
+    if let Err(sleep) = ratelimiter.try_wait() {
+           std::thread::sleep(sleep);
+           continue;
+    }
+    
+    // do some ratelimited action here    
+}
+```
+"#
+)]
 //!
-//! // For very high rates, we should avoid using too short of an interval due
-//! // to limits of system clock resolution. Instead, it's better to allow some
-//! // burst and add multiple tokens per interval. The resulting ratelimiter
-//! // here generates 50 million tokens/s and allows no more than 50 tokens to
-//! // be acquired in any 1 microsecond long window.
-//! let ratelimiter = Ratelimiter::builder(50, Duration::from_micros(1))
-//!     .max_tokens(50)
-//!     .build()
-//!     .unwrap();
+//! Without the default `std` feature, the crate is `no_std` and only provides
+//! the [`TokenBucket`] and the [`StaticRatelimiter`], which run the same
+//! algorithm on a user-supplied [`Clock`].
 //!
-//! // constructs a ratelimiter that generates 100 tokens/s with no burst
-//! let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
-//!     .build()
-//!     .unwrap();
+//! On targets without native 64bit atomics, such as 32bit MIPS or PowerPC, the
+//! `portable-atomic` feature provides them from the `portable-atomic` crate.
+//! The `TokenBucket` does not use atomics, so it does not need the feature.
 //!
-//! for _ in 0..10 {
-//!     // a simple sleep-wait
-//!     if let Err(sleep) = ratelimiter.try_wait() {
-//!            std::thread::sleep(sleep);
-//!            continue;
-//!     }
-//!     
-//!     // do some ratelimited action here    
-//! }
-//! ```
-
-use clocksource::precise::{AtomicInstant, Duration, Instant};
-use core::sync::atomic::{AtomicU64, Ordering};
+//! On `wasm32-unknown-unknown`, such as in browsers and worker runtimes, the
+//! `wasm` feature reads the clocks with `performance.now()` and `Date.now()`.
+//! Functions which sleep the thread are unavailable there, so use the `try_*`
+//! functions and pace requests with the timers of the runtime.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// asserts that a value is within 0.1% of the target, for the tests of the
+// modules which are declared below
+#[cfg(all(test, feature = "std"))]
+macro_rules! approx_eq {
+    ($value:expr, $target:expr) => {
+        let value: f64 = $value;
+        let target: f64 = $target;
+        assert!(value >= target * 0.999, "{value} >= {}", target * 0.999);
+        assert!(value <= target * 1.001, "{value} <= {}", target * 1.001);
+    };
+}
+
+#[cfg(feature = "std")]
+mod adaptive;
+#[cfg(feature = "std")]
+mod carry_over;
+#[cfg(feature = "std")]
+mod composite;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "std")]
+mod control;
+#[cfg(feature = "std")]
+mod cost;
+#[cfg(feature = "distributed")]
+mod distributed;
+#[cfg(feature = "std")]
+mod distribution;
+#[cfg(feature = "std")]
+mod drr;
+#[cfg(feature = "std")]
+mod early_drop;
+#[cfg(feature = "std")]
+mod events;
+#[cfg(feature = "std")]
+mod experiment;
+#[cfg(feature = "std")]
+mod fair;
+#[cfg(feature = "std")]
+mod firing;
+#[cfg(feature = "std")]
+mod fractional;
+#[cfg(all(feature = "std", fuzzing))]
+mod fuzzing;
+#[cfg(feature = "std")]
+mod gate;
+#[cfg(feature = "std")]
+mod headers;
+#[cfg(feature = "histogram")]
+mod heatmap;
+#[cfg(feature = "std")]
+mod hot_keys;
+#[cfg(feature = "std")]
+mod janitor;
+#[cfg(feature = "std")]
+mod keyed;
+#[cfg(feature = "histogram")]
+mod latency;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(all(feature = "std", ratelimit_loom))]
+mod model;
+#[cfg(feature = "std")]
+mod multi;
+#[cfg(feature = "std")]
+mod notify;
+#[cfg(feature = "std")]
+mod observed;
+#[cfg(feature = "std")]
+mod observer;
+#[cfg(feature = "std")]
+mod pacer;
+#[cfg(feature = "std")]
+mod penalty;
+#[cfg(feature = "persist")]
+mod persist;
+#[cfg(feature = "futures")]
+mod poll;
+#[cfg(feature = "std")]
+mod prefix;
+#[cfg(feature = "std")]
+mod priority;
+#[cfg(feature = "std")]
+mod probabilistic;
+// proptest reads its regression files, which Miri's isolation prevents
+#[cfg(all(feature = "std", test, not(miri)))]
+mod properties;
+#[cfg(feature = "std")]
+mod quota;
+#[cfg(feature = "std")]
+mod ramp;
+#[cfg(feature = "std")]
+mod random;
+#[cfg(feature = "std")]
+mod rate;
+#[cfg(feature = "std")]
+mod ratio;
+#[cfg(feature = "std")]
+mod remote;
+#[cfg(feature = "std")]
+mod retry_after;
+#[cfg(feature = "std")]
+mod schedule;
+#[cfg(feature = "std")]
+mod set;
+#[cfg(all(feature = "shm", unix))]
+mod shm;
+#[cfg(feature = "std")]
+mod simulation;
+#[cfg(feature = "std")]
+mod sketch;
+#[cfg(feature = "std")]
+mod slab;
+#[cfg(feature = "std")]
+mod sleep;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+mod state;
+mod static_ratelimiter;
+#[cfg(feature = "std")]
+mod stats;
+mod token_bucket;
+#[cfg(feature = "std")]
+mod tune;
+#[cfg(feature = "std")]
+mod wait;
+#[cfg(feature = "std")]
+mod warmup;
+#[cfg(feature = "futures")]
+mod wfq;
+#[cfg(feature = "futures")]
+mod wheel;
+
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "channel")]
+pub mod channel;
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "hyper")]
+pub mod hyper;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "std")]
+pub mod iter;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "reqwest")]
+pub mod reqwest;
+#[cfg(feature = "rls")]
+pub mod rls;
+#[cfg(feature = "futures")]
+pub mod sink;
+#[cfg(feature = "futures")]
+pub mod stream;
+#[cfg(feature = "tower")]
+pub mod tower;
+
+#[cfg(feature = "std")]
+pub use adaptive::Adaptive;
+#[cfg(feature = "std")]
+pub use carry_over::CarryOver;
+#[cfg(feature = "std")]
+pub use composite::Composite;
+#[cfg(feature = "std")]
+pub use config::RatelimiterConfig;
+#[cfg(feature = "std")]
+pub use control::Mode;
+#[cfg(feature = "std")]
+pub use cost::{Cost, CostExtractor, ItemCost, UnitCost};
+#[cfg(feature = "redis")]
+pub use distributed::{AsyncRedisRatelimiter, RedisRatelimiter};
+#[cfg(feature = "distributed")]
+pub use distributed::{
+    BucketState, DistributedBackend, DistributedError, DistributedRatelimiter, FailurePolicy,
+    LeasedRatelimiter, MemoryBackend,
+};
+#[cfg(feature = "std")]
+pub use distribution::Distribution;
+#[cfg(feature = "std")]
+pub use drr::DeficitRoundRobin;
+#[cfg(feature = "std")]
+pub use events::Event;
+#[cfg(feature = "std")]
+pub use experiment::{Experiment, Stage};
+#[cfg(feature = "std")]
+pub use fractional::{FractionalBuilder, FractionalRatelimiter, DEFAULT_PRECISION};
+#[cfg(feature = "std")]
+pub use gate::{Gate, GateFactor};
+#[cfg(feature = "std")]
+pub use headers::RateLimitHeaders;
+#[cfg(feature = "histogram")]
+pub use heatmap::{Heatmap, Slice};
+#[cfg(feature = "std")]
+pub use hot_keys::{HotKey, HotKeys};
+#[cfg(feature = "std")]
+pub use janitor::{Janitor, SweepStats};
+#[cfg(feature = "std")]
+pub use keyed::{KeyedRatelimiter, KeyedRef, MemoryUsage};
+#[cfg(feature = "histogram")]
+pub use latency::LatencySnapshot;
+#[cfg(feature = "std")]
+pub use multi::{MultiResource, ResourceError, ResourcePermit};
+#[cfg(feature = "std")]
+pub use observer::RatelimiterObserver;
+#[cfg(feature = "std")]
+pub use pacer::Pacer;
+#[cfg(feature = "persist")]
+pub use persist::{Persistence, PersistenceHandle};
+#[cfg(feature = "futures")]
+pub use poll::Waiter;
+#[cfg(feature = "std")]
+pub use prefix::IpPrefix;
+#[cfg(feature = "std")]
+pub use probabilistic::ProbabilisticLimiter;
+#[cfg(feature = "std")]
+pub use quota::{AwsHeaders, GitHubHeaders, Quota, QuotaHeaders, StandardHeaders, StripeHeaders};
+#[cfg(feature = "std")]
+pub use ramp::{Curve, Ramp, RampBuilder};
+#[cfg(feature = "std")]
+pub use rate::Rate;
+#[cfg(feature = "std")]
+pub use retry_after::RetryAfter;
+#[cfg(feature = "std")]
+pub use schedule::{RateSchedule, Sine, Steps};
+#[cfg(feature = "std")]
+pub use set::LimiterSet;
+#[cfg(all(feature = "shm", unix))]
+pub use shm::SharedRatelimiter;
+#[cfg(feature = "std")]
+pub use simulation::{Simulation, SimulationReport};
+#[cfg(feature = "std")]
+pub use sketch::SketchRatelimiter;
+#[cfg(feature = "std")]
+pub use snapshot::Snapshot;
+#[cfg(feature = "std")]
+pub use state::State;
+pub use static_ratelimiter::StaticRatelimiter;
+#[cfg(feature = "std")]
+pub use stats::{Dropped, Stats};
+pub use token_bucket::{Clock, TokenBucket};
+#[cfg(feature = "std")]
+pub use tune::Adjustment;
+#[cfg(feature = "std")]
+pub use wait::WaitStrategy;
+#[cfg(feature = "std")]
+pub use warmup::DEFAULT_COLD_FACTOR;
+#[cfg(feature = "futures")]
+pub use wfq::WeightedFairQueue;
+
+// atomics which are provided by `portable-atomic` on targets without native
+// 64bit atomics, or by `loom` for the concurrency model tests
+#[cfg(all(not(feature = "portable-atomic"), not(ratelimit_loom)))]
+use core::sync::atomic;
+#[cfg(ratelimit_loom)]
+use loom::sync::atomic;
+#[cfg(all(feature = "portable-atomic", not(ratelimit_loom)))]
+use portable_atomic as atomic;
+
+#[cfg(feature = "std")]
+use atomic::{AtomicU64, AtomicU8, Ordering};
+#[cfg(all(feature = "std", not(ratelimit_loom)))]
+use clocksource::precise::AtomicInstant;
+#[cfg(feature = "std")]
+use clocksource::precise::{Duration, Instant, UnixInstant};
+#[cfg(feature = "std")]
+use early_drop::EarlyDrop;
+#[cfg(feature = "std")]
+use fair::Queue;
+#[cfg(all(feature = "std", ratelimit_loom))]
+use model::AtomicInstant;
+#[cfg(feature = "std")]
+use notify::Wakers;
+#[cfg(feature = "std")]
 use parking_lot::RwLock;
+#[cfg(feature = "std")]
+use priority::PriorityClass;
+#[cfg(feature = "std")]
+use random::Random;
+#[cfg(feature = "std")]
+use stats::{Counters, DropCause};
+#[cfg(feature = "std")]
 use thiserror::Error;
+#[cfg(feature = "std")]
+use warmup::Warmup;
 
+#[cfg(feature = "std")]
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum Error {
     #[error("available tokens cannot be set higher than max tokens")]
@@ -68,22 +390,328 @@ pub enum Error {
     RefillAmountTooHigh,
     #[error("refill interval in nanoseconds exceeds maximum u64")]
     RefillIntervalTooLong,
+    #[error("refill interval must be at least one microsecond")]
+    RefillIntervalTooShort,
+    #[error("rate must be a finite number of tokens/s greater than zero")]
+    InvalidRate,
+    #[error("scale must be a finite number greater than zero")]
+    InvalidScale,
+    #[error("cold factor must be a finite number no less than one")]
+    InvalidColdFactor,
+    #[error("jitter must be in the range 0.0..=1.0")]
+    InvalidJitter,
+    #[error("priority reserves must be in the range 0.0..1.0")]
+    InvalidReserve,
+    #[error("weights must be finite numbers greater than zero")]
+    InvalidWeight,
+    #[error(
+        "early drop threshold and probability must be in the range 0.0..=1.0 and greater than zero"
+    )]
+    InvalidEarlyDrop,
+    #[error("fraction must be in the range 0.0..=1.0")]
+    InvalidFraction,
+    #[error("prefix length cannot exceed the length of the address")]
+    InvalidPrefix,
+    #[error(
+        "penalty denials, window, and cooldown must be greater than zero and scale in the range 0.0..1.0"
+    )]
+    InvalidPenalty,
+    #[error("precision must be at least one unit per token")]
+    InvalidPrecision,
+    #[error("sketch error bounds must be in the range 0.0..1.0 and greater than zero")]
+    InvalidSketch,
+    #[error("tolerance must be a finite number no less than zero")]
+    InvalidTolerance,
+    #[cfg(feature = "histogram")]
+    #[error("heatmap resolution must be greater than zero and no longer than the span")]
+    InvalidHeatmap,
+    #[error("rate string is malformed, expected a form like `100/s`")]
+    MalformedRate,
+    #[error("environment variable `{0}` is not set")]
+    MissingEnvVar(String),
+    #[error("environment variable `{0}` has an invalid value")]
+    InvalidEnvVar(String),
+    #[error("invalid ratelimiter config: {0}")]
+    InvalidConfig(String),
+}
+
+/// The reasons that an attempt to acquire tokens may fail.
+#[cfg(feature = "std")]
+#[non_exhaustive]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryAcquireError {
+    #[error("insufficient tokens, next refill in {0:?}")]
+    Insufficient(core::time::Duration),
+    #[error("the ratelimiter is paused")]
+    Paused,
+    #[error("the ratelimiter is denying all requests")]
+    Denied,
+    #[error("the ratelimiter is closed")]
+    Closed,
+}
+
+/// The shortest refill interval that will be selected when the refill amount
+/// and interval are derived from a rate. Shorter intervals are not reliably
+/// achievable due to the system clock resolution.
+#[cfg(feature = "std")]
+const MIN_REFILL_INTERVAL_NS: u64 = 1_000;
+
+#[cfg(feature = "std")]
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Internal function to select the refill amount and interval for a rate in
+/// tokens/s. We prefer adding a single token per interval, but will add more
+/// tokens per interval if that is required to keep the interval at or above
+/// `MIN_REFILL_INTERVAL_NS`.
+#[cfg(feature = "std")]
+fn amount_and_interval(tokens_per_second: f64) -> Result<(u64, core::time::Duration), Error> {
+    if !tokens_per_second.is_finite() || tokens_per_second <= 0.0 {
+        return Err(Error::InvalidRate);
+    }
+
+    // the number of nanoseconds between each token
+    let nanos_per_token = NANOS_PER_SEC as f64 / tokens_per_second;
+
+    if nanos_per_token >= u64::MAX as f64 {
+        return Err(Error::RefillIntervalTooLong);
+    }
+
+    let amount = if nanos_per_token >= MIN_REFILL_INTERVAL_NS as f64 {
+        1
+    } else {
+        (MIN_REFILL_INTERVAL_NS as f64 / nanos_per_token).ceil() as u64
+    };
+
+    let interval = (amount as f64 * nanos_per_token).round() as u64;
+
+    Ok((amount, core::time::Duration::from_nanos(interval)))
+}
+
+/// Internal function to construct a builder which allows `amount` tokens over
+/// each `window`. The tokens are spread across the window using the smallest
+/// refill amount that keeps the interval at or above `MIN_REFILL_INTERVAL_NS`
+/// and the max tokens is set to allow the full `amount` to be used in a burst.
+#[cfg(feature = "std")]
+fn per_window(amount: u64, window: core::time::Duration) -> Builder {
+    if amount == 0 {
+        return Builder::new(0, window).max_tokens(0);
+    }
+
+    let window = window.as_nanos();
+
+    let refill_amount = (MIN_REFILL_INTERVAL_NS as u128 * amount as u128)
+        .div_ceil(window)
+        .clamp(1, amount as u128);
+
+    // round the interval up, and add the tokens lost to rounding as a fraction
+    // of a token on each refill so that the rate is exact
+    let scaled = window * refill_amount;
+    let interval = scaled.div_ceil(amount as u128);
+    let excess = interval * amount as u128 - scaled;
+
+    Builder::new(
+        refill_amount as u64,
+        core::time::Duration::from_nanos(interval as u64),
+    )
+    .max_tokens(amount)
+    .refill_fraction(excess, window)
+}
+
+/// Internal function to return the current system time. The clock is read
+/// through `clocksource`, as `SystemTime::now` panics on `wasm32` targets.
+#[cfg(feature = "std")]
+fn system_now() -> std::time::SystemTime {
+    let since_epoch = UnixInstant::now().duration_since(UnixInstant::EPOCH);
+
+    std::time::UNIX_EPOCH + core::time::Duration::from_nanos(since_epoch.as_nanos())
+}
+
+/// Internal function to return the time `intervals` refill intervals of
+/// `interval` after `start`, in the same units. The math is widened to `u128`
+/// so that long intervals and idle periods saturate rather than overflow.
+#[cfg(feature = "std")]
+pub(crate) fn advance(start: u64, intervals: u64, interval: u64) -> u64 {
+    (start as u128 + intervals as u128 * interval as u128).min(u64::MAX as u128) as u64
+}
+
+/// Internal function to convert a number of refill intervals into a
+/// multiplier for a `Duration`, saturating rather than truncating.
+#[cfg(feature = "std")]
+pub(crate) fn intervals_u32(intervals: u64) -> u32 {
+    intervals.min(u32::MAX as u64) as u32
+}
+
+/// Internal function to return the instant `intervals` refill intervals of
+/// `interval` nanoseconds after `start`. See [`advance`].
+#[cfg(feature = "std")]
+pub(crate) fn advance_instant(start: Instant, intervals: u64, interval: u64) -> Instant {
+    let epoch = Instant::default();
+    let start = start.duration_since(epoch).as_nanos();
+
+    epoch + Duration::from_nanos(advance(start, intervals, interval))
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq)]
 struct Parameters {
     capacity: u64,
+    // the fraction of a token added on each refill, as a numerator and
+    // denominator, for rates which are not a whole number of tokens per
+    // refill interval
+    fraction: (u64, u64),
     refill_amount: u64,
     refill_interval: Duration,
+    // multiplier from the gate, if any
+    gate: f64,
+    // multiplier applied to the configured rate
+    scale: f64,
+    // the refill interval with the scale and gate applied
+    scaled_interval: Duration,
 }
 
+#[cfg(feature = "std")]
+impl Parameters {
+    fn new(capacity: u64, refill_amount: u64, refill_interval: Duration) -> Self {
+        let mut parameters = Self {
+            capacity,
+            fraction: (0, 1),
+            refill_amount,
+            refill_interval,
+            gate: 1.0,
+            scale: 1.0,
+            scaled_interval: refill_interval,
+        };
+
+        // a zero interval is treated as the shortest interval, as it is when
+        // set at runtime
+        parameters.rescale();
+        parameters
+    }
+
+    /// Internal function to recalculate the scaled interval. Must be called
+    /// whenever the refill interval, scale, or gate factor is changed.
+    fn rescale(&mut self) {
+        let nanos = (self.refill_interval.as_nanos() as f64 / (self.scale * self.gate))
+            .round()
+            .clamp(1.0, u64::MAX as f64);
+
+        self.scaled_interval = Duration::from_nanos(nanos as u64);
+    }
+}
+
+#[cfg(feature = "std")]
 pub struct Ratelimiter {
     available: AtomicU64,
+    created: Instant,
     dropped: AtomicU64,
+    extensions: Option<Box<Extensions>>,
+    // the fractional tokens carried over between refills
+    fraction: AtomicU64,
     parameters: RwLock<Parameters>,
+    paused_at: AtomicInstant,
     refill_at: AtomicInstant,
+    state: AtomicU8,
+    wakers: Wakers,
+    #[cfg(feature = "futures")]
+    wheel: std::sync::OnceLock<std::sync::Arc<wheel::Wheel>>,
 }
 
+// the reference counts which precede the value in the allocation of an `Arc`
+#[cfg(feature = "std")]
+const ARC_HEADER: usize = 2 * core::mem::size_of::<usize>();
+
+/// Internal type which holds the state of the optional features of a
+/// `Ratelimiter`. It is only allocated if one of them is configured, so that a
+/// plain ratelimiter stays small and the hot path checks for all of them with
+/// a single branch.
+#[cfg(feature = "std")]
+struct Extensions {
+    adjustment: Option<Adjustment>,
+    carry_over: CarryOver,
+    counters: Option<Counters>,
+    distribution: Distribution,
+    early_drop: Option<EarlyDrop>,
+    early_dropped: AtomicU64,
+    events: Option<std::sync::mpsc::SyncSender<Event>>,
+    gate: Option<Box<dyn Gate>>,
+    #[cfg(feature = "histogram")]
+    heatmap: Option<heatmap::Timeline>,
+    jitter: f64,
+    #[cfg(feature = "histogram")]
+    latency: Option<histogram::AtomicHistogram>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<metrics::Metrics>,
+    observer: Option<Box<dyn RatelimiterObserver>>,
+    penalty: Option<penalty::Penalty>,
+    priorities: Vec<PriorityClass>,
+    queue: Option<std::sync::Arc<Queue>>,
+    random: Random,
+    schedule: Option<Box<dyn RateSchedule>>,
+    smooth: bool,
+    wait_strategy: WaitStrategy,
+    warmup: Option<Warmup>,
+}
+
+#[cfg(feature = "std")]
+impl Extensions {
+    /// Internal function to return `true` if none of the optional features are
+    /// configured, in which case the extensions don't need to be kept.
+    fn is_empty(&self) -> bool {
+        #[cfg(feature = "histogram")]
+        if self.heatmap.is_some() || self.latency.is_some() {
+            return false;
+        }
+
+        #[cfg(feature = "metrics")]
+        if self.metrics.is_some() {
+            return false;
+        }
+
+        self.adjustment.is_none()
+            && self.carry_over == CarryOver::Unlimited
+            && self.counters.is_none()
+            && self.distribution == Distribution::Uniform
+            && self.early_drop.is_none()
+            && self.events.is_none()
+            && self.gate.is_none()
+            && self.jitter == 0.0
+            && self.observer.is_none()
+            && self.penalty.is_none()
+            && self.priorities.is_empty()
+            && self.queue.is_none()
+            && self.schedule.is_none()
+            && !self.smooth
+            && self.wait_strategy == WaitStrategy::default()
+            && self.warmup.is_none()
+    }
+
+    /// Internal function to return the number of bytes allocated by the
+    /// optional features, not including the extensions themselves.
+    fn heap_size(&self) -> usize {
+        let bytes = self.priorities.capacity() * core::mem::size_of::<PriorityClass>()
+            + self.gate.as_deref().map_or(0, core::mem::size_of_val)
+            + self.observer.as_deref().map_or(0, core::mem::size_of_val)
+            + self.schedule.as_deref().map_or(0, core::mem::size_of_val)
+            + self.queue.as_deref().map_or(0, |queue| {
+                ARC_HEADER + core::mem::size_of::<Queue>() + queue.heap_size()
+            });
+
+        #[cfg(feature = "histogram")]
+        let bytes = bytes
+            + self
+                .heatmap
+                .as_ref()
+                .map_or(0, |heatmap| heatmap.heap_size())
+            + self
+                .latency
+                .as_ref()
+                .map_or(0, |_| latency::histogram_size());
+
+        bytes
+    }
+}
+
+#[cfg(feature = "std")]
 impl Ratelimiter {
     /// Initialize a builder that will construct a `Ratelimiter` that adds the
     /// specified `amount` of tokens to the token bucket after each `interval`
@@ -93,16 +721,116 @@ impl Ratelimiter {
     /// the `interval`. To be safe, it is recommended to set the interval to be
     /// no less than 1 microsecond. This also means that the number of tokens
     /// per interval should be > 1 to achieve rates beyond 1 million tokens/s.
+    /// [`Builder::auto_tune`] can select these for the clock resolution.
     pub fn builder(amount: u64, interval: core::time::Duration) -> Builder {
         Builder::new(amount, interval)
     }
 
-    /// Return the current effective rate of the Ratelimiter in tokens/second
+    /// Initialize a builder that will construct a `Ratelimiter` which
+    /// generates tokens at the provided rate in tokens/s and allows bursts of
+    /// up to `burst` tokens.
+    ///
+    /// The refill amount and interval are selected automatically. A single
+    /// token is added per interval unless that would require an interval
+    /// shorter than 1 microsecond, in which case multiple tokens are added on
+    /// each refill. If the selected refill amount is larger than `burst`, the
+    /// max tokens will be raised to the refill amount.
+    ///
+    /// Returns an error if the rate is not a finite number greater than zero
+    /// or if the rate is so low that the interval cannot be represented.
+    pub fn from_rate(tokens_per_second: f64, burst: u64) -> Result<Builder, Error> {
+        let (amount, interval) = amount_and_interval(tokens_per_second)?;
+
+        Ok(Builder::new(amount, interval).max_tokens(burst.max(amount)))
+    }
+
+    /// Initialize a builder that will construct a `Ratelimiter` which allows
+    /// `amount` tokens per second. The tokens are added evenly across each
+    /// second and the max tokens defaults to `amount` so that the full budget
+    /// for a second may be used in a single burst.
+    pub fn per_second(amount: u64) -> Builder {
+        per_window(amount, core::time::Duration::from_secs(1))
+    }
+
+    /// Initialize a builder that will construct a `Ratelimiter` which allows
+    /// `amount` tokens per minute. The tokens are added evenly across each
+    /// minute and the max tokens defaults to `amount` so that the full budget
+    /// for a minute may be used in a single burst.
+    pub fn per_minute(amount: u64) -> Builder {
+        per_window(amount, core::time::Duration::from_secs(60))
+    }
+
+    /// Initialize a builder that will construct a `Ratelimiter` which allows
+    /// `amount` tokens per hour. The tokens are added evenly across each hour
+    /// and the max tokens defaults to `amount` so that the full budget for an
+    /// hour may be used in a single burst.
+    pub fn per_hour(amount: u64) -> Builder {
+        per_window(amount, core::time::Duration::from_secs(3600))
+    }
+
+    /// Return the current effective rate of the Ratelimiter in tokens/second.
+    /// This includes any scaling applied with [`Ratelimiter::set_scale`] or by
+    /// a [`Gate`].
     pub fn rate(&self) -> f64 {
         let parameters = self.parameters.read();
 
-        parameters.refill_amount as f64 * 1_000_000_000.0
-            / parameters.refill_interval.as_nanos() as f64
+        let (numerator, denominator) = parameters.fraction;
+        let amount = parameters.refill_amount as f64 + numerator as f64 / denominator as f64;
+
+        amount * 1_000_000_000.0 / parameters.scaled_interval.as_nanos() as f64
+    }
+
+    /// Return the current multiplier applied to the configured rate.
+    pub fn scale(&self) -> f64 {
+        self.parameters.read().scale
+    }
+
+    /// Allows for proportionally scaling the configured rate at runtime. For
+    /// example, a scale of `0.5` halves the effective rate and a scale of `2.0`
+    /// doubles it. The configured refill amount and interval are unchanged, so
+    /// restoring a scale of `1.0` returns to the configured rate.
+    ///
+    /// The scale is applied by adjusting the interval between refills. It is
+    /// retained across changes to the refill interval or rate.
+    pub fn set_scale(&self, scale: f64) -> Result<(), Error> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(Error::InvalidScale);
+        }
+
+        let mut parameters = self.parameters.write();
+
+        parameters.scale = scale;
+        parameters.rescale();
+        drop(parameters);
+
+        self.notify_parameters();
+        Ok(())
+    }
+
+    /// Allows for changing the effective rate, in tokens/s, at runtime. The
+    /// refill amount and interval are selected in the same way as for
+    /// [`Ratelimiter::from_rate`] and are updated together so that the new
+    /// rate takes effect atomically.
+    ///
+    /// Returns an error if the rate is invalid or if the selected refill amount
+    /// would exceed the max tokens.
+    pub fn set_rate(&self, tokens_per_second: f64) -> Result<(), Error> {
+        let (amount, interval) = amount_and_interval(tokens_per_second)?;
+
+        let mut parameters = self.parameters.write();
+
+        if amount > parameters.capacity {
+            return Err(Error::RefillAmountTooHigh);
+        }
+
+        parameters.refill_amount = amount;
+        parameters.refill_interval = Duration::from_nanos(interval.as_nanos() as u64);
+        parameters.fraction = (0, 1);
+        parameters.rescale();
+        drop(parameters);
+
+        self.notify_parameters();
+        Ok(())
     }
 
     /// Return the current interval between refills.
@@ -112,6 +840,12 @@ impl Ratelimiter {
         core::time::Duration::from_nanos(parameters.refill_interval.as_nanos())
     }
 
+    /// Internal function to return the interval between refills with the scale
+    /// applied.
+    fn scaled_interval(&self) -> core::time::Duration {
+        core::time::Duration::from_nanos(self.parameters.read().scaled_interval.as_nanos())
+    }
+
     /// Allows for changing the interval between refills at runtime.
     pub fn set_refill_interval(&self, duration: core::time::Duration) -> Result<(), Error> {
         if duration.as_nanos() > u64::MAX as u128 {
@@ -121,6 +855,11 @@ impl Ratelimiter {
         let mut parameters = self.parameters.write();
 
         parameters.refill_interval = Duration::from_nanos(duration.as_nanos() as u64);
+        parameters.fraction = (0, 1);
+        parameters.rescale();
+        drop(parameters);
+
+        self.notify_parameters();
         Ok(())
     }
 
@@ -139,6 +878,10 @@ impl Ratelimiter {
             Err(Error::RefillAmountTooHigh)
         } else {
             parameters.refill_amount = amount;
+            parameters.fraction = (0, 1);
+            drop(parameters);
+
+            self.notify_parameters();
             Ok(())
         }
     }
@@ -153,6 +896,7 @@ impl Ratelimiter {
     /// Allows for changing the maximum number of tokens that can be held by the
     /// ratelimiter for immediate use. This effectively sets the burst size. The
     /// configured value must be greater than or equal to the refill amount.
+    /// Any tokens available in excess of the new max tokens are dropped.
     pub fn set_max_tokens(&self, amount: u64) -> Result<(), Error> {
         let mut parameters = self.parameters.write();
 
@@ -160,20 +904,13 @@ impl Ratelimiter {
             Err(Error::MaxTokensTooLow)
         } else {
             parameters.capacity = amount;
-            loop {
-                let available = self.available();
-                if amount > available {
-                    if self
-                        .available
-                        .compare_exchange(available, amount, Ordering::AcqRel, Ordering::Acquire)
-                        .is_ok()
-                    {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
+
+            // tokens in excess of the new capacity are dropped
+            let available = self.available.fetch_min(amount, Ordering::AcqRel);
+            drop(parameters);
+
+            self.record_dropped(DropCause::Overflow, available.saturating_sub(amount));
+            self.notify_parameters();
             Ok(())
         }
     }
@@ -196,24 +933,57 @@ impl Ratelimiter {
             Err(Error::AvailableTokensTooHigh)
         } else {
             self.available.store(amount, Ordering::Release);
+            drop(parameters);
+            self.wakers.wake_all();
             Ok(())
         }
     }
 
-    /// Returns the number of tokens that have been dropped due to bucket
-    /// overflowing.
+    /// Returns the total number of tokens that have been dropped, whether due
+    /// to the bucket overflowing or for another cause. See
+    /// [`Ratelimiter::dropped_by_cause`].
     pub fn dropped(&self) -> u64 {
         self.dropped.load(Ordering::Relaxed)
     }
 
+    /// Internal function to return the number of bytes allocated by the
+    /// ratelimiter, not including the ratelimiter itself.
+    pub(crate) fn heap_size(&self) -> usize {
+        let bytes = self.wakers.heap_size()
+            + self.extensions().map_or(0, |extensions| {
+                core::mem::size_of::<Extensions>() + extensions.heap_size()
+            });
+
+        #[cfg(feature = "futures")]
+        let bytes = bytes
+            + self.wheel.get().map_or(0, |wheel| {
+                ARC_HEADER + core::mem::size_of::<wheel::Wheel>() + wheel.heap_size()
+            });
+
+        bytes
+    }
+
+    /// Internal function to return the state of the optional features, if any
+    /// of them are configured.
+    #[inline]
+    fn extensions(&self) -> Option<&Extensions> {
+        self.extensions.as_deref()
+    }
+
     /// Internal function to refill the token bucket. Called as part of
     /// `try_wait()`
     fn refill(&self, time: Instant) -> Result<(), core::time::Duration> {
         // will hold the number of elapsed refill intervals
         let mut intervals;
+        // will hold the number of tokens to add per interval
+        let mut amount_per_interval;
         // will hold a read lock for the refill parameters
         let mut parameters;
 
+        // the schedule is evaluated before checking if a refill is due, since
+        // a change in rate may move the next refill earlier
+        self.update_schedule(time);
+
         loop {
             // determine when next refill should occur
             let refill_at = self.refill_at.load(Ordering::Relaxed);
@@ -225,14 +995,32 @@ impl Ratelimiter {
                 ));
             }
 
+            // refills are suspended while paused
+            if self.is_paused() {
+                return Err(self.scaled_interval());
+            }
+
+            // apply any change in rate due to a gate before refilling
+            self.update_gate();
+
             // acquire read lock for refill parameters
             parameters = self.parameters.read();
 
-            intervals = (time - refill_at).as_nanos() / parameters.refill_interval.as_nanos() + 1;
+            let interval;
+            (interval, amount_per_interval) = self.refill_step(time, &parameters);
 
-            // calculate when the following refill would be
-            let next_refill =
-                refill_at + Duration::from_nanos(intervals * parameters.refill_interval.as_nanos());
+            // calculate the number of refills which are due and when the
+            // following refill would be
+            let next_refill;
+            (intervals, next_refill) = self.refills_due(refill_at, time, interval);
+
+            // once the refill time has saturated at the latest instant, the
+            // refills cannot move forward and no further tokens are issued
+            if next_refill <= refill_at {
+                return Err(core::time::Duration::from_nanos(
+                    parameters.scaled_interval.as_nanos(),
+                ));
+            }
 
             // compare/exchange, if race, loop and check if we still need to
             // refill before trying again
@@ -246,44 +1034,176 @@ impl Ratelimiter {
         }
 
         // figure out how many tokens we might add
-        let amount = intervals * parameters.refill_amount;
+        let amount = intervals
+            .saturating_mul(amount_per_interval)
+            .saturating_add(self.refill_fraction(intervals, amount_per_interval, &parameters));
 
-        let available = self.available.load(Ordering::Acquire);
+        let (expired, overflow) = if self.carry_over() != CarryOver::Unlimited {
+            self.refill_windowed(intervals, amount_per_interval, parameters.capacity)
+        } else {
+            // we will fill the bucket up to the capacity and the remainder is
+            // dropped. The capacity is checked within the update, since tokens
+            // may be returned concurrently.
+            let to_add = |available: u64| amount.min(parameters.capacity.saturating_sub(available));
 
-        if available + amount >= parameters.capacity {
-            // we will fill the bucket up to the capacity
-            let to_add = parameters.capacity - available;
-            self.available.fetch_add(to_add, Ordering::Release);
+            let previous = self
+                .available
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |a| Some(a + to_add(a)))
+                .unwrap();
 
-            // and increment the number of tokens dropped
-            self.dropped.fetch_add(amount - to_add, Ordering::Relaxed);
-        } else {
-            self.available.fetch_add(amount, Ordering::Release);
-        }
+            (0, amount - to_add(previous))
+        };
+
+        // tasks waiting for tokens are woken without holding the lock
+        drop(parameters);
+        self.wakers.wake_all();
+
+        self.notify_refill(amount);
+        self.record_dropped(DropCause::Overflow, overflow);
+        self.record_dropped(DropCause::Expired, expired);
 
         Ok(())
     }
 
+    /// Internal function to return the time between refills and the number of
+    /// tokens to add on each refill. This accounts for any warm-up and, when
+    /// smoothing is enabled, spreads the refill amount evenly across the
+    /// refill interval.
+    fn refill_step(&self, time: Instant, parameters: &Parameters) -> (Duration, u64) {
+        let interval = self.warmup_interval(time, parameters.scaled_interval);
+
+        if self.extensions().is_some_and(|e| e.smooth) && parameters.refill_amount > 1 {
+            (
+                Duration::from_nanos((interval.as_nanos() / parameters.refill_amount).max(1)),
+                1,
+            )
+        } else {
+            (interval, parameters.refill_amount)
+        }
+    }
+
     pub fn return_n(&self, n: u64) {
-        self.available
+        self.refund(n);
+        self.record_returned(n);
+    }
+
+    /// Internal function to put `n` tokens back in the bucket without counting
+    /// them as returned. Tokens which would overfill the bucket are dropped.
+    pub(crate) fn refund(&self, n: u64) {
+        let capacity = self.max_tokens();
+
+        let previous = self
+            .available
             .fetch_update(Ordering::AcqRel, Ordering::Acquire, |a| {
-                Some(std::cmp::min(a + n, self.max_tokens()))
+                Some(a.saturating_add(n).min(capacity).max(a))
             })
             .unwrap();
+
+        self.wakers.wake_all();
+
+        let added = previous
+            .saturating_add(n)
+            .min(capacity)
+            .saturating_sub(previous);
+        self.record_dropped(DropCause::Overflow, n - added);
     }
 
-    /// Non-blocking function to "wait" for a single token. On success, a single
-    /// token has been acquired. On failure, a `Duration` hinting at when the
-    /// next refill would occur is returned.
+    /// Non-blocking function to "wait" for `n` tokens. On success, the tokens
+    /// have been acquired. On failure, a `Duration` hinting at when the next
+    /// refill would occur is returned.
+    ///
+    /// If the ratelimiter is paused or denying all requests, the hint is the
+    /// refill interval. Use
+    /// [`Ratelimiter::try_acquire_n`] to distinguish between the causes of a
+    /// failure.
     pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        self.try_acquire_n(n).map_err(|e| match e {
+            TryAcquireError::Insufficient(duration) => duration,
+            TryAcquireError::Paused | TryAcquireError::Denied | TryAcquireError::Closed => {
+                self.scaled_interval()
+            }
+        })
+    }
+
+    /// Non-blocking function to acquire `n` tokens. On success, the tokens
+    /// have been acquired. On failure, the error indicates why the tokens could
+    /// not be acquired. When there are insufficient tokens, the error contains
+    /// a `Duration` hinting at when the next refill would occur.
+    ///
+    /// If FIFO ordering is enabled, this fails while other callers are queued
+    /// waiting for tokens. See [`Builder::fifo`].
+    pub fn try_acquire_n(&self, n: u64) -> Result<(), TryAcquireError> {
+        let result = self.attempt(n);
+        self.record(n, result)
+    }
+
+    /// Internal function to attempt to acquire `n` tokens without recording
+    /// the outcome. See [`Ratelimiter::try_acquire_n`].
+    fn attempt(&self, n: u64) -> Result<(), TryAcquireError> {
+        match self.check_state().or_else(|| self.check_penalty()) {
+            Some(result) => result,
+            None if self.queue().is_some_and(|queue| !queue.is_empty()) => {
+                Err(TryAcquireError::Insufficient(self.scaled_interval()))
+            }
+            None => self.take_or_drop(n, 0),
+        }
+    }
+
+    /// Internal function to take `n` tokens from the bucket, leaving at least
+    /// `floor` tokens, and then reject the acquisition if it is chosen by
+    /// probabilistic early drop.
+    pub(crate) fn take_or_drop(&self, n: u64, floor: u64) -> Result<(), TryAcquireError> {
+        self.take(n, floor).and_then(|()| {
+            if self.early_drop(n) {
+                Err(TryAcquireError::Insufficient(self.scaled_interval()))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Internal function to determine the outcome of an acquisition when the
+    /// ratelimiter is not simply enforcing. Returns `None` if the acquisition
+    /// should proceed as normal.
+    fn check_state(&self) -> Option<Result<(), TryAcquireError>> {
+        // a single load determines if the ratelimiter is enforcing as normal
+        let state = self.state.load(Ordering::Acquire);
+        if state != control::ENFORCE {
+            control::check(state)
+        } else {
+            None
+        }
+    }
+
+    /// Internal function to take `n` tokens from the bucket, refilling it
+    /// first if a refill is due. The tokens are only taken if at least `floor`
+    /// tokens would remain.
+    fn take(&self, n: u64, floor: u64) -> Result<(), TryAcquireError> {
+        self.take_at(n, floor, Instant::now)
+    }
+
+    /// Internal function to take `n` tokens from the bucket, with the current
+    /// time provided by `now`. This allows the concurrency model tests to run
+    /// the ratelimiter at fixed times.
+    fn take_at(
+        &self,
+        n: u64,
+        floor: u64,
+        now: impl Fn() -> Instant,
+    ) -> Result<(), TryAcquireError> {
         // We have an outer loop that drives the refilling of the token bucket.
         // This will only be repeated if we refill successfully, but somebody
         // else takes the newly available token(s) before we can attempt to
         // acquire one.
         loop {
+            let now = now();
+
+            // Track activity for the warm-up, if there is one.
+            self.update_warmup(now);
+
             // Attempt to refill the bucket. This makes sure we are moving the
             // time forward, issuing new tokens, hitting our max capacity, etc.
-            let refill_result = self.refill(Instant::now());
+            let refill_result = self.refill(now);
 
             // Note: right now it doesn't matter if refill succeeded or failed.
             // We might already have tokens available. Even if refill failed we
@@ -313,7 +1233,7 @@ impl Ratelimiter {
                 // Note: this is when it matters if the refill was successful.
                 // We use the success or failure to determine if there was a
                 // race.
-                if available == 0 {
+                if available <= floor {
                     match refill_result {
                         Ok(_) => {
                             // This means we raced. Refill succeeded but another
@@ -324,20 +1244,33 @@ impl Ratelimiter {
                         Err(e) => {
                             // Refill failed and there were no tokens already
                             // available. We return the error which contains a
-                            // duration until the next refill.
-                            return Err(e * (n/self.refill_amount()) as u32);
+                            // duration until the next refill, plus any further
+                            // refills needed to cover the shortfall.
+                            let short = n.saturating_add(floor) - available;
+                            let intervals = short.div_ceil(self.refill_amount().max(1));
+                            return Err(TryAcquireError::Insufficient(
+                                e.saturating_add(
+                                    self.scaled_interval()
+                                        .saturating_mul(intervals_u32(intervals.saturating_sub(1))),
+                                ),
+                            ));
                         }
                     }
                 }
 
-                // If we made it here, available is > 0 and so we can attempt to
-                // acquire a token by doing a simple compare exchange on
-                // available with the new value.
-                match available.overflowing_sub(n) {
-                    (new, false) => {
+                // If we made it here, available is above the floor and so we
+                // can attempt to acquire a token by doing a simple compare
+                // exchange on available with the new value.
+                match available.overflowing_sub(n.saturating_add(floor)) {
+                    (_, false) => {
                         if self
                             .available
-                            .compare_exchange(available, new, Ordering::AcqRel, Ordering::Acquire)
+                            .compare_exchange(
+                                available,
+                                available - n,
+                                Ordering::AcqRel,
+                                Ordering::Acquire,
+                            )
                             .is_ok()
                         {
                             // We have acquired a token and can return successfully
@@ -345,12 +1278,16 @@ impl Ratelimiter {
                         }
                     }
                     (new, true) => {
-                        let short = u64::MAX - new;
-                        return Err(self.refill_interval() * (short/self.refill_amount()) as u32);
+                        // the wrapped difference is the number of tokens short
+                        let short = new.wrapping_neg();
+                        return Err(TryAcquireError::Insufficient(
+                            self.scaled_interval().saturating_mul(intervals_u32(
+                                short.div_ceil(self.refill_amount().max(1)),
+                            )),
+                        ));
                     }
                 }
 
-
                 // If we raced on the compare exchange, we need to repeat the
                 // token acquisition. Either there will be another token we can
                 // try to acquire, or we will break and attempt a refill again.
@@ -361,26 +1298,89 @@ impl Ratelimiter {
     pub fn try_wait(&self) -> Result<(), core::time::Duration> {
         self.try_wait_n(1)
     }
+
+    /// Non-blocking function to acquire a single token. See
+    /// [`Ratelimiter::try_acquire_n`] for details.
+    pub fn try_acquire(&self) -> Result<(), TryAcquireError> {
+        self.try_acquire_n(1)
+    }
 }
 
+#[cfg(feature = "std")]
 pub struct Builder {
+    aligned: bool,
+    auto_tune: bool,
+    carry_over: CarryOver,
+    counters: bool,
+    distribution: Distribution,
+    early_drop: Option<EarlyDrop>,
+    events: Option<std::sync::mpsc::SyncSender<Event>>,
+    fifo: bool,
+    gate: Option<Box<dyn Gate>>,
+    #[cfg(feature = "histogram")]
+    heatmap: Option<heatmap::HeatmapConfig>,
     initial_available: u64,
+    jitter: f64,
+    #[cfg(feature = "histogram")]
+    latency_histogram: bool,
     max_tokens: u64,
+    #[cfg(feature = "metrics")]
+    metrics: Option<String>,
+    observed_rate_window: core::time::Duration,
+    observer: Option<Box<dyn RatelimiterObserver>>,
+    penalty: Option<penalty::PenaltyConfig>,
     refill_amount: u64,
+    refill_fraction: (u64, u64),
     refill_interval: core::time::Duration,
+    reserves: Vec<f64>,
+    restore: Option<State>,
+    schedule: Option<Box<dyn RateSchedule>>,
+    smooth: bool,
+    wait_strategy: WaitStrategy,
+    warmup: Option<core::time::Duration>,
+    cold_factor: f64,
 }
 
+#[cfg(feature = "std")]
 impl Builder {
     /// Initialize a new builder that will add `amount` tokens after each
     /// `interval` has elapsed.
     fn new(amount: u64, interval: core::time::Duration) -> Self {
         Self {
+            aligned: false,
+            auto_tune: false,
+            carry_over: CarryOver::Unlimited,
+            counters: false,
+            distribution: Distribution::Uniform,
+            early_drop: None,
+            events: None,
+            fifo: false,
+            gate: None,
+            #[cfg(feature = "histogram")]
+            heatmap: None,
             // default of zero tokens initially
             initial_available: 0,
+            // default of no jitter
+            jitter: 0.0,
+            #[cfg(feature = "histogram")]
+            latency_histogram: false,
             // default of one to prohibit bursts
             max_tokens: 1,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            observed_rate_window: observed::DEFAULT_WINDOW,
+            observer: None,
+            penalty: None,
             refill_amount: amount,
+            refill_fraction: (0, 1),
             refill_interval: interval,
+            reserves: Vec::new(),
+            restore: None,
+            schedule: None,
+            smooth: false,
+            wait_strategy: WaitStrategy::Sleep,
+            warmup: None,
+            cold_factor: warmup::DEFAULT_COLD_FACTOR,
         }
     }
 
@@ -405,14 +1405,46 @@ impl Builder {
     /// availble initially to make your application more well-behaved in event
     /// of process restarts.
     ///
+    /// The initial tokens are limited to `max_tokens`, as they are for a
+    /// [`TokenBucket`] or [`StaticRatelimiter`].
+    ///
     /// The default is that no tokens are initially available.
     pub fn initial_available(mut self, tokens: u64) -> Self {
         self.initial_available = tokens;
         self
     }
 
+    /// Spread the tokens from each refill evenly across the refill interval
+    /// instead of adding them all at once at the end of the interval. When the
+    /// refill amount is greater than one, this avoids micro-bursts and results
+    /// in smoother pacing with coarse refill intervals.
+    ///
+    /// The default is that smoothing is disabled.
+    pub fn smooth(mut self, enabled: bool) -> Self {
+        self.smooth = enabled;
+        self
+    }
+
+    /// Align refills to wall-clock boundaries which are a multiple of the
+    /// refill interval since the unix epoch, rather than relative to the time
+    /// the ratelimiter was constructed. For example, with a refill interval of
+    /// one minute, refills will happen at the start of each minute. This
+    /// matches third-party APIs whose quotas reset on fixed boundaries.
+    ///
+    /// Note: the alignment is established when the ratelimiter is constructed.
+    /// The refill schedule follows the monotonic clock afterwards and will not
+    /// track adjustments to the system wall-clock.
+    ///
+    /// The default is that refills are not aligned to the wall-clock.
+    pub fn align_to_wall_clock(mut self, enabled: bool) -> Self {
+        self.aligned = enabled;
+        self
+    }
+
     /// Consumes this `Builder` and attempts to construct a `Ratelimiter`.
-    pub fn build(self) -> Result<Ratelimiter, Error> {
+    pub fn build(mut self) -> Result<Ratelimiter, Error> {
+        let adjustment = self.tune();
+
         if self.max_tokens < self.refill_amount {
             return Err(Error::MaxTokensTooLow);
         }
@@ -421,39 +1453,153 @@ impl Builder {
             return Err(Error::RefillIntervalTooLong);
         }
 
-        let available = AtomicU64::new(self.initial_available);
+        if !self.cold_factor.is_finite() || self.cold_factor < 1.0 {
+            return Err(Error::InvalidColdFactor);
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(Error::InvalidJitter);
+        }
+
+        if !self.reserves.iter().all(|r| (0.0..1.0).contains(r)) {
+            return Err(Error::InvalidReserve);
+        }
 
-        let parameters = Parameters {
-            capacity: self.max_tokens,
-            refill_amount: self.refill_amount,
-            refill_interval: Duration::from_nanos(self.refill_interval.as_nanos() as u64),
+        if !self.early_drop.map(|e| e.is_valid()).unwrap_or(true) {
+            return Err(Error::InvalidEarlyDrop);
+        }
+
+        if !self.penalty.map(|p| p.is_valid()).unwrap_or(true) {
+            return Err(Error::InvalidPenalty);
+        }
+
+        #[cfg(feature = "histogram")]
+        if !self.heatmap.map(|h| h.is_valid()).unwrap_or(true) {
+            return Err(Error::InvalidHeatmap);
+        }
+
+        Ok(self.assemble(adjustment))
+    }
+
+    /// Internal function to construct the `Ratelimiter` from a builder which
+    /// has been validated, which cannot fail.
+    fn assemble(self, adjustment: Option<Adjustment>) -> Ratelimiter {
+        let available = match self.restore {
+            Some(state) => state.available.min(self.max_tokens),
+            None => self.initial_available.min(self.max_tokens),
         };
 
-        let refill_at = AtomicInstant::new(Instant::now() + self.refill_interval);
+        let mut parameters = Parameters::new(
+            self.max_tokens,
+            self.refill_amount,
+            Duration::from_nanos(self.refill_interval.as_nanos() as u64),
+        );
+        parameters.fraction = self.refill_fraction;
+
+        let first_refill = if self.smooth && self.refill_amount > 1 {
+            Duration::from_nanos(
+                (self.refill_interval.as_nanos() as u64 / self.refill_amount).max(1),
+            )
+        } else {
+            Duration::from_nanos(self.refill_interval.as_nanos() as u64)
+        };
+
+        let created = Instant::now();
+
+        // move the first refill to the next wall-clock boundary of the refill
+        // interval, even when smoothing refills more often
+        let first_refill = if self.aligned {
+            let since_epoch = UnixInstant::now()
+                .duration_since(UnixInstant::EPOCH)
+                .as_nanos();
+            let step = (self.refill_interval.as_nanos() as u64).max(1);
+
+            Duration::from_nanos(step - since_epoch % step)
+        } else {
+            first_refill
+        };
+
+        // apply jitter to the first refill so that ratelimiters created at the
+        // same moment do not refill in lock-step
+        let random = Random::new();
+        let first_refill = if self.jitter > 0.0 {
+            let offset = self.jitter * (2.0 * random.next_f64() - 1.0);
+            Duration::from_nanos(
+                ((first_refill.as_nanos() as f64 * (1.0 + offset)).round() as u64).max(1),
+            )
+        } else {
+            first_refill
+        };
 
-        Ok(Ratelimiter {
-            available,
-            dropped: AtomicU64::new(0),
+        // a restored ratelimiter continues from where the snapshot left off
+        let first_refill = match self.restore {
+            Some(state) => Duration::from_nanos(state.next_refill.as_nanos() as u64),
+            None => first_refill,
+        };
+
+        let refill_at = AtomicInstant::new(advance_instant(created, 1, first_refill.as_nanos()));
+
+        let extensions = Extensions {
+            adjustment,
+            carry_over: self.carry_over,
+            counters: self
+                .counters
+                .then(|| Counters::new(self.observed_rate_window, created)),
+            distribution: self.distribution,
+            early_drop: self.early_drop,
+            early_dropped: AtomicU64::new(0),
+            events: self.events,
+            gate: self.gate,
+            #[cfg(feature = "histogram")]
+            heatmap: self
+                .heatmap
+                .map(|config| heatmap::Timeline::new(config, created)),
+            jitter: self.jitter,
+            #[cfg(feature = "histogram")]
+            latency: self.latency_histogram.then(latency::histogram),
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics.as_deref().map(metrics::Metrics::new),
+            observer: self.observer,
+            penalty: self
+                .penalty
+                .map(|config| penalty::Penalty::new(config, created)),
+            priorities: self
+                .reserves
+                .iter()
+                .map(|reserve| PriorityClass::new(*reserve))
+                .collect(),
+            queue: self.fifo.then(Default::default),
+            random,
+            schedule: self.schedule,
+            smooth: self.smooth,
+            wait_strategy: self.wait_strategy,
+            warmup: self
+                .warmup
+                .map(|period| Warmup::new(period, self.cold_factor, created)),
+        };
+
+        Ratelimiter {
+            available: AtomicU64::new(available),
+            created,
+            dropped: AtomicU64::new(self.restore.map(|state| state.dropped).unwrap_or(0)),
+            extensions: (!extensions.is_empty()).then(|| Box::new(extensions)),
+            fraction: AtomicU64::new(0),
             parameters: parameters.into(),
+            paused_at: AtomicInstant::new(created),
             refill_at,
-        })
+            state: AtomicU8::new(control::ENFORCE),
+            wakers: Wakers::default(),
+            #[cfg(feature = "futures")]
+            wheel: std::sync::OnceLock::new(),
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::*;
     use std::time::{Duration, Instant};
 
-    macro_rules! approx_eq {
-        ($value:expr, $target:expr) => {
-            let value: f64 = $value;
-            let target: f64 = $target;
-            assert!(value >= target * 0.999, "{value} >= {}", target * 0.999);
-            assert!(value <= target * 1.001, "{value} <= {}", target * 1.001);
-        };
-    }
-
     // test that the configured rate and calculated effective rate are close
     #[test]
     pub fn rate() {
@@ -466,6 +1612,203 @@ mod tests {
         approx_eq!(rl.rate(), 12012012.0);
     }
 
+    // test that a rate-based builder selects a safe amount and interval
+    #[test]
+    pub fn from_rate() {
+        let rl = Ratelimiter::from_rate(100.0, 10).unwrap().build().unwrap();
+        assert_eq!(rl.refill_amount(), 1);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(10));
+        assert_eq!(rl.max_tokens(), 10);
+        approx_eq!(rl.rate(), 100.0);
+
+        // high rates require multiple tokens per refill
+        let rl = Ratelimiter::from_rate(50_000_000.0, 1)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(rl.refill_amount(), 50);
+        assert_eq!(rl.refill_interval(), Duration::from_micros(1));
+        assert_eq!(rl.max_tokens(), 50);
+        approx_eq!(rl.rate(), 50_000_000.0);
+
+        assert_eq!(
+            Ratelimiter::from_rate(0.0, 1).err(),
+            Some(Error::InvalidRate)
+        );
+        assert_eq!(
+            Ratelimiter::from_rate(-1.0, 1).err(),
+            Some(Error::InvalidRate)
+        );
+        assert_eq!(
+            Ratelimiter::from_rate(f64::NAN, 1).err(),
+            Some(Error::InvalidRate)
+        );
+    }
+
+    // test the convenience constructors for common windows
+    #[test]
+    pub fn per_window() {
+        let rl = Ratelimiter::per_second(1000).build().unwrap();
+        assert_eq!(rl.refill_amount(), 1);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(1));
+        assert_eq!(rl.max_tokens(), 1000);
+
+        let rl = Ratelimiter::per_minute(120).build().unwrap();
+        assert_eq!(rl.refill_amount(), 1);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(500));
+        assert_eq!(rl.max_tokens(), 120);
+
+        let rl = Ratelimiter::per_hour(1000).build().unwrap();
+        assert_eq!(rl.refill_amount(), 1);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(3600));
+        assert_eq!(rl.max_tokens(), 1000);
+
+        // very high rates add multiple tokens per refill
+        let rl = Ratelimiter::per_second(100_000_000).build().unwrap();
+        assert_eq!(rl.refill_amount(), 100);
+        assert_eq!(rl.refill_interval(), Duration::from_micros(1));
+        approx_eq!(rl.rate(), 100_000_000.0);
+
+        // a zero budget never admits anything
+        let rl = Ratelimiter::per_second(0).build().unwrap();
+        assert_eq!(rl.max_tokens(), 0);
+        assert!(rl.try_wait().is_err());
+    }
+
+    // test that the rate can be changed at runtime
+    #[test]
+    pub fn set_rate() {
+        let rl = Ratelimiter::from_rate(100.0, 100).unwrap().build().unwrap();
+
+        rl.set_rate(1000.0).unwrap();
+        assert_eq!(rl.refill_amount(), 1);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(1));
+        approx_eq!(rl.rate(), 1000.0);
+
+        rl.set_rate(50_000_000.0).unwrap();
+        assert_eq!(rl.refill_amount(), 50);
+        assert_eq!(rl.refill_interval(), Duration::from_micros(1));
+
+        // the refill amount cannot exceed the max tokens
+        assert_eq!(rl.set_rate(500_000_000.0), Err(Error::RefillAmountTooHigh));
+        approx_eq!(rl.rate(), 50_000_000.0);
+
+        assert_eq!(rl.set_rate(0.0), Err(Error::InvalidRate));
+    }
+
+    // test that scaling adjusts the effective rate but not the configuration
+    #[test]
+    pub fn scale() {
+        let rl = Ratelimiter::from_rate(100.0, 100).unwrap().build().unwrap();
+
+        rl.set_scale(0.5).unwrap();
+        approx_eq!(rl.rate(), 50.0);
+        assert_eq!(rl.refill_interval(), Duration::from_millis(10));
+
+        // the scale is retained when the rate changes
+        rl.set_rate(1000.0).unwrap();
+        approx_eq!(rl.rate(), 500.0);
+
+        rl.set_scale(2.0).unwrap();
+        approx_eq!(rl.rate(), 2000.0);
+
+        rl.set_scale(1.0).unwrap();
+        approx_eq!(rl.rate(), 1000.0);
+
+        assert_eq!(rl.set_scale(0.0), Err(Error::InvalidScale));
+        assert_eq!(rl.set_scale(f64::INFINITY), Err(Error::InvalidScale));
+        assert_eq!(rl.scale(), 1.0);
+    }
+
+    // test that refills with long intervals or large amounts saturate rather
+    // than overflow
+    #[test]
+    pub fn long_interval() {
+        use clocksource::precise::Duration;
+
+        // the refill after next is beyond the range of the clock
+        let interval = core::time::Duration::from_secs(400 * 365 * 86_400);
+        let rl = Ratelimiter::builder(1, interval)
+            .max_tokens(2)
+            .build()
+            .unwrap();
+
+        let first = rl.created + Duration::from_nanos(interval.as_nanos() as u64);
+        rl.refill(first + Duration::from_secs(1)).unwrap();
+        assert_eq!(rl.available(), 1);
+        assert!(rl.next_refill() > first);
+
+        // a large refill amount after a long idle period fills the bucket
+        let rl = Ratelimiter::builder(u64::MAX / 2, core::time::Duration::from_micros(1))
+            .max_tokens(u64::MAX)
+            .build()
+            .unwrap();
+
+        rl.refill(rl.created + Duration::from_secs(365 * 86_400))
+            .unwrap();
+        assert_eq!(rl.available(), u64::MAX);
+    }
+
+    // test that smoothing spreads tokens across the refill interval
+    #[test]
+    pub fn smooth() {
+        use clocksource::precise::Duration;
+
+        let rl = Ratelimiter::builder(10, core::time::Duration::from_millis(10))
+            .max_tokens(10)
+            .build()
+            .unwrap();
+
+        assert!(rl.refill(rl.created + Duration::from_millis(5)).is_err());
+        assert_eq!(rl.available(), 0);
+        rl.refill(rl.created + Duration::from_millis(10)).unwrap();
+        assert_eq!(rl.available(), 10);
+
+        let rl = Ratelimiter::builder(10, core::time::Duration::from_millis(10))
+            .max_tokens(10)
+            .smooth(true)
+            .build()
+            .unwrap();
+
+        rl.refill(rl.created + Duration::from_millis(1)).unwrap();
+        assert_eq!(rl.available(), 1);
+        rl.refill(rl.created + Duration::from_millis(5)).unwrap();
+        assert_eq!(rl.available(), 5);
+        rl.refill(rl.created + Duration::from_millis(10)).unwrap();
+        assert_eq!(rl.available(), 10);
+        approx_eq!(rl.rate(), 1000.0);
+    }
+
+    // test that refills can be aligned to the wall-clock
+    #[test]
+    pub fn align_to_wall_clock() {
+        // smoothed refills are still aligned to the refill interval
+        for smooth in [false, true] {
+            let rl = Ratelimiter::builder(10, Duration::from_secs(1))
+                .max_tokens(10)
+                .smooth(smooth)
+                .align_to_wall_clock(true)
+                .build()
+                .unwrap();
+
+            let now = clocksource::precise::Instant::now();
+            let unix = clocksource::precise::UnixInstant::now()
+                .duration_since(clocksource::precise::UnixInstant::EPOCH)
+                .as_nanos();
+
+            // the wall-clock time of the next refill is a whole second
+            let refill = unix + (rl.next_refill() - now).as_nanos();
+            let error = (refill % 1_000_000_000).min(1_000_000_000 - refill % 1_000_000_000);
+            assert!(error < 1_000_000, "{error}");
+        }
+
+        // a zero refill interval has no boundary to align to
+        assert!(Ratelimiter::builder(1, Duration::ZERO)
+            .align_to_wall_clock(true)
+            .build()
+            .is_ok());
+    }
+
     // quick test that a ratelimiter yields tokens at the desired rate
     #[test]
     pub fn wait() {
@@ -511,6 +1854,24 @@ mod tests {
         assert!(count <= 460);
     }
 
+    // quick test that the hint covers the whole shortfall of tokens
+    #[test]
+    pub fn wait_n_shortfall() {
+        let rl = Ratelimiter::builder(2, Duration::from_secs(10))
+            .max_tokens(4)
+            .initial_available(3)
+            .build()
+            .unwrap();
+
+        assert_eq!(rl.try_wait_n(4), Err(Duration::from_secs(10)));
+        assert_eq!(rl.available(), 3);
+
+        // an empty bucket waits for the next refill and then one more
+        rl.set_available(0).unwrap();
+        assert!((Duration::from_secs(10)..=Duration::from_secs(20))
+            .contains(&rl.try_wait_n(4).unwrap_err()));
+    }
+
     // quick test that a ratelimiter accepts n returned tokens
     #[test]
     pub fn return_n() {
@@ -567,4 +1928,31 @@ mod tests {
         assert!(rl.try_wait().is_ok());
         assert!(rl.try_wait().is_err());
     }
+
+    // test that extreme parameters are clamped or saturate
+    #[test]
+    pub fn extremes() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .max_tokens(10)
+            .initial_available(11)
+            .build()
+            .unwrap();
+        assert_eq!(rl.available(), 10);
+
+        // a zero interval is the shortest interval
+        let rl = Ratelimiter::builder(1, Duration::ZERO).build().unwrap();
+        assert_eq!(
+            rl.parameters.read().scaled_interval,
+            clocksource::precise::Duration::from_nanos(1)
+        );
+
+        // once the refill time saturates, no more tokens are issued
+        let rl = Ratelimiter::builder(1, Duration::from_nanos(u64::MAX))
+            .build()
+            .unwrap();
+        let end = advance_instant(rl.created, 1, u64::MAX);
+        let available = rl.available();
+        assert!(rl.refill(end).is_err());
+        assert_eq!(rl.available(), available);
+    }
 }
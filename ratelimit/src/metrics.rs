@@ -0,0 +1,152 @@
+use crate::{Builder, Ratelimiter};
+use ::metrics::{counter, describe_counter, describe_gauge, gauge, Counter, Gauge};
+
+/// Internal type which holds the handles for the metrics of a ratelimiter.
+pub(crate) struct Metrics {
+    acquired: Counter,
+    available: Gauge,
+    denied: Counter,
+    dropped: Counter,
+    issued: Counter,
+    returned: Counter,
+}
+
+impl Metrics {
+    /// Register the metrics for the ratelimiter with the provided name with
+    /// the installed recorder.
+    pub(crate) fn new(name: &str) -> Self {
+        describe_counter!("ratelimit_acquired", "number of successful acquisitions");
+        describe_gauge!("ratelimit_available", "number of tokens available");
+        describe_counter!(
+            "ratelimit_denied",
+            "number of attempts to acquire tokens which failed"
+        );
+        describe_counter!(
+            "ratelimit_dropped",
+            "number of tokens dropped because the bucket was full"
+        );
+        describe_counter!(
+            "ratelimit_issued",
+            "number of tokens handed out by successful acquisitions"
+        );
+        describe_counter!(
+            "ratelimit_returned",
+            "number of tokens returned to the bucket"
+        );
+
+        let limiter = name.to_string();
+
+        Self {
+            acquired: counter!("ratelimit_acquired", "limiter" => limiter.clone()),
+            available: gauge!("ratelimit_available", "limiter" => limiter.clone()),
+            denied: counter!("ratelimit_denied", "limiter" => limiter.clone()),
+            dropped: counter!("ratelimit_dropped", "limiter" => limiter.clone()),
+            issued: counter!("ratelimit_issued", "limiter" => limiter.clone()),
+            returned: counter!("ratelimit_returned", "limiter" => limiter),
+        }
+    }
+}
+
+impl Builder {
+    /// Emit the counters and the number of tokens available through the
+    /// [`metrics`](::metrics) crate, labeled with the provided name for the
+    /// ratelimiter. The metrics are registered with the recorder which is
+    /// installed when the ratelimiter is built.
+    ///
+    /// The counters are `ratelimit_acquired`, `ratelimit_denied`,
+    /// `ratelimit_dropped`, `ratelimit_issued`, and `ratelimit_returned`, see
+    /// [`Stats`](crate::Stats) for details. The tokens available are reported
+    /// by the `ratelimit_available` gauge. Each has a `limiter` label with the
+    /// name.
+    pub fn metrics(mut self, name: impl Into<String>) -> Self {
+        self.metrics = Some(name.into());
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to emit the outcome of an attempt to acquire `n`
+    /// tokens.
+    pub(crate) fn emit_acquire(&self, n: u64, acquired: bool) {
+        let Some(metrics) = self.extensions().and_then(|e| e.metrics.as_ref()) else {
+            return;
+        };
+
+        if acquired {
+            metrics.acquired.increment(1);
+            metrics.issued.increment(n);
+        } else {
+            metrics.denied.increment(1);
+        }
+
+        metrics.available.set(self.available() as f64);
+    }
+
+    /// Internal function to emit tokens which were dropped.
+    pub(crate) fn emit_dropped(&self, n: u64) {
+        if let Some(metrics) = self.extensions().and_then(|e| e.metrics.as_ref()) {
+            metrics.dropped.increment(n);
+        }
+    }
+
+    /// Internal function to emit tokens which were returned.
+    pub(crate) fn emit_returned(&self, n: u64) {
+        if let Some(metrics) = self.extensions().and_then(|e| e.metrics.as_ref()) {
+            metrics.returned.increment(n);
+            metrics.available.set(self.available() as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use std::time::Duration;
+
+    #[test]
+    fn metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        let rl = ::metrics::with_local_recorder(&recorder, || {
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(10)
+                .initial_available(10)
+                .metrics("api")
+                .build()
+                .unwrap()
+        });
+
+        rl.try_acquire_n(4).unwrap();
+        assert!(rl.try_acquire_n(7).is_err());
+        rl.return_n(1);
+
+        let metrics: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                let label = key.labels().next().unwrap();
+                assert_eq!((label.key(), label.value()), ("limiter", "api"));
+                (key.name().to_string(), value)
+            })
+            .collect();
+
+        let value = |name: &str| {
+            metrics
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value)
+                .unwrap()
+        };
+
+        assert_eq!(value("ratelimit_acquired"), &DebugValue::Counter(1));
+        assert_eq!(value("ratelimit_denied"), &DebugValue::Counter(1));
+        assert_eq!(value("ratelimit_dropped"), &DebugValue::Counter(0));
+        assert_eq!(value("ratelimit_issued"), &DebugValue::Counter(4));
+        assert_eq!(value("ratelimit_returned"), &DebugValue::Counter(1));
+        assert_eq!(value("ratelimit_available"), &DebugValue::Gauge(7.0.into()));
+    }
+}
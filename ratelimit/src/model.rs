@@ -0,0 +1,253 @@
+//! Concurrency model tests for the refill and acquire paths, which are only
+//! built with `--cfg ratelimit_loom`. The atomics of the ratelimiter are then
+//! provided by `loom`, which runs each test under every interleaving of its
+//! threads. Run them with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg ratelimit_loom" cargo test --release --lib model
+//! ```
+//!
+//! Only the tests in this module may be run, since the other tests use the
+//! ratelimiter outside of a loom model.
+
+use crate::atomic::{AtomicU64, Ordering};
+use clocksource::precise::{Duration, Instant};
+
+/// Internal type which stands in for the `AtomicInstant` from `clocksource`,
+/// so that the refill time is modelled along with the other atomics.
+#[derive(Debug)]
+pub(crate) struct AtomicInstant {
+    ns: AtomicU64,
+}
+
+impl AtomicInstant {
+    pub fn new(value: Instant) -> Self {
+        Self {
+            ns: AtomicU64::new(nanos(value)),
+        }
+    }
+
+    pub fn load(&self, ordering: Ordering) -> Instant {
+        instant(self.ns.load(ordering))
+    }
+
+    pub fn store(&self, value: Instant, ordering: Ordering) {
+        self.ns.store(nanos(value), ordering)
+    }
+
+    pub fn compare_exchange(
+        &self,
+        current: Instant,
+        new: Instant,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Instant, Instant> {
+        self.ns
+            .compare_exchange(nanos(current), nanos(new), success, failure)
+            .map(instant)
+            .map_err(instant)
+    }
+
+    pub fn fetch_add(&self, value: Duration, ordering: Ordering) -> Instant {
+        instant(self.ns.fetch_add(value.as_nanos(), ordering))
+    }
+
+    pub fn fetch_max(&self, value: Instant, ordering: Ordering) -> Instant {
+        instant(self.ns.fetch_max(nanos(value), ordering))
+    }
+
+    pub fn fetch_min(&self, value: Instant, ordering: Ordering) -> Instant {
+        instant(self.ns.fetch_min(nanos(value), ordering))
+    }
+}
+
+/// Internal function to convert an instant into nanoseconds.
+fn nanos(value: Instant) -> u64 {
+    (value - Instant::default()).as_nanos()
+}
+
+/// Internal function to convert nanoseconds into an instant.
+fn instant(ns: u64) -> Instant {
+    Instant::default() + Duration::from_nanos(ns)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    /// Returns the time `intervals` refill intervals of 1ms after the
+    /// ratelimiter was created.
+    fn at(rl: &Ratelimiter, intervals: f64) -> Instant {
+        rl.created + Duration::from_nanos((intervals * 1_000_000.0) as u64)
+    }
+
+    #[test]
+    fn acquire() {
+        // more callers than tokens, with no refill due
+        loom::model(|| {
+            let rl = Arc::new(
+                Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                    .max_tokens(2)
+                    .initial_available(2)
+                    .build()
+                    .unwrap(),
+            );
+            let now = at(&rl, 0.5);
+
+            let threads: Vec<_> = (0..3)
+                .map(|_| {
+                    let rl = rl.clone();
+                    thread::spawn(move || rl.take_at(1, 0, || now).is_ok())
+                })
+                .collect();
+
+            let acquired = threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .filter(|ok| *ok)
+                .count() as u64;
+
+            // each token is issued exactly once
+            assert_eq!(acquired, 2);
+            assert_eq!(rl.available(), 0);
+        });
+    }
+
+    #[test]
+    fn refill() {
+        // both callers race to perform the same refill
+        loom::model(|| {
+            let rl = Arc::new(
+                Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                    .max_tokens(4)
+                    .build()
+                    .unwrap(),
+            );
+            let now = at(&rl, 3.5);
+
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let rl = rl.clone();
+                    thread::spawn(move || rl.take_at(1, 0, || now).is_ok())
+                })
+                .collect();
+
+            // a caller may observe the refill before the winner of the race
+            // has added its tokens, in which case it fails to acquire one
+            let acquired = threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .filter(|ok| *ok)
+                .count() as u64;
+            assert!(acquired >= 1);
+
+            // the three refills which are due are applied once
+            assert_eq!(acquired + rl.available(), 3);
+            assert_eq!(rl.next_refill(), at(&rl, 4.0));
+            assert_eq!(rl.dropped(), 0);
+        });
+    }
+
+    #[test]
+    fn pause() {
+        // concurrent pauses race with a resume
+        loom::model(|| {
+            let rl = Arc::new(
+                Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                    .build()
+                    .unwrap(),
+            );
+            let refill_at = rl.next_refill();
+
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let rl = rl.clone();
+                    thread::spawn(move || rl.pause())
+                })
+                .collect();
+
+            rl.resume();
+
+            for thread in threads {
+                thread.join().unwrap();
+            }
+
+            // whichever pause is left in place can be resumed, without the
+            // state of a pause which is starting being left behind
+            rl.resume();
+            assert!(!rl.is_paused());
+            assert_eq!(rl.mode(), Mode::Enforce);
+            assert!(rl
+                .try_acquire()
+                .is_err_and(|e| e != TryAcquireError::Paused));
+            assert!(rl.next_refill() >= refill_at);
+        });
+    }
+
+    #[test]
+    fn refill_overflow() {
+        // a refill which overflows the bucket races with a caller taking a
+        // token
+        loom::model(|| {
+            let rl = Arc::new(
+                Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                    .max_tokens(2)
+                    .initial_available(2)
+                    .build()
+                    .unwrap(),
+            );
+            let now = at(&rl, 1.5);
+
+            let refill = {
+                let rl = rl.clone();
+                thread::spawn(move || {
+                    let _ = rl.refill(now);
+                })
+            };
+            let take = {
+                let rl = rl.clone();
+                thread::spawn(move || rl.take_at(1, 0, || now).is_ok())
+            };
+
+            refill.join().unwrap();
+            assert!(take.join().unwrap());
+
+            // the refilled token is either available or counted as dropped
+            assert_eq!(rl.available() + 1 + rl.dropped(), 3);
+        });
+    }
+
+    #[test]
+    fn refill_refund() {
+        // a refill races with a caller returning a token, which must not
+        // overfill the bucket
+        loom::model(|| {
+            let rl = Arc::new(
+                Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                    .max_tokens(2)
+                    .initial_available(1)
+                    .build()
+                    .unwrap(),
+            );
+            let now = at(&rl, 1.5);
+
+            let refill = {
+                let rl = rl.clone();
+                thread::spawn(move || {
+                    let _ = rl.refill(now);
+                })
+            };
+            let refund = {
+                let rl = rl.clone();
+                thread::spawn(move || rl.refund(1))
+            };
+
+            refill.join().unwrap();
+            refund.join().unwrap();
+
+            assert_eq!(rl.available(), 2);
+        });
+    }
+}
@@ -0,0 +1,217 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::{Ratelimiter, TryAcquireError};
+use core::borrow::Borrow;
+use parking_lot::Mutex;
+use thiserror::Error;
+
+/// The reasons that an attempt to acquire from a [`MultiResource`] may fail.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceError {
+    #[error("dimension {0} could not be charged: {1}")]
+    Dimension(usize, TryAcquireError),
+    #[error("the limit on concurrent operations has been reached")]
+    Concurrency,
+}
+
+/// A limiter which tracks several dimensions at once, for example requests/s
+/// and bytes/s, along with an optional limit on the number of concurrent
+/// operations.
+///
+/// An operation is admitted only if every dimension has capacity. The
+/// dimensions are charged together, and if any of them can't be charged, the
+/// ones which were already charged are refunded before the error is returned.
+/// Operations on the same limiter are serialized while charging, so that
+/// concurrent callers can't each take part of the capacity and all fail.
+///
+/// ```
+/// use ratelimit::{MultiResource, Ratelimiter};
+/// use std::time::Duration;
+///
+/// let requests = Ratelimiter::builder(10, Duration::from_secs(1))
+///     .max_tokens(10)
+///     .initial_available(10)
+///     .build()
+///     .unwrap();
+/// let bytes = Ratelimiter::builder(1_000_000, Duration::from_secs(1))
+///     .max_tokens(1_000_000)
+///     .initial_available(1_000_000)
+///     .build()
+///     .unwrap();
+///
+/// let limiter = MultiResource::new(vec![requests, bytes]).max_concurrent(16);
+///
+/// // a request which transfers 1500 bytes
+/// let permit = limiter.try_acquire(&[1, 1500]).unwrap();
+/// assert_eq!(limiter.in_flight(), 1);
+///
+/// // the concurrent operation completes when the permit is dropped
+/// drop(permit);
+/// assert_eq!(limiter.in_flight(), 0);
+/// ```
+pub struct MultiResource<L> {
+    // serializes charging the dimensions
+    charging: Mutex<()>,
+    dimensions: Vec<L>,
+    in_flight: AtomicU64,
+    max_concurrent: Option<u64>,
+}
+
+impl<L: Borrow<Ratelimiter>> MultiResource<L> {
+    /// Create a limiter with a dimension for each of the provided
+    /// ratelimiters. Dimensions are identified by their index.
+    pub fn new(dimensions: Vec<L>) -> Self {
+        Self {
+            charging: Mutex::new(()),
+            dimensions,
+            in_flight: AtomicU64::new(0),
+            max_concurrent: None,
+        }
+    }
+
+    /// Limit the number of operations which may hold a [`ResourcePermit`] at
+    /// the same time.
+    pub fn max_concurrent(mut self, limit: u64) -> Self {
+        self.max_concurrent = Some(limit);
+        self
+    }
+
+    /// Returns the ratelimiter for a dimension.
+    pub fn dimension(&self, index: usize) -> Option<&Ratelimiter> {
+        self.dimensions.get(index).map(|l| l.borrow())
+    }
+
+    /// Returns the number of dimensions.
+    pub fn dimensions(&self) -> usize {
+        self.dimensions.len()
+    }
+
+    /// Returns the number of operations which currently hold a permit.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Non-blocking function to charge each dimension with the amount at the
+    /// same index, returning a permit which holds a concurrent operation until
+    /// it is dropped. Missing amounts are treated as zero and amounts beyond
+    /// the number of dimensions are ignored.
+    ///
+    /// On failure, nothing has been charged and the error indicates which
+    /// dimension could not be charged, or that the limit on concurrent
+    /// operations has been reached.
+    pub fn try_acquire(&self, amounts: &[u64]) -> Result<ResourcePermit<'_, L>, ResourceError> {
+        let _charging = self.charging.lock();
+
+        if let Some(limit) = self.max_concurrent {
+            if self.in_flight() >= limit {
+                return Err(ResourceError::Concurrency);
+            }
+        }
+
+        let charges = self
+            .dimensions
+            .iter()
+            .zip(amounts.iter().copied())
+            .map(|(dimension, n)| (dimension.borrow(), n));
+
+        charge_all(charges).map_err(|(index, e)| ResourceError::Dimension(index, e))?;
+
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+
+        Ok(ResourcePermit { limiter: self })
+    }
+}
+
+/// A permit for an operation admitted by a [`MultiResource`]. The operation
+/// counts against the limit on concurrent operations until this is dropped.
+#[must_use = "the operation completes when the permit is dropped"]
+pub struct ResourcePermit<'a, L> {
+    limiter: &'a MultiResource<L>,
+}
+
+impl<L> Drop for ResourcePermit<'_, L> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Internal function to charge several ratelimiters, each with its own number
+/// of tokens. If any of them can't be charged, the ones which were already
+/// charged are refunded and the index of the failing ratelimiter is returned
+/// with its error.
+pub(crate) fn charge_all<'a>(
+    charges: impl Iterator<Item = (&'a Ratelimiter, u64)> + Clone,
+) -> Result<(), (usize, TryAcquireError)> {
+    for (index, (ratelimiter, n)) in charges.clone().enumerate() {
+        if n == 0 {
+            continue;
+        }
+
+        if let Err(e) = ratelimiter.try_acquire_n(n) {
+            for (ratelimiter, n) in charges.take(index) {
+                if n > 0 {
+                    ratelimiter.return_n(n);
+                }
+            }
+
+            return Err((index, e));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn ratelimiter(tokens: u64) -> Ratelimiter {
+        Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(tokens)
+            .initial_available(tokens)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn rollback() {
+        let limiter = MultiResource::new(vec![ratelimiter(10), ratelimiter(100)]);
+        assert_eq!(limiter.dimensions(), 2);
+
+        let permit = limiter.try_acquire(&[1, 60]).unwrap();
+        drop(permit);
+
+        // the second dimension is short, so the first is refunded
+        assert!(matches!(
+            limiter.try_acquire(&[1, 60]).err(),
+            Some(ResourceError::Dimension(
+                1,
+                TryAcquireError::Insufficient(_)
+            ))
+        ));
+        assert_eq!(limiter.dimension(0).unwrap().available(), 9);
+        assert_eq!(limiter.dimension(1).unwrap().available(), 40);
+
+        // missing amounts are zero
+        let _permit = limiter.try_acquire(&[9]).unwrap();
+        assert_eq!(limiter.dimension(0).unwrap().available(), 0);
+        assert_eq!(limiter.dimension(1).unwrap().available(), 40);
+    }
+
+    #[test]
+    fn concurrency() {
+        let limiter = MultiResource::new(vec![ratelimiter(10)]).max_concurrent(2);
+
+        let a = limiter.try_acquire(&[1]).unwrap();
+        let _b = limiter.try_acquire(&[1]).unwrap();
+        assert_eq!(
+            limiter.try_acquire(&[1]).err(),
+            Some(ResourceError::Concurrency)
+        );
+        assert_eq!(limiter.dimension(0).unwrap().available(), 8);
+
+        drop(a);
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(limiter.try_acquire(&[1]).is_ok());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::{Ratelimiter, WaitStrategy};
+use core::task::Waker;
+use core::time::Duration;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::task::Wake;
+use std::thread::Thread;
+
+/// Internal type which holds the wakers of the tasks waiting for tokens. The
+/// count allows the hot path to skip the lock when nobody is waiting.
+#[derive(Default)]
+pub(crate) struct Wakers {
+    count: AtomicUsize,
+    // set while a blocked thread is waiting for the next refill on behalf of
+    // the other blocked threads
+    timekeeper: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+/// Internal type which wakes a blocked thread by unparking it.
+struct Unparker(Thread);
+
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+std::thread_local! {
+    // each thread reuses a single waker so that repeated registrations are
+    // recognized as the same waiter
+    static THREAD_WAKER: Waker = Waker::from(Arc::new(Unparker(std::thread::current())));
+}
+
+impl Wakers {
+    /// Register a waker, unless it would wake the same task as a waker which
+    /// is already registered.
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock();
+
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+            self.count.store(wakers.len(), Ordering::Release);
+        }
+    }
+
+    /// Returns the number of bytes allocated to hold the wakers.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.wakers.lock().capacity() * core::mem::size_of::<Waker>()
+    }
+
+    /// Block the calling thread for up to `delay` while waiting for tokens.
+    ///
+    /// A single blocked thread at a time is the timekeeper, which waits for
+    /// the delay using the wait strategy and then returns to retry, which
+    /// performs the refill. The other threads are parked until they are woken
+    /// by tokens being added, so they wake once tokens are available rather
+    /// than each oversleeping its own hint. They also wake after the delay and
+    /// a further `grace` period in case the timekeeper has gone away.
+    ///
+    /// With the `Park` strategy, the timekeeper is also woken early if tokens
+    /// are added.
+    pub(crate) fn block(&self, strategy: WaitStrategy, delay: Duration, grace: Duration) {
+        if self
+            .timekeeper
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            if strategy == WaitStrategy::Park {
+                THREAD_WAKER.with(|waker| self.register(waker));
+            }
+            strategy.wait(delay);
+            self.timekeeper.store(false, Ordering::Release);
+            return;
+        }
+
+        // a wake between registering and parking leaves the unpark token set,
+        // so the park returns immediately rather than missing the wake
+        THREAD_WAKER.with(|waker| self.register(waker));
+        std::thread::park_timeout(delay.saturating_add(grace));
+    }
+
+    /// Wake and remove all the registered wakers.
+    pub(crate) fn wake_all(&self) {
+        if self.count.load(Ordering::Acquire) == 0 {
+            return;
+        }
+
+        let wakers = {
+            let mut wakers = self.wakers.lock();
+            self.count.store(0, Ordering::Release);
+            core::mem::take(&mut *wakers)
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Ratelimiter {
+    /// Register a waker to be woken the next time tokens are added to the
+    /// bucket, whether by a refill, by [`Ratelimiter::return_n`], or by
+    /// [`Ratelimiter::set_available`]. Registered wakers are also woken when
+    /// the ratelimiter is resumed, closed, or its mode is changed.
+    ///
+    /// Each waker is woken once and must be registered again to be woken by a
+    /// later refill. Refills happen lazily when tokens are acquired, so a task
+    /// which relies on this should still set a timer for the next refill in
+    /// case no other caller triggers it.
+    pub fn register_waker(&self, waker: &Waker) {
+        self.wakers.register(waker);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::time::Duration;
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn register_waker() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(10))
+            .max_tokens(10)
+            .build()
+            .unwrap();
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+
+        // duplicate registrations are only woken once
+        rl.register_waker(&waker);
+        rl.register_waker(&waker);
+        rl.return_n(1);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+
+        // wakers must be registered again after being woken
+        rl.return_n(1);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+
+        // a refill wakes the registered wakers
+        rl.register_waker(&waker);
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(rl.try_acquire().is_ok());
+        assert_eq!(counter.0.load(Ordering::Relaxed), 2);
+
+        rl.register_waker(&waker);
+        rl.close();
+        assert_eq!(counter.0.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn blocked_threads() {
+        let rl = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(5))
+                .build()
+                .unwrap(),
+        );
+
+        // each token is taken by one of the blocked threads as it is added
+        let start = std::time::Instant::now();
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let rl = rl.clone();
+                std::thread::spawn(move || rl.wait().unwrap())
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(35), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+
+    #[test]
+    fn woken_by_tokens() {
+        let rl = Arc::new(
+            Ratelimiter::builder(1, Duration::from_secs(10))
+                .wait_strategy(WaitStrategy::Park)
+                .build()
+                .unwrap(),
+        );
+        let acquired = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let rl = rl.clone();
+                let acquired = acquired.clone();
+                std::thread::spawn(move || {
+                    if rl.wait().is_ok() {
+                        acquired.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+
+        // a returned token wakes a blocked thread long before the refill
+        std::thread::sleep(Duration::from_millis(50));
+        rl.return_n(1);
+        let start = std::time::Instant::now();
+        while acquired.load(Ordering::Relaxed) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            std::thread::yield_now();
+        }
+
+        rl.close();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(acquired.load(Ordering::Relaxed), 1);
+    }
+}
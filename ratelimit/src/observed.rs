@@ -0,0 +1,206 @@
+use crate::{Builder, Ratelimiter};
+use clocksource::precise::Instant;
+
+/// The default time constant for the achieved-rate estimator.
+pub(crate) const DEFAULT_WINDOW: core::time::Duration = core::time::Duration::from_secs(10);
+
+/// Internal type which holds the state of the achieved-rate estimator. The
+/// estimate is an exponentially weighted moving average which is brought up
+/// to date from the issued counter whenever it is read, so nothing is added to
+/// the cost of acquiring tokens.
+#[derive(Debug)]
+pub(crate) struct Observed {
+    issued: u64,
+    rate: f64,
+    updated: Instant,
+    window: f64,
+}
+
+impl Observed {
+    pub(crate) fn new(window: core::time::Duration, created: Instant) -> Self {
+        Self {
+            issued: 0,
+            rate: 0.0,
+            updated: created,
+            window: window.as_secs_f64(),
+        }
+    }
+
+    /// Fold the tokens issued since the last update into the estimate and
+    /// return it.
+    fn update(&mut self, issued: u64, now: Instant) -> f64 {
+        let elapsed = match now.checked_duration_since(self.updated) {
+            Some(elapsed) if elapsed.as_nanos() > 0 => elapsed.as_nanos() as f64 / 1e9,
+            _ => return self.rate,
+        };
+
+        // the counter wraps on overflow
+        let instantaneous = issued.wrapping_sub(self.issued) as f64 / elapsed;
+
+        // the weight accounts for the elapsed time, so the estimate does not
+        // depend on how often it is read
+        let alpha = if self.window > 0.0 {
+            1.0 - (-elapsed / self.window).exp()
+        } else {
+            1.0
+        };
+
+        self.rate += alpha * (instantaneous - self.rate);
+        self.issued = issued;
+        self.updated = now;
+
+        self.rate
+    }
+}
+
+impl Builder {
+    /// Set the time constant of the estimator behind
+    /// [`Ratelimiter::observed_rate`]. Longer windows give a steadier
+    /// estimate which is slower to follow changes in the achieved rate.
+    ///
+    /// The default is ten seconds.
+    pub fn observed_rate_window(mut self, window: core::time::Duration) -> Self {
+        self.observed_rate_window = window;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Return an estimate of the rate at which tokens are actually being
+    /// acquired, in tokens/second. Comparing this with [`Ratelimiter::rate`]
+    /// shows whether the ratelimiter is the bottleneck: an achieved rate well
+    /// below the configured rate means callers are limited elsewhere.
+    ///
+    /// The estimate is an exponentially weighted moving average with the time
+    /// constant set by [`Builder::observed_rate_window`]. Tokens which are
+    /// returned are not subtracted. The estimate is derived from the counters,
+    /// so it is zero unless they are enabled with [`Builder::counters`].
+    ///
+    /// ```
+    /// use ratelimit::Ratelimiter;
+    /// use std::time::Duration;
+    ///
+    /// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+    ///     .counters(true)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // nothing has been acquired yet
+    /// assert_eq!(ratelimiter.observed_rate(), 0.0);
+    /// ```
+    pub fn observed_rate(&self) -> f64 {
+        self.counters()
+            .map(|counters| {
+                counters
+                    .observed
+                    .lock()
+                    .update(self.issued(), Instant::now())
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Internal function to restart the estimate from zero when the issued
+    /// counter is reset.
+    pub(crate) fn reset_observed(&self) {
+        let Some(counters) = self.counters() else {
+            return;
+        };
+
+        let mut observed = counters.observed.lock();
+        observed.issued = 0;
+        observed.rate = 0.0;
+        observed.updated = Instant::now();
+    }
+
+    /// Return how much of the refill is being consumed, from `0.0` when idle
+    /// to `1.0` when saturated. This is the [achieved rate] as a fraction of
+    /// the [current rate], so it rises as the ratelimiter approaches its limit
+    /// and can be acted on before requests begin to be denied.
+    ///
+    /// Bursts which draw down the available tokens consume faster than the
+    /// refill and are reported as `1.0`.
+    ///
+    /// [achieved rate]: Ratelimiter::observed_rate
+    /// [current rate]: Ratelimiter::rate
+    pub fn utilization(&self) -> f64 {
+        let utilization = self.observed_rate() / self.rate();
+
+        if utilization.is_finite() {
+            utilization.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clocksource::precise::Duration;
+    use std::time::Duration as StdDuration;
+
+    #[test]
+    fn estimate() {
+        let start = Instant::now();
+        let mut observed = Observed::new(StdDuration::from_secs(1), start);
+
+        // a steady 100 tokens/s converges on 100, however often it is read
+        let mut issued = 0;
+        for i in 1..=100 {
+            issued += 10;
+            observed.update(issued, start + Duration::from_millis(100 * i));
+        }
+        assert!((observed.rate - 100.0).abs() < 1.0);
+
+        issued += 5_000;
+        let rate = observed.update(issued, start + Duration::from_secs(60));
+        assert!((rate - 100.0).abs() < 1.0);
+
+        // when nothing is acquired, the estimate decays towards zero
+        let rate = observed.update(issued, start + Duration::from_secs(65));
+        assert!(rate < 1.0);
+
+        // reading again at the same instant does not change the estimate
+        assert_eq!(
+            observed.update(issued, start + Duration::from_secs(65)),
+            rate
+        );
+    }
+
+    #[test]
+    fn observed_rate() {
+        let rl = Ratelimiter::builder(1, StdDuration::from_secs(60))
+            .counters(true)
+            .max_tokens(100)
+            .initial_available(100)
+            .observed_rate_window(StdDuration::ZERO)
+            .build()
+            .unwrap();
+
+        std::thread::sleep(StdDuration::from_millis(10));
+        rl.try_acquire_n(100).unwrap();
+
+        // with no window the estimate is the rate since it was last read
+        let rate = rl.observed_rate();
+        assert!(rate > 0.0 && rate < 10_000.0);
+    }
+
+    #[test]
+    fn utilization() {
+        let rl = Ratelimiter::builder(1, StdDuration::from_millis(1))
+            .counters(true)
+            .max_tokens(100)
+            .initial_available(100)
+            .observed_rate_window(StdDuration::ZERO)
+            .build()
+            .unwrap();
+        assert_eq!(rl.utilization(), 0.0);
+
+        // draining the bucket consumes faster than the refill
+        rl.try_acquire_n(100).unwrap();
+        assert_eq!(rl.utilization(), 1.0);
+
+        std::thread::sleep(StdDuration::from_millis(10));
+        assert_eq!(rl.utilization(), 0.0);
+    }
+}
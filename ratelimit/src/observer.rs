@@ -0,0 +1,181 @@
+use crate::{Builder, Event, Ratelimiter, TryAcquireError};
+use std::sync::Arc;
+
+/// Receives the events of a `Ratelimiter`, so that logging or alerting can be
+/// built without this crate choosing a telemetry stack. See
+/// [`Builder::observer`].
+///
+/// Each method does nothing by default, so only the events of interest need
+/// to be implemented. The methods are called on the thread which caused the
+/// event, often while it is acquiring tokens, so they should be cheap.
+///
+/// ```
+/// use ratelimit::{Ratelimiter, RatelimiterObserver, TryAcquireError};
+/// use std::time::Duration;
+///
+/// struct Log;
+///
+/// impl RatelimiterObserver for Log {
+///     fn on_deny(&self, tokens: u64, error: &TryAcquireError) {
+///         eprintln!("denied {tokens} tokens: {error}");
+///     }
+/// }
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .observer(Log)
+///     .build()
+///     .unwrap();
+/// ```
+pub trait RatelimiterObserver: Send + Sync {
+    /// Called when `tokens` are acquired.
+    fn on_acquire(&self, tokens: u64) {
+        let _ = tokens;
+    }
+
+    /// Called when an attempt to acquire `tokens` fails.
+    fn on_deny(&self, tokens: u64, error: &TryAcquireError) {
+        let _ = (tokens, error);
+    }
+
+    /// Called when the bucket is refilled with `tokens`. This includes any
+    /// tokens which are then dropped because the bucket is full.
+    fn on_refill(&self, tokens: u64) {
+        let _ = tokens;
+    }
+
+    /// Called when `tokens` are dropped because the bucket is full.
+    fn on_drop(&self, tokens: u64) {
+        let _ = tokens;
+    }
+
+    /// Called after the rate or max tokens of the ratelimiter has changed,
+    /// whether by a setter, a [`RateSchedule`](crate::RateSchedule), or a
+    /// [`Gate`](crate::Gate). The new parameters can be read from the
+    /// ratelimiter.
+    fn on_parameter_change(&self, ratelimiter: &Ratelimiter) {
+        let _ = ratelimiter;
+    }
+}
+
+impl<T: RatelimiterObserver + ?Sized> RatelimiterObserver for Arc<T> {
+    fn on_acquire(&self, tokens: u64) {
+        (**self).on_acquire(tokens)
+    }
+
+    fn on_deny(&self, tokens: u64, error: &TryAcquireError) {
+        (**self).on_deny(tokens, error)
+    }
+
+    fn on_refill(&self, tokens: u64) {
+        (**self).on_refill(tokens)
+    }
+
+    fn on_drop(&self, tokens: u64) {
+        (**self).on_drop(tokens)
+    }
+
+    fn on_parameter_change(&self, ratelimiter: &Ratelimiter) {
+        (**self).on_parameter_change(ratelimiter)
+    }
+}
+
+impl Builder {
+    /// Provide an observer which is notified of the events of the ratelimiter.
+    /// See [`RatelimiterObserver`].
+    pub fn observer(mut self, observer: impl RatelimiterObserver + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to notify the observer and the event channel, if
+    /// any, that the parameters have changed. Must be called without holding
+    /// the parameters lock.
+    pub(crate) fn notify_parameters(&self) {
+        if let Some(observer) = self.extensions().and_then(|e| e.observer.as_ref()) {
+            observer.on_parameter_change(self);
+        }
+
+        self.send_event(|| self.parameters_event());
+    }
+
+    /// Internal function to notify the observer and the event channel, if
+    /// any, of a refill.
+    pub(crate) fn notify_refill(&self, tokens: u64) {
+        if let Some(observer) = self.extensions().and_then(|e| e.observer.as_ref()) {
+            observer.on_refill(tokens);
+        }
+
+        self.send_event(|| Event::Refilled { amount: tokens });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl RatelimiterObserver for Events {
+        fn on_acquire(&self, tokens: u64) {
+            self.0.lock().push(format!("acquire {tokens}"));
+        }
+
+        fn on_deny(&self, tokens: u64, error: &TryAcquireError) {
+            self.0.lock().push(format!("deny {tokens} {error:?}"));
+        }
+
+        fn on_refill(&self, tokens: u64) {
+            self.0.lock().push(format!("refill {tokens}"));
+        }
+
+        fn on_drop(&self, tokens: u64) {
+            self.0.lock().push(format!("drop {tokens}"));
+        }
+
+        fn on_parameter_change(&self, ratelimiter: &Ratelimiter) {
+            self.0
+                .lock()
+                .push(format!("parameters {}", ratelimiter.max_tokens()));
+        }
+    }
+
+    #[test]
+    fn events() {
+        let events = Arc::new(Events::default());
+
+        let rl = Ratelimiter::builder(2, Duration::from_millis(50))
+            .max_tokens(3)
+            .initial_available(3)
+            .observer(events.clone())
+            .build()
+            .unwrap();
+
+        rl.try_acquire().unwrap();
+        rl.pause();
+        assert!(rl.try_acquire().is_err());
+        rl.resume();
+
+        std::thread::sleep(Duration::from_millis(50));
+        rl.try_acquire().unwrap();
+
+        rl.set_max_tokens(4).unwrap();
+        assert!(rl.set_max_tokens(1).is_err());
+
+        assert_eq!(
+            *events.0.lock(),
+            vec![
+                "acquire 1",
+                "deny 1 Paused",
+                "refill 2",
+                "drop 1",
+                "acquire 1",
+                "parameters 4",
+            ]
+        );
+    }
+}
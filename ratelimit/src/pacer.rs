@@ -0,0 +1,155 @@
+use crate::{Ratelimiter, TryAcquireError};
+use clocksource::precise::{Duration, Instant};
+use core::borrow::Borrow;
+use parking_lot::Mutex;
+
+/// A pacer for load generators which reports the intended start time of each
+/// request along with its tokens. The intended times follow the ideal schedule
+/// for the rate of the ratelimiter, rather than the time that the caller
+/// actually woke up or acquired the tokens.
+///
+/// Measuring latency from the intended start time corrects for coordinated
+/// omission: when the system under test stalls and the load generator falls
+/// behind, the requests it could not send on time are still charged for the
+/// time they spent waiting to be sent.
+///
+/// The schedule starts with the first acquisition. While the caller keeps up
+/// with the rate, the intended start time is the time of acquisition. Once the
+/// caller falls behind, the intended start times stay on the schedule and are
+/// earlier than the time of acquisition.
+///
+/// ```
+/// use ratelimit::{Pacer, Ratelimiter};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+///     .max_tokens(100)
+///     .build()
+///     .unwrap();
+/// let pacer = Pacer::new(ratelimiter);
+///
+/// for _ in 0..3 {
+///     let intended = pacer.wait().unwrap();
+///
+///     // send the request, then record the latency from the intended start
+///     let latency = intended.elapsed();
+/// }
+/// ```
+pub struct Pacer<L> {
+    ratelimiter: L,
+    // the intended start time of the next token, once the schedule starts
+    next: Mutex<Option<Instant>>,
+}
+
+impl<L: Borrow<Ratelimiter>> Pacer<L> {
+    /// Create a pacer which acquires tokens from the provided ratelimiter.
+    pub fn new(ratelimiter: L) -> Self {
+        Self {
+            ratelimiter,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Returns the ratelimiter which tokens are acquired from.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Restart the schedule from the next acquisition. This should be used
+    /// after the load generator has been idle, so that the idle time is not
+    /// counted against the following requests.
+    pub fn reset(&self) {
+        *self.next.lock() = None;
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens. On success, the
+    /// intended start time for the tokens is returned. On failure, a
+    /// `Duration` hinting at when the next refill would occur is returned. See
+    /// [`Ratelimiter::try_wait_n`].
+    pub fn try_wait_n(&self, n: u64) -> Result<Instant, core::time::Duration> {
+        let mut next = self.next.lock();
+        self.ratelimiter().try_wait_n(n)?;
+        Ok(self.schedule(&mut next, n))
+    }
+
+    /// Non-blocking function to "wait" for a single token. See
+    /// [`Pacer::try_wait_n`].
+    pub fn try_wait(&self) -> Result<Instant, core::time::Duration> {
+        self.try_wait_n(1)
+    }
+
+    /// Blocking function to wait until `n` tokens have been acquired. Returns
+    /// the intended start time for the tokens, or an error if the ratelimiter
+    /// is denying all requests or is closed. See [`Ratelimiter::wait_n`].
+    pub fn wait_n(&self, n: u64) -> Result<Instant, TryAcquireError> {
+        self.ratelimiter().wait_n(n)?;
+        Ok(self.schedule(&mut self.next.lock(), n))
+    }
+
+    /// Blocking function to wait until a single token has been acquired. See
+    /// [`Pacer::wait_n`].
+    pub fn wait(&self) -> Result<Instant, TryAcquireError> {
+        self.wait_n(1)
+    }
+
+    /// Internal function to return the intended start time for `n` tokens
+    /// which were just acquired, and to advance the schedule past them.
+    fn schedule(&self, next: &mut Option<Instant>, n: u64) -> Instant {
+        let now = Instant::now();
+
+        // a caller which is ahead of the schedule, for instance while using
+        // a burst of tokens, starts on time
+        let intended = match *next {
+            Some(next) if next < now => next,
+            _ => now,
+        };
+
+        let rate = self.ratelimiter().rate();
+        let spacing = if rate > 0.0 {
+            (n as f64 * 1_000_000_000.0 / rate).min(u64::MAX as f64) as u64
+        } else {
+            0
+        };
+
+        *next = Some(intended + Duration::from_nanos(spacing));
+        intended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use clocksource::precise::{Duration, Instant};
+
+    #[test]
+    fn intended() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(10))
+            .max_tokens(10)
+            .build()
+            .unwrap();
+        let pacer = Pacer::new(&rl);
+
+        let first = pacer.wait().unwrap();
+        assert!(first <= Instant::now());
+
+        // a stall builds up tokens, which are all intended to have been sent
+        // on the schedule while the caller was stalled
+        std::thread::sleep(core::time::Duration::from_millis(50));
+        let mut previous = first;
+        for _ in 0..4 {
+            let intended = pacer.try_wait().unwrap();
+            assert_eq!(intended - previous, Duration::from_millis(10));
+            previous = intended;
+        }
+        assert!(Instant::now() - previous >= Duration::from_millis(10));
+
+        // batches advance the schedule by each of their tokens
+        let intended = pacer.wait_n(2).unwrap();
+        assert_eq!(intended - previous, Duration::from_millis(10));
+
+        pacer.reset();
+        let now = Instant::now();
+        let intended = pacer.wait().unwrap();
+        assert!(intended >= now);
+    }
+}
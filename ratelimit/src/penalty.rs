@@ -0,0 +1,302 @@
+use crate::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::{Builder, KeyedRatelimiter, Ratelimiter, TryAcquireError};
+use clocksource::precise::{AtomicInstant, Duration, Instant};
+use core::hash::Hash;
+use parking_lot::Mutex;
+
+/// The configuration for the penalty box, see [`Builder::penalty`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PenaltyConfig {
+    cooldown: Duration,
+    denials: u64,
+    scale: Option<f64>,
+    window: Duration,
+}
+
+impl PenaltyConfig {
+    /// Returns `true` if the configuration is valid.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.denials > 0
+            && self.window.as_nanos() > 0
+            && self.cooldown.as_nanos() > 0
+            && self
+                .scale
+                .map(|scale| scale > 0.0 && scale < 1.0)
+                .unwrap_or(true)
+    }
+}
+
+/// Internal type which tracks the denials of a ratelimiter and whether it is
+/// in the penalty box.
+pub(crate) struct Penalty {
+    active: AtomicBool,
+    config: PenaltyConfig,
+    denials: AtomicU64,
+    penalties: AtomicU64,
+    // the scale to restore once the cooldown ends
+    restore: Mutex<Option<f64>>,
+    until: AtomicInstant,
+    window_start: AtomicInstant,
+}
+
+impl Penalty {
+    pub(crate) fn new(config: PenaltyConfig, now: Instant) -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            config,
+            denials: AtomicU64::new(0),
+            penalties: AtomicU64::new(0),
+            restore: Mutex::new(None),
+            until: AtomicInstant::new(now),
+            window_start: AtomicInstant::new(now),
+        }
+    }
+}
+
+impl Builder {
+    /// Put the ratelimiter in a penalty box when it is denied more than
+    /// `denials` times within the `window`, for example to stop an abusive
+    /// client which keeps retrying. While in the penalty box, all acquisitions
+    /// fail until the `cooldown` has passed. See [`Builder::penalty_scale`] to
+    /// lower the rate instead.
+    ///
+    /// Only denials due to insufficient tokens are counted, and denials while
+    /// in the penalty box do not extend the cooldown. With a
+    /// [`KeyedRatelimiter`], each key has its own penalty box, and the keys
+    /// which are in it are returned by [`KeyedRatelimiter::penalized`].
+    ///
+    /// ```
+    /// use ratelimit::Ratelimiter;
+    /// use std::time::Duration;
+    ///
+    /// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+    ///     .penalty(3, Duration::from_secs(1), Duration::from_secs(60))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// for _ in 0..4 {
+    ///     assert!(ratelimiter.try_acquire_n(2).is_err());
+    /// }
+    ///
+    /// assert!(ratelimiter.is_penalized());
+    /// std::thread::sleep(Duration::from_millis(5));
+    /// assert!(ratelimiter.try_acquire().is_err());
+    /// ```
+    pub fn penalty(
+        mut self,
+        denials: u64,
+        window: core::time::Duration,
+        cooldown: core::time::Duration,
+    ) -> Self {
+        self.penalty = Some(PenaltyConfig {
+            cooldown: Duration::from_nanos(cooldown.as_nanos() as u64),
+            denials,
+            scale: self.penalty.and_then(|penalty| penalty.scale),
+            window: Duration::from_nanos(window.as_nanos() as u64),
+        });
+        self
+    }
+
+    /// While in the penalty box, scale the rate by the provided factor rather
+    /// than failing all acquisitions. The factor must be in the range
+    /// `0.0..1.0` and greater than zero. Has no effect unless a penalty is
+    /// configured with [`Builder::penalty`].
+    ///
+    /// The rate is lowered through [`Ratelimiter::set_scale`] and restored
+    /// once the cooldown has passed.
+    pub fn penalty_scale(mut self, scale: f64) -> Self {
+        if let Some(penalty) = &mut self.penalty {
+            penalty.scale = Some(scale);
+        }
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns true if the ratelimiter is in the penalty box. See
+    /// [`Builder::penalty`].
+    pub fn is_penalized(&self) -> bool {
+        self.penalty()
+            .is_some_and(|penalty| penalty.until.load(Ordering::Acquire) > Instant::now())
+    }
+
+    /// Returns the number of times the ratelimiter has been put in the penalty
+    /// box. See [`Builder::penalty`].
+    pub fn penalties(&self) -> u64 {
+        self.penalty()
+            .map(|penalty| penalty.penalties.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Internal function to return the penalty box, if one is configured.
+    fn penalty(&self) -> Option<&Penalty> {
+        self.extensions()?.penalty.as_ref()
+    }
+
+    /// Internal function to determine the outcome of an acquisition while in
+    /// the penalty box. Returns `None` if the acquisition should proceed as
+    /// normal. A lowered rate is restored here once the cooldown has passed.
+    pub(crate) fn check_penalty(&self) -> Option<Result<(), TryAcquireError>> {
+        let penalty = self.penalty()?;
+
+        if !penalty.active.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let now = Instant::now();
+        let until = penalty.until.load(Ordering::Acquire);
+
+        if until > now {
+            return match penalty.config.scale {
+                Some(_) => None,
+                None => Some(Err(TryAcquireError::Insufficient(
+                    core::time::Duration::from_nanos((until - now).as_nanos()),
+                ))),
+            };
+        }
+
+        let mut restore = penalty.restore.lock();
+        if penalty.active.swap(false, Ordering::AcqRel) {
+            if let Some(scale) = restore.take() {
+                let _ = self.set_scale(scale);
+            }
+        }
+
+        None
+    }
+
+    /// Internal function to count a denial due to insufficient tokens, which
+    /// puts the ratelimiter in the penalty box if there have been too many.
+    pub(crate) fn offend(&self) {
+        let Some(penalty) = self.penalty() else {
+            return;
+        };
+
+        let now = Instant::now();
+        if penalty.active.load(Ordering::Acquire) {
+            return;
+        }
+
+        // start a new window if the current one has ended
+        let start = penalty.window_start.load(Ordering::Acquire);
+        if now - start >= penalty.config.window
+            && penalty
+                .window_start
+                .compare_exchange(start, now, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            penalty.denials.store(0, Ordering::Release);
+        }
+
+        if penalty.denials.fetch_add(1, Ordering::AcqRel) < penalty.config.denials {
+            return;
+        }
+
+        let mut restore = penalty.restore.lock();
+        if penalty.active.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        penalty.denials.store(0, Ordering::Release);
+        penalty.penalties.fetch_add(1, Ordering::Relaxed);
+        penalty
+            .until
+            .store(now + penalty.config.cooldown, Ordering::Release);
+
+        if let Some(scale) = penalty.config.scale {
+            let current = self.scale();
+            *restore = Some(current);
+            let _ = self.set_scale(current * scale);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send + Sync> KeyedRatelimiter<K> {
+    /// Returns the keys which are currently in the penalty box. See
+    /// [`Builder::penalty`].
+    pub fn penalized(&self) -> Vec<K> {
+        self.keys_where(|limiter| limiter.is_penalized())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn block() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .penalty(2, Duration::from_secs(60), Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        // two denials are tolerated
+        assert!(rl.try_acquire_n(2).is_err());
+        assert!(rl.try_acquire_n(2).is_err());
+        assert!(!rl.is_penalized());
+
+        assert!(rl.try_acquire_n(2).is_err());
+        assert!(rl.is_penalized());
+        assert_eq!(rl.penalties(), 1);
+
+        // the bucket refills, but acquisitions fail until the cooldown ends
+        std::thread::sleep(Duration::from_millis(10));
+        match rl.try_acquire() {
+            Err(TryAcquireError::Insufficient(wait)) => {
+                assert!(wait > Duration::from_millis(10) && wait <= Duration::from_millis(40))
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(rl.try_acquire().is_ok());
+        assert!(!rl.is_penalized());
+        assert_eq!(rl.penalties(), 1);
+
+        assert!(Ratelimiter::builder(1, Duration::from_millis(1))
+            .penalty(0, Duration::from_secs(1), Duration::from_secs(1))
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn scale() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .penalty(1, Duration::from_secs(60), Duration::from_millis(20))
+            .penalty_scale(0.1)
+            .build()
+            .unwrap();
+
+        assert!(rl.try_acquire_n(2).is_err());
+        assert!(rl.try_acquire_n(2).is_err());
+        assert!(rl.is_penalized());
+        assert_eq!(rl.rate(), 100.0);
+
+        std::thread::sleep(Duration::from_millis(20));
+        let _ = rl.try_acquire();
+        assert_eq!(rl.rate(), 1000.0);
+
+        assert!(Ratelimiter::builder(1, Duration::from_millis(1))
+            .penalty(1, Duration::from_secs(1), Duration::from_secs(1))
+            .penalty_scale(1.0)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn keyed() {
+        let rl: KeyedRatelimiter = KeyedRatelimiter::new(|_| {
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .penalty(1, Duration::from_secs(60), Duration::from_secs(60))
+                .build()
+                .unwrap()
+        });
+
+        assert!(rl.try_acquire("alice").is_err());
+        assert!(rl.try_acquire("alice").is_err());
+        assert!(rl.try_acquire("bob").is_err());
+
+        assert_eq!(rl.penalized(), vec!["alice".to_string()]);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{Builder, Ratelimiter, State};
+use parking_lot::{Condvar, Mutex};
+use std::fs::File;
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Periodically persists the state of a `Ratelimiter` to a file so that long
+/// window quotas survive crashes and restarts without an external datastore.
+///
+/// The state is written as JSON to a temporary file, which is flushed to disk
+/// and then atomically renamed over the destination, so a crash or power loss
+/// mid-write never leaves a partial file behind.
+///
+/// ```no_run
+/// use ratelimit::{Persistence, Ratelimiter};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let persistence = Persistence::new("/var/lib/service/ratelimit.json", Duration::from_secs(5));
+///
+/// // restore the previous state, if there is one
+/// let builder = persistence.restore(Ratelimiter::per_hour(1000)).unwrap();
+/// let ratelimiter = Arc::new(builder.build().unwrap());
+///
+/// // the state is persisted until the handle is dropped
+/// let handle = persistence.spawn(ratelimiter.clone());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Persistence {
+    path: PathBuf,
+    interval: Duration,
+}
+
+impl Persistence {
+    /// Create a new `Persistence` which writes to the provided path each time
+    /// the interval elapses.
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+        }
+    }
+
+    /// Returns the path the state is persisted to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the interval between writes.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Load the persisted state. Returns `None` if nothing has been persisted
+    /// yet.
+    pub fn load(&self) -> Result<Option<State>, Error> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Atomically write the provided state to the file.
+    pub fn save(&self, state: &State) -> Result<(), Error> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+
+        let json = serde_json::to_vec(state).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        // the contents must be on disk before the rename, otherwise the rename
+        // may be persisted first and leave an empty file after a power loss
+        let mut file = File::create(&tmp)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&tmp, &self.path)?;
+
+        // persist the rename itself, which is recorded in the directory
+        #[cfg(unix)]
+        {
+            let parent = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            File::open(parent)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+
+    /// Restore any persisted state into the provided builder. If nothing has
+    /// been persisted yet, the builder is returned unchanged.
+    pub fn restore(&self, builder: Builder) -> Result<Builder, Error> {
+        Ok(match self.load()? {
+            Some(state) => builder.restore(state),
+            None => builder,
+        })
+    }
+
+    /// Start a background thread which persists the state of the ratelimiter
+    /// each time the interval elapses. A final write is made when the
+    /// returned handle is stopped or dropped.
+    ///
+    /// Errors writing the file from the background thread are ignored and the
+    /// write is retried on the next interval.
+    pub fn spawn(self, ratelimiter: Arc<Ratelimiter>) -> PersistenceHandle {
+        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let thread = {
+            let shutdown = shutdown.clone();
+
+            std::thread::spawn(move || {
+                let (stopped, condvar) = &*shutdown;
+
+                loop {
+                    let mut stopped = stopped.lock();
+
+                    if !*stopped {
+                        condvar.wait_for(&mut stopped, self.interval);
+                    }
+
+                    if *stopped {
+                        return self.save(&ratelimiter.snapshot());
+                    }
+
+                    drop(stopped);
+
+                    let _ = self.save(&ratelimiter.snapshot());
+                }
+            })
+        };
+
+        PersistenceHandle {
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// A handle to the background thread started by [`Persistence::spawn`]. The
+/// thread is stopped, after a final write, when the handle is dropped.
+pub struct PersistenceHandle {
+    shutdown: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl PersistenceHandle {
+    /// Stop the background thread and return the result of the final write.
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        let (stopped, condvar) = &*self.shutdown;
+
+        *stopped.lock() = true;
+        condvar.notify_all();
+
+        match self.thread.take().map(|thread| thread.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Error::other("persistence thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PersistenceHandle {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ratelimit-{}-{name}.json", std::process::id()))
+    }
+
+    #[test]
+    fn save_and_load() {
+        let persistence = Persistence::new(path("save"), Duration::from_secs(1));
+        assert_eq!(persistence.load().unwrap(), None);
+
+        let state = State {
+            available: 3,
+            dropped: 5,
+            next_refill: Duration::from_millis(1500),
+        };
+        persistence.save(&state).unwrap();
+        assert_eq!(persistence.load().unwrap(), Some(state));
+
+        let rl = persistence
+            .restore(Ratelimiter::per_hour(1000))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(rl.available(), 3);
+
+        std::fs::write(persistence.path(), "garbage").unwrap();
+        assert!(persistence.load().is_err());
+
+        std::fs::remove_file(persistence.path()).unwrap();
+    }
+
+    #[test]
+    fn spawn() {
+        let persistence = Persistence::new(path("spawn"), Duration::from_millis(10));
+
+        let rl = Arc::new(
+            Ratelimiter::per_hour(1000)
+                .initial_available(1000)
+                .build()
+                .unwrap(),
+        );
+
+        let handle = persistence.clone().spawn(rl.clone());
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(persistence.load().unwrap().unwrap().available, 1000);
+
+        // the final state is written when stopped
+        rl.try_acquire_n(10).unwrap();
+        handle.stop().unwrap();
+        assert_eq!(persistence.load().unwrap().unwrap().available, 990);
+
+        std::fs::remove_file(persistence.path()).unwrap();
+    }
+}
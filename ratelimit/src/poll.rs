@@ -0,0 +1,263 @@
+use crate::fair::Ticket;
+use crate::sleep::retry_delay;
+use crate::wheel::Wheel;
+use crate::{Ratelimiter, TryAcquireError};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures_timer::Delay;
+use std::sync::Arc;
+
+/// The state needed to poll a ratelimiter for tokens with
+/// [`Ratelimiter::poll_acquire`]. This holds the timer which wakes the task
+/// once tokens are expected to be available, and should be kept in the
+/// `Future` or `Stream` which is polling the ratelimiter.
+///
+/// The timer is runtime agnostic, using [`futures_timer`]. The tasks waiting
+/// for the same refill share a single timer, which is held by one of them, so
+/// that many waiting tasks don't each register a timer with the runtime.
+#[derive(Debug, Default)]
+pub struct Waiter {
+    delay: Option<Delay>,
+    // the delay must elapse before the next attempt to acquire tokens
+    deferred: bool,
+    // the deadline this task is waiting for in the timer wheel of the
+    // ratelimiter, and whether this task holds the timer for it
+    deadline: Option<(Arc<Wheel>, u64)>,
+    // the place in the queue when FIFO ordering is enabled
+    ticket: Option<Ticket>,
+}
+
+impl Waiter {
+    /// Create a waiter which is ready to acquire tokens immediately.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay the next attempt to acquire tokens. This can be used to retry
+    /// later when [`Ratelimiter::poll_acquire`] fails because the ratelimiter
+    /// is denying all requests.
+    pub fn wait(&mut self, duration: core::time::Duration) {
+        self.delay = Some(Delay::new(duration));
+        self.deferred = true;
+    }
+
+    /// Returns `true` if the task is waiting for a timer before the next
+    /// attempt to acquire tokens.
+    pub fn is_waiting(&self) -> bool {
+        self.delay.is_some() || self.deadline.is_some()
+    }
+
+    /// Internal function to stop waiting in the timer wheel. If this task
+    /// holds the timer for its deadline, the other tasks waiting for the
+    /// deadline are woken so that one of them takes it over.
+    fn leave(&mut self) {
+        if let Some((wheel, deadline)) = self.deadline.take() {
+            if self.delay.take().is_some() {
+                wheel.wake(deadline);
+            }
+        }
+    }
+
+    /// Internal function to poll the timer, if any.
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(delay) = self.delay.as_mut() {
+            ready!(Pin::new(delay).poll(cx));
+            self.delay = None;
+            self.deferred = false;
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
+
+impl Ratelimiter {
+    /// Poll for `n` tokens from a manual `Future` or `Stream` implementation.
+    /// If the tokens are not available, `Poll::Pending` is returned and the
+    /// task is woken as soon as tokens are added to the bucket. The waiter
+    /// holds a timer for the next refill, so that the task is also woken if no
+    /// other caller triggers the refill.
+    ///
+    /// Returns an error if the ratelimiter is denying all requests or is
+    /// closed. A paused ratelimiter is waited on as if it had no tokens.
+    ///
+    /// ```
+    /// use ratelimit::{Ratelimiter, Waiter};
+    /// use std::future::poll_fn;
+    /// use std::time::Duration;
+    ///
+    /// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let mut waiter = Waiter::new();
+    /// let result = futures::executor::block_on(poll_fn(|cx| {
+    ///     ratelimiter.poll_acquire(cx, &mut waiter, 1)
+    /// }));
+    /// assert!(result.is_ok());
+    /// ```
+    pub fn poll_acquire(
+        &self,
+        cx: &mut Context<'_>,
+        waiter: &mut Waiter,
+        n: u64,
+    ) -> Poll<Result<(), TryAcquireError>> {
+        if waiter.deferred {
+            ready!(waiter.poll_delay(cx));
+        }
+
+        let mut registered = false;
+
+        loop {
+            let result = self.attempt_queued(n, &mut waiter.ticket);
+            let delay = result.as_ref().err().and_then(|e| retry_delay(self, e));
+
+            // tokens may have landed before the waker was registered, so we
+            // try again once it is. Only the second attempt is recorded, so
+            // that each poll counts as a single denial.
+            if delay.is_some() && !registered {
+                self.register_waker(cx.waker());
+                registered = true;
+                continue;
+            }
+
+            match self.record(n, result) {
+                Ok(()) => {
+                    waiter.leave();
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) => {
+                    let Some(delay) = delay else {
+                        waiter.leave();
+                        waiter.ticket = None;
+                        return Poll::Ready(Err(e));
+                    };
+
+                    // join the batch of tasks waiting for the refill, unless
+                    // this task already holds the timer for it
+                    let deadline = self.wheel_deadline(delay);
+                    if waiter.delay.is_none()
+                        || waiter.deadline.as_ref().map(|(_, d)| *d) != Some(deadline)
+                    {
+                        waiter.leave();
+                        if self.wheel().insert(deadline, cx.waker()) {
+                            waiter.delay = Some(Delay::new(self.until_deadline(deadline)));
+                        }
+                        waiter.deadline = Some((self.wheel().clone(), deadline));
+                    }
+
+                    if waiter.delay.is_none() {
+                        return Poll::Pending;
+                    }
+
+                    // the leader wakes the batch once the timer fires
+                    ready!(waiter.poll_delay(cx));
+                    if let Some((wheel, deadline)) = waiter.deadline.take() {
+                        wheel.wake(deadline);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::future::poll_fn;
+    use futures::executor::block_on;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Wake, Waker};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn poll_acquire() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut waiter = Waiter::new();
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            block_on(poll_fn(|cx| ratelimiter.poll_acquire(cx, &mut waiter, 1))).unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // the waiter holds the timer while the tokens are unavailable
+        assert!(poll_fn(|cx| ratelimiter.poll_acquire(cx, &mut waiter, 1))
+            .now_or_never()
+            .is_none());
+        assert!(waiter.is_waiting());
+
+        // returned tokens wake the task before the timer fires
+        ratelimiter.return_n(1);
+        assert!(poll_fn(|cx| ratelimiter.poll_acquire(cx, &mut waiter, 1))
+            .now_or_never()
+            .is_some());
+        assert!(!waiter.is_waiting());
+
+        ratelimiter.set_mode(crate::Mode::DenyAll);
+        assert_eq!(
+            block_on(poll_fn(|cx| ratelimiter.poll_acquire(cx, &mut waiter, 1))),
+            Err(TryAcquireError::Denied)
+        );
+    }
+
+    // test that a poll which is pending is counted as a single denial
+    #[test]
+    fn single_denial() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .counters(true)
+            .penalty(1, Duration::from_secs(60), Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let mut waiter = Waiter::new();
+
+        assert!(poll_fn(|cx| ratelimiter.poll_acquire(cx, &mut waiter, 1))
+            .now_or_never()
+            .is_none());
+        assert_eq!(ratelimiter.denied(), 1);
+        assert!(!ratelimiter.is_penalized());
+    }
+
+    struct Task(Arc<AtomicUsize>);
+
+    impl Wake for Task {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn shared_timer() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let woken = Arc::new(AtomicUsize::new(0));
+
+        let mut waiters: Vec<Waiter> = (0..100).map(|_| Waiter::new()).collect();
+        for waiter in waiters.iter_mut() {
+            let waker = Waker::from(Arc::new(Task(woken.clone())));
+            let mut cx = Context::from_waker(&waker);
+            assert!(ratelimiter.poll_acquire(&mut cx, waiter, 1).is_pending());
+            assert!(waiter.is_waiting());
+        }
+
+        // the tasks waiting for the same refill share a single timer
+        let leaders: Vec<usize> = (0..waiters.len())
+            .filter(|i| waiters[*i].delay.is_some())
+            .collect();
+        assert_eq!(leaders, vec![0]);
+
+        // when the leader stops waiting, the batch is woken to take over
+        drop(waiters.remove(0));
+        assert_eq!(woken.load(Ordering::Relaxed), 100);
+    }
+}
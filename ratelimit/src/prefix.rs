@@ -0,0 +1,139 @@
+use crate::{Error, KeyedRatelimiter};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Groups client addresses by their network prefix, so that a client with many
+/// addresses shares a single budget rather than having one per address. Used
+/// with [`KeyedRatelimiter::ip_prefix`].
+///
+/// By default, IPv4 addresses are grouped by their /24 and IPv6 addresses by
+/// their /56, which is a typical allocation for a single customer. IPv4
+/// addresses which are mapped into IPv6 are grouped as IPv4 addresses.
+///
+/// ```
+/// use ratelimit::IpPrefix;
+/// use std::net::IpAddr;
+///
+/// let prefix = IpPrefix::default();
+///
+/// let ip: IpAddr = "192.0.2.17".parse().unwrap();
+/// assert_eq!(prefix.normalize(ip), "192.0.2.0".parse::<IpAddr>().unwrap());
+///
+/// let ip: IpAddr = "2001:db8:aa:bbcc::1".parse().unwrap();
+/// assert_eq!(prefix.normalize(ip), "2001:db8:aa:bb00::".parse::<IpAddr>().unwrap());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpPrefix {
+    v4: u8,
+    v6: u8,
+}
+
+impl Default for IpPrefix {
+    fn default() -> Self {
+        Self { v4: 24, v6: 56 }
+    }
+}
+
+impl IpPrefix {
+    /// Group addresses by the provided prefix lengths for IPv4 and IPv6.
+    /// Returns an error if a length is longer than the address, which is 32
+    /// bits for IPv4 and 128 bits for IPv6.
+    pub fn new(v4: u8, v6: u8) -> Result<Self, Error> {
+        if v4 > 32 || v6 > 128 {
+            return Err(Error::InvalidPrefix);
+        }
+
+        Ok(Self { v4, v6 })
+    }
+
+    /// Returns the prefix length for IPv4 addresses.
+    pub fn v4(&self) -> u8 {
+        self.v4
+    }
+
+    /// Returns the prefix length for IPv6 addresses.
+    pub fn v6(&self) -> u8 {
+        self.v6
+    }
+
+    /// Returns the network address for the address, which has every bit after
+    /// the prefix cleared.
+    pub fn normalize(&self, ip: IpAddr) -> IpAddr {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - self.v4 as u32).unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - self.v6 as u32).unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+            }
+        }
+    }
+}
+
+impl KeyedRatelimiter<IpAddr> {
+    /// Group client addresses by their network prefix before looking up their
+    /// bucket. See [`IpPrefix`] for details.
+    ///
+    /// ```
+    /// use ratelimit::{IpPrefix, KeyedRatelimiter, RatelimiterConfig};
+    /// use std::net::IpAddr;
+    ///
+    /// let config = RatelimiterConfig::new("10/s".parse().unwrap()).initial_available(10);
+    /// let ratelimiter = KeyedRatelimiter::from_config(config)
+    ///     .unwrap()
+    ///     .ip_prefix(IpPrefix::default());
+    ///
+    /// let a: IpAddr = "192.0.2.1".parse().unwrap();
+    /// let b: IpAddr = "192.0.2.2".parse().unwrap();
+    ///
+    /// // both addresses share the budget of 192.0.2.0/24
+    /// assert!(ratelimiter.try_wait_n(&a, 10).is_ok());
+    /// assert!(ratelimiter.try_wait(&b).is_err());
+    /// ```
+    pub fn ip_prefix(self, prefix: IpPrefix) -> Self {
+        self.normalize_with(move |ip| prefix.normalize(*ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn normalize() {
+        let prefix = IpPrefix::new(16, 48).unwrap();
+        assert_eq!(prefix.normalize(ip("10.1.2.3")), ip("10.1.0.0"));
+        assert_eq!(prefix.normalize(ip("2001:db8:1:2::3")), ip("2001:db8:1::"));
+        assert_eq!(prefix.normalize(ip("::ffff:10.1.2.3")), ip("10.1.0.0"));
+
+        let prefix = IpPrefix::new(0, 128).unwrap();
+        assert_eq!(prefix.normalize(ip("10.1.2.3")), ip("0.0.0.0"));
+        assert_eq!(prefix.normalize(ip("2001:db8::1")), ip("2001:db8::1"));
+
+        let prefix = IpPrefix::new(32, 0).unwrap();
+        assert_eq!(prefix.normalize(ip("10.1.2.3")), ip("10.1.2.3"));
+        assert_eq!(prefix.normalize(ip("2001:db8::1")), ip("::"));
+
+        assert_eq!(IpPrefix::new(33, 56), Err(Error::InvalidPrefix));
+        assert_eq!(IpPrefix::new(24, 129), Err(Error::InvalidPrefix));
+    }
+
+    #[test]
+    fn keyed() {
+        let config = crate::RatelimiterConfig::new("1/s".parse().unwrap()).initial_available(1);
+        let rl = KeyedRatelimiter::from_config(config)
+            .unwrap()
+            .ip_prefix(IpPrefix::default());
+
+        assert!(rl.try_acquire(&ip("2001:db8:0:1::1")).is_ok());
+        assert!(rl.try_acquire(&ip("2001:db8:0:ff::2")).is_err());
+        assert!(rl.try_acquire(&ip("2001:db8:0:100::1")).is_ok());
+        assert_eq!(rl.len(), 2);
+        assert!(rl.remove(&ip("2001:db8:0:1::ffff")).is_some());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::{Builder, Ratelimiter, TryAcquireError};
+
+/// Internal type which holds the reserve and the deny counter for a priority
+/// class.
+pub(crate) struct PriorityClass {
+    denied: AtomicU64,
+    reserve: f64,
+}
+
+impl PriorityClass {
+    pub(crate) fn new(reserve: f64) -> Self {
+        Self {
+            denied: AtomicU64::new(0),
+            reserve,
+        }
+    }
+}
+
+impl Builder {
+    /// Configure priority classes for [`Ratelimiter::try_acquire_priority`].
+    /// Each entry is the fraction of the max tokens which is held back from
+    /// the class at that index, so that a class only succeeds while at least
+    /// its reserve would remain in the bucket. Reserves must be in the range
+    /// `0.0..1.0`.
+    ///
+    /// For example, `&[0.0, 0.3]` allows class `0` to draw the bucket down to
+    /// zero, while class `1` only succeeds while at least 30% of the capacity
+    /// would remain.
+    ///
+    /// The default is no priority classes, in which case all priorities behave
+    /// as if they had no reserve.
+    pub fn priority_reserves(mut self, reserves: &[f64]) -> Self {
+        self.reserves = reserves.to_vec();
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Non-blocking function to acquire `n` tokens on behalf of a priority
+    /// class. The tokens are only acquired if the tokens held in reserve for
+    /// the class would remain in the bucket. See
+    /// [`Builder::priority_reserves`].
+    ///
+    /// A class beyond those which are configured is treated as the last class.
+    /// A failure due to the reserve is reported as
+    /// [`TryAcquireError::Insufficient`].
+    pub fn try_acquire_priority(&self, n: u64, priority: usize) -> Result<(), TryAcquireError> {
+        let Some(class) = self.priority_class(priority) else {
+            return self.try_acquire_n(n);
+        };
+
+        let result = match self.check_state().or_else(|| self.check_penalty()) {
+            Some(result) => result,
+            None if self.queue().is_some_and(|queue| !queue.is_empty()) => {
+                Err(TryAcquireError::Insufficient(self.scaled_interval()))
+            }
+            None => {
+                let floor = (class.reserve * self.max_tokens() as f64) as u64;
+                self.take_or_drop(n, floor)
+            }
+        };
+        let result = self.record(n, result);
+
+        if let Err(TryAcquireError::Insufficient(_)) = result {
+            class.denied.fetch_add(1, Ordering::Relaxed);
+        }
+
+        result
+    }
+
+    /// Returns the number of times that [`Ratelimiter::try_acquire_priority`]
+    /// failed for lack of tokens for the provided priority class. A class
+    /// beyond those which are configured is counted as the last class.
+    pub fn priority_denied(&self, priority: usize) -> u64 {
+        self.priority_class(priority)
+            .map(|class| class.denied.load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Internal function to return the priority class, treating a class
+    /// beyond those which are configured as the last class.
+    fn priority_class(&self, priority: usize) -> Option<&PriorityClass> {
+        let priorities = &self.extensions()?.priorities;
+        priorities.get(priority).or_else(|| priorities.last())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn priority_reserves() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .priority_reserves(&[0.0, 0.3])
+            .build()
+            .unwrap();
+
+        // the low priority class stops once the reserve would be drawn down
+        for _ in 0..7 {
+            assert!(rl.try_acquire_priority(1, 1).is_ok());
+        }
+        assert!(rl.try_acquire_priority(1, 1).is_err());
+        assert!(rl.try_acquire_priority(1, 5).is_err());
+        assert_eq!(rl.available(), 3);
+
+        // a class may draw the bucket down to exactly its reserve
+        rl.set_available(4).unwrap();
+        assert!(rl.try_acquire_priority(1, 1).is_ok());
+        assert_eq!(rl.available(), 3);
+
+        // the high priority class can draw the bucket down to zero
+        assert!(rl.try_acquire_priority(3, 0).is_ok());
+        assert!(rl.try_acquire_priority(1, 0).is_err());
+
+        assert_eq!(rl.priority_denied(0), 1);
+        assert_eq!(rl.priority_denied(1), 2);
+    }
+
+    #[test]
+    fn penalty() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .priority_reserves(&[0.0, 0.3])
+            .penalty(1, Duration::from_secs(60), Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        assert!(rl.try_acquire_n(20).is_err());
+        assert!(rl.try_acquire_n(20).is_err());
+        assert!(rl.is_penalized());
+
+        // a penalized caller can't skip the penalty box with a priority
+        assert!(matches!(
+            rl.try_acquire_priority(1, 0),
+            Err(TryAcquireError::Insufficient(_))
+        ));
+        assert_eq!(rl.available(), 10);
+    }
+
+    #[test]
+    fn early_drop() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10_000)
+            .initial_available(100)
+            .priority_reserves(&[0.0])
+            .early_drop(0.5, 1.0)
+            .build()
+            .unwrap();
+
+        // priority acquisitions are subject to early drop too
+        let admitted = (0..100)
+            .filter(|_| rl.try_acquire_priority(1, 0).is_ok())
+            .count() as u64;
+        assert!(rl.early_dropped() > 50);
+        assert_eq!(rl.early_dropped(), 100 - admitted);
+        assert_eq!(rl.available(), 100 - admitted);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            Ratelimiter::builder(1, Duration::from_secs(1))
+                .priority_reserves(&[0.0, 1.0])
+                .build()
+                .err(),
+            Some(Error::InvalidReserve)
+        );
+
+        // without classes, priorities have no reserve
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(2)
+            .initial_available(2)
+            .build()
+            .unwrap();
+        assert!(rl.try_acquire_priority(2, 3).is_ok());
+        assert_eq!(rl.priority_denied(3), 0);
+    }
+}
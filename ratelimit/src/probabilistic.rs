@@ -0,0 +1,123 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::random::Random;
+use crate::Error;
+
+/// A limiter which admits a fraction of requests at random. This is commonly
+/// composed with a token bucket for gradual rollouts, where a feature is
+/// enabled for a growing fraction of requests, and for brownouts, where a
+/// fraction of requests is deliberately shed.
+///
+/// Unlike a [`Ratelimiter`](crate::Ratelimiter), the number of admitted
+/// requests scales with the offered load. The fraction can be changed at
+/// runtime.
+///
+/// ```
+/// use ratelimit::ProbabilisticLimiter;
+///
+/// // admit 12.5% of requests
+/// let limiter = ProbabilisticLimiter::new(0.125).unwrap();
+///
+/// if limiter.admit() {
+///     // use the new code path
+/// }
+///
+/// // roll out to everyone
+/// limiter.set_fraction(1.0).unwrap();
+/// assert!(limiter.admit());
+/// ```
+pub struct ProbabilisticLimiter {
+    admitted: AtomicU64,
+    // the bits of the fraction as an `f64`
+    fraction: AtomicU64,
+    random: Random,
+    rejected: AtomicU64,
+}
+
+impl ProbabilisticLimiter {
+    /// Create a limiter which admits the provided fraction of requests. The
+    /// fraction must be in the range `0.0..=1.0`.
+    pub fn new(fraction: f64) -> Result<Self, Error> {
+        check(fraction)?;
+
+        Ok(Self {
+            admitted: AtomicU64::new(0),
+            fraction: AtomicU64::new(fraction.to_bits()),
+            random: Random::new(),
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns the fraction of requests which are admitted.
+    pub fn fraction(&self) -> f64 {
+        f64::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+
+    /// Change the fraction of requests which are admitted. The fraction must
+    /// be in the range `0.0..=1.0`.
+    pub fn set_fraction(&self, fraction: f64) -> Result<(), Error> {
+        check(fraction)?;
+        self.fraction.store(fraction.to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns `true` if the request should be admitted.
+    pub fn admit(&self) -> bool {
+        if self.random.next_f64() < self.fraction() {
+            self.admitted.fetch_add(1, Ordering::Relaxed);
+            true
+        } else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// Returns the number of requests which have been admitted.
+    pub fn admitted(&self) -> u64 {
+        self.admitted.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of requests which have been rejected.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+/// Internal function to validate a fraction of requests.
+fn check(fraction: f64) -> Result<(), Error> {
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(())
+    } else {
+        Err(Error::InvalidFraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admit() {
+        let limiter = ProbabilisticLimiter::new(0.125).unwrap();
+
+        let admitted = (0..100_000).filter(|_| limiter.admit()).count();
+        assert!((11_500..13_500).contains(&admitted), "{admitted}");
+        assert_eq!(limiter.admitted(), admitted as u64);
+        assert_eq!(limiter.rejected(), 100_000 - admitted as u64);
+
+        limiter.set_fraction(0.0).unwrap();
+        assert!((0..1_000).all(|_| !limiter.admit()));
+
+        limiter.set_fraction(1.0).unwrap();
+        assert!((0..1_000).all(|_| limiter.admit()));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(ProbabilisticLimiter::new(1.5).is_err());
+        assert!(ProbabilisticLimiter::new(f64::NAN).is_err());
+
+        let limiter = ProbabilisticLimiter::new(0.5).unwrap();
+        assert_eq!(limiter.set_fraction(-0.1), Err(Error::InvalidFraction));
+        assert_eq!(limiter.fraction(), 0.5);
+    }
+}
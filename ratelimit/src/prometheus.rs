@@ -0,0 +1,217 @@
+//! Render the health of ratelimiters in the Prometheus text exposition
+//! format, so that services without a metrics pipeline can serve it to be
+//! scraped directly.
+//!
+//! The counters use the same names as the `metrics` feature and each sample
+//! has a `limiter` label with the name of the ratelimiter. They are only kept
+//! by ratelimiters which enable them with
+//! [`Builder::counters`](crate::Builder::counters).
+//!
+//! ```
+//! use ratelimit::{prometheus, Ratelimiter};
+//! use std::time::Duration;
+//!
+//! let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+//!     .counters(true)
+//!     .max_tokens(10)
+//!     .initial_available(10)
+//!     .build()
+//!     .unwrap();
+//!
+//! ratelimiter.try_acquire_n(4).unwrap();
+//!
+//! let text = prometheus::render("search-api", &ratelimiter);
+//! assert!(text.contains("ratelimit_issued{limiter=\"search-api\"} 4\n"));
+//! ```
+
+use crate::{registry, Ratelimiter};
+use core::borrow::Borrow;
+use core::fmt::Write;
+
+/// The metric families which are rendered for each ratelimiter.
+const FAMILIES: &[Family] = &[
+    Family {
+        name: "ratelimit_acquired",
+        kind: "counter",
+        help: "number of successful acquisitions",
+        value: |limiter| limiter.acquired() as f64,
+    },
+    Family {
+        name: "ratelimit_available",
+        kind: "gauge",
+        help: "number of tokens available",
+        value: |limiter| limiter.available() as f64,
+    },
+    Family {
+        name: "ratelimit_denied",
+        kind: "counter",
+        help: "number of attempts to acquire tokens which failed",
+        value: |limiter| limiter.denied() as f64,
+    },
+    Family {
+        name: "ratelimit_dropped",
+        kind: "counter",
+        help: "number of tokens dropped because the bucket was full",
+        value: |limiter| limiter.dropped() as f64,
+    },
+    Family {
+        name: "ratelimit_issued",
+        kind: "counter",
+        help: "number of tokens handed out by successful acquisitions",
+        value: |limiter| limiter.issued() as f64,
+    },
+    Family {
+        name: "ratelimit_max_tokens",
+        kind: "gauge",
+        help: "maximum number of tokens in the bucket",
+        value: |limiter| limiter.max_tokens() as f64,
+    },
+    Family {
+        name: "ratelimit_observed_rate",
+        kind: "gauge",
+        help: "estimated rate at which tokens are acquired in tokens/second",
+        value: |limiter| limiter.observed_rate(),
+    },
+    Family {
+        name: "ratelimit_rate",
+        kind: "gauge",
+        help: "current effective rate in tokens/second",
+        value: |limiter| limiter.rate(),
+    },
+    Family {
+        name: "ratelimit_returned",
+        kind: "counter",
+        help: "number of tokens returned to the bucket",
+        value: |limiter| limiter.returned() as f64,
+    },
+    Family {
+        name: "ratelimit_utilization",
+        kind: "gauge",
+        help: "fraction of the refill which is being consumed",
+        value: |limiter| limiter.utilization(),
+    },
+];
+
+/// Internal type which describes a metric family.
+struct Family {
+    name: &'static str,
+    kind: &'static str,
+    help: &'static str,
+    value: fn(&Ratelimiter) -> f64,
+}
+
+/// Render the metrics of a single ratelimiter, labeled with the provided name.
+pub fn render(name: &str, limiter: &Ratelimiter) -> String {
+    render_all([(name, limiter)])
+}
+
+/// Render the metrics of each of the provided ratelimiters, labeled with their
+/// names. Each metric family is written once, with a sample for each
+/// ratelimiter.
+pub fn render_all<N, L>(limiters: impl IntoIterator<Item = (N, L)>) -> String
+where
+    N: AsRef<str>,
+    L: Borrow<Ratelimiter>,
+{
+    let limiters: Vec<(String, L)> = limiters
+        .into_iter()
+        .map(|(name, limiter)| (escape(name.as_ref()), limiter))
+        .collect();
+
+    let mut text = String::new();
+
+    for family in FAMILIES {
+        let _ = writeln!(text, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(text, "# TYPE {} {}", family.name, family.kind);
+
+        for (name, limiter) in &limiters {
+            let _ = writeln!(
+                text,
+                "{}{{limiter=\"{}\"}} {}",
+                family.name,
+                name,
+                (family.value)(limiter.borrow())
+            );
+        }
+    }
+
+    text
+}
+
+/// Render the metrics of every ratelimiter in the [`registry`], labeled with
+/// the names they are registered with.
+pub fn render_registry() -> String {
+    render_all(registry::entries())
+}
+
+/// Internal function to escape a label value.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn ratelimiter() -> Ratelimiter {
+        Ratelimiter::builder(1, Duration::from_secs(60))
+            .counters(true)
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn render_all() {
+        let a = ratelimiter();
+        let b = ratelimiter();
+        a.try_acquire_n(4).unwrap();
+        assert!(b.try_acquire_n(11).is_err());
+
+        let text = super::render_all([("a", &a), ("say \"b\"\n", &b)]);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(
+            &lines[0..4],
+            &[
+                "# HELP ratelimit_acquired number of successful acquisitions",
+                "# TYPE ratelimit_acquired counter",
+                "ratelimit_acquired{limiter=\"a\"} 1",
+                "ratelimit_acquired{limiter=\"say \\\"b\\\"\\n\"} 0",
+            ]
+        );
+        assert!(lines.contains(&"ratelimit_denied{limiter=\"say \\\"b\\\"\\n\"} 1"));
+        assert!(lines.contains(&"ratelimit_available{limiter=\"a\"} 6"));
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("# TYPE"))
+                .count(),
+            FAMILIES.len()
+        );
+    }
+
+    #[test]
+    fn render_registry() {
+        registry::insert("prometheus-test", Arc::new(ratelimiter()));
+
+        assert!(super::render_registry()
+            .contains("ratelimit_max_tokens{limiter=\"prometheus-test\"} 10\n"));
+
+        registry::remove("prometheus-test");
+    }
+}
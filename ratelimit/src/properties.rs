@@ -0,0 +1,166 @@
+//! Property-based tests which drive ratelimiters with random parameters
+//! through random sequences of operations in virtual time, and check the
+//! invariants of the token bucket after each operation.
+
+use crate::*;
+use proptest::prelude::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// An operation on the ratelimiter.
+#[derive(Clone, Debug)]
+enum Op {
+    /// Advance the virtual time by the number of nanoseconds.
+    Advance(u64),
+    Acquire(u64),
+    Return(u64),
+    SetRefillAmount(u64),
+    SetRefillInterval(u64),
+    SetMaxTokens(u64),
+    SetAvailable(u64),
+}
+
+/// The parameters of a ratelimiter.
+#[derive(Clone, Debug)]
+struct Params {
+    amount: u64,
+    interval: u64,
+    max_tokens: u64,
+    initial: u64,
+    carry_over: CarryOver,
+    smooth: bool,
+}
+
+/// Counts the tokens added by refills.
+#[derive(Default)]
+struct Refilled(AtomicU64);
+
+impl RatelimiterObserver for Refilled {
+    fn on_refill(&self, tokens: u64) {
+        self.0.fetch_add(tokens, Ordering::Relaxed);
+    }
+}
+
+fn params() -> impl Strategy<Value = Params> {
+    (
+        1..=100u64,
+        1_000..=10_000_000u64,
+        0..=1000u64,
+        any::<bool>(),
+    )
+        .prop_flat_map(|(amount, interval, extra, smooth)| {
+            let max_tokens = amount + extra;
+            (
+                Just((amount, interval, max_tokens, smooth)),
+                0..=max_tokens,
+                prop_oneof![
+                    Just(CarryOver::Unlimited),
+                    Just(CarryOver::None),
+                    (0..=max_tokens).prop_map(CarryOver::Capped),
+                ],
+            )
+        })
+        .prop_map(
+            |((amount, interval, max_tokens, smooth), initial, carry_over)| Params {
+                amount,
+                interval,
+                max_tokens,
+                initial,
+                carry_over,
+                smooth,
+            },
+        )
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..50_000_000u64).prop_map(Op::Advance),
+        4 => (0..200u64).prop_map(Op::Acquire),
+        2 => prop_oneof![0..200u64, Just(1 << 40)].prop_map(Op::Return),
+        1 => (0..200u64).prop_map(Op::SetRefillAmount),
+        1 => (1_000..10_000_000u64).prop_map(Op::SetRefillInterval),
+        1 => (0..2000u64).prop_map(Op::SetMaxTokens),
+        1 => (0..2000u64).prop_map(Op::SetAvailable),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn invariants(params in params(), ops in prop::collection::vec(op(), 1..100)) {
+        let refilled = Arc::new(Refilled::default());
+
+        let rl = Ratelimiter::builder(params.amount, core::time::Duration::from_nanos(params.interval))
+            .max_tokens(params.max_tokens)
+            .initial_available(params.initial)
+            .carry_over(params.carry_over)
+            .smooth(params.smooth)
+            .observer(refilled.clone())
+            .build()
+            .unwrap();
+
+        let mut time = rl.created;
+        let mut refill_at = rl.next_refill();
+
+        // the tokens in the bucket when the ledger was last reset, and the
+        // tokens taken from it since
+        let mut baseline = params.initial;
+        let mut acquired: u64 = 0;
+        let mut returned = 0;
+        let mut refills = 0;
+        let mut dropped = 0;
+
+        for op in ops {
+            match op {
+                Op::Advance(ns) => {
+                    time += Duration::from_nanos(ns);
+                    let _ = rl.refill(time);
+                }
+                Op::Acquire(n) => {
+                    if rl.take_at(n, 0, || time).is_ok() {
+                        acquired += n;
+                    }
+                }
+                Op::Return(n) => {
+                    rl.return_n(n);
+                    returned += n;
+                }
+                Op::SetRefillAmount(amount) => {
+                    let _ = rl.set_refill_amount(amount);
+                }
+                Op::SetRefillInterval(ns) => {
+                    rl.set_refill_interval(core::time::Duration::from_nanos(ns)).unwrap();
+                }
+                Op::SetMaxTokens(amount) => {
+                    let _ = rl.set_max_tokens(amount);
+                }
+                Op::SetAvailable(amount) => {
+                    if rl.set_available(amount).is_ok() {
+                        baseline = amount;
+                        acquired = 0;
+                        returned = 0;
+                        refills = refilled.0.load(Ordering::Relaxed);
+                        dropped = rl.dropped();
+                    }
+                }
+            }
+
+            let available = rl.available();
+
+            // the bucket never holds more than its capacity
+            prop_assert!(available <= rl.max_tokens(), "{op:?}: {available} > {}", rl.max_tokens());
+
+            // the refill time never moves backwards
+            let next = rl.next_refill();
+            prop_assert!(next >= refill_at, "{op:?}: refill time moved backwards");
+            refill_at = next;
+
+            // every token is accounted for
+            let added = baseline as u128
+                + (refilled.0.load(Ordering::Relaxed) - refills) as u128
+                + returned as u128;
+            let removed = acquired as u128 + (rl.dropped() - dropped) as u128;
+            prop_assert!(added >= removed, "{op:?}: more tokens removed than added");
+            prop_assert_eq!(added - removed, available as u128, "{:?}", op);
+        }
+    }
+}
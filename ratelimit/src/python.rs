@@ -0,0 +1,229 @@
+//! Python bindings, so that Python tooling and test harnesses share the exact
+//! semantics of the ratelimiters used by Rust services.
+//!
+//! The module is built as an extension module, for example with `maturin` or
+//! with:
+//!
+//! ```sh
+//! cargo rustc --release --features python,pyo3/extension-module --crate-type cdylib
+//! ```
+//!
+//! ```python
+//! from ratelimit import KeyedRatelimiter, Ratelimiter
+//!
+//! # 10 tokens every second, with a burst of 10 tokens
+//! ratelimiter = Ratelimiter(10, 1.0, max_tokens=10, initial_available=10)
+//!
+//! if (wait := ratelimiter.try_wait()) is not None:
+//!     print(f"try again in {wait}s")
+//!
+//! # blocks without holding the GIL until a token is acquired
+//! ratelimiter.wait()
+//!
+//! keyed = KeyedRatelimiter("100/s", max_tokens=100, idle_timeout=60.0)
+//! keyed.try_wait("alice")
+//! ```
+
+use crate::{KeyedRatelimiter, Rate, Ratelimiter, RatelimiterConfig};
+use core::time::Duration;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+/// A ratelimiter, see [`Ratelimiter`].
+#[pyclass(name = "Ratelimiter", module = "ratelimit", frozen)]
+pub struct PyRatelimiter {
+    ratelimiter: Ratelimiter,
+}
+
+#[pymethods]
+impl PyRatelimiter {
+    /// Create a ratelimiter which adds `amount` tokens each `interval`
+    /// seconds, up to `max_tokens`, which defaults to `amount`.
+    #[new]
+    #[pyo3(signature = (amount, interval, max_tokens = None, initial_available = 0))]
+    fn new(
+        amount: u64,
+        interval: f64,
+        max_tokens: Option<u64>,
+        initial_available: u64,
+    ) -> PyResult<Self> {
+        let interval = Duration::try_from_secs_f64(interval)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let ratelimiter = Ratelimiter::builder(amount, interval)
+            .max_tokens(max_tokens.unwrap_or(amount))
+            .initial_available(initial_available)
+            .build()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        Ok(Self { ratelimiter })
+    }
+
+    /// Try to acquire `n` tokens without blocking. Returns `None` if the
+    /// tokens were acquired, otherwise the seconds until the next refill.
+    #[pyo3(signature = (n = 1))]
+    fn try_wait(&self, n: u64) -> Option<f64> {
+        self.ratelimiter
+            .try_wait_n(n)
+            .err()
+            .map(|wait| wait.as_secs_f64())
+    }
+
+    /// Block until `n` tokens have been acquired. The GIL is released while
+    /// waiting. Raises `RuntimeError` if the ratelimiter is denying all
+    /// requests or is closed.
+    #[pyo3(signature = (n = 1))]
+    fn wait(&self, py: Python<'_>, n: u64) -> PyResult<()> {
+        py.allow_threads(|| crate::sleep::wait_n(&self.ratelimiter, n))
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Returns the number of tokens currently available.
+    fn available(&self) -> u64 {
+        self.ratelimiter.available()
+    }
+
+    /// Returns the most tokens which may be available.
+    fn max_tokens(&self) -> u64 {
+        self.ratelimiter.max_tokens()
+    }
+
+    /// Returns the number of tokens added each refill interval.
+    fn refill_amount(&self) -> u64 {
+        self.ratelimiter.refill_amount()
+    }
+
+    /// Returns the seconds between refills.
+    fn refill_interval(&self) -> f64 {
+        self.ratelimiter.refill_interval().as_secs_f64()
+    }
+}
+
+/// A ratelimiter with a bucket for each string key, see
+/// [`KeyedRatelimiter`].
+#[pyclass(name = "KeyedRatelimiter", module = "ratelimit", frozen)]
+pub struct PyKeyedRatelimiter {
+    ratelimiter: KeyedRatelimiter<String>,
+}
+
+#[pymethods]
+impl PyKeyedRatelimiter {
+    /// Create a keyed ratelimiter where each key is limited to the rate, such
+    /// as `"100/s"`. Buckets unused for `idle_timeout` seconds are evicted.
+    #[new]
+    #[pyo3(signature = (rate, max_tokens = None, initial_available = None, idle_timeout = None))]
+    fn new(
+        rate: &str,
+        max_tokens: Option<u64>,
+        initial_available: Option<u64>,
+        idle_timeout: Option<f64>,
+    ) -> PyResult<Self> {
+        let rate: Rate = rate
+            .parse()
+            .map_err(|e: crate::Error| PyValueError::new_err(e.to_string()))?;
+
+        let mut config = RatelimiterConfig::new(rate);
+        if let Some(tokens) = max_tokens {
+            config = config.max_tokens(tokens);
+        }
+        if let Some(tokens) = initial_available {
+            config = config.initial_available(tokens);
+        }
+
+        let mut ratelimiter = KeyedRatelimiter::from_config(config)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        if let Some(timeout) = idle_timeout {
+            let timeout = Duration::try_from_secs_f64(timeout)
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            ratelimiter = ratelimiter.idle_timeout(timeout);
+        }
+
+        Ok(Self { ratelimiter })
+    }
+
+    /// Try to acquire `n` tokens for the key without blocking. Returns `None`
+    /// if the tokens were acquired, otherwise the seconds until the next
+    /// refill.
+    #[pyo3(signature = (key, n = 1))]
+    fn try_wait(&self, key: &str, n: u64) -> Option<f64> {
+        self.ratelimiter
+            .try_wait_n(key, n)
+            .err()
+            .map(|wait| wait.as_secs_f64())
+    }
+
+    /// Block until `n` tokens have been acquired for the key. The GIL is
+    /// released while waiting. Raises `RuntimeError` if the ratelimiter for
+    /// the key is denying all requests or is closed.
+    #[pyo3(signature = (key, n = 1))]
+    fn wait(&self, py: Python<'_>, key: &str, n: u64) -> PyResult<()> {
+        if self.ratelimiter.exempt_request(key) {
+            return Ok(());
+        }
+
+        let result = py.allow_threads(|| crate::sleep::wait_keyed(&self.ratelimiter, key, n));
+        self.ratelimiter.record_hot_key(key, result.is_ok());
+
+        result.map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Returns the number of tokens currently available for the key.
+    fn available(&self, key: &str) -> u64 {
+        self.ratelimiter.get(key).available()
+    }
+
+    /// Returns the number of keys with a bucket.
+    fn __len__(&self) -> usize {
+        self.ratelimiter.len()
+    }
+}
+
+/// The `ratelimit` Python module.
+#[pymodule]
+pub fn ratelimit(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyRatelimiter>()?;
+    m.add_class::<PyKeyedRatelimiter>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn python() {
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "ratelimit").unwrap();
+            ratelimit(&module).unwrap();
+
+            let locals = pyo3::types::PyDict::new(py);
+            locals.set_item("ratelimit", module).unwrap();
+
+            py.run(
+                c"
+r = ratelimit.Ratelimiter(1, 1.0, max_tokens=2, initial_available=2)
+assert r.try_wait(2) is None
+assert 0 < r.try_wait() <= 1.0
+assert r.max_tokens() == 2
+
+k = ratelimit.KeyedRatelimiter('1/s', initial_available=1)
+k.wait('a')
+assert k.try_wait('a') is not None
+assert k.try_wait('b') is None
+assert len(k) == 2
+
+try:
+    ratelimit.Ratelimiter(2, 1.0, max_tokens=1)
+    assert False
+except ValueError:
+    pass
+",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
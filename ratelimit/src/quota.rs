@@ -0,0 +1,207 @@
+use crate::{system_now, RetryAfter};
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The quota reported in the headers of a response from a remote API, as
+/// parsed by a [`QuotaHeaders`] implementation. Any of these may be missing,
+/// since most providers only report some of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// The number of requests remaining until the quota resets.
+    pub remaining: Option<u64>,
+    /// The time at which the quota resets.
+    pub reset_at: Option<SystemTime>,
+    /// The time to wait before retrying a throttled request.
+    pub retry_after: Option<Duration>,
+}
+
+/// A parser for the quota headers of a particular provider, which is used by
+/// [`Adaptive::response`](crate::Adaptive::response) to follow the quota of a
+/// remote API.
+///
+/// Headers are looked up by their lowercase names using the provided function,
+/// which for an `http::HeaderMap` is `&|name| headers.get(name)?.to_str().ok()`.
+///
+/// ```
+/// use ratelimit::{GitHubHeaders, QuotaHeaders};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let headers = [("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1372700873")];
+/// let header = |name: &str| headers.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+///
+/// let quota = GitHubHeaders.parse(&header);
+/// assert_eq!(quota.remaining, Some(0));
+/// assert_eq!(quota.reset_at, Some(UNIX_EPOCH + Duration::from_secs(1372700873)));
+///
+/// // GitHub responds to an exhausted quota with a `403 Forbidden`
+/// assert!(GitHubHeaders.is_throttled(403, &quota));
+/// ```
+pub trait QuotaHeaders: Send + Sync {
+    /// Parse the quota from the headers of a response.
+    fn parse<'a>(&self, header: &dyn Fn(&str) -> Option<&'a str>) -> Quota;
+
+    /// Returns whether a response with this status and quota means that the
+    /// request was throttled. By default, this is a `429 Too Many Requests`.
+    fn is_throttled(&self, status: u16, quota: &Quota) -> bool {
+        let _ = quota;
+        status == 429
+    }
+}
+
+/// The standard `RateLimit-Remaining` and `RateLimit-Reset` headers, where the
+/// reset is a number of seconds, along with `Retry-After`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardHeaders;
+
+impl QuotaHeaders for StandardHeaders {
+    fn parse<'a>(&self, header: &dyn Fn(&str) -> Option<&'a str>) -> Quota {
+        Quota {
+            remaining: number(header, "ratelimit-remaining"),
+            reset_at: number(header, "ratelimit-reset")
+                .and_then(|seconds| system_now().checked_add(Duration::from_secs(seconds))),
+            retry_after: retry_after(header),
+        }
+    }
+}
+
+/// The headers used by GitHub, where `X-RateLimit-Reset` is a unix time in
+/// seconds. GitHub reports an exhausted primary rate limit with a
+/// `403 Forbidden` rather than a `429`, which is also treated as throttled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GitHubHeaders;
+
+impl QuotaHeaders for GitHubHeaders {
+    fn parse<'a>(&self, header: &dyn Fn(&str) -> Option<&'a str>) -> Quota {
+        Quota {
+            remaining: number(header, "x-ratelimit-remaining"),
+            reset_at: number(header, "x-ratelimit-reset")
+                .and_then(|seconds| UNIX_EPOCH.checked_add(Duration::from_secs(seconds))),
+            retry_after: retry_after(header),
+        }
+    }
+
+    fn is_throttled(&self, status: u16, quota: &Quota) -> bool {
+        status == 429 || (status == 403 && quota.remaining == Some(0))
+    }
+}
+
+/// The responses of AWS services, which do not report the remaining quota.
+/// Throttling is a `429`, or a `503 Service Unavailable` for services such as
+/// S3 which respond to excessive request rates with `SlowDown`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AwsHeaders;
+
+impl QuotaHeaders for AwsHeaders {
+    fn parse<'a>(&self, header: &dyn Fn(&str) -> Option<&'a str>) -> Quota {
+        Quota {
+            retry_after: retry_after(header),
+            ..Default::default()
+        }
+    }
+
+    fn is_throttled(&self, status: u16, _quota: &Quota) -> bool {
+        status == 429 || status == 503
+    }
+}
+
+/// The responses of Stripe, which throttles with a `429` but reports neither
+/// the remaining quota nor, usually, a `Retry-After`. Throttled requests are
+/// then suppressed for a single refill interval, so a backoff should be
+/// configured with [`Adaptive::backoff`](crate::Adaptive::backoff).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StripeHeaders;
+
+impl QuotaHeaders for StripeHeaders {
+    fn parse<'a>(&self, header: &dyn Fn(&str) -> Option<&'a str>) -> Quota {
+        Quota {
+            retry_after: retry_after(header),
+            ..Default::default()
+        }
+    }
+}
+
+/// Internal function to parse a header which holds a number.
+fn number<'a>(header: &dyn Fn(&str) -> Option<&'a str>, name: &str) -> Option<u64> {
+    header(name)?.trim().parse().ok()
+}
+
+/// Internal function to parse the `Retry-After` header.
+fn retry_after<'a>(header: &dyn Fn(&str) -> Option<&'a str>) -> Option<Duration> {
+    RetryAfter::parse(header("retry-after")?).map(|r| r.delay())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(schema: &dyn QuotaHeaders, headers: &[(&'static str, &'static str)]) -> Quota {
+        let headers: HashMap<_, _> = headers.iter().copied().collect();
+        schema.parse(&|name| headers.get(name).copied())
+    }
+
+    #[test]
+    fn standard() {
+        let quota = parse(
+            &StandardHeaders,
+            &[("ratelimit-remaining", "7"), ("ratelimit-reset", "30")],
+        );
+
+        assert_eq!(quota.remaining, Some(7));
+        let reset = quota.reset_at.unwrap().duration_since(SystemTime::now());
+        assert!(reset.unwrap() > Duration::from_secs(29));
+        assert_eq!(quota.retry_after, None);
+        assert!(StandardHeaders.is_throttled(429, &quota));
+        assert!(!StandardHeaders.is_throttled(403, &quota));
+    }
+
+    #[test]
+    fn github() {
+        let quota = parse(
+            &GitHubHeaders,
+            &[
+                ("x-ratelimit-remaining", "0"),
+                ("x-ratelimit-reset", "1372700873"),
+            ],
+        );
+
+        assert_eq!(quota.remaining, Some(0));
+        assert_eq!(
+            quota.reset_at,
+            Some(UNIX_EPOCH + Duration::from_secs(1_372_700_873))
+        );
+        assert!(GitHubHeaders.is_throttled(403, &quota));
+        assert!(!GitHubHeaders.is_throttled(403, &Quota::default()));
+    }
+
+    #[test]
+    fn overflow() {
+        // a reset too far in the future to represent is treated as missing
+        let quota = parse(
+            &StandardHeaders,
+            &[
+                ("ratelimit-remaining", "18446744073709551615"),
+                ("ratelimit-reset", "18446744073709551615"),
+            ],
+        );
+        assert_eq!(quota.remaining, Some(u64::MAX));
+        assert_eq!(quota.reset_at, None);
+
+        let quota = parse(
+            &GitHubHeaders,
+            &[("x-ratelimit-reset", "18446744073709551615")],
+        );
+        assert_eq!(quota.reset_at, None);
+    }
+
+    #[test]
+    fn aws_and_stripe() {
+        let quota = parse(&AwsHeaders, &[("retry-after", "3")]);
+        assert_eq!(quota.retry_after, Some(Duration::from_secs(3)));
+        assert!(AwsHeaders.is_throttled(503, &quota));
+
+        let quota = parse(&StripeHeaders, &[("ratelimit-remaining", "3")]);
+        assert_eq!(quota, Quota::default());
+        assert!(!StripeHeaders.is_throttled(503, &quota));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{amount_and_interval, Error, RateSchedule, Ratelimiter};
+
+/// The shape of the curve followed by a [`Ramp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// The rate changes by the same number of tokens/s per unit time.
+    Linear,
+    /// The rate changes by the same factor per unit time. This is useful when
+    /// ramping across several orders of magnitude.
+    Exponential,
+}
+
+/// A change in rate from one value to another over some duration. After the
+/// duration has elapsed, the rate remains at the final value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ramp {
+    from: f64,
+    to: f64,
+    duration: core::time::Duration,
+    curve: Curve,
+}
+
+impl Ramp {
+    /// Returns the rate in tokens/s after `elapsed` time since the start of the
+    /// ramp.
+    pub fn rate_at(&self, elapsed: core::time::Duration) -> f64 {
+        if elapsed >= self.duration {
+            return self.to;
+        }
+
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+
+        match self.curve {
+            Curve::Linear => self.from + (self.to - self.from) * progress,
+            Curve::Exponential => self.from * (self.to / self.from).powf(progress),
+        }
+    }
+}
+
+/// A builder for a `Ratelimiter` whose rate changes over time, for example to
+/// warm-up a load generator or to gradually roll out traffic.
+///
+/// The rate is updated lazily as tokens are acquired, so no background thread
+/// is required.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// // ramp from 100 tokens/s to 10k tokens/s over a minute
+/// let ratelimiter = Ratelimiter::ramp(100.0, 10_000.0, Duration::from_secs(60))
+///     .exponential()
+///     .max_tokens(100)
+///     .build()
+///     .unwrap();
+/// ```
+pub struct RampBuilder {
+    ramp: Ramp,
+    max_tokens: u64,
+    initial_available: u64,
+}
+
+impl RampBuilder {
+    /// Initialize a new builder that ramps linearly from the `from` rate to the
+    /// `to` rate, both in tokens/s, over the provided `duration`.
+    pub fn new(from: f64, to: f64, duration: core::time::Duration) -> Self {
+        Self {
+            ramp: Ramp {
+                from,
+                to,
+                duration,
+                curve: Curve::Linear,
+            },
+            max_tokens: 1,
+            initial_available: 0,
+        }
+    }
+
+    /// Change the rate linearly over the duration of the ramp. This is the
+    /// default.
+    pub fn linear(mut self) -> Self {
+        self.ramp.curve = Curve::Linear;
+        self
+    }
+
+    /// Change the rate exponentially over the duration of the ramp.
+    pub fn exponential(mut self) -> Self {
+        self.ramp.curve = Curve::Exponential;
+        self
+    }
+
+    /// Set the max tokens that can be held in the `Ratelimiter` at any time.
+    /// If the refill amount required at the highest rate in the ramp is larger
+    /// than this, the max tokens will be raised to the refill amount.
+    pub fn max_tokens(mut self, tokens: u64) -> Self {
+        self.max_tokens = tokens;
+        self
+    }
+
+    /// Set the number of tokens that are initially available.
+    pub fn initial_available(mut self, tokens: u64) -> Self {
+        self.initial_available = tokens;
+        self
+    }
+
+    /// Consumes this `RampBuilder` and attempts to construct a `Ratelimiter`.
+    pub fn build(self) -> Result<Ratelimiter, Error> {
+        let ramp = self.ramp;
+
+        // validate both ends of the ramp and find the largest refill amount
+        let (from, _) = amount_and_interval(ramp.from)?;
+        let (to, _) = amount_and_interval(ramp.to)?;
+        let peak = from.max(to);
+
+        Ratelimiter::from_rate(ramp.from, self.max_tokens.max(peak))?
+            .initial_available(self.initial_available)
+            .schedule(ramp)
+            .build()
+    }
+}
+
+impl RateSchedule for Ramp {
+    fn rate_at(&self, elapsed: core::time::Duration) -> f64 {
+        Ramp::rate_at(self, elapsed)
+    }
+}
+
+impl Ratelimiter {
+    /// Initialize a builder that will construct a `Ratelimiter` whose rate
+    /// changes from `from` to `to`, both in tokens/s, over the provided
+    /// `duration`. See [`RampBuilder`] for details.
+    pub fn ramp(from: f64, to: f64, duration: core::time::Duration) -> RampBuilder {
+        RampBuilder::new(from, to, duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_at() {
+        let ramp = RampBuilder::new(100.0, 1000.0, core::time::Duration::from_secs(10)).ramp;
+        approx_eq!(ramp.rate_at(core::time::Duration::ZERO), 100.0);
+        approx_eq!(ramp.rate_at(core::time::Duration::from_secs(5)), 550.0);
+        approx_eq!(ramp.rate_at(core::time::Duration::from_secs(20)), 1000.0);
+
+        let ramp = RampBuilder::new(100.0, 10000.0, core::time::Duration::from_secs(10))
+            .exponential()
+            .ramp;
+        approx_eq!(ramp.rate_at(core::time::Duration::from_secs(5)), 1000.0);
+    }
+
+    use clocksource::precise::Duration;
+
+    #[test]
+    fn lazy_update() {
+        let rl = Ratelimiter::ramp(100.0, 1000.0, core::time::Duration::from_secs(10))
+            .build()
+            .unwrap();
+        approx_eq!(rl.rate(), 100.0);
+
+        let _ = rl.refill(rl.created + Duration::from_secs(5));
+        approx_eq!(rl.rate(), 550.0);
+
+        let _ = rl.refill(rl.created + Duration::from_secs(60));
+        approx_eq!(rl.rate(), 1000.0);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(
+            Ratelimiter::ramp(0.0, 1000.0, core::time::Duration::from_secs(1))
+                .build()
+                .is_err()
+        );
+        assert!(
+            Ratelimiter::ramp(100.0, f64::NAN, core::time::Duration::from_secs(1))
+                .build()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use crate::atomic::{AtomicU64, Ordering};
+use clocksource::precise::UnixInstant;
+
+/// A small, fast, and thread-safe pseudorandom number generator based on
+/// SplitMix64. This is not suitable for cryptographic purposes, but is more
+/// than sufficient for adding randomness to refill timing and admission.
+pub(crate) struct Random {
+    state: AtomicU64,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        // seed from the clock and the address of a stack variable so that
+        // limiters created at the same moment do not share a sequence
+        let local = 0_u8;
+        let seed = UnixInstant::now()
+            .duration_since(UnixInstant::EPOCH)
+            .as_nanos()
+            ^ (&local as *const u8 as u64).rotate_left(32);
+
+        Self::with_seed(seed)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Returns the next pseudorandom `u64`.
+    pub fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a pseudorandom `f64` in the range `0.0..1.0`.
+    pub fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1_u64 << 53) as f64)
+    }
+
+    /// Returns a sample from the exponential distribution with a mean of one.
+    pub fn exponential(&self) -> f64 {
+        // use 1 - u to avoid taking the log of zero
+        -(1.0 - self.next_f64()).ln()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential() {
+        let random = Random::with_seed(42);
+
+        let samples: Vec<f64> = (0..100_000).map(|_| random.exponential()).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance =
+            samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+
+        assert!((0.98..1.02).contains(&mean), "{mean}");
+        assert!((0.95..1.05).contains(&variance), "{variance}");
+    }
+}
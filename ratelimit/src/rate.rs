@@ -0,0 +1,233 @@
+use crate::{Builder, Error, Ratelimiter};
+use core::fmt;
+use core::str::FromStr;
+use core::time::Duration;
+
+/// A rate expressed as a number of tokens over a window of time. This is most
+/// useful when limits come from configuration files or command line flags as
+/// strings.
+///
+/// Rates can be parsed from strings in the form `<tokens>/<window>` where the
+/// tokens may have an optional `k`, `M`, or `G` suffix and the window is a
+/// time unit that may have an optional integer multiple. For example: `100/s`,
+/// `10/min`, `5000/h`, `1.5k/s`, and `100/10s` are all valid rates.
+///
+/// The supported units are: `ns`, `us`, `ms`, `s` (`sec`, `second`), `m`
+/// (`min`, `minute`), `h` (`hr`, `hour`), and `d` (`day`). Units may also be
+/// pluralized.
+///
+/// ```
+/// use ratelimit::Rate;
+///
+/// let rate: Rate = "1.5k/s".parse().unwrap();
+/// assert_eq!(rate.tokens_per_second(), 1500.0);
+///
+/// let ratelimiter = rate.builder().unwrap().build().unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    tokens: f64,
+    window: Duration,
+}
+
+impl Rate {
+    /// Create a new `Rate` which allows `tokens` over each `window`. Returns
+    /// an error if the number of tokens is negative or not finite or if the
+    /// window is zero.
+    pub fn new(tokens: f64, window: Duration) -> Result<Self, Error> {
+        if !tokens.is_finite() || tokens < 0.0 || window.is_zero() {
+            return Err(Error::InvalidRate);
+        }
+
+        Ok(Self { tokens, window })
+    }
+
+    /// Returns the number of tokens allowed in each window.
+    pub fn tokens(&self) -> f64 {
+        self.tokens
+    }
+
+    /// Returns the length of the window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the rate in tokens/s.
+    pub fn tokens_per_second(&self) -> f64 {
+        self.tokens / self.window.as_secs_f64()
+    }
+
+    /// Initialize a builder for a `Ratelimiter` with this rate. The max tokens
+    /// defaults to the number of tokens in the window, rounded up, so that the
+    /// full budget for a window may be used in a single burst.
+    ///
+    /// Decimal token counts, such as `0.3/ms`, are scaled up to an exact ratio
+    /// of whole tokens over a longer window, such as 3 tokens every 10ms.
+    pub fn builder(&self) -> Result<Builder, Error> {
+        if self.tokens.fract() == 0.0 && self.tokens <= u64::MAX as f64 {
+            return Ok(crate::per_window(self.tokens as u64, self.window));
+        }
+
+        let mut scale = 1;
+        for _ in 0..9 {
+            scale *= 10;
+
+            let tokens = self.tokens * scale as f64;
+            if (tokens - tokens.round()).abs() > 1e-6 || tokens >= u64::MAX as f64 {
+                continue;
+            }
+
+            // windows which cannot be represented fall back to the rate
+            let Some(window) = self
+                .window
+                .checked_mul(scale)
+                .filter(|window| window.as_nanos() <= u64::MAX as u128)
+            else {
+                break;
+            };
+
+            let builder = crate::per_window(tokens.round() as u64, window);
+            let max_tokens = (self.tokens.ceil() as u64).max(builder.refill_amount);
+            return Ok(builder.max_tokens(max_tokens));
+        }
+
+        Ratelimiter::from_rate(self.tokens_per_second(), self.tokens.ceil() as u64)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tokens, window) = s.split_once('/').ok_or(Error::MalformedRate)?;
+
+        let tokens = tokens.trim();
+        let (tokens, multiplier) = match tokens.chars().last() {
+            Some('k') | Some('K') => (&tokens[..tokens.len() - 1], 1e3),
+            Some('M') => (&tokens[..tokens.len() - 1], 1e6),
+            Some('G') => (&tokens[..tokens.len() - 1], 1e9),
+            _ => (tokens, 1.0),
+        };
+        let tokens: f64 = tokens.parse().map_err(|_| Error::MalformedRate)?;
+
+        let window = window.trim();
+        let split = window
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or(Error::MalformedRate)?;
+        let (count, unit) = window.split_at(split);
+        let count: u64 = if count.is_empty() {
+            1
+        } else {
+            count.parse().map_err(|_| Error::MalformedRate)?
+        };
+
+        let unit = match unit.trim() {
+            "ns" => Duration::from_nanos(1),
+            "us" => Duration::from_micros(1),
+            "ms" => Duration::from_millis(1),
+            "s" | "sec" | "secs" | "second" | "seconds" => Duration::from_secs(1),
+            "m" | "min" | "mins" | "minute" | "minutes" => Duration::from_secs(60),
+            "h" | "hr" | "hrs" | "hour" | "hours" => Duration::from_secs(3600),
+            "d" | "day" | "days" => Duration::from_secs(86400),
+            _ => {
+                return Err(Error::MalformedRate);
+            }
+        };
+
+        let count: u32 = count.try_into().map_err(|_| Error::MalformedRate)?;
+        let window = unit.checked_mul(count).ok_or(Error::MalformedRate)?;
+
+        Rate::new(tokens * multiplier, window)
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.window.as_nanos();
+
+        write!(f, "{}/", self.tokens)?;
+
+        match nanos {
+            1_000_000_000 => write!(f, "s"),
+            60_000_000_000 => write!(f, "min"),
+            3_600_000_000_000 => write!(f, "h"),
+            86_400_000_000_000 => write!(f, "d"),
+            _ if nanos.is_multiple_of(1_000_000_000) => write!(f, "{}s", nanos / 1_000_000_000),
+            _ if nanos.is_multiple_of(1_000_000) => write!(f, "{}ms", nanos / 1_000_000),
+            _ if nanos.is_multiple_of(1_000) => write!(f, "{}us", nanos / 1_000),
+            _ => write!(f, "{nanos}ns"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rate {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Builder {
+    /// Initialize a builder from a human-readable rate string such as
+    /// `100/s`, `10/min`, or `1.5k/s`. See [`Rate`] for the accepted format.
+    pub fn parse(rate: &str) -> Result<Builder, Error> {
+        rate.parse::<Rate>()?.builder()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let rate: Rate = "100/s".parse().unwrap();
+        assert_eq!(rate.tokens(), 100.0);
+        assert_eq!(rate.window(), Duration::from_secs(1));
+
+        let rate: Rate = "10/min".parse().unwrap();
+        assert_eq!(rate.tokens(), 10.0);
+        assert_eq!(rate.window(), Duration::from_secs(60));
+
+        let rate: Rate = "5000/h".parse().unwrap();
+        assert_eq!(rate.tokens(), 5000.0);
+        assert_eq!(rate.window(), Duration::from_secs(3600));
+
+        let rate: Rate = "1.5k/s".parse().unwrap();
+        assert_eq!(rate.tokens_per_second(), 1500.0);
+
+        let rate: Rate = " 2M / 10 seconds ".parse().unwrap();
+        assert_eq!(rate.tokens_per_second(), 200_000.0);
+
+        for invalid in ["", "100", "100/", "/s", "abc/s", "100/fortnight", "-1/s"] {
+            assert!(invalid.parse::<Rate>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn display() {
+        for s in ["100/s", "10/min", "5000/h", "1/d", "1.5/10s", "3/250ms"] {
+            let rate: Rate = s.parse().unwrap();
+            assert_eq!(rate.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let rl = Builder::parse("10/min").unwrap().build().unwrap();
+        assert_eq!(rl.max_tokens(), 10);
+        assert_eq!(rl.refill_interval(), Duration::from_secs(6));
+
+        let rl = Builder::parse("0.5/s").unwrap().build().unwrap();
+        assert_eq!(rl.max_tokens(), 1);
+        assert_eq!(rl.refill_interval(), Duration::from_secs(2));
+    }
+}
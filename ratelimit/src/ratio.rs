@@ -0,0 +1,145 @@
+use crate::atomic::Ordering;
+use crate::{per_window, Builder, Error, Parameters, Ratelimiter};
+
+impl Ratelimiter {
+    /// Initialize a builder that will construct a `Ratelimiter` which allows
+    /// exactly `tokens` tokens over each `window`, such as 3 tokens every 7
+    /// seconds. The max tokens defaults to `tokens`, so that the full budget
+    /// for a window may be used in a single burst.
+    ///
+    /// When the window does not divide evenly into refill intervals of whole
+    /// nanoseconds, the interval is rounded up and the tokens lost to rounding
+    /// are accumulated as a fraction of a token on each refill. The rate is
+    /// exact over time, rather than drifting with the rounding of the
+    /// interval.
+    ///
+    /// Returns an error if there are no tokens, or if the window is zero or
+    /// cannot be represented.
+    ///
+    /// ```
+    /// use ratelimit::Ratelimiter;
+    /// use std::time::Duration;
+    ///
+    /// let ratelimiter = Ratelimiter::from_ratio(3, Duration::from_secs(7))
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert!((ratelimiter.rate() - 3.0 / 7.0).abs() < 1e-12);
+    /// ```
+    pub fn from_ratio(tokens: u64, window: core::time::Duration) -> Result<Builder, Error> {
+        if tokens == 0 || window.is_zero() {
+            return Err(Error::InvalidRate);
+        }
+
+        if window.as_nanos() > u64::MAX as u128 {
+            return Err(Error::RefillIntervalTooLong);
+        }
+
+        Ok(per_window(tokens, window))
+    }
+
+    /// Internal function to return the whole tokens accumulated from the
+    /// fractional part of `intervals` refills of `amount_per_interval` tokens.
+    /// The remainder is carried over to the following refills.
+    pub(crate) fn refill_fraction(
+        &self,
+        intervals: u64,
+        amount_per_interval: u64,
+        parameters: &Parameters,
+    ) -> u64 {
+        let (numerator, denominator) = parameters.fraction;
+        if numerator == 0 {
+            return 0;
+        }
+
+        // smooth refills add a single token per step, and so add the fraction
+        // spread across the steps of each interval
+        let steps = (parameters.refill_amount / amount_per_interval.max(1)).max(1);
+        let denominator = denominator as u128 * steps as u128;
+        if denominator > u64::MAX as u128 {
+            return 0;
+        }
+
+        let added = intervals as u128 * numerator as u128;
+        let mut whole = 0;
+
+        let _ = self
+            .fraction
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |carry| {
+                let total = carry as u128 + added;
+                whole = (total / denominator).min(u64::MAX as u128) as u64;
+                Some((total % denominator) as u64)
+            });
+
+        whole
+    }
+}
+
+impl Builder {
+    /// Internal function to add `numerator / denominator` of a token to each
+    /// refill, in addition to the refill amount. The fraction is dropped if it
+    /// cannot be represented.
+    pub(crate) fn refill_fraction(mut self, numerator: u128, denominator: u128) -> Self {
+        let divisor = gcd(numerator, denominator).max(1);
+        let (numerator, denominator) = (numerator / divisor, denominator / divisor);
+
+        self.refill_fraction = match (u64::try_from(numerator), u64::try_from(denominator)) {
+            (Ok(numerator), Ok(denominator)) if numerator < denominator => (numerator, denominator),
+            _ => (0, 1),
+        };
+        self
+    }
+}
+
+/// Internal function to return the greatest common divisor.
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use clocksource::precise::Duration;
+
+    #[test]
+    fn exact() {
+        let rl = Ratelimiter::from_ratio(3, core::time::Duration::from_micros(7))
+            .unwrap()
+            .max_tokens(u64::MAX)
+            .build()
+            .unwrap();
+
+        // the interval is rounded up, and the fraction makes up the difference
+        assert_eq!(rl.refill_amount(), 1);
+        assert_eq!(
+            rl.refill_interval(),
+            core::time::Duration::from_nanos(2_334)
+        );
+
+        // after 7 seconds, 3 million tokens have been added, where rounding
+        // the interval alone would be short by over 800 tokens
+        rl.refill(rl.created + Duration::from_secs(7)).unwrap();
+        assert!((2_999_999..=3_000_000).contains(&rl.available()));
+
+        assert!(Ratelimiter::from_ratio(0, core::time::Duration::from_secs(1)).is_err());
+        assert!(Ratelimiter::from_ratio(1, core::time::Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn decimal() {
+        // fractional tokens are represented as an exact ratio
+        let rl = "0.1/s".parse::<Rate>().unwrap().builder().unwrap();
+        let rl = rl.build().unwrap();
+        assert_eq!(rl.refill_interval(), core::time::Duration::from_secs(10));
+        assert_eq!(rl.max_tokens(), 1);
+
+        let rl = "0.3/ms".parse::<Rate>().unwrap().builder().unwrap();
+        let rl = rl.max_tokens(u64::MAX).build().unwrap();
+        rl.refill(rl.created + Duration::from_secs(10)).unwrap();
+        assert!((2_999..=3_000).contains(&rl.available()));
+    }
+}
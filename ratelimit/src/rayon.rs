@@ -0,0 +1,152 @@
+//! An adapter which paces a [`rayon`](::rayon) parallel iterator with a
+//! ratelimiter, capping the aggregate rate of work across all the threads in
+//! the pool.
+//!
+//! Each worker thread sleeps until the tokens for its next item are
+//! available, so the pool should only be shared with work that can tolerate
+//! being stalled while the ratelimiter is exhausted.
+//!
+//! ```
+//! use ratelimit::rayon::RatelimitParallelIteratorExt;
+//! use ratelimit::Ratelimiter;
+//! use rayon::prelude::*;
+//! use std::time::Duration;
+//!
+//! let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+//!     .max_tokens(4)
+//!     .build()
+//!     .unwrap();
+//!
+//! // no more than 1000 calls/s are made to the external service, no matter
+//! // how many threads are in the pool
+//! let sum: u64 = (0..10_u64)
+//!     .into_par_iter()
+//!     .ratelimited(&ratelimiter)
+//!     .map(|id| id * 2)
+//!     .sum();
+//!
+//! assert_eq!(sum, 90);
+//! ```
+
+pub use crate::iter::UnitCost;
+
+use crate::Ratelimiter;
+use ::rayon::iter::{MapWith, ParallelIterator, WhileSome};
+use core::borrow::Borrow;
+
+/// The function used by [`RatelimitedParIter`] to charge the ratelimiter for
+/// each item.
+pub type ChargeFn<L, F, T> = fn(&mut (L, F), T) -> Option<T>;
+
+/// A parallel iterator which yields the items of the inner iterator no faster
+/// than the rate of a ratelimiter. Created by
+/// [`RatelimitParallelIteratorExt::ratelimited`] and
+/// [`RatelimitParallelIteratorExt::ratelimited_with`].
+///
+/// The iterator stops early if the ratelimiter is closed. As with
+/// [`ParallelIterator::while_some`], items which are already being processed
+/// by other threads may still be yielded.
+pub type RatelimitedParIter<I, L, F> =
+    WhileSome<MapWith<I, (L, F), ChargeFn<L, F, <I as ParallelIterator>::Item>>>;
+
+/// An extension trait which adds ratelimiting adapters to any
+/// [`ParallelIterator`].
+pub trait RatelimitParallelIteratorExt: ParallelIterator {
+    /// Yield items no faster than the rate of the ratelimiter, charging a
+    /// single token for each item.
+    fn ratelimited<L>(self, ratelimiter: L) -> RatelimitedParIter<Self, L, UnitCost<Self::Item>>
+    where
+        L: Borrow<Ratelimiter> + Clone + Send,
+    {
+        self.ratelimited_with(ratelimiter, |_| 1)
+    }
+
+    /// Yield items no faster than the rate of the ratelimiter, charging the
+    /// number of tokens returned by the cost function for each item.
+    ///
+    /// Note: costs above the maximum number of tokens in the ratelimiter are
+    /// charged as the maximum, since they could never be acquired otherwise.
+    fn ratelimited_with<L, F>(self, ratelimiter: L, cost: F) -> RatelimitedParIter<Self, L, F>
+    where
+        L: Borrow<Ratelimiter> + Clone + Send,
+        F: Fn(&Self::Item) -> u64 + Clone + Send,
+    {
+        self.map_with((ratelimiter, cost), charge as ChargeFn<L, F, Self::Item>)
+            .while_some()
+    }
+}
+
+impl<I: ParallelIterator> RatelimitParallelIteratorExt for I {}
+
+/// Internal function which waits for the tokens for an item. Returns `None`
+/// if the ratelimiter is closed.
+fn charge<L, F, T>((ratelimiter, cost): &mut (L, F), item: T) -> Option<T>
+where
+    L: Borrow<Ratelimiter>,
+    F: Fn(&T) -> u64,
+{
+    match cost(&item) {
+        0 => Some(item),
+        cost => crate::iter::wait((*ratelimiter).borrow(), cost).then_some(item),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rayon::prelude::*;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ratelimited() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+
+        // the rate is shared by all the threads in the pool
+        let start = Instant::now();
+        let mut items: Vec<u64> = (0..4_u64)
+            .into_par_iter()
+            .ratelimited(ratelimiter.clone())
+            .collect();
+        items.sort();
+        assert_eq!(items, vec![0, 1, 2, 3]);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        // the iterator stops when the ratelimiter is closed
+        ratelimiter.close();
+        let count = (0..4_u64)
+            .into_par_iter()
+            .ratelimited(&*ratelimiter)
+            .count();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn cost() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        // zero cost items are free, and costs above the burst are clamped
+        let items: Vec<u64> = vec![4, 0, 2]
+            .into_par_iter()
+            .ratelimited_with(&ratelimiter, |n| *n)
+            .collect();
+        assert_eq!(items, vec![4, 0, 2]);
+        assert_eq!(ratelimiter.available(), 4);
+
+        ratelimiter.return_n(6);
+        let items: Vec<u64> = vec![20]
+            .into_par_iter()
+            .ratelimited_with(&ratelimiter, |n| *n)
+            .collect();
+        assert_eq!(items, vec![20]);
+        assert_eq!(ratelimiter.available(), 0);
+    }
+}
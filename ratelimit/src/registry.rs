@@ -0,0 +1,106 @@
+//! A process-wide registry of named ratelimiters. This allows different parts
+//! of an application to share a ratelimiter by name without needing to pass
+//! an `Arc<Ratelimiter>` through every layer.
+//!
+//! ```
+//! use ratelimit::{registry, RatelimiterConfig};
+//!
+//! let config = RatelimiterConfig::new("100/s".parse().unwrap());
+//!
+//! // the first caller creates the ratelimiter, later callers share it
+//! let a = registry::get_or_create("search-api", &config).unwrap();
+//! let b = registry::get_or_create("search-api", &config).unwrap();
+//! assert!(std::sync::Arc::ptr_eq(&a, &b));
+//! ```
+
+use crate::{Error, Ratelimiter, RatelimiterConfig};
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+static REGISTRY: RwLock<BTreeMap<String, Arc<Ratelimiter>>> =
+    parking_lot::const_rwlock(BTreeMap::new());
+
+/// Returns the ratelimiter registered with the provided name, if any.
+pub fn get(name: &str) -> Option<Arc<Ratelimiter>> {
+    REGISTRY.read().get(name).cloned()
+}
+
+/// Returns the ratelimiter registered with the provided name. If there is no
+/// such ratelimiter, one is constructed from the config and registered.
+///
+/// Note: the config is only used when the ratelimiter is created. If a
+/// ratelimiter is already registered with this name, it is returned as-is.
+pub fn get_or_create(name: &str, config: &RatelimiterConfig) -> Result<Arc<Ratelimiter>, Error> {
+    if let Some(limiter) = get(name) {
+        return Ok(limiter);
+    }
+
+    let mut registry = REGISTRY.write();
+
+    // we may have raced with another caller between the read and write lock
+    if let Some(limiter) = registry.get(name) {
+        return Ok(limiter.clone());
+    }
+
+    let limiter = Arc::new(config.builder()?.build()?);
+    registry.insert(name.to_string(), limiter.clone());
+
+    Ok(limiter)
+}
+
+/// Register a ratelimiter with the provided name, returning the ratelimiter
+/// that was previously registered with that name, if any.
+pub fn insert(
+    name: impl Into<String>,
+    limiter: impl Into<Arc<Ratelimiter>>,
+) -> Option<Arc<Ratelimiter>> {
+    REGISTRY.write().insert(name.into(), limiter.into())
+}
+
+/// Remove the ratelimiter registered with the provided name from the
+/// registry. Existing handles to the ratelimiter remain valid.
+pub fn remove(name: &str) -> Option<Arc<Ratelimiter>> {
+    REGISTRY.write().remove(name)
+}
+
+/// Returns the names of all registered ratelimiters, ordered by name.
+pub fn names() -> Vec<String> {
+    REGISTRY.read().keys().cloned().collect()
+}
+
+/// Returns all registered ratelimiters with their names, ordered by name. This
+/// is intended for debugging and exporting metrics.
+pub fn entries() -> Vec<(String, Arc<Ratelimiter>)> {
+    REGISTRY
+        .read()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let config = RatelimiterConfig::new("10/s".parse().unwrap());
+
+        assert!(get("registry-test").is_none());
+
+        let a = get_or_create("registry-test", &config).unwrap();
+        let b = get_or_create("registry-test", &config).unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(Arc::ptr_eq(&a, &get("registry-test").unwrap()));
+        assert!(names().contains(&"registry-test".to_string()));
+        assert!(entries().iter().any(|(name, _)| name == "registry-test"));
+
+        let invalid = RatelimiterConfig::new("10/s".parse().unwrap()).max_tokens(0);
+        assert!(get_or_create("registry-test-invalid", &invalid).is_err());
+        assert!(get("registry-test-invalid").is_none());
+
+        assert!(remove("registry-test").is_some());
+        assert!(get("registry-test").is_none());
+    }
+}
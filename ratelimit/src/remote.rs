@@ -0,0 +1,100 @@
+use crate::atomic::Ordering;
+use crate::{advance_instant, system_now, Ratelimiter};
+use clocksource::precise::Instant;
+use std::time::SystemTime;
+
+impl Ratelimiter {
+    /// Reconcile the bucket with the quota reported by a remote API, typically
+    /// from its `X-RateLimit-Remaining` and `X-RateLimit-Reset` headers. This
+    /// keeps several clients which share an external quota from exceeding it,
+    /// since each learns about the tokens the others have used.
+    ///
+    /// The available tokens are clamped to the `remaining` quota. If that
+    /// leaves no room for further refills within the remote window, the next
+    /// refill is postponed until `reset_at`, when the remote quota is renewed.
+    /// The bucket is never given more tokens than it already has, and a
+    /// `reset_at` which has already passed is ignored, since the remote
+    /// quota it describes has since been renewed.
+    ///
+    /// ```
+    /// use ratelimit::Ratelimiter;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let ratelimiter = Ratelimiter::per_second(10).initial_available(10).build().unwrap();
+    ///
+    /// // the remote API reports that only 4 requests remain for the next minute
+    /// ratelimiter.sync_remote(4, SystemTime::now() + Duration::from_secs(60));
+    ///
+    /// assert_eq!(ratelimiter.available(), 4);
+    /// ```
+    pub fn sync_remote(&self, remaining: u64, reset_at: SystemTime) {
+        let Ok(until_reset) = reset_at.duration_since(system_now()) else {
+            return;
+        };
+
+        let available = self.available.fetch_min(remaining, Ordering::AcqRel);
+
+        // the remote quota is the limit until it resets, so tokens added by a
+        // refill before then would only be denied by the remote API
+        if available >= remaining {
+            let nanos = until_reset.as_nanos().min(u64::MAX as u128) as u64;
+            self.refill_at
+                .fetch_max(advance_instant(Instant::now(), 1, nanos), Ordering::AcqRel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn sync_remote() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(10))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        // a reset which has passed is ignored
+        rl.sync_remote(0, SystemTime::now() - Duration::from_secs(1));
+        assert_eq!(rl.available(), 10);
+
+        // the remote quota has more room than the bucket, so only the
+        // available tokens are clamped
+        rl.try_acquire_n(8).unwrap();
+        rl.sync_remote(5, SystemTime::now() + Duration::from_secs(60));
+        assert_eq!(rl.available(), 2);
+        assert!(rl.next_refill() < clocksource::precise::Instant::now() + Duration::from_secs(1));
+
+        // the remote quota is used up, so refills wait for it to reset
+        rl.sync_remote(1, SystemTime::now() + Duration::from_secs(60));
+        assert_eq!(rl.available(), 1);
+        assert!(rl.next_refill() > clocksource::precise::Instant::now() + Duration::from_secs(59));
+
+        rl.try_acquire().unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(rl.try_acquire().is_err());
+    }
+
+    #[test]
+    fn sync_remote_overflow() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(10))
+            .max_tokens(10)
+            .build()
+            .unwrap();
+
+        // a far-future reset saturates the next refill rather than wrapping
+        // into the past
+        let reset_at = SystemTime::now() + Duration::from_secs(1 << 40);
+        rl.sync_remote(0, reset_at);
+        assert!(
+            rl.next_refill()
+                > clocksource::precise::Instant::now() + Duration::from_secs(100 * 365 * 86_400)
+        );
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(rl.try_acquire().is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Middleware which paces the outbound requests of a
+//! [`reqwest_middleware`] client.
+//!
+//! Each request waits until a token is available before it is sent, so client
+//! code stays within the limits of third-party APIs without calling
+//! [`Ratelimiter::try_wait`] itself. The limit can be shared by all requests or
+//! kept separately for each host. Each request costs a single token unless a
+//! cost extractor is provided with [`Throttle::cost_with`].
+//!
+//! ```no_run
+//! use ratelimit::reqwest::Throttle;
+//! use ratelimit::Ratelimiter;
+//! use reqwest_middleware::ClientBuilder;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let ratelimiter = Arc::new(Ratelimiter::builder(1, Duration::from_millis(100)).build()?);
+//!
+//! let client = ClientBuilder::new(reqwest_middleware::reqwest::Client::new())
+//!     .with(Throttle::new(ratelimiter))
+//!     .build();
+//!
+//! client.get("https://example.com").send().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{CostExtractor, KeyedRatelimiter, Ratelimiter, UnitCost};
+use http::Extensions;
+use reqwest_middleware::reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result};
+use std::sync::Arc;
+
+/// A middleware which waits for the tokens for each request before it is
+/// sent. Each request costs a single token unless a cost extractor is
+/// provided.
+///
+/// If the ratelimiter is closed or denying all requests, the request fails
+/// with a middleware error which wraps a
+/// [`TryAcquireError`](crate::TryAcquireError).
+#[derive(Clone)]
+pub struct Throttle {
+    cost: Arc<dyn CostExtractor<Request>>,
+    ratelimiter: Limiter,
+}
+
+#[derive(Clone)]
+enum Limiter {
+    Global(Arc<Ratelimiter>),
+    PerHost(Arc<KeyedRatelimiter>),
+}
+
+impl Throttle {
+    /// Create a middleware where all requests share the provided ratelimiter.
+    pub fn new(ratelimiter: Arc<Ratelimiter>) -> Self {
+        Self {
+            cost: Arc::new(UnitCost),
+            ratelimiter: Limiter::Global(ratelimiter),
+        }
+    }
+
+    /// Create a middleware where requests are limited separately for each
+    /// host, using the host of the request URL as the key.
+    pub fn per_host(ratelimiter: Arc<KeyedRatelimiter>) -> Self {
+        Self {
+            cost: Arc::new(UnitCost),
+            ratelimiter: Limiter::PerHost(ratelimiter),
+        }
+    }
+
+    /// Charge each request the number of tokens returned by the cost
+    /// extractor, for example to match the quota accounting of an API. Costs
+    /// above the maximum number of tokens are charged as the maximum.
+    pub fn cost_with(mut self, cost: impl CostExtractor<Request> + 'static) -> Self {
+        self.cost = Arc::new(cost);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Throttle {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let cost = self.cost.cost(&req);
+
+        match &self.ratelimiter {
+            Limiter::Global(ratelimiter) => {
+                crate::sleep::acquire_n(ratelimiter, ratelimiter.charge(cost)).await
+            }
+            Limiter::PerHost(ratelimiter) => {
+                let host = req.url().host_str().unwrap_or("");
+                crate::sleep::acquire_keyed(ratelimiter, host, cost).await
+            }
+        }
+        .map_err(reqwest_middleware::Error::middleware)?;
+
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RatelimiterConfig, TryAcquireError};
+    use reqwest_middleware::reqwest::{Client, Method, Url};
+    use std::time::Duration;
+
+    fn request(url: &str) -> Request {
+        Request::new(Method::GET, Url::parse(url).unwrap())
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn per_host() {
+        let config = RatelimiterConfig::new("1/min".parse().unwrap()).initial_available(1);
+        let ratelimiter = Arc::new(KeyedRatelimiter::from_config(config).unwrap());
+        let client = reqwest_middleware::ClientBuilder::new(Client::new())
+            .with(Throttle::per_host(ratelimiter.clone()))
+            .build();
+
+        runtime().block_on(async {
+            // each host has a token, so the requests are sent and fail to
+            // connect to the closed port
+            for host in ["127.0.0.1", "localhost"] {
+                let url = format!("http://{host}:9/");
+                let error = client.execute(request(&url)).await.unwrap_err();
+                assert!(matches!(error, reqwest_middleware::Error::Reqwest(_)));
+            }
+        });
+
+        assert_eq!(ratelimiter.len(), 2);
+        assert_eq!(ratelimiter.get("127.0.0.1").available(), 0);
+    }
+
+    #[test]
+    fn closed() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+        let client = reqwest_middleware::ClientBuilder::new(Client::new())
+            .with(Throttle::new(ratelimiter.clone()))
+            .build();
+
+        ratelimiter.close();
+
+        runtime().block_on(async {
+            let error = client
+                .execute(request("http://127.0.0.1:9/"))
+                .await
+                .unwrap_err();
+
+            let reqwest_middleware::Error::Middleware(error) = error else {
+                panic!("expected a middleware error");
+            };
+            assert!(matches!(
+                error.downcast_ref::<TryAcquireError>(),
+                Some(TryAcquireError::Closed)
+            ));
+        });
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{system_now, Ratelimiter};
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// The value for the `Retry-After` header of a response to a denied request,
+/// from [`Ratelimiter::retry_after`]. This can be rendered as a number of
+/// seconds, which is also its `Display` form, or as an HTTP-date.
+///
+/// Both forms are rounded up, so that a client which waits as instructed is
+/// never early.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1500))
+///     .max_tokens(4)
+///     .build()
+///     .unwrap();
+///
+/// // three tokens need three refills
+/// let retry_after = ratelimiter.retry_after(3);
+/// assert_eq!(retry_after.seconds(), 5);
+/// assert_eq!(retry_after.to_string(), "5");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryAfter {
+    delay: Duration,
+}
+
+impl RetryAfter {
+    /// Create a value for the `Retry-After` header from a delay.
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+
+    /// Returns the exact delay.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Returns the delay in whole seconds, rounded up.
+    pub fn seconds(&self) -> u64 {
+        ceil_seconds(self.delay)
+    }
+
+    /// Parse the value of a `Retry-After` header, which is either a number of
+    /// seconds or an HTTP-date. A date in the past is a delay of zero. Returns
+    /// `None` if the value is in neither form.
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::parse_from(value, system_now())
+    }
+
+    /// Internal function to parse a `Retry-After` value relative to `now`.
+    fn parse_from(value: &str, now: SystemTime) -> Option<Self> {
+        let value = value.trim();
+
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Self::new(Duration::from_secs(seconds)));
+        }
+
+        // an HTTP-date has the form `Sun, 06 Nov 1994 08:49:37 GMT`
+        let mut parts = value.split_ascii_whitespace();
+        let (_, day, month, year, time, "GMT", None) = (
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+            parts.next()?,
+            parts.next(),
+        ) else {
+            return None;
+        };
+
+        let day: u64 = day.parse().ok()?;
+        let month = MONTHS.iter().position(|m| *m == month)? as u64 + 1;
+        let year: u64 = year.parse().ok()?;
+
+        let mut time = time.split(':').map(|t| t.parse::<u64>().ok());
+        let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+            (time.next(), time.next(), time.next(), time.next())
+        else {
+            return None;
+        };
+
+        // an HTTP-date has a four digit year
+        if !(1970..=9999).contains(&year)
+            || !(1..=31).contains(&day)
+            || hours > 23
+            || minutes > 59
+            || seconds > 60
+        {
+            return None;
+        }
+
+        // convert the civil date to days since the epoch, see
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let y = year - (month <= 2) as u64;
+        let era = y / 400;
+        let yoe = y - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        // the header is untrusted, so overflow is treated as invalid
+        let seconds = days
+            .checked_mul(86_400)?
+            .checked_add(hours * 3_600 + minutes * 60 + seconds)?;
+        let at = UNIX_EPOCH.checked_add(Duration::from_secs(seconds))?;
+
+        Some(Self::new(at.duration_since(now).unwrap_or_default()))
+    }
+
+    /// Returns the time at which to retry as an HTTP-date, such as
+    /// `Sun, 06 Nov 1994 08:49:37 GMT`.
+    pub fn http_date(&self) -> String {
+        self.http_date_from(system_now())
+    }
+
+    /// Internal function to return the HTTP-date for the delay after `now`.
+    fn http_date_from(&self, now: SystemTime) -> String {
+        let time = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_add(self.delay);
+        let seconds = ceil_seconds(time);
+
+        let days = seconds / 86_400;
+        let time = seconds % 86_400;
+
+        // convert the days since the epoch to a civil date, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as u64;
+
+        format!(
+            "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            // the epoch was a thursday
+            DAYS[((days + 4) % 7) as usize],
+            day,
+            MONTHS[(month - 1) as usize],
+            year,
+            time / 3_600,
+            time % 3_600 / 60,
+            time % 60,
+        )
+    }
+}
+
+impl From<Duration> for RetryAfter {
+    fn from(delay: Duration) -> Self {
+        Self::new(delay)
+    }
+}
+
+impl core::fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.seconds())
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the value for the `Retry-After` header of a response to a
+    /// request for `n` tokens which was denied. This accounts for the full
+    /// shortfall, so a request for several tokens is told to wait for as many
+    /// refills as it needs rather than just the next one.
+    ///
+    /// This assumes no other callers acquire tokens in the meantime.
+    pub fn retry_after(&self, n: u64) -> RetryAfter {
+        RetryAfter::new(self.time_until_available(n))
+    }
+
+    /// Internal function to return the time until `n` tokens would be
+    /// available, assuming no other tokens are acquired.
+    pub(crate) fn time_until_available(&self, n: u64) -> Duration {
+        let available = self.available();
+        if n <= available {
+            return Duration::ZERO;
+        }
+
+        let refills = (n - available).div_ceil(self.refill_amount().max(1));
+        let remaining_refills = u32::try_from(refills - 1).unwrap_or(u32::MAX);
+
+        self.snapshot()
+            .next_refill
+            .saturating_add(self.scaled_interval().saturating_mul(remaining_refills))
+    }
+}
+
+/// Internal function to return a duration in whole seconds, rounded up.
+pub(crate) fn ceil_seconds(duration: Duration) -> u64 {
+    duration.as_secs() + (duration.subsec_nanos() > 0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_date() {
+        let retry_after = RetryAfter::new(Duration::from_millis(1_500));
+
+        assert_eq!(
+            retry_after.http_date_from(UNIX_EPOCH + Duration::from_secs(784_111_775)),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            retry_after.http_date_from(UNIX_EPOCH + Duration::from_secs(951_782_398)),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_775);
+
+        assert_eq!(
+            RetryAfter::parse_from(" 120 ", now).map(|r| r.delay()),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            RetryAfter::parse_from("Sun, 06 Nov 1994 08:49:37 GMT", now).map(|r| r.delay()),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            RetryAfter::parse_from("Tue, 29 Feb 2000 00:00:00 GMT", now).map(|r| r.delay()),
+            Some(Duration::from_secs(951_782_400 - 784_111_775))
+        );
+        assert_eq!(
+            RetryAfter::parse_from("Sat, 05 Nov 1994 08:49:37 GMT", now).map(|r| r.delay()),
+            Some(Duration::ZERO)
+        );
+
+        for invalid in [
+            "",
+            "-1",
+            "soon",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Nov 99999999999999 08:49:37 GMT",
+            "Sun, 06 Nov 18446744073709551615 08:49:37 GMT",
+        ] {
+            assert_eq!(RetryAfter::parse_from(invalid, now), None);
+        }
+    }
+
+    #[test]
+    fn retry_after() {
+        let rl = Ratelimiter::builder(2, Duration::from_secs(10))
+            .max_tokens(10)
+            .initial_available(3)
+            .build()
+            .unwrap();
+
+        assert_eq!(rl.retry_after(3).delay(), Duration::ZERO);
+
+        // a shortfall of three tokens needs two refills
+        let retry_after = rl.retry_after(6);
+        assert!(retry_after.delay() > Duration::from_secs(19));
+        assert_eq!(retry_after.seconds(), 20);
+        assert_eq!(RetryAfter::from(Duration::from_nanos(1)).seconds(), 1);
+    }
+}
@@ -0,0 +1,265 @@
+use super::proto::{Code, Entry, RateLimitDescriptor, RateLimitRequest, RateLimitResponse};
+use super::{DescriptorKey, SHOULD_RATE_LIMIT};
+use crate::Ratelimiter;
+use clocksource::precise::Instant;
+use core::time::Duration;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use thiserror::Error;
+use tonic::codegen::{http, Body, Bytes, StdError};
+use tonic::transport::Channel;
+
+/// The error returned when an acquisition through a rate limit service fails.
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum RlsError {
+    /// The descriptor is over its limit. Contains the time until the limit
+    /// resets, as reported by the service.
+    #[error("over limit, retry after {0:?}")]
+    OverLimit(Duration),
+    /// The service could not be reached or returned an error, and there is no
+    /// fallback ratelimiter.
+    #[error("rate limit service unavailable: {0}")]
+    Unavailable(tonic::Status),
+}
+
+/// A ratelimiter which defers decisions to a remote Envoy rate limit service,
+/// for example to share the global limits of a service mesh.
+///
+/// Descriptors which are over their limit are cached until the reset time
+/// reported by the service so that rejected requests do not reach the
+/// service. An optional fallback ratelimiter is used while the service is
+/// unavailable.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use ratelimit::rls::RlsClient;
+/// use ratelimit::Ratelimiter;
+///
+/// let channel = tonic::transport::Endpoint::from_static("http://ratelimit:8081")
+///     .timeout(std::time::Duration::from_millis(50))
+///     .connect_lazy();
+///
+/// let client = RlsClient::new(channel, "edge")
+///     .fallback(Ratelimiter::builder(10, std::time::Duration::from_millis(100)).build()?);
+///
+/// client.try_acquire(&[("remote_address", "10.0.0.1")]).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RlsClient<T = Channel> {
+    cache: Mutex<HashMap<DescriptorKey, Instant>>,
+    domain: String,
+    fallback: Option<Ratelimiter>,
+    grpc: tonic::client::Grpc<T>,
+}
+
+impl<T> RlsClient<T>
+where
+    T: tonic::client::GrpcService<tonic::body::BoxBody> + Clone,
+    T::Error: Into<StdError>,
+    T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+    <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+{
+    /// Create a new client which sends requests for the provided domain over
+    /// the provided channel.
+    pub fn new(channel: T, domain: impl Into<String>) -> Self {
+        Self {
+            cache: Mutex::new(HashMap::new()),
+            domain: domain.into(),
+            fallback: None,
+            grpc: tonic::client::Grpc::new(channel),
+        }
+    }
+
+    /// Use the provided ratelimiter while the service is unavailable instead
+    /// of returning an error. Its rate should typically be this instance's
+    /// share of the global limit.
+    pub fn fallback(mut self, ratelimiter: Ratelimiter) -> Self {
+        self.fallback = Some(ratelimiter);
+        self
+    }
+
+    /// Returns the domain of the requests sent by this client.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Acquire `n` hits for the provided descriptor entries. Returns an error
+    /// with the time until the limit resets if the descriptor is over its
+    /// limit.
+    pub async fn try_acquire_n(&self, descriptor: &[(&str, &str)], n: u64) -> Result<(), RlsError> {
+        let key: DescriptorKey = (
+            self.domain.clone(),
+            descriptor
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+
+        let now = Instant::now();
+
+        if let Some(reset) = self.cache.lock().get(&key) {
+            if *reset > now {
+                return Err(RlsError::OverLimit(Duration::from_nanos(
+                    (*reset - now).as_nanos(),
+                )));
+            }
+        }
+
+        let request = RateLimitRequest {
+            domain: self.domain.clone(),
+            descriptors: vec![RateLimitDescriptor {
+                entries: descriptor
+                    .iter()
+                    .map(|(key, value)| Entry {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    })
+                    .collect(),
+                hits_addend: Some(super::proto::UInt64Value { value: n }),
+            }],
+            hits_addend: n.min(u32::MAX as u64) as u32,
+        };
+
+        let response = match self.should_rate_limit(request).await {
+            Ok(response) => response,
+            Err(status) => {
+                return match &self.fallback {
+                    Some(fallback) => fallback.try_wait_n(n).map_err(RlsError::OverLimit),
+                    None => Err(RlsError::Unavailable(status)),
+                };
+            }
+        };
+
+        if response.overall_code != Code::OverLimit as i32 {
+            return Ok(());
+        }
+
+        let reset: Duration = response
+            .statuses
+            .first()
+            .and_then(|status| status.duration_until_reset)
+            .map(|d| d.into())
+            .unwrap_or_default();
+
+        if !reset.is_zero() {
+            let mut cache = self.cache.lock();
+
+            // drop entries which have expired so the cache stays bounded
+            cache.retain(|_, reset| *reset > now);
+            cache.insert(
+                key,
+                now + clocksource::precise::Duration::from_nanos(reset.as_nanos() as u64),
+            );
+        }
+
+        Err(RlsError::OverLimit(reset))
+    }
+
+    /// Acquire a single hit for the provided descriptor entries. See
+    /// [`RlsClient::try_acquire_n`] for details.
+    pub async fn try_acquire(&self, descriptor: &[(&str, &str)]) -> Result<(), RlsError> {
+        self.try_acquire_n(descriptor, 1).await
+    }
+
+    /// Send a request to the service without using the cache or fallback.
+    pub async fn should_rate_limit(
+        &self,
+        request: RateLimitRequest,
+    ) -> Result<RateLimitResponse, tonic::Status> {
+        let mut grpc = self.grpc.clone();
+
+        grpc.ready().await.map_err(|e| {
+            tonic::Status::unavailable(format!("service was not ready: {}", e.into()))
+        })?;
+
+        let codec = tonic::codec::ProstCodec::default();
+        let path = http::uri::PathAndQuery::from_static(SHOULD_RATE_LIMIT);
+
+        grpc.unary(tonic::Request::new(request), path, codec)
+            .await
+            .map(|response| response.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{DescriptorConfig, RlsConfig, RlsServer};
+    use super::*;
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn client() {
+        let server = RlsServer::new([RlsConfig::new("edge").descriptor(
+            DescriptorConfig::new("remote_address").rate_limit("2/min".parse().unwrap()),
+        )])
+        .unwrap()
+        .into_service();
+        let client = RlsClient::new(server, "edge");
+
+        runtime().block_on(async {
+            let alice = [("remote_address", "10.0.0.1")];
+            assert!(client.try_acquire(&alice).await.is_ok());
+            assert!(client.try_acquire(&alice).await.is_ok());
+            assert!(matches!(
+                client.try_acquire(&alice).await,
+                Err(RlsError::OverLimit(_))
+            ));
+
+            // over limit descriptors are answered from the cache
+            assert!(client.try_acquire(&alice).await.is_err());
+            assert_eq!(client.cache.lock().len(), 1);
+
+            assert!(client
+                .try_acquire_n(&[("remote_address", "10.0.0.2")], 3)
+                .await
+                .is_err());
+            assert!(client.try_acquire(&[("path", "/")]).await.is_ok());
+
+            // a descriptor which would join to the same string as the cached
+            // one is still sent to the service
+            assert!(client
+                .try_acquire(&[("remote", "address_10.0.0.1")])
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn fallback() {
+        runtime().block_on(async {
+            // nothing listens on the discard port
+            let channel =
+                tonic::transport::Endpoint::from_static("http://127.0.0.1:9").connect_lazy();
+            let client = RlsClient::new(channel, "edge");
+
+            assert!(matches!(
+                client.try_acquire(&[("remote_address", "10.0.0.1")]).await,
+                Err(RlsError::Unavailable(_))
+            ));
+
+            let client = client.fallback(
+                Ratelimiter::builder(1, std::time::Duration::from_secs(60))
+                    .initial_available(1)
+                    .build()
+                    .unwrap(),
+            );
+
+            assert!(client
+                .try_acquire(&[("remote_address", "10.0.0.1")])
+                .await
+                .is_ok());
+            assert!(matches!(
+                client.try_acquire(&[("remote_address", "10.0.0.1")]).await,
+                Err(RlsError::OverLimit(_))
+            ));
+        });
+    }
+}
@@ -0,0 +1,260 @@
+//! Support for the Envoy rate limit service (RLS) protocol. This allows the
+//! ratelimiters in this crate to back the global rate limiting of an Envoy or
+//! Contour deployment.
+//!
+//! Limits are configured per domain as a tree of descriptors, following the
+//! format used by the reference implementation of the service:
+//!
+//! ```yaml
+//! domain: edge
+//! descriptors:
+//!   - key: remote_address
+//!     rate_limit: 100/s
+//!   - key: path
+//!     value: /login
+//!     rate_limit: 10/min
+//!     descriptors:
+//!       - key: user
+//!         rate_limit: 1/min
+//! ```
+//!
+//! A descriptor config without a value matches any value, and each distinct
+//! value is limited independently.
+//!
+//! The [`RlsServer`] answers requests from Envoy, while the [`RlsClient`]
+//! allows an application to consult a rate limit service directly.
+
+use crate::{Error, Rate};
+use core::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+mod client;
+pub mod proto;
+mod server;
+
+pub use client::{RlsClient, RlsError};
+pub use server::{RateLimitServiceServer, RlsServer};
+
+/// Internal type which identifies the bucket of a descriptor by its domain and
+/// entries. The entries are kept as separate strings rather than joined, so
+/// that distinct descriptors can never share a bucket.
+type DescriptorKey = (String, Vec<(String, String)>);
+
+/// The full name of the gRPC service.
+const SERVICE: &str = "envoy.service.ratelimit.v3.RateLimitService";
+
+/// The path of the only method of the service.
+const SHOULD_RATE_LIMIT: &str = "/envoy.service.ratelimit.v3.RateLimitService/ShouldRateLimit";
+
+/// The limits for a single domain.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RlsConfig {
+    /// The domain, which must match the domain of the requests.
+    pub domain: String,
+    /// The top-level descriptors for the domain.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub descriptors: Vec<DescriptorConfig>,
+}
+
+/// A node of the descriptor tree.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DescriptorConfig {
+    /// The key of the descriptor entry.
+    pub key: String,
+    /// The value of the descriptor entry. When not provided, any value
+    /// matches and each value has its own limit.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub value: Option<String>,
+    /// The limit which applies when this is the last entry of a descriptor.
+    /// When not provided, the descriptor is not limited.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub rate_limit: Option<Rate>,
+    /// Descriptors which match the entries that follow this one.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub descriptors: Vec<DescriptorConfig>,
+}
+
+impl DescriptorConfig {
+    /// Create a new descriptor config which matches any value for the key.
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: None,
+            rate_limit: None,
+            descriptors: Vec::new(),
+        }
+    }
+
+    /// Only match the provided value.
+    pub fn value(mut self, value: impl Into<String>) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Set the limit for this descriptor.
+    pub fn rate_limit(mut self, rate: Rate) -> Self {
+        self.rate_limit = Some(rate);
+        self
+    }
+
+    /// Add a nested descriptor.
+    pub fn descriptor(mut self, descriptor: DescriptorConfig) -> Self {
+        self.descriptors.push(descriptor);
+        self
+    }
+
+    /// Internal function to check that every rate in the tree is valid.
+    /// Returns the longest time for any of the buckets to refill completely.
+    fn validate(&self) -> Result<Duration, Error> {
+        let mut longest = match &self.rate_limit {
+            Some(rate) => {
+                let limiter = rate.builder()?.build()?;
+                let refills = limiter
+                    .max_tokens()
+                    .div_ceil(limiter.refill_amount().max(1));
+                let nanos = limiter.refill_interval().as_nanos() * refills as u128;
+                Duration::from_nanos(nanos.min(u64::MAX as u128) as u64)
+            }
+            None => Duration::ZERO,
+        };
+
+        for descriptor in &self.descriptors {
+            longest = longest.max(descriptor.validate()?);
+        }
+
+        Ok(longest)
+    }
+}
+
+impl RlsConfig {
+    /// Create a new config for the provided domain with no descriptors.
+    pub fn new(domain: impl Into<String>) -> Self {
+        Self {
+            domain: domain.into(),
+            descriptors: Vec::new(),
+        }
+    }
+
+    /// Add a top-level descriptor.
+    pub fn descriptor(mut self, descriptor: DescriptorConfig) -> Self {
+        self.descriptors.push(descriptor);
+        self
+    }
+
+    /// Find the limit for the provided descriptor entries. Every entry must be
+    /// matched and the limit is taken from the config matching the last entry.
+    /// An exact value match takes precedence over a config without a value.
+    pub fn find(&self, entries: &[proto::Entry]) -> Option<&Rate> {
+        let mut level = &self.descriptors;
+        let mut matched = None;
+
+        for entry in entries {
+            let config = level
+                .iter()
+                .find(|c| c.key == entry.key && c.value.as_deref() == Some(entry.value.as_str()))
+                .or_else(|| {
+                    level
+                        .iter()
+                        .find(|c| c.key == entry.key && c.value.is_none())
+                })?;
+
+            matched = Some(config);
+            level = &config.descriptors;
+        }
+
+        matched.and_then(|config| config.rate_limit.as_ref())
+    }
+}
+
+/// Internal function to express a rate as a number of requests per unit of
+/// time. Rates which do not fit a unit exactly are expressed per second.
+fn rate_limit(rate: &Rate) -> proto::RateLimit {
+    let unit = match rate.window().as_secs() {
+        1 => proto::Unit::Second,
+        60 => proto::Unit::Minute,
+        3600 => proto::Unit::Hour,
+        86400 => proto::Unit::Day,
+        _ => proto::Unit::Unknown,
+    };
+
+    let exact = rate.window().subsec_nanos() == 0 && rate.tokens().fract() == 0.0;
+
+    let (requests_per_unit, unit) = if unit != proto::Unit::Unknown && exact {
+        (rate.tokens(), unit)
+    } else {
+        (rate.tokens_per_second().round(), proto::Unit::Second)
+    };
+
+    proto::RateLimit {
+        requests_per_unit: requests_per_unit.min(u32::MAX as f64) as u32,
+        unit: unit as i32,
+        name: rate.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(entries: &[(&str, &str)]) -> Vec<proto::Entry> {
+        entries
+            .iter()
+            .map(|(key, value)| proto::Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find() {
+        let config = RlsConfig::new("edge")
+            .descriptor(
+                DescriptorConfig::new("remote_address").rate_limit("100/s".parse().unwrap()),
+            )
+            .descriptor(
+                DescriptorConfig::new("path")
+                    .value("/login")
+                    .rate_limit("10/min".parse().unwrap())
+                    .descriptor(DescriptorConfig::new("user").rate_limit("1/min".parse().unwrap())),
+            )
+            .descriptor(DescriptorConfig::new("path").rate_limit("1k/s".parse().unwrap()));
+
+        let find = |e: &[(&str, &str)]| config.find(&entries(e)).map(|r| r.to_string());
+
+        assert_eq!(find(&[("remote_address", "10.0.0.1")]).unwrap(), "100/s");
+        assert_eq!(find(&[("path", "/login")]).unwrap(), "10/min");
+        assert_eq!(find(&[("path", "/")]).unwrap(), "1000/s");
+        assert_eq!(
+            find(&[("path", "/login"), ("user", "alice")]).unwrap(),
+            "1/min"
+        );
+        assert_eq!(find(&[("path", "/"), ("user", "alice")]), None);
+        assert_eq!(find(&[("unknown", "")]), None);
+        assert_eq!(find(&[]), None);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let limit = super::rate_limit(&"10/min".parse().unwrap());
+        assert_eq!(limit.requests_per_unit, 10);
+        assert_eq!(limit.unit, proto::Unit::Minute as i32);
+
+        let limit = super::rate_limit(&"5/10s".parse().unwrap());
+        assert_eq!(limit.requests_per_unit, 1);
+        assert_eq!(limit.unit, proto::Unit::Second as i32);
+    }
+}
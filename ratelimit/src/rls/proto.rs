@@ -0,0 +1,126 @@
+//! The subset of the Envoy rate limit service protocol
+//! (`envoy.service.ratelimit.v3`) which is used by this crate. Fields which
+//! are not listed here are skipped when decoding.
+
+/// A request to check the limits for a set of descriptors.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitRequest {
+    /// The domain which the descriptors belong to.
+    #[prost(string, tag = "1")]
+    pub domain: String,
+    /// The descriptors to check. Each descriptor is checked independently.
+    #[prost(message, repeated, tag = "2")]
+    pub descriptors: Vec<RateLimitDescriptor>,
+    /// The number of hits to charge each descriptor. Zero is treated as one.
+    #[prost(uint32, tag = "3")]
+    pub hits_addend: u32,
+}
+
+/// A list of key-value entries identifying a limit.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitDescriptor {
+    /// The entries of the descriptor, from least to most specific.
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<Entry>,
+    /// Overrides the hits addend of the request for this descriptor.
+    #[prost(message, optional, tag = "3")]
+    pub hits_addend: Option<UInt64Value>,
+}
+
+/// A single key-value entry of a descriptor.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Entry {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// A wrapped `uint64`, equivalent to `google.protobuf.UInt64Value`.
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct UInt64Value {
+    #[prost(uint64, tag = "1")]
+    pub value: u64,
+}
+
+/// A duration, equivalent to `google.protobuf.Duration`.
+#[derive(Clone, Copy, PartialEq, prost::Message)]
+pub struct Duration {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
+
+impl From<core::time::Duration> for Duration {
+    fn from(duration: core::time::Duration) -> Self {
+        Self {
+            seconds: duration.as_secs() as i64,
+            nanos: duration.subsec_nanos() as i32,
+        }
+    }
+}
+
+impl From<Duration> for core::time::Duration {
+    fn from(duration: Duration) -> Self {
+        core::time::Duration::new(duration.seconds.max(0) as u64, duration.nanos.max(0) as u32)
+    }
+}
+
+/// The outcome of checking the limits.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitResponse {
+    /// Over limit if any of the descriptors are over their limit.
+    #[prost(enumeration = "Code", tag = "1")]
+    pub overall_code: i32,
+    /// The status of each descriptor, in the order of the request.
+    #[prost(message, repeated, tag = "2")]
+    pub statuses: Vec<DescriptorStatus>,
+}
+
+/// The outcome of checking the limit for a single descriptor.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DescriptorStatus {
+    #[prost(enumeration = "Code", tag = "1")]
+    pub code: i32,
+    /// The limit which applies to the descriptor, if any.
+    #[prost(message, optional, tag = "2")]
+    pub current_limit: Option<RateLimit>,
+    /// The number of hits remaining before the limit is reached.
+    #[prost(uint32, tag = "3")]
+    pub limit_remaining: u32,
+    /// The time until more hits are allowed.
+    #[prost(message, optional, tag = "4")]
+    pub duration_until_reset: Option<Duration>,
+}
+
+/// A limit expressed as a number of requests per unit of time.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimit {
+    #[prost(uint32, tag = "1")]
+    pub requests_per_unit: u32,
+    #[prost(enumeration = "Unit", tag = "2")]
+    pub unit: i32,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Code {
+    Unknown = 0,
+    Ok = 1,
+    OverLimit = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum Unit {
+    Unknown = 0,
+    Second = 1,
+    Minute = 2,
+    Hour = 3,
+    Day = 4,
+    Month = 5,
+    Year = 6,
+}
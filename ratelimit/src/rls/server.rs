@@ -0,0 +1,408 @@
+use super::proto::{
+    Code, DescriptorStatus, RateLimitDescriptor, RateLimitRequest, RateLimitResponse,
+};
+use super::{rate_limit, DescriptorKey, RlsConfig, SERVICE, SHOULD_RATE_LIMIT};
+use crate::{Error, KeyedRatelimiter, Ratelimiter};
+use clocksource::precise::Instant;
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Poll, Service, StdError};
+
+// the shortest default idle timeout, so that buckets with fast refills are not
+// swept on almost every new descriptor
+const MIN_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An implementation of the Envoy rate limit service. Each distinct
+/// descriptor is limited by its own bucket in a [`KeyedRatelimiter`], which
+/// starts full.
+///
+/// Buckets which have been idle for longer than the idle timeout are evicted
+/// as new descriptors arrive, so that clients which send many distinct values
+/// can't grow the memory used without bound. See [`RlsServer::idle_timeout`].
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use ratelimit::rls::{DescriptorConfig, RlsConfig, RlsServer};
+///
+/// let config = RlsConfig::new("edge")
+///     .descriptor(DescriptorConfig::new("remote_address").rate_limit("100/s".parse()?));
+///
+/// let server = RlsServer::new([config])?;
+///
+/// tonic::transport::Server::builder()
+///     .add_service(server.into_service())
+///     .serve("0.0.0.0:8081".parse()?)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RlsServer {
+    domains: BTreeMap<String, RlsConfig>,
+    limiters: KeyedRatelimiter<DescriptorKey>,
+}
+
+impl RlsServer {
+    /// Create a new server with the limits for each domain. Returns an error
+    /// if any of the rates are invalid.
+    ///
+    /// The idle timeout defaults to the longest time for any of the buckets
+    /// to refill completely, but not less than a minute.
+    pub fn new(configs: impl IntoIterator<Item = RlsConfig>) -> Result<Self, Error> {
+        let mut domains = BTreeMap::new();
+        let mut idle_timeout = MIN_IDLE_TIMEOUT;
+
+        for config in configs {
+            for descriptor in &config.descriptors {
+                let refill = descriptor
+                    .validate()
+                    .map_err(|e| Error::InvalidConfig(format!("{}: {e}", config.domain)))?;
+                idle_timeout = idle_timeout.max(refill);
+            }
+
+            domains.insert(config.domain.clone(), config);
+        }
+
+        Ok(Self {
+            domains,
+            // buckets are created with the rate from the config, so a bucket
+            // for any other key denies every request
+            limiters: KeyedRatelimiter::new(|_| closed()).idle_timeout(idle_timeout),
+        })
+    }
+
+    /// Evict the buckets of descriptors which have been idle for longer than
+    /// the timeout. An evicted bucket starts full when the descriptor is next
+    /// seen, so the timeout should be long enough for any bucket to refill.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.limiters = self.limiters.idle_timeout(timeout);
+        self
+    }
+
+    /// Returns the number of descriptors which have a bucket.
+    pub fn len(&self) -> usize {
+        self.limiters.len()
+    }
+
+    /// Returns true if no descriptors have a bucket.
+    pub fn is_empty(&self) -> bool {
+        self.limiters.is_empty()
+    }
+
+    /// Check and charge the limits for each descriptor in the request.
+    pub fn should_rate_limit(&self, request: &RateLimitRequest) -> RateLimitResponse {
+        let hits = request.hits_addend.max(1) as u64;
+
+        let statuses: Vec<DescriptorStatus> = request
+            .descriptors
+            .iter()
+            .map(|descriptor| self.check(&request.domain, descriptor, hits))
+            .collect();
+
+        let overall_code = if statuses.iter().any(|s| s.code == Code::OverLimit as i32) {
+            Code::OverLimit
+        } else {
+            Code::Ok
+        };
+
+        RateLimitResponse {
+            overall_code: overall_code as i32,
+            statuses,
+        }
+    }
+
+    fn check(&self, domain: &str, descriptor: &RateLimitDescriptor, hits: u64) -> DescriptorStatus {
+        let Some(rate) = self
+            .domains
+            .get(domain)
+            .and_then(|config| config.find(&descriptor.entries))
+        else {
+            return DescriptorStatus {
+                code: Code::Ok as i32,
+                ..Default::default()
+            };
+        };
+
+        let key: DescriptorKey = (
+            domain.to_string(),
+            descriptor
+                .entries
+                .iter()
+                .map(|entry| (entry.key.clone(), entry.value.clone()))
+                .collect(),
+        );
+
+        let limiter = self.limiters.get_or_insert_with(&key, |_| {
+            let builder = rate.builder().expect("rate was validated");
+            let burst = builder.max_tokens;
+
+            builder
+                .initial_available(burst)
+                .build()
+                .expect("rate was validated")
+        });
+
+        let hits = descriptor.hits_addend.map(|h| h.value).unwrap_or(hits);
+
+        let (code, reset) = match limiter.try_wait_n(hits) {
+            Ok(()) => (Code::Ok, until_refill(&limiter)),
+            Err(reset) => (Code::OverLimit, reset),
+        };
+
+        DescriptorStatus {
+            code: code as i32,
+            current_limit: Some(rate_limit(rate)),
+            limit_remaining: limiter.available().min(u32::MAX as u64) as u32,
+            duration_until_reset: Some(reset.into()),
+        }
+    }
+
+    /// Convert into a gRPC service which can be added to a tonic server.
+    pub fn into_service(self) -> RateLimitServiceServer {
+        RateLimitServiceServer {
+            inner: Arc::new(self),
+        }
+    }
+}
+
+/// Internal function to create a bucket which denies every request.
+fn closed() -> Ratelimiter {
+    let limiter = Ratelimiter::builder(0, Duration::ZERO).assemble(None);
+    limiter.close();
+    limiter
+}
+
+/// Internal function to return the time until the next refill.
+fn until_refill(limiter: &Ratelimiter) -> Duration {
+    let now = Instant::now();
+    let next = limiter.next_refill();
+
+    if next > now {
+        Duration::from_nanos((next - now).as_nanos())
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// The gRPC service for an [`RlsServer`]. This is cheap to clone.
+#[derive(Clone)]
+pub struct RateLimitServiceServer {
+    inner: Arc<RlsServer>,
+}
+
+impl tonic::server::NamedService for RateLimitServiceServer {
+    const NAME: &'static str = SERVICE;
+}
+
+struct ShouldRateLimit(Arc<RlsServer>);
+
+impl tonic::server::UnaryService<RateLimitRequest> for ShouldRateLimit {
+    type Response = RateLimitResponse;
+    type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+
+    fn call(&mut self, request: tonic::Request<RateLimitRequest>) -> Self::Future {
+        let response = self.0.should_rate_limit(request.get_ref());
+        Box::pin(async move { Ok(tonic::Response::new(response)) })
+    }
+}
+
+impl<B> Service<http::Request<B>> for RateLimitServiceServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if request.uri().path() != SHOULD_RATE_LIMIT {
+            return Box::pin(async move {
+                // grpc-status 12 is `UNIMPLEMENTED`
+                Ok(http::Response::builder()
+                    .status(200)
+                    .header("grpc-status", "12")
+                    .header("content-type", "application/grpc")
+                    .body(empty_body())
+                    .unwrap())
+            });
+        }
+
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            let codec = tonic::codec::ProstCodec::default();
+            let mut grpc = tonic::server::Grpc::new(codec);
+
+            Ok(grpc.unary(ShouldRateLimit(inner), request).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::proto::*;
+    use super::super::{DescriptorConfig, RlsConfig};
+    use super::*;
+    use core::time::Duration;
+
+    fn request(domain: &str, descriptors: &[&[(&str, &str)]]) -> RateLimitRequest {
+        RateLimitRequest {
+            domain: domain.to_string(),
+            descriptors: descriptors
+                .iter()
+                .map(|entries| RateLimitDescriptor {
+                    entries: entries
+                        .iter()
+                        .map(|(key, value)| Entry {
+                            key: key.to_string(),
+                            value: value.to_string(),
+                        })
+                        .collect(),
+                    hits_addend: None,
+                })
+                .collect(),
+            hits_addend: 0,
+        }
+    }
+
+    #[test]
+    fn should_rate_limit() {
+        let server = RlsServer::new([RlsConfig::new("edge").descriptor(
+            DescriptorConfig::new("remote_address").rate_limit("2/min".parse().unwrap()),
+        )])
+        .unwrap();
+
+        let alice = request("edge", &[&[("remote_address", "10.0.0.1")]]);
+        let bob = request("edge", &[&[("remote_address", "10.0.0.2")]]);
+
+        let response = server.should_rate_limit(&alice);
+        assert_eq!(response.overall_code, Code::Ok as i32);
+        assert_eq!(response.statuses[0].limit_remaining, 1);
+        assert_eq!(
+            response.statuses[0].current_limit,
+            Some(RateLimit {
+                requests_per_unit: 2,
+                unit: Unit::Minute as i32,
+                name: "2/min".to_string(),
+            })
+        );
+
+        assert_eq!(
+            server.should_rate_limit(&alice).overall_code,
+            Code::Ok as i32
+        );
+        let response = server.should_rate_limit(&alice);
+        assert_eq!(response.overall_code, Code::OverLimit as i32);
+        assert!(response.statuses[0].duration_until_reset.is_some());
+
+        // each value has its own limit
+        assert_eq!(server.should_rate_limit(&bob).overall_code, Code::Ok as i32);
+        assert_eq!(server.len(), 2);
+
+        // descriptors without a limit are allowed
+        let response = server.should_rate_limit(&request("edge", &[&[("path", "/")]]));
+        assert_eq!(response.overall_code, Code::Ok as i32);
+        assert_eq!(response.statuses[0].current_limit, None);
+        let response = server.should_rate_limit(&request("other", &[&[("remote_address", "")]]));
+        assert_eq!(response.overall_code, Code::Ok as i32);
+
+        assert!(RlsServer::new([RlsConfig::new("edge")
+            .descriptor(DescriptorConfig::new("a").rate_limit("0.5/1000000d".parse().unwrap()))])
+        .is_err());
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let server = RlsServer::new([RlsConfig::new("edge").descriptor(
+            DescriptorConfig::new("remote_address").rate_limit("100/s".parse().unwrap()),
+        )])
+        .unwrap()
+        .idle_timeout(Duration::from_millis(20));
+
+        for address in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            server.should_rate_limit(&request("edge", &[&[("remote_address", address)]]));
+        }
+        assert_eq!(server.len(), 3);
+
+        // idle buckets are evicted as new descriptors arrive
+        std::thread::sleep(Duration::from_millis(30));
+        server.should_rate_limit(&request("edge", &[&[("remote_address", "10.0.0.4")]]));
+        assert_eq!(server.len(), 1);
+
+        // a bucket for a descriptor without a limit denies every request
+        let key = (
+            "edge".to_string(),
+            vec![("path".to_string(), "/".to_string())],
+        );
+        assert!(server.limiters.get(&key).try_acquire().is_err());
+    }
+
+    // test that descriptors which would join to the same string have their
+    // own buckets
+    #[test]
+    fn distinct_keys() {
+        let server = RlsServer::new([RlsConfig::new("edge")
+            .descriptor(
+                DescriptorConfig::new("path")
+                    .value("/login")
+                    .descriptor(DescriptorConfig::new("user").rate_limit("1/min".parse().unwrap())),
+            )
+            .descriptor(DescriptorConfig::new("path").rate_limit("1k/s".parse().unwrap()))])
+        .unwrap();
+
+        let wildcard = request("edge", &[&[("path", "/login.user_alice")]]);
+        let user = request("edge", &[&[("path", "/login"), ("user", "alice")]]);
+
+        assert_eq!(
+            server.should_rate_limit(&wildcard).overall_code,
+            Code::Ok as i32
+        );
+
+        // the user keeps the limit from its own rule
+        let response = server.should_rate_limit(&user);
+        assert_eq!(response.overall_code, Code::Ok as i32);
+        assert_eq!(response.statuses[0].limit_remaining, 0);
+        assert_eq!(
+            server.should_rate_limit(&user).overall_code,
+            Code::OverLimit as i32
+        );
+        assert_eq!(server.len(), 2);
+    }
+
+    #[test]
+    fn service() {
+        use prost::Message;
+
+        let server = RlsServer::new([RlsConfig::new("edge").descriptor(
+            DescriptorConfig::new("remote_address").rate_limit("1/min".parse().unwrap()),
+        )])
+        .unwrap();
+        let mut service = server.into_service();
+
+        // a gRPC message is prefixed with a compression flag and length
+        let message = request("edge", &[&[("remote_address", "10.0.0.1")]]).encode_to_vec();
+        let mut body = vec![0];
+        body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        body.extend_from_slice(&message);
+
+        let request = http::Request::builder()
+            .method("POST")
+            .uri(SHOULD_RATE_LIMIT)
+            .header("content-type", "application/grpc")
+            .body(http_body::Full::new(bytes::Bytes::from(body)))
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let response = runtime.block_on(service.call(request)).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(service.inner.len(), 1);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::atomic::Ordering;
+use crate::{amount_and_interval, Builder, Ratelimiter};
+use clocksource::precise::{Duration, Instant};
+
+/// A schedule which determines the rate of a `Ratelimiter` over time. This can
+/// be used to model realistic traffic shapes, such as diurnal patterns, when
+/// building benchmarking tools.
+///
+/// The schedule is consulted lazily as tokens are acquired, so no background
+/// thread is required. Rates which are not finite or are not greater than zero
+/// are ignored and the previous rate remains in effect.
+///
+/// Closures of the form `Fn(Duration) -> f64` implement this trait:
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// // double the rate every minute
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
+///     .max_tokens(100)
+///     .schedule(|elapsed: Duration| 100.0 * 2.0_f64.powf(elapsed.as_secs_f64() / 60.0))
+///     .build()
+///     .unwrap();
+/// ```
+pub trait RateSchedule: Send + Sync {
+    /// Returns the rate in tokens/s after `elapsed` time since the start of the
+    /// schedule.
+    fn rate_at(&self, elapsed: core::time::Duration) -> f64;
+}
+
+impl<F> RateSchedule for F
+where
+    F: Fn(core::time::Duration) -> f64 + Send + Sync,
+{
+    fn rate_at(&self, elapsed: core::time::Duration) -> f64 {
+        self(elapsed)
+    }
+}
+
+/// A schedule where the rate changes in discrete steps. Each step begins at an
+/// offset from the start of the schedule and the rate before the first step
+/// is the rate of the first step. Optionally, the steps may repeat with some
+/// period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Steps {
+    steps: Vec<(core::time::Duration, f64)>,
+    period: Option<core::time::Duration>,
+}
+
+impl Steps {
+    /// Create a new step schedule from pairs of offsets and rates in tokens/s.
+    pub fn new(steps: impl IntoIterator<Item = (core::time::Duration, f64)>) -> Self {
+        let mut steps: Vec<_> = steps.into_iter().collect();
+        steps.sort_by_key(|(offset, _)| *offset);
+
+        Self {
+            steps,
+            period: None,
+        }
+    }
+
+    /// Repeat the steps with the provided period.
+    pub fn repeat(mut self, period: core::time::Duration) -> Self {
+        self.period = Some(period).filter(|p| !p.is_zero());
+        self
+    }
+}
+
+impl RateSchedule for Steps {
+    fn rate_at(&self, elapsed: core::time::Duration) -> f64 {
+        let elapsed = match self.period {
+            Some(period) => {
+                core::time::Duration::from_nanos((elapsed.as_nanos() % period.as_nanos()) as u64)
+            }
+            None => elapsed,
+        };
+
+        self.steps
+            .iter()
+            .take_while(|(offset, _)| *offset <= elapsed)
+            .last()
+            .or(self.steps.first())
+            .map(|(_, rate)| *rate)
+            .unwrap_or(0.0)
+    }
+}
+
+/// A schedule where the rate follows a sine wave around a mean rate. With a
+/// period of one day, this approximates the diurnal traffic pattern seen by
+/// many services.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sine {
+    mean: f64,
+    amplitude: f64,
+    period: core::time::Duration,
+    phase: core::time::Duration,
+}
+
+impl Sine {
+    /// Create a new sinusoidal schedule which varies by `amplitude` around the
+    /// `mean` rate, both in tokens/s, with the provided `period`.
+    pub fn new(mean: f64, amplitude: f64, period: core::time::Duration) -> Self {
+        Self {
+            mean,
+            amplitude,
+            period,
+            phase: core::time::Duration::ZERO,
+        }
+    }
+
+    /// Offset the start of the wave. For example, with a phase of one quarter
+    /// of the period, the schedule starts at the peak rate.
+    pub fn phase(mut self, phase: core::time::Duration) -> Self {
+        self.phase = phase;
+        self
+    }
+}
+
+impl RateSchedule for Sine {
+    fn rate_at(&self, elapsed: core::time::Duration) -> f64 {
+        let t = (elapsed + self.phase).as_secs_f64() / self.period.as_secs_f64();
+
+        self.mean + self.amplitude * (2.0 * core::f64::consts::PI * t).sin()
+    }
+}
+
+impl Builder {
+    /// Set a schedule which will determine the rate of the `Ratelimiter` over
+    /// time. See [`RateSchedule`] for details.
+    ///
+    /// The refill amount is limited by the max tokens, so the max tokens should
+    /// be set high enough for the peak rate of the schedule. If a rate would
+    /// require a larger refill amount, a shorter refill interval will be used
+    /// instead.
+    pub fn schedule(mut self, schedule: impl RateSchedule + 'static) -> Self {
+        self.schedule = Some(Box::new(schedule));
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to update the refill parameters to match the
+    /// schedule, if there is one. Called as part of `refill()`
+    pub(crate) fn update_schedule(&self, time: Instant) {
+        let Some(schedule) = self.extensions().and_then(|e| e.schedule.as_ref()) else {
+            return;
+        };
+
+        let elapsed = time
+            .checked_duration_since(self.created)
+            .unwrap_or_default();
+        let rate = schedule.rate_at(core::time::Duration::from_nanos(elapsed.as_nanos()));
+
+        self.apply_rate(rate);
+    }
+
+    /// Internal function to change the rate, using a shorter refill interval
+    /// if the refill amount would exceed the max tokens. Rates which are not
+    /// finite or are not greater than zero are ignored.
+    pub(crate) fn apply_rate(&self, rate: f64) {
+        let Ok((mut amount, interval)) = amount_and_interval(rate) else {
+            return;
+        };
+        let mut interval = Duration::from_nanos(interval.as_nanos() as u64);
+
+        {
+            let parameters = self.parameters.read();
+
+            // if the refill amount would exceed the max tokens, use a shorter
+            // interval instead
+            if amount > parameters.capacity && parameters.capacity > 0 {
+                amount = parameters.capacity;
+                interval = Duration::from_nanos(((amount as f64 * 1e9 / rate) as u64).max(1));
+            }
+
+            if parameters.refill_amount == amount && parameters.refill_interval == interval {
+                return;
+            }
+        }
+
+        let mut parameters = self.parameters.write();
+        if amount <= parameters.capacity {
+            let previous = parameters.scaled_interval;
+            parameters.refill_amount = amount;
+            parameters.refill_interval = interval;
+            parameters.fraction = (0, 1);
+            parameters.rescale();
+            let scaled = parameters.scaled_interval;
+            drop(parameters);
+
+            // a shorter interval moves the next refill earlier, otherwise a
+            // change from a long interval would not apply until it elapsed
+            if scaled < previous {
+                let refill_at = self.refill_at.load(Ordering::Relaxed);
+                if let Some(last) = refill_at.checked_sub(previous) {
+                    self.refill_at.fetch_min(last + scaled, Ordering::AcqRel);
+                }
+            }
+
+            self.notify_parameters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps() {
+        let steps = Steps::new([
+            (core::time::Duration::from_secs(10), 200.0),
+            (core::time::Duration::ZERO, 100.0),
+            (core::time::Duration::from_secs(20), 50.0),
+        ]);
+
+        assert_eq!(steps.rate_at(core::time::Duration::ZERO), 100.0);
+        assert_eq!(steps.rate_at(core::time::Duration::from_secs(15)), 200.0);
+        assert_eq!(steps.rate_at(core::time::Duration::from_secs(45)), 50.0);
+
+        let steps = steps.repeat(core::time::Duration::from_secs(30));
+        assert_eq!(steps.rate_at(core::time::Duration::from_secs(45)), 200.0);
+    }
+
+    #[test]
+    fn sine() {
+        let sine = Sine::new(100.0, 50.0, core::time::Duration::from_secs(40));
+
+        approx_eq!(sine.rate_at(core::time::Duration::ZERO), 100.0);
+        approx_eq!(sine.rate_at(core::time::Duration::from_secs(10)), 150.0);
+        approx_eq!(sine.rate_at(core::time::Duration::from_secs(30)), 50.0);
+
+        let sine = sine.phase(core::time::Duration::from_secs(10));
+        approx_eq!(sine.rate_at(core::time::Duration::ZERO), 150.0);
+    }
+
+    #[test]
+    fn schedule() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(10))
+            .max_tokens(10)
+            .schedule(|elapsed: core::time::Duration| {
+                if elapsed < core::time::Duration::from_secs(1) {
+                    100.0
+                } else if elapsed < core::time::Duration::from_secs(2) {
+                    0.0
+                } else {
+                    100_000_000.0
+                }
+            })
+            .build()
+            .unwrap();
+
+        let _ = rl.refill(rl.created + Duration::from_millis(500));
+        approx_eq!(rl.rate(), 100.0);
+
+        // invalid rates are ignored
+        let _ = rl.refill(rl.created + Duration::from_millis(1500));
+        approx_eq!(rl.rate(), 100.0);
+
+        // rates which need a refill amount beyond the max tokens use a shorter
+        // interval instead
+        let _ = rl.refill(rl.created + Duration::from_millis(2500));
+        assert_eq!(rl.refill_amount(), 10);
+        approx_eq!(rl.rate(), 100_000_000.0);
+    }
+
+    // test that a change in rate applies before the next refill is due
+    #[test]
+    fn long_interval() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(0)
+            .schedule(|elapsed: core::time::Duration| {
+                if elapsed < core::time::Duration::from_secs(1) {
+                    1.0 / 60.0
+                } else {
+                    10.0
+                }
+            })
+            .build()
+            .unwrap();
+
+        assert!(rl.refill(rl.created + Duration::from_millis(500)).is_err());
+        assert_eq!(rl.next_refill(), rl.created + Duration::from_secs(60));
+
+        // the faster rate takes effect long before the first refill was due
+        let _ = rl.refill(rl.created + Duration::from_secs(2));
+        approx_eq!(rl.rate(), 10.0);
+        assert_eq!(
+            rl.next_refill(),
+            rl.created + Duration::from_secs(2) + Duration::from_millis(100)
+        );
+        assert_eq!(rl.available(), 10);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{Error, Ratelimiter, RatelimiterConfig};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A named collection of ratelimiters. This is useful for services which have
+/// many limits defined declaratively in a single configuration document.
+///
+/// With the `toml` or `json` features enabled, a set can be loaded directly
+/// from a document that maps each name to a [`RatelimiterConfig`]:
+///
+/// ```toml
+/// [search]
+/// rate = "100/s"
+/// max_tokens = 10
+///
+/// [upload]
+/// rate = "1000/h"
+/// initial_available = 1000
+/// ```
+#[derive(Default)]
+pub struct LimiterSet {
+    limiters: BTreeMap<String, Arc<Ratelimiter>>,
+}
+
+impl LimiterSet {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Construct a set from named configs. Returns an error naming the first
+    /// limiter which could not be constructed.
+    pub fn from_configs<I, S>(configs: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (S, RatelimiterConfig)>,
+        S: Into<String>,
+    {
+        let mut set = Self::new();
+
+        for (name, config) in configs {
+            let name = name.into();
+            let limiter = config
+                .builder()
+                .and_then(|builder| builder.build())
+                .map_err(|e| Error::InvalidConfig(format!("{name}: {e}")))?;
+
+            set.insert(name, limiter);
+        }
+
+        Ok(set)
+    }
+
+    /// Construct a set from a TOML document where each top-level table is a
+    /// named [`RatelimiterConfig`].
+    #[cfg(feature = "toml")]
+    pub fn from_toml(document: &str) -> Result<Self, Error> {
+        let configs: BTreeMap<String, RatelimiterConfig> =
+            toml::from_str(document).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+
+        Self::from_configs(configs)
+    }
+
+    /// Construct a set from a JSON document where each member of the top-level
+    /// object is a named [`RatelimiterConfig`].
+    #[cfg(feature = "json")]
+    pub fn from_json(document: &str) -> Result<Self, Error> {
+        let configs: BTreeMap<String, RatelimiterConfig> =
+            serde_json::from_str(document).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+
+        Self::from_configs(configs)
+    }
+
+    /// Add a limiter to the set, returning the limiter previously registered
+    /// with the same name, if any.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        limiter: impl Into<Arc<Ratelimiter>>,
+    ) -> Option<Arc<Ratelimiter>> {
+        self.limiters.insert(name.into(), limiter.into())
+    }
+
+    /// Returns the limiter with the provided name.
+    pub fn get(&self, name: &str) -> Option<&Arc<Ratelimiter>> {
+        self.limiters.get(name)
+    }
+
+    /// Returns an iterator across the named limiters, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<Ratelimiter>)> {
+        self.limiters.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Returns the number of limiters in the set.
+    pub fn len(&self) -> usize {
+        self.limiters.len()
+    }
+
+    /// Returns true if the set contains no limiters.
+    pub fn is_empty(&self) -> bool {
+        self.limiters.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_configs() {
+        let set = LimiterSet::from_configs([
+            ("a", RatelimiterConfig::new("10/s".parse().unwrap())),
+            (
+                "b",
+                RatelimiterConfig::new("10/s".parse().unwrap()).max_tokens(0),
+            ),
+        ]);
+
+        // max tokens is less than the refill amount
+        assert!(matches!(set, Err(Error::InvalidConfig(_))));
+
+        let set = LimiterSet::from_configs([
+            ("a", RatelimiterConfig::new("10/s".parse().unwrap())),
+            ("b", RatelimiterConfig::new("1/s".parse().unwrap())),
+        ])
+        .unwrap();
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get("a").unwrap().max_tokens(), 10);
+        assert!(set.get("c").is_none());
+        assert_eq!(
+            set.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn from_toml() {
+        let set = LimiterSet::from_toml(
+            r#"
+            [search]
+            rate = "100/s"
+            max_tokens = 10
+
+            [upload]
+            rate = "1000/h"
+            initial_available = 1000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(set.get("search").unwrap().max_tokens(), 10);
+        assert_eq!(set.get("upload").unwrap().available(), 1000);
+
+        assert!(LimiterSet::from_toml("[search]\nrate = 100").is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn from_json() {
+        let set = LimiterSet::from_json(
+            r#"{
+                "search": { "rate": "100/s", "max_tokens": 10 },
+                "upload": { "rate": "1000/h", "initial_available": 1000 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(set.get("search").unwrap().max_tokens(), 10);
+        assert_eq!(set.get("upload").unwrap().available(), 1000);
+    }
+}
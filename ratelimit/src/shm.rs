@@ -0,0 +1,402 @@
+use crate::{advance, intervals_u32, Builder};
+use clocksource::precise::UnixInstant;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Marks a fully initialized mapping. The low bits hold the layout version.
+const MAGIC: u64 = 0x524c_5348_4d00_0001;
+
+/// Set in the header while a process is initializing the mapping. The
+/// remaining bits identify the process and attempt so that a stalled
+/// initialization can be taken over safely.
+const INITIALIZING: u64 = 1 << 63;
+
+/// How long to wait for another process to finish initializing the mapping
+/// before assuming it has crashed and taking over.
+const INIT_TIMEOUT: core::time::Duration = core::time::Duration::from_millis(100);
+
+static ATTEMPT: AtomicU32 = AtomicU32::new(0);
+
+/// The layout of the memory-mapped file. Every field is an atomic so that
+/// the bucket remains consistent if a process crashes at any point.
+#[repr(C)]
+struct Shared {
+    header: AtomicU64,
+    capacity: AtomicU64,
+    refill_amount: AtomicU64,
+    refill_interval: AtomicU64,
+    available: AtomicU64,
+    refill_at: AtomicU64,
+}
+
+/// Returns the current time in nanoseconds since the unix epoch. The system
+/// clock is used since it is meaningful across processes and reboots.
+fn now() -> u64 {
+    UnixInstant::now()
+        .duration_since(UnixInstant::EPOCH)
+        .as_nanos()
+}
+
+/// A token bucket which lives in a memory-mapped file so that multiple
+/// processes on one host, such as the workers of a prefork server, share a
+/// single budget without any network dependency.
+///
+/// The first process to open the file initializes the bucket from the
+/// builder. Later processes must use the same refill amount, refill interval,
+/// and max tokens or opening the file fails. If a process crashes while
+/// initializing the file, or the file is corrupted, the next process to open
+/// it re-initializes the bucket.
+///
+/// Only the refill amount, refill interval, max tokens, and initial available
+/// are used from the builder.
+///
+/// ```no_run
+/// use ratelimit::{Ratelimiter, SharedRatelimiter};
+///
+/// let ratelimiter =
+///     SharedRatelimiter::open("/dev/shm/ratelimit", Ratelimiter::per_second(1000)).unwrap();
+///
+/// if ratelimiter.try_wait().is_ok() {
+///     // do some ratelimited action here
+/// }
+/// ```
+pub struct SharedRatelimiter {
+    mmap: MmapMut,
+    path: PathBuf,
+}
+
+impl SharedRatelimiter {
+    /// Open, creating if necessary, the shared bucket at the provided path.
+    pub fn open(path: impl AsRef<Path>, builder: Builder) -> Result<Self, Error> {
+        if builder.max_tokens < builder.refill_amount {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                crate::Error::MaxTokensTooLow,
+            ));
+        }
+
+        if builder.refill_interval.as_nanos() > u64::MAX as u128 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                crate::Error::RefillIntervalTooLong,
+            ));
+        }
+
+        let path = path.as_ref().to_path_buf();
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let len = core::mem::size_of::<Shared>() as u64;
+
+        if file.metadata()?.len() < len {
+            file.set_len(len)?;
+        }
+
+        // SAFETY: the file is only ever accessed through atomics, so it is
+        // sound for other processes to modify it concurrently.
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        let ratelimiter = Self { mmap, path };
+        ratelimiter.initialize(&builder)?;
+
+        Ok(ratelimiter)
+    }
+
+    fn shared(&self) -> &Shared {
+        // SAFETY: the mapping is page aligned and at least the size of the
+        // layout, which consists only of atomics.
+        unsafe { &*(self.mmap.as_ptr() as *const Shared) }
+    }
+
+    /// Internal function to initialize the mapping, or to wait for another
+    /// process to do so, and then check that the parameters match.
+    fn initialize(&self, builder: &Builder) -> Result<(), Error> {
+        let shared = self.shared();
+
+        let refill_interval = builder.refill_interval.as_nanos() as u64;
+        let tag = INITIALIZING
+            | ((std::process::id() as u64) << 24)
+            | (ATTEMPT.fetch_add(1, Ordering::Relaxed) as u64 & 0xFF_FFFF);
+
+        let mut observed = shared.header.load(Ordering::Acquire);
+        let mut since = Instant::now();
+
+        loop {
+            if observed == MAGIC {
+                break;
+            }
+
+            // Take over the initialization if the mapping is new or corrupt,
+            // or if another process has stalled while initializing it.
+            let stalled = observed & INITIALIZING != 0 && since.elapsed() >= INIT_TIMEOUT;
+
+            if observed & INITIALIZING == 0 || stalled {
+                if shared
+                    .header
+                    .compare_exchange(observed, tag, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                {
+                    shared.capacity.store(builder.max_tokens, Ordering::Relaxed);
+                    shared
+                        .refill_amount
+                        .store(builder.refill_amount, Ordering::Relaxed);
+                    shared
+                        .refill_interval
+                        .store(refill_interval, Ordering::Relaxed);
+                    shared.available.store(
+                        builder.initial_available.min(builder.max_tokens),
+                        Ordering::Relaxed,
+                    );
+                    shared
+                        .refill_at
+                        .store(now().saturating_add(refill_interval), Ordering::Relaxed);
+
+                    // if this process stalled and the initialization was
+                    // taken over, the new owner publishes the mapping instead
+                    if shared
+                        .header
+                        .compare_exchange(tag, MAGIC, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        break;
+                    }
+                }
+            } else {
+                std::thread::yield_now();
+            }
+
+            let current = shared.header.load(Ordering::Acquire);
+
+            if current != observed {
+                observed = current;
+                since = Instant::now();
+            }
+        }
+
+        if shared.capacity.load(Ordering::Relaxed) != builder.max_tokens
+            || shared.refill_amount.load(Ordering::Relaxed) != builder.refill_amount
+            || shared.refill_interval.load(Ordering::Relaxed) != refill_interval
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the shared bucket was created with different parameters",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the path of the memory-mapped file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the number of tokens currently available.
+    pub fn available(&self) -> u64 {
+        self.shared().available.load(Ordering::Relaxed)
+    }
+
+    /// Returns the maximum number of tokens that can be held in the bucket.
+    pub fn max_tokens(&self) -> u64 {
+        self.shared().capacity.load(Ordering::Relaxed)
+    }
+
+    /// Internal function to refill the token bucket.
+    fn refill(&self, time: u64) -> Result<(), core::time::Duration> {
+        let shared = self.shared();
+
+        let interval = shared.refill_interval.load(Ordering::Relaxed).max(1);
+        let refill_at = shared.refill_at.load(Ordering::Acquire);
+
+        // If the system clock has stepped backwards, or the file has outlived
+        // a reboot, restart the schedule from the current time.
+        if refill_at > time.saturating_add(interval) {
+            let _ = shared.refill_at.compare_exchange(
+                refill_at,
+                time.saturating_add(interval),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+            return Err(core::time::Duration::from_nanos(interval));
+        }
+
+        if time < refill_at {
+            return Err(core::time::Duration::from_nanos(refill_at - time));
+        }
+
+        let intervals = (time - refill_at) / interval + 1;
+
+        if shared
+            .refill_at
+            .compare_exchange(
+                refill_at,
+                advance(refill_at, intervals, interval),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            let amount = intervals.saturating_mul(shared.refill_amount.load(Ordering::Relaxed));
+            let capacity = shared.capacity.load(Ordering::Relaxed);
+
+            let _ =
+                shared
+                    .available
+                    .fetch_update(Ordering::AcqRel, Ordering::Acquire, |available| {
+                        Some(available.saturating_add(amount).min(capacity))
+                    });
+        }
+
+        Ok(())
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens. On success, the tokens
+    /// have been acquired. On failure, a `Duration` hinting at when the next
+    /// refill would occur is returned.
+    pub fn try_wait_n(&self, n: u64) -> Result<(), core::time::Duration> {
+        let shared = self.shared();
+
+        loop {
+            let refill_result = self.refill(now());
+
+            loop {
+                let available = shared.available.load(Ordering::Acquire);
+
+                if available < n {
+                    match refill_result {
+                        Ok(_) => break,
+                        Err(e) => {
+                            // the next refill, plus any further refills needed
+                            // to cover the shortfall
+                            let amount = shared.refill_amount.load(Ordering::Relaxed).max(1);
+                            let interval = core::time::Duration::from_nanos(
+                                shared.refill_interval.load(Ordering::Relaxed),
+                            );
+                            let intervals = (n - available).div_ceil(amount) - 1;
+
+                            return Err(e.saturating_add(
+                                interval.saturating_mul(intervals_u32(intervals)),
+                            ));
+                        }
+                    }
+                }
+
+                if shared
+                    .available
+                    .compare_exchange(
+                        available,
+                        available - n,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_ok()
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Non-blocking function to "wait" for a single token. See
+    /// [`SharedRatelimiter::try_wait_n`] for details.
+    pub fn try_wait(&self) -> Result<(), core::time::Duration> {
+        self.try_wait_n(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ratelimiter;
+    use std::time::Duration;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ratelimit-{}-{name}.shm", std::process::id()))
+    }
+
+    fn builder() -> Builder {
+        Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(4)
+    }
+
+    #[test]
+    fn shared() {
+        let path = path("shared");
+
+        let a = SharedRatelimiter::open(&path, builder()).unwrap();
+        let b = SharedRatelimiter::open(&path, builder().initial_available(10)).unwrap();
+
+        // the second process does not reset the bucket
+        assert_eq!(b.available(), 4);
+
+        assert!(a.try_wait_n(2).is_ok());
+        assert!(b.try_wait().is_ok());
+        assert!(a.try_wait().is_ok());
+        assert!(b.try_wait().is_err());
+        assert_eq!(a.available(), 0);
+
+        assert!(SharedRatelimiter::open(&path, builder().max_tokens(20)).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn recovery() {
+        let path = path("recovery");
+
+        let rl = SharedRatelimiter::open(&path, builder()).unwrap();
+
+        // a corrupt header is re-initialized
+        rl.shared().header.store(42, Ordering::Release);
+        rl.shared().available.store(0, Ordering::Release);
+        let rl = SharedRatelimiter::open(&path, builder()).unwrap();
+        assert_eq!(rl.available(), 4);
+
+        // a stalled initialization is taken over
+        rl.shared()
+            .header
+            .store(INITIALIZING | 7, Ordering::Release);
+        rl.shared().available.store(0, Ordering::Release);
+        let rl = SharedRatelimiter::open(&path, builder()).unwrap();
+        assert_eq!(rl.available(), 4);
+
+        // the schedule restarts if the next refill is too far in the future
+        rl.shared().refill_at.store(u64::MAX / 2, Ordering::Release);
+        assert!(rl.try_wait_n(5).is_err());
+        assert!(rl.shared().refill_at.load(Ordering::Acquire) <= now() + 60_000_000_000);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn shortfall() {
+        let path = path("shortfall");
+
+        let rl = SharedRatelimiter::open(&path, builder().initial_available(0)).unwrap();
+
+        // the hint covers each refill needed for the shortfall
+        let hint = rl.try_wait_n(3).unwrap_err();
+        assert!(hint > Duration::from_secs(120) && hint <= Duration::from_secs(180));
+        std::fs::remove_file(path).unwrap();
+
+        // a huge shortfall saturates rather than overflowing
+        let path = self::path("saturate");
+        let builder = Ratelimiter::builder(1, Duration::from_nanos(u64::MAX / 2))
+            .max_tokens(u64::MAX)
+            .initial_available(0);
+        let rl = SharedRatelimiter::open(&path, builder).unwrap();
+        assert!(rl.try_wait_n(u64::MAX).unwrap_err() > Duration::from_secs(u64::MAX / 2));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,208 @@
+use crate::atomic::Ordering;
+use crate::{advance_instant, Ratelimiter};
+
+/// A deterministic simulation of a ratelimiter, which drives it with a virtual
+/// clock rather than the system clock. This allows the worst-case behavior of
+/// a configuration to be checked before it is deployed, covering days of
+/// traffic in moments.
+///
+/// The virtual time advances in steps. In each step, the demand function is
+/// asked how many tokens are requested, given the virtual time elapsed so far.
+/// Requests which cannot be admitted are retried in the following steps, so
+/// the deficit is the backlog of tokens which have been requested but not yet
+/// admitted.
+///
+/// The simulation takes ownership of the ratelimiter, since its state is
+/// moved forward in virtual time.
+///
+/// ```
+/// use ratelimit::{Ratelimiter, Simulation};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::per_second(100)
+///     .max_tokens(50)
+///     .build()
+///     .unwrap();
+///
+/// // steady demand at 80/s, with a spike of 1000 tokens once an hour
+/// let report = Simulation::new(ratelimiter, |elapsed: Duration| {
+///     if elapsed.as_secs() % 3600 == 0 && elapsed.subsec_nanos() == 0 {
+///         1008
+///     } else {
+///         8
+///     }
+/// })
+/// .step(Duration::from_millis(100))
+/// .run(Duration::from_secs(86_400));
+///
+/// assert!(report.max_burst <= 50 + 10);
+/// assert!(report.max_deficit >= 900);
+/// ```
+pub struct Simulation<F> {
+    demand: F,
+    ratelimiter: Ratelimiter,
+    step: core::time::Duration,
+    // the virtual time in nanoseconds since the ratelimiter was created
+    elapsed: u64,
+    // tokens which were requested but not yet admitted
+    backlog: u64,
+}
+
+/// The results of a [`Simulation`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimulationReport {
+    /// The virtual time which was simulated.
+    pub elapsed: core::time::Duration,
+    /// The number of tokens which were requested.
+    pub requested: u64,
+    /// The number of tokens which were admitted.
+    pub admitted: u64,
+    /// The average rate of admitted tokens in tokens/s.
+    pub achieved_rate: f64,
+    /// The most tokens which were admitted in a single step.
+    pub max_burst: u64,
+    /// The largest backlog of tokens which were requested but not yet
+    /// admitted.
+    pub max_deficit: u64,
+    /// The backlog at the end of the simulation.
+    pub deficit: u64,
+}
+
+impl<F: FnMut(core::time::Duration) -> u64> Simulation<F> {
+    /// Create a simulation of the ratelimiter, where `demand` returns the
+    /// number of tokens requested in each step given the virtual time elapsed.
+    pub fn new(ratelimiter: Ratelimiter, demand: F) -> Self {
+        let step = ratelimiter.refill_interval();
+
+        Self {
+            demand,
+            ratelimiter,
+            step,
+            elapsed: 0,
+            backlog: 0,
+        }
+    }
+
+    /// Set the length of each step of virtual time. Shorter steps model the
+    /// arrival of requests more precisely, while longer steps allow longer
+    /// periods to be simulated quickly. The max burst is measured per step.
+    ///
+    /// The default is the refill interval of the ratelimiter.
+    pub fn step(mut self, step: core::time::Duration) -> Self {
+        self.step = step.max(core::time::Duration::from_nanos(1));
+        self
+    }
+
+    /// Returns the ratelimiter, in the state reached by the simulation.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        &self.ratelimiter
+    }
+
+    /// Run the simulation for `duration` of virtual time, rounded up to a
+    /// whole number of steps. Each run continues from where the previous run
+    /// ended, including any backlog, and the report covers only this run.
+    pub fn run(&mut self, duration: core::time::Duration) -> SimulationReport {
+        let step = self.step.as_nanos().min(u64::MAX as u128) as u64;
+        let steps = duration
+            .as_nanos()
+            .div_ceil(step as u128)
+            .min(u64::MAX as u128) as u64;
+
+        let rl = &self.ratelimiter;
+
+        let mut report = SimulationReport {
+            elapsed: core::time::Duration::ZERO,
+            requested: 0,
+            admitted: 0,
+            achieved_rate: 0.0,
+            max_burst: 0,
+            max_deficit: 0,
+            deficit: 0,
+        };
+
+        for _ in 0..steps {
+            let time = advance_instant(rl.created, 1, self.elapsed);
+
+            let requested = (self.demand)(core::time::Duration::from_nanos(self.elapsed));
+            report.requested = report.requested.saturating_add(requested);
+
+            let wanted = self.backlog.saturating_add(requested);
+            if wanted > 0 {
+                rl.update_warmup(time);
+            }
+            let _ = rl.refill(time);
+
+            let mut admitted = 0;
+            let _ = rl
+                .available
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |available| {
+                    admitted = available.min(wanted);
+                    Some(available - admitted)
+                });
+
+            report.admitted = report.admitted.saturating_add(admitted);
+            report.max_burst = report.max_burst.max(admitted);
+            self.backlog = wanted - admitted;
+            report.max_deficit = report.max_deficit.max(self.backlog);
+
+            self.elapsed = self.elapsed.saturating_add(step);
+        }
+
+        report.deficit = self.backlog;
+        report.elapsed = core::time::Duration::from_nanos(steps.saturating_mul(step));
+        if !report.elapsed.is_zero() {
+            report.achieved_rate = report.admitted as f64 / report.elapsed.as_secs_f64();
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    // a million steps take too long to interpret
+    #[cfg_attr(miri, ignore)]
+    fn greedy() {
+        let rl = Ratelimiter::builder(10, Duration::from_millis(100))
+            .max_tokens(100)
+            .initial_available(100)
+            .build()
+            .unwrap();
+
+        // a million virtual seconds of demand at twice the rate
+        let mut simulation = Simulation::new(rl, |_| 200).step(Duration::from_secs(1));
+        let report = simulation.run(Duration::from_secs(1_000_000));
+
+        assert_eq!(report.elapsed, Duration::from_secs(1_000_000));
+        assert_eq!(report.requested, 200_000_000);
+        assert_eq!(report.admitted, 100_000_000);
+        assert_eq!(report.achieved_rate, 100.0);
+        assert_eq!(report.max_burst, 100);
+        assert_eq!(report.max_deficit, 100_000_000);
+        assert_eq!(report.deficit, 100_000_000);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn within_rate() {
+        let rl = Ratelimiter::per_second(100).max_tokens(10).build().unwrap();
+
+        // demand below the rate is only held back while the bucket starts out
+        // empty
+        let mut simulation = Simulation::new(rl, |_| 5).step(Duration::from_millis(100));
+        let report = simulation.run(Duration::from_secs(3600));
+        assert_eq!(report.admitted, report.requested);
+        assert_eq!(report.achieved_rate, 50.0);
+        assert_eq!(report.max_deficit, 5);
+        assert_eq!(report.max_burst, 10);
+
+        // runs continue in virtual time
+        let report = simulation.run(Duration::from_secs(10));
+        assert_eq!(report.elapsed, Duration::from_secs(10));
+        assert_eq!(report.deficit, 0);
+    }
+}
@@ -0,0 +1,205 @@
+//! An adapter which applies backpressure to an asynchronous [`Sink`] with a
+//! ratelimiter, so that producers are paced automatically.
+//!
+//! ```
+//! use futures::SinkExt;
+//! use ratelimit::sink::RatelimitSinkExt;
+//! use ratelimit::Ratelimiter;
+//! use std::time::Duration;
+//!
+//! let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+//!     .build()
+//!     .unwrap();
+//!
+//! let mut sink = Vec::new().ratelimit_sink(&ratelimiter);
+//!
+//! futures::executor::block_on(async {
+//!     for i in 0..3 {
+//!         sink.send(i).await.unwrap();
+//!     }
+//! });
+//!
+//! assert_eq!(sink.get_ref(), &[0, 1, 2]);
+//! ```
+
+use crate::stream::{poll_wait, UnitCost};
+use crate::{Ratelimiter, Waiter};
+use core::borrow::Borrow;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures_sink::Sink;
+use thiserror::Error;
+
+/// The error returned by a [`RatelimitedSink`].
+#[derive(Error, Debug)]
+pub enum SinkError<E> {
+    /// The ratelimiter has been closed, so no more items can be sent.
+    #[error("the ratelimiter is closed")]
+    Closed,
+    /// The inner sink returned an error.
+    #[error(transparent)]
+    Inner(E),
+}
+
+/// An extension trait which adds a ratelimiting adapter to any [`Sink`].
+pub trait RatelimitSinkExt<Item>: Sink<Item> + Sized {
+    /// Accept items no faster than the rate of the ratelimiter, charging a
+    /// single token for each item.
+    fn ratelimit_sink<L: Borrow<Ratelimiter>>(
+        self,
+        ratelimiter: L,
+    ) -> RatelimitedSink<Self, L, UnitCost<Item>> {
+        self.ratelimit_sink_with(ratelimiter, |_| 1)
+    }
+
+    /// Accept items no faster than the rate of the ratelimiter, charging the
+    /// number of tokens returned by the cost function for each item.
+    ///
+    /// Since the cost of an item is only known once it is sent, a single token
+    /// is reserved before the sink is ready and the remainder of the cost is
+    /// charged before the next item can be sent.
+    fn ratelimit_sink_with<L, F>(self, ratelimiter: L, cost: F) -> RatelimitedSink<Self, L, F>
+    where
+        L: Borrow<Ratelimiter>,
+        F: FnMut(&Item) -> u64,
+    {
+        RatelimitedSink {
+            cost,
+            debt: 0,
+            ratelimiter,
+            reserved: false,
+            sink: self,
+            waiter: Waiter::new(),
+        }
+    }
+}
+
+impl<S: Sink<Item>, Item> RatelimitSinkExt<Item> for S {}
+
+pin_project_lite::pin_project! {
+    /// A sink which is only ready to accept items when the ratelimiter has
+    /// tokens available. Created by [`RatelimitSinkExt::ratelimit_sink`] and
+    /// [`RatelimitSinkExt::ratelimit_sink_with`].
+    #[must_use = "sinks do nothing unless polled"]
+    pub struct RatelimitedSink<S, L, F> {
+        cost: F,
+        debt: u64,
+        ratelimiter: L,
+        reserved: bool,
+        #[pin]
+        sink: S,
+        waiter: Waiter,
+    }
+}
+
+impl<S, L, F> RatelimitedSink<S, L, F> {
+    /// Returns a reference to the inner sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Consumes this adapter, returning the inner sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S, L, F, Item> Sink<Item> for RatelimitedSink<S, L, F>
+where
+    S: Sink<Item>,
+    L: Borrow<Ratelimiter>,
+    F: FnMut(&Item) -> u64,
+{
+    type Error = SinkError<S::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        let ratelimiter: &Ratelimiter = (*this.ratelimiter).borrow();
+
+        if !*this.reserved {
+            // pay for the previous item and reserve a token for the next one
+            let n = this.debt.saturating_add(1).min(ratelimiter.max_tokens());
+
+            if !ready!(poll_wait(ratelimiter, cx, this.waiter, n)) {
+                return Poll::Ready(Err(SinkError::Closed));
+            }
+
+            *this.debt = 0;
+            *this.reserved = true;
+        }
+
+        this.sink.as_mut().poll_ready(cx).map_err(SinkError::Inner)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        let this = self.project();
+
+        match (this.cost)(&item) {
+            // the reserved token is not needed
+            0 => (*this.ratelimiter).borrow().return_n(1),
+            cost => *this.debt = cost - 1,
+        }
+        *this.reserved = false;
+
+        this.sink.start_send(item).map_err(SinkError::Inner)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_flush(cx).map_err(SinkError::Inner)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_close(cx).map_err(SinkError::Inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::{FutureExt, SinkExt};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ratelimit_sink() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut sink = Vec::new().ratelimit_sink(&ratelimiter);
+
+        let start = Instant::now();
+        block_on(async {
+            for i in 0..3 {
+                sink.send(i).await.unwrap();
+            }
+        });
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(sink.get_ref(), &[0, 1, 2]);
+
+        ratelimiter.close();
+        assert!(matches!(block_on(sink.send(3)), Err(SinkError::Closed)));
+    }
+
+    #[test]
+    fn cost() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+        let mut sink = Vec::new().ratelimit_sink_with(&ratelimiter, |n: &u64| *n);
+
+        block_on(async {
+            sink.send(0).await.unwrap();
+            assert_eq!(ratelimiter.available(), 10);
+
+            sink.send(4).await.unwrap();
+            sink.send(6).await.unwrap();
+        });
+
+        // the cost of the last item is charged before the next is accepted
+        assert_eq!(ratelimiter.available(), 5);
+        assert!(sink.send(1).now_or_never().is_none());
+        assert_eq!(sink.get_ref(), &[0, 4, 6]);
+    }
+}
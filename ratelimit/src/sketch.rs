@@ -0,0 +1,263 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::{Error, TryAcquireError};
+use clocksource::precise::{AtomicInstant, Duration, Instant};
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+
+// the default error bounds, which use about 100KiB of counters
+const DEFAULT_EPSILON: f64 = 0.001;
+const DEFAULT_DELTA: f64 = 0.01;
+
+/// An approximate per-key limiter for key spaces which are too large for a
+/// bucket per key, such as raw URLs or user agents. The count for each key is
+/// held in a count-min sketch, so memory use is fixed regardless of the number
+/// of keys.
+///
+/// Each key may acquire up to `limit` tokens, and the count of every key is
+/// halved at the end of each window. This allows a burst of `limit` tokens
+/// and a sustained rate of about `limit / 2` tokens per window.
+///
+/// Counts are overestimated when keys collide in the sketch, which can deny a
+/// key early but never admits a key over its limit. See
+/// [`SketchRatelimiter::error_bounds`] to trade memory for accuracy. Under
+/// concurrent acquisitions for the same key, the limit may be briefly exceeded.
+///
+/// ```
+/// use ratelimit::SketchRatelimiter;
+/// use std::time::Duration;
+///
+/// let limiter = SketchRatelimiter::new(10, Duration::from_secs(60));
+///
+/// assert!(limiter.try_acquire_n("/search?q=a", 10).is_ok());
+/// assert!(limiter.try_acquire("/search?q=a").is_err());
+/// assert!(limiter.try_acquire("/search?q=b").is_ok());
+/// ```
+pub struct SketchRatelimiter<S = RandomState> {
+    admitted: AtomicU64,
+    counters: Box<[AtomicU64]>,
+    decayed_at: AtomicInstant,
+    depth: usize,
+    hasher: S,
+    limit: u64,
+    rejected: AtomicU64,
+    width: usize,
+    window: Duration,
+}
+
+impl SketchRatelimiter {
+    /// Create a limiter which allows each key `limit` tokens, with the count
+    /// of every key halved at the end of each `window`.
+    pub fn new(limit: u64, window: core::time::Duration) -> Self {
+        Self::with_hasher(limit, window, RandomState::new())
+    }
+}
+
+impl<S: BuildHasher> SketchRatelimiter<S> {
+    /// Create a limiter which uses the provided hasher for keys. See
+    /// [`SketchRatelimiter::new`].
+    pub fn with_hasher(limit: u64, window: core::time::Duration, hasher: S) -> Self {
+        let (width, depth) = dimensions(DEFAULT_EPSILON, DEFAULT_DELTA);
+        let window = Duration::from_nanos((window.as_nanos() as u64).max(1));
+
+        Self {
+            admitted: AtomicU64::new(0),
+            counters: (0..width * depth).map(|_| AtomicU64::new(0)).collect(),
+            decayed_at: AtomicInstant::now(),
+            depth,
+            hasher,
+            limit,
+            rejected: AtomicU64::new(0),
+            width,
+            window,
+        }
+    }
+
+    /// Size the sketch so that, with probability `1 - delta`, the count of a
+    /// key is overestimated by at most `epsilon` times the total count of all
+    /// keys. Both must be in the range `0.0..1.0` and greater than zero. The
+    /// sketch uses `e / epsilon * ln(1 / delta)` counters of 8 bytes each.
+    ///
+    /// The default is an `epsilon` of 0.001 and a `delta` of 0.01.
+    pub fn error_bounds(mut self, epsilon: f64, delta: f64) -> Result<Self, Error> {
+        if !(epsilon > 0.0 && epsilon < 1.0 && delta > 0.0 && delta < 1.0) {
+            return Err(Error::InvalidSketch);
+        }
+
+        (self.width, self.depth) = dimensions(epsilon, delta);
+        self.counters = (0..self.width * self.depth)
+            .map(|_| AtomicU64::new(0))
+            .collect();
+
+        Ok(self)
+    }
+
+    /// Returns the number of tokens each key may acquire.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the estimated count for the provided key, which is never lower
+    /// than its true count.
+    pub fn estimate<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        self.decay();
+
+        self.slots(key)
+            .map(|slot| self.counters[slot].load(Ordering::Relaxed))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Non-blocking function to acquire `n` tokens for the provided key. When
+    /// the key is over its limit, the error contains the time until the
+    /// counts are next decayed.
+    pub fn try_acquire_n<K: Hash + ?Sized>(&self, key: &K, n: u64) -> Result<(), TryAcquireError> {
+        let estimate = self.estimate(key);
+
+        if estimate.saturating_add(n) > self.limit {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+
+            let next = self.decayed_at.load(Ordering::Acquire) + self.window;
+            let now = Instant::now();
+            let wait = if next > now {
+                (next - now).as_nanos()
+            } else {
+                0
+            };
+
+            return Err(TryAcquireError::Insufficient(
+                core::time::Duration::from_nanos(wait),
+            ));
+        }
+
+        // conservative update, which only raises the counters which are below
+        // the new estimate, reducing the overestimate for other keys
+        let target = estimate + n;
+        for slot in self.slots(key) {
+            self.counters[slot].fetch_max(target, Ordering::Relaxed);
+        }
+
+        self.admitted.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Non-blocking function to acquire a single token for the provided key.
+    /// See [`SketchRatelimiter::try_acquire_n`].
+    pub fn try_acquire<K: Hash + ?Sized>(&self, key: &K) -> Result<(), TryAcquireError> {
+        self.try_acquire_n(key, 1)
+    }
+
+    /// Returns the number of acquisitions which have been admitted.
+    pub fn admitted(&self) -> u64 {
+        self.admitted.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of acquisitions which have been rejected.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Internal function to return the counter for the key in each row of the
+    /// sketch, using double hashing to derive each row's hash from one hash.
+    fn slots<K: Hash + ?Sized>(&self, key: &K) -> impl Iterator<Item = usize> + '_ {
+        let hash = self.hasher.hash_one(key);
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+
+        (0..self.depth).map(move |row| {
+            let column = h1.wrapping_add((row as u64).wrapping_mul(h2)) % self.width as u64;
+            row * self.width + column as usize
+        })
+    }
+
+    /// Internal function to halve the counts once for each window which has
+    /// ended since they were last decayed.
+    fn decay(&self) {
+        let now = Instant::now();
+        let decayed_at = self.decayed_at.load(Ordering::Acquire);
+        if now <= decayed_at {
+            return;
+        }
+
+        let windows = (now - decayed_at).as_nanos() / self.window.as_nanos();
+        if windows == 0 {
+            return;
+        }
+
+        let next = decayed_at + Duration::from_nanos(self.window.as_nanos() * windows);
+        if self
+            .decayed_at
+            .compare_exchange(decayed_at, next, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // another caller is decaying the counts
+            return;
+        }
+
+        let shift = windows.min(64) as u32;
+        for counter in self.counters.iter() {
+            let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.checked_shr(shift).unwrap_or(0))
+            });
+        }
+    }
+}
+
+/// Internal function to return the width and depth of a sketch with the
+/// provided error bounds.
+fn dimensions(epsilon: f64, delta: f64) -> (usize, usize) {
+    let width = (core::f64::consts::E / epsilon).ceil() as usize;
+    let depth = (1.0 / delta).ln().ceil() as usize;
+
+    (width.max(1), depth.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn limit() {
+        let limiter = SketchRatelimiter::new(100, Duration::from_secs(60));
+
+        for key in 0..1_000 {
+            limiter.try_acquire_n(&key, 50).unwrap();
+        }
+        assert_eq!(limiter.estimate(&0), 50);
+
+        assert!(limiter.try_acquire_n(&0, 50).is_ok());
+        match limiter.try_acquire(&0) {
+            Err(TryAcquireError::Insufficient(wait)) => assert!(wait > Duration::from_secs(59)),
+            result => panic!("unexpected result: {result:?}"),
+        }
+        assert_eq!(limiter.admitted(), 1_001);
+        assert_eq!(limiter.rejected(), 1);
+
+        // a small sketch overestimates, but never underestimates
+        let limiter = SketchRatelimiter::new(100, Duration::from_secs(60))
+            .error_bounds(0.5, 0.5)
+            .unwrap();
+        for key in 0..10 {
+            let _ = limiter.try_acquire_n(&key, 10);
+        }
+        assert!((0..10).all(|key| limiter.estimate(&key) >= 10));
+
+        assert!(SketchRatelimiter::new(1, Duration::from_secs(1))
+            .error_bounds(0.0, 0.5)
+            .is_err());
+    }
+
+    #[test]
+    fn decay() {
+        let limiter = SketchRatelimiter::new(8, Duration::from_millis(100));
+
+        limiter.try_acquire_n("a", 8).unwrap();
+        assert!(limiter.try_acquire("a").is_err());
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(limiter.estimate("a"), 4);
+        assert!(limiter.try_acquire_n("a", 4).is_ok());
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(limiter.estimate("a") <= 2);
+    }
+}
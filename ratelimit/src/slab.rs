@@ -0,0 +1,105 @@
+/// Internal type which stores values contiguously and reuses the slots of
+/// removed values, so that a churn of insertions and removals does not
+/// fragment memory. Values are addressed by the index of their slot.
+pub(crate) struct Slab<T> {
+    entries: Vec<Entry<T>>,
+    // the most recently vacated slot, which links to the previous one
+    next_free: Option<usize>,
+}
+
+enum Entry<T> {
+    Occupied(T),
+    Vacant(Option<usize>),
+}
+
+impl<T> Slab<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_free: None,
+        }
+    }
+
+    /// Returns the number of slots, including vacant slots.
+    pub(crate) fn slots(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the number of slots which have been allocated.
+    pub(crate) fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// Returns the size in bytes of the allocated slots.
+    pub(crate) fn bytes(&self) -> usize {
+        self.capacity() * core::mem::size_of::<Entry<T>>()
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        match self.entries.get(index) {
+            Some(Entry::Occupied(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Insert a value into a vacant slot, or a new slot if there are none, and
+    /// return its index.
+    pub(crate) fn insert(&mut self, value: T) -> usize {
+        match self.next_free {
+            Some(index) => {
+                if let Entry::Vacant(next) = self.entries[index] {
+                    self.next_free = next;
+                }
+                self.entries[index] = Entry::Occupied(value);
+                index
+            }
+            None => {
+                self.entries.push(Entry::Occupied(value));
+                self.entries.len() - 1
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> Option<T> {
+        let entry = self.entries.get_mut(index)?;
+        if matches!(entry, Entry::Vacant(_)) {
+            return None;
+        }
+
+        let entry = core::mem::replace(entry, Entry::Vacant(self.next_free));
+        self.next_free = Some(index);
+
+        match entry {
+            Entry::Occupied(value) => Some(value),
+            Entry::Vacant(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slab() {
+        let mut slab = Slab::new();
+
+        assert_eq!(slab.insert("a"), 0);
+        assert_eq!(slab.insert("b"), 1);
+        assert_eq!(slab.insert("c"), 2);
+
+        assert_eq!(slab.remove(1), Some("b"));
+        assert_eq!(slab.remove(1), None);
+        assert_eq!(slab.remove(0), Some("a"));
+
+        // vacant slots are reused, most recently vacated first
+        assert_eq!(slab.insert("d"), 0);
+        assert_eq!(slab.insert("e"), 1);
+        assert_eq!(slab.insert("f"), 3);
+        assert_eq!(slab.slots(), 4);
+
+        assert_eq!(slab.get(1), Some(&"e"));
+        assert_eq!(slab.get(2), Some(&"c"));
+        assert_eq!(slab.get(4), None);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{Ratelimiter, TryAcquireError};
+use core::time::Duration;
+#[cfg(any(feature = "python", feature = "reqwest"))]
+use {crate::KeyedRatelimiter, core::borrow::Borrow, core::hash::Hash};
+
+/// Internal function to return the time to wait before retrying an
+/// acquisition which failed with the provided error. Returns `None` if a retry
+/// cannot succeed because the ratelimiter is denying all requests or is closed.
+///
+/// Note: a zero hint is replaced with the refill interval so that waiters
+/// don't spin while they are waiting for a partial refill.
+pub(crate) fn retry_delay(ratelimiter: &Ratelimiter, error: &TryAcquireError) -> Option<Duration> {
+    match error {
+        TryAcquireError::Insufficient(duration) if !duration.is_zero() => Some(*duration),
+        TryAcquireError::Insufficient(_) | TryAcquireError::Paused => {
+            Some(ratelimiter.scaled_interval())
+        }
+        TryAcquireError::Denied | TryAcquireError::Closed => None,
+    }
+}
+
+/// Internal function to asynchronously wait until `n` tokens have been
+/// acquired, sleeping on the tokio timer between attempts. Returns an error if
+/// the ratelimiter is denying all requests or is closed.
+#[cfg(any(feature = "hyper", feature = "reqwest", feature = "tower"))]
+pub(crate) async fn acquire_n(ratelimiter: &Ratelimiter, n: u64) -> Result<(), TryAcquireError> {
+    let mut ticket = None;
+
+    loop {
+        match ratelimiter.try_acquire_queued(n, &mut ticket) {
+            Ok(()) => return Ok(()),
+            Err(e) => match retry_delay(ratelimiter, &e) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+/// Internal function to asynchronously wait until `n` tokens have been
+/// acquired for the key. The ratelimiter for the key is looked up for each
+/// attempt, so that the lock on the keyed ratelimiter is not held while
+/// sleeping. Costs above the maximum number of tokens are charged as the
+/// maximum.
+#[cfg(feature = "reqwest")]
+pub(crate) async fn acquire_keyed<K, Q>(
+    keyed: &KeyedRatelimiter<K>,
+    key: &Q,
+    n: u64,
+) -> Result<(), TryAcquireError>
+where
+    K: Hash + Eq + Clone + Send + Sync + Borrow<Q>,
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+{
+    let mut ticket = None;
+
+    loop {
+        let delay = {
+            let ratelimiter = keyed.get(key);
+            let n = ratelimiter.charge(n);
+
+            match ratelimiter.try_acquire_queued(n, &mut ticket) {
+                Ok(()) => return Ok(()),
+                Err(e) => match retry_delay(&ratelimiter, &e) {
+                    Some(delay) => delay,
+                    None => return Err(e),
+                },
+            }
+        };
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Internal function to block the calling thread until `n` tokens have been
+/// acquired for the key, waiting between attempts with the wait strategy of
+/// the ratelimiter for the key. As with the asynchronous version, the lock
+/// on the keyed ratelimiter is not held while waiting.
+#[cfg(feature = "python")]
+pub(crate) fn wait_keyed<K, Q>(
+    keyed: &KeyedRatelimiter<K>,
+    key: &Q,
+    n: u64,
+) -> Result<(), TryAcquireError>
+where
+    K: Hash + Eq + Clone + Send + Sync + Borrow<Q>,
+    Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+{
+    let mut ticket = None;
+
+    loop {
+        let (delay, strategy) = {
+            let ratelimiter = keyed.get(key);
+
+            match ratelimiter.try_acquire_queued(n, &mut ticket) {
+                Ok(()) => return Ok(()),
+                Err(e) => match retry_delay(&ratelimiter, &e) {
+                    Some(delay) => (delay, ratelimiter.wait_strategy()),
+                    None => return Err(e),
+                },
+            }
+        };
+
+        strategy.wait(delay);
+    }
+}
+
+/// Internal function to block the calling thread until `n` tokens have been
+/// acquired, waiting between attempts with the wait strategy of the
+/// ratelimiter. Returns an error if the ratelimiter is denying all requests or is
+/// closed.
+pub(crate) fn wait_n(ratelimiter: &Ratelimiter, n: u64) -> Result<(), TryAcquireError> {
+    let mut ticket = None;
+
+    loop {
+        match ratelimiter.try_acquire_queued(n, &mut ticket) {
+            Ok(()) => return Ok(()),
+            Err(e) => match retry_delay(ratelimiter, &e) {
+                Some(delay) => block(ratelimiter, delay),
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+/// Internal function to block the calling thread for up to `delay` before
+/// retrying an acquisition. When many threads are blocked on the ratelimiter,
+/// only one waits for the delay and the others are woken when it refills the
+/// bucket, so that they don't stampede or oversleep.
+pub(crate) fn block(ratelimiter: &Ratelimiter, delay: Duration) {
+    ratelimiter.wakers.block(
+        ratelimiter.wait_strategy(),
+        delay,
+        ratelimiter.scaled_interval(),
+    );
+}
@@ -0,0 +1,146 @@
+use crate::{Mode, Ratelimiter, Stats};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A point-in-time capture of everything about a `Ratelimiter`: its
+/// parameters, the tokens available, the time until the next refill, its
+/// administrative state, and its counters, if they are enabled with
+/// [`Builder::counters`](crate::Builder::counters). This is intended for admin
+/// and debugging endpoints, and can be serialized with the `serde` feature.
+///
+/// Unlike [`State`](crate::State), a snapshot is not used to restore a
+/// ratelimiter.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .counters(true)
+///     .max_tokens(10)
+///     .initial_available(10)
+///     .build()
+///     .unwrap();
+///
+/// ratelimiter.try_acquire_n(4).unwrap();
+///
+/// let snapshot = ratelimiter.inspect();
+/// assert_eq!(snapshot.available, 6);
+/// assert_eq!(snapshot.max_tokens, 10);
+/// assert_eq!(snapshot.stats.issued, 4);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    /// The number of tokens available.
+    pub available: u64,
+    /// The maximum number of tokens which can be held.
+    pub max_tokens: u64,
+    /// The number of tokens added on each refill.
+    pub refill_amount: u64,
+    /// The configured interval between refills, before any scaling.
+    pub refill_interval: core::time::Duration,
+    /// The multiplier applied to the configured rate.
+    pub scale: f64,
+    /// The current effective rate in tokens/second.
+    pub rate: f64,
+    /// The time remaining until the next refill.
+    pub next_refill: core::time::Duration,
+    /// The administrative mode.
+    pub mode: Mode,
+    /// Whether the ratelimiter is paused.
+    pub paused: bool,
+    /// Whether the ratelimiter has been closed.
+    pub closed: bool,
+    /// The counters of the ratelimiter.
+    pub stats: Stats,
+}
+
+impl Ratelimiter {
+    /// Capture everything about the ratelimiter, for example to serve from a
+    /// `/debug/ratelimits` endpoint. See [`Snapshot`] for details.
+    pub fn inspect(&self) -> Snapshot {
+        Snapshot {
+            available: self.available(),
+            max_tokens: self.max_tokens(),
+            refill_amount: self.refill_amount(),
+            refill_interval: self.refill_interval(),
+            scale: self.scale(),
+            rate: self.rate(),
+            next_refill: self.snapshot().next_refill,
+            mode: self.mode(),
+            paused: self.is_paused(),
+            closed: self.is_closed(),
+            stats: self.stats(),
+        }
+    }
+}
+
+impl core::fmt::Debug for Ratelimiter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let snapshot = self.inspect();
+
+        f.debug_struct("Ratelimiter")
+            .field("available", &snapshot.available)
+            .field("max_tokens", &snapshot.max_tokens)
+            .field("refill_amount", &snapshot.refill_amount)
+            .field("refill_interval", &snapshot.refill_interval)
+            .field("scale", &snapshot.scale)
+            .field("rate", &snapshot.rate)
+            .field("next_refill", &snapshot.next_refill)
+            .field("mode", &snapshot.mode)
+            .field("paused", &snapshot.paused)
+            .field("closed", &snapshot.closed)
+            .field("stats", &snapshot.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn inspect() {
+        let rl = Ratelimiter::builder(2, Duration::from_secs(60))
+            .counters(true)
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        rl.try_acquire_n(3).unwrap();
+        rl.set_scale(2.0).unwrap();
+        rl.pause();
+
+        let snapshot = rl.inspect();
+        assert_eq!(snapshot.available, 7);
+        assert_eq!(snapshot.refill_amount, 2);
+        assert_eq!(snapshot.refill_interval, Duration::from_secs(60));
+        assert_eq!(snapshot.scale, 2.0);
+        assert!(snapshot.next_refill <= Duration::from_secs(60));
+        assert_eq!(snapshot.mode, Mode::Enforce);
+        assert!(snapshot.paused);
+        assert!(!snapshot.closed);
+        assert_eq!(snapshot.stats.acquired, 1);
+
+        let debug = format!("{rl:?}");
+        assert!(debug.starts_with("Ratelimiter { available: 7, max_tokens: 10,"));
+        assert!(debug.ends_with(".. }"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(1))
+            .build()
+            .unwrap();
+
+        let json = serde_json::to_value(rl.inspect()).unwrap();
+        assert_eq!(json["max_tokens"], 1);
+        assert_eq!(json["mode"], "Enforce");
+        assert_eq!(json["stats"]["denied"], 0);
+    }
+}
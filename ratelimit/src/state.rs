@@ -0,0 +1,148 @@
+use crate::atomic::Ordering;
+use crate::{Builder, Ratelimiter};
+use clocksource::precise::Instant;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A point-in-time capture of the state of a `Ratelimiter`. This allows the
+/// budget of a ratelimiter to survive a process restart instead of resetting
+/// to the initial configuration.
+///
+/// The time until the next refill is recorded as a relative duration since
+/// instants are not meaningful across processes.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .max_tokens(10)
+///     .initial_available(10)
+///     .build()
+///     .unwrap();
+///
+/// let state = ratelimiter.snapshot();
+///
+/// let restored = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .max_tokens(10)
+///     .restore(state)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(restored.available(), 10);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct State {
+    /// The number of tokens available.
+    pub available: u64,
+    /// The number of tokens which have been dropped because the bucket was
+    /// full.
+    pub dropped: u64,
+    /// The time remaining until the next refill.
+    pub next_refill: core::time::Duration,
+}
+
+impl Builder {
+    /// Restore the state from a previous snapshot. The tokens available and
+    /// dropped count are taken from the snapshot instead of the configured
+    /// initial available, and the first refill happens after the recorded time
+    /// remaining.
+    ///
+    /// If the snapshot holds more tokens than the max tokens, the tokens
+    /// available are reduced to the max tokens.
+    pub fn restore(mut self, state: State) -> Self {
+        self.restore = Some(state);
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Capture the current state of the ratelimiter. See [`State`] for
+    /// details.
+    ///
+    /// If the ratelimiter is paused, the time until the next refill is
+    /// measured from when it was paused.
+    pub fn snapshot(&self) -> State {
+        let now = if self.is_paused() {
+            self.paused_at.load(Ordering::Acquire)
+        } else {
+            Instant::now()
+        };
+
+        let refill_at = self.refill_at.load(Ordering::Acquire);
+
+        let next_refill = if refill_at > now {
+            core::time::Duration::from_nanos((refill_at - now).as_nanos())
+        } else {
+            core::time::Duration::ZERO
+        };
+
+        State {
+            available: self.available(),
+            dropped: self.dropped(),
+            next_refill,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn snapshot() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(7)
+            .build()
+            .unwrap();
+
+        let state = rl.snapshot();
+        assert_eq!(state.available, 7);
+        assert_eq!(state.dropped, 0);
+        assert!(state.next_refill <= Duration::from_secs(60));
+        assert!(state.next_refill > Duration::from_secs(59));
+
+        let restored = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .restore(State {
+                available: 3,
+                dropped: 5,
+                next_refill: Duration::from_secs(30),
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(restored.available(), 3);
+        assert_eq!(restored.dropped(), 5);
+        let next_refill = restored.snapshot().next_refill;
+        assert!(next_refill <= Duration::from_secs(30));
+        assert!(next_refill > Duration::from_secs(29));
+
+        // tokens beyond the max tokens are not restored
+        let restored = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(2)
+            .restore(state)
+            .build()
+            .unwrap();
+
+        assert_eq!(restored.available(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let state = State {
+            available: 3,
+            dropped: 5,
+            next_refill: Duration::from_millis(1500),
+        };
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<State>(&json).unwrap(), state);
+    }
+}
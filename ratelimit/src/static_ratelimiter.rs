@@ -0,0 +1,184 @@
+#[cfg(not(ratelimit_loom))]
+use crate::atomic::{AtomicU64, Ordering};
+// loom atomics cannot be created in a const context, so the static ratelimiter
+// keeps the core atomics when the concurrency model tests are built
+use crate::Clock;
+#[cfg(ratelimit_loom)]
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+// the refill time before the first use, when the clock is not yet known
+const UNSTARTED: u64 = 0;
+
+/// A ratelimiter which is configured at compile time and can be initialized
+/// in a `static`, without allocation. It adds `AMOUNT` tokens every
+/// `INTERVAL_NS` nanoseconds, up to `CAP` tokens. This is available without
+/// the default `std` feature.
+///
+/// The clock is provided with each acquisition, and the first acquisition
+/// starts the refills. The ratelimiter uses atomics, so it may be shared
+/// between threads and interrupt handlers. On targets without 64bit atomics,
+/// enable the `portable-atomic` feature.
+///
+/// The configuration is checked at compile time: `AMOUNT` and `INTERVAL_NS`
+/// must be non-zero and `CAP` must be at least `AMOUNT`.
+///
+/// ```
+/// use core::time::Duration;
+/// use ratelimit::StaticRatelimiter;
+///
+/// // a radio which may transmit 1 packet every 100ms, with a burst of 4
+/// static RADIO: StaticRatelimiter<1, 100_000_000, 4> =
+///     StaticRatelimiter::new().initial_available(4);
+///
+/// // a clock which would read a hardware timer
+/// let clock = || Duration::from_millis(0);
+///
+/// assert!(RADIO.try_wait_n(&clock, 4).is_ok());
+/// assert_eq!(RADIO.try_wait(&clock), Err(Duration::from_millis(100)));
+/// ```
+pub struct StaticRatelimiter<const AMOUNT: u64, const INTERVAL_NS: u64, const CAP: u64> {
+    available: AtomicU64,
+    refill_at: AtomicU64,
+}
+
+impl<const AMOUNT: u64, const INTERVAL_NS: u64, const CAP: u64>
+    StaticRatelimiter<AMOUNT, INTERVAL_NS, CAP>
+{
+    // evaluated when the ratelimiter is constructed, so that an invalid
+    // configuration fails to compile
+    const VALID: () = assert!(
+        AMOUNT > 0 && INTERVAL_NS > 0 && CAP >= AMOUNT,
+        "the amount and interval must be non-zero and the cap at least the amount"
+    );
+
+    /// Create a ratelimiter which starts with no tokens available.
+    pub const fn new() -> Self {
+        let () = Self::VALID;
+
+        Self {
+            available: AtomicU64::new(0),
+            refill_at: AtomicU64::new(UNSTARTED),
+        }
+    }
+
+    /// Set the number of tokens which are initially available, up to `CAP`.
+    pub const fn initial_available(mut self, tokens: u64) -> Self {
+        self.available = AtomicU64::new(if tokens < CAP { tokens } else { CAP });
+        self
+    }
+
+    /// Returns the number of tokens currently available, after any refills
+    /// which are due.
+    pub fn available(&self, clock: &impl Clock) -> u64 {
+        self.refill(clock);
+        self.available.load(Ordering::Acquire)
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens. On success, the tokens
+    /// have been acquired. On failure, a `Duration` hinting at when the next
+    /// refill would occur is returned.
+    pub fn try_wait_n(&self, clock: &impl Clock, n: u64) -> Result<(), Duration> {
+        let now = self.refill(clock);
+
+        if self
+            .available
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |available| {
+                available.checked_sub(n)
+            })
+            .is_ok()
+        {
+            return Ok(());
+        }
+
+        let refill_at = self.refill_at.load(Ordering::Acquire);
+        Err(Duration::from_nanos(refill_at.saturating_sub(now)))
+    }
+
+    /// Non-blocking function to "wait" for a single token. See
+    /// [`StaticRatelimiter::try_wait_n`].
+    pub fn try_wait(&self, clock: &impl Clock) -> Result<(), Duration> {
+        self.try_wait_n(clock, 1)
+    }
+
+    /// Internal function to add the tokens for any refill intervals which have
+    /// elapsed, starting the refills on first use. Returns the current time in
+    /// nanoseconds.
+    fn refill(&self, clock: &impl Clock) -> u64 {
+        let now = clock.now().as_nanos().min(u64::MAX as u128) as u64;
+
+        loop {
+            let refill_at = self.refill_at.load(Ordering::Acquire);
+
+            if refill_at == UNSTARTED {
+                let next = now.saturating_add(INTERVAL_NS);
+                if self
+                    .refill_at
+                    .compare_exchange(UNSTARTED, next, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                {
+                    return now;
+                }
+                continue;
+            }
+
+            if now < refill_at {
+                return now;
+            }
+
+            let intervals = (now - refill_at) / INTERVAL_NS + 1;
+            let next = refill_at.saturating_add(intervals.saturating_mul(INTERVAL_NS));
+
+            if self
+                .refill_at
+                .compare_exchange(refill_at, next, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                let amount = intervals.saturating_mul(AMOUNT);
+                let _ =
+                    self.available
+                        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |available| {
+                            Some(available.saturating_add(amount).min(CAP))
+                        });
+                return now;
+            }
+        }
+    }
+}
+
+impl<const AMOUNT: u64, const INTERVAL_NS: u64, const CAP: u64> Default
+    for StaticRatelimiter<AMOUNT, INTERVAL_NS, CAP>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TIME: AtomicU64 = AtomicU64::new(0);
+
+    fn clock() -> Duration {
+        Duration::from_millis(TIME.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn refill() {
+        static LIMITER: StaticRatelimiter<2, 10_000_000, 5> = StaticRatelimiter::new();
+
+        // the first use starts the refills
+        TIME.store(1_000, Ordering::Relaxed);
+        assert_eq!(LIMITER.available(&clock), 0);
+        assert_eq!(LIMITER.try_wait(&clock), Err(Duration::from_millis(10)));
+
+        TIME.store(1_025, Ordering::Relaxed);
+        assert_eq!(LIMITER.available(&clock), 4);
+        assert!(LIMITER.try_wait_n(&clock, 3).is_ok());
+        assert_eq!(LIMITER.try_wait_n(&clock, 2), Err(Duration::from_millis(5)));
+
+        TIME.store(2_000, Ordering::Relaxed);
+        assert_eq!(LIMITER.available(&clock), 5);
+    }
+}
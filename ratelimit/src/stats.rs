@@ -0,0 +1,447 @@
+use crate::atomic::{AtomicU64, Ordering};
+use crate::observed::Observed;
+use crate::{Builder, Event, Ratelimiter, TryAcquireError};
+use clocksource::precise::Instant;
+use parking_lot::Mutex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A point-in-time capture of the counters of a `Ratelimiter`. The counters
+/// are only kept if they are enabled with [`Builder::counters`], otherwise
+/// they are always zero, except for the dropped tokens. They start from zero
+/// when the ratelimiter is built and are only reset by
+/// [`Ratelimiter::reset_counters`]. They wrap around on overflow, so rates
+/// should be computed from the wrapping difference between two captures.
+///
+/// ```
+/// use ratelimit::Ratelimiter;
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_secs(1))
+///     .counters(true)
+///     .max_tokens(10)
+///     .initial_available(10)
+///     .build()
+///     .unwrap();
+///
+/// ratelimiter.try_acquire_n(4).unwrap();
+/// assert!(ratelimiter.try_acquire_n(7).is_err());
+/// ratelimiter.return_n(2);
+///
+/// let stats = ratelimiter.stats();
+/// assert_eq!(stats.acquired, 1);
+/// assert_eq!(stats.denied, 1);
+/// assert_eq!(stats.issued, 4);
+/// assert_eq!(stats.returned, 2);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Stats {
+    /// The number of successful acquisitions.
+    pub acquired: u64,
+    /// The number of attempts to acquire tokens which failed, for any reason.
+    pub denied: u64,
+    /// The total number of tokens which have been dropped, for any cause. See
+    /// [`Dropped`] for the breakdown.
+    pub dropped: u64,
+    /// The total number of tokens handed out by successful acquisitions.
+    pub issued: u64,
+    /// The number of tokens which have been returned to the bucket.
+    pub returned: u64,
+}
+
+/// The number of tokens which have been dropped by a `Ratelimiter`, broken
+/// down by cause. This shows whether a larger max tokens would help: only
+/// tokens dropped because of overflow would have been kept by a larger bucket.
+///
+/// ```
+/// use ratelimit::{CarryOver, Ratelimiter};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(4, Duration::from_millis(10))
+///     .counters(true)
+///     .max_tokens(8)
+///     .initial_available(6)
+///     .carry_over(CarryOver::Capped(1))
+///     .build()
+///     .unwrap();
+///
+/// std::thread::sleep(Duration::from_millis(10));
+/// ratelimiter.try_acquire().unwrap();
+///
+/// // only one of the six unused tokens was carried over
+/// assert_eq!(ratelimiter.dropped_by_cause().expired, 5);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Dropped {
+    /// Tokens from a refill or a return which did not fit because the bucket
+    /// was at the max tokens, or which were in excess of the max tokens when
+    /// it was lowered.
+    pub overflow: u64,
+    /// Tokens accumulated while idle which were discarded when the warm-up
+    /// restarted, so that they were not released as a burst. See
+    /// [`Builder::warmup`](crate::Builder::warmup).
+    pub catch_up: u64,
+    /// Unused tokens which expired at a refill because of the
+    /// [`CarryOver`](crate::CarryOver) policy.
+    pub expired: u64,
+}
+
+impl Dropped {
+    /// Returns the number of tokens dropped for all causes.
+    pub fn total(&self) -> u64 {
+        self.overflow
+            .wrapping_add(self.catch_up)
+            .wrapping_add(self.expired)
+    }
+}
+
+/// Internal type for the causes of dropped tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DropCause {
+    Overflow,
+    CatchUp,
+    Expired,
+}
+
+/// Internal type which holds the counters for a ratelimiter, along with the
+/// achieved-rate estimator which is derived from them.
+#[derive(Debug)]
+pub(crate) struct Counters {
+    acquired: AtomicU64,
+    catch_up: AtomicU64,
+    denied: AtomicU64,
+    expired: AtomicU64,
+    issued: AtomicU64,
+    pub(crate) observed: Mutex<Observed>,
+    overflow: AtomicU64,
+    returned: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn new(window: core::time::Duration, created: Instant) -> Self {
+        Self {
+            acquired: AtomicU64::new(0),
+            catch_up: AtomicU64::new(0),
+            denied: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+            issued: AtomicU64::new(0),
+            observed: Observed::new(window, created).into(),
+            overflow: AtomicU64::new(0),
+            returned: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Builder {
+    /// Keep counters of the acquisitions, denials, and the tokens which are
+    /// issued, returned, and dropped by cause. See [`Stats`] and
+    /// [`Dropped`]. The counters are also needed for
+    /// [`Ratelimiter::observed_rate`] and [`Ratelimiter::utilization`].
+    ///
+    /// Each acquisition then updates shared counters, which adds contention
+    /// when many threads use the same ratelimiter.
+    ///
+    /// The default is that the counters are disabled and read as zero. The
+    /// total dropped tokens, [`Ratelimiter::dropped`], are always counted.
+    pub fn counters(mut self, enabled: bool) -> Self {
+        self.counters = enabled;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to return the counters, if they are enabled.
+    pub(crate) fn counters(&self) -> Option<&Counters> {
+        self.extensions()?.counters.as_ref()
+    }
+
+    /// Internal function to load one of the counters, which is zero if they
+    /// are disabled.
+    fn load(&self, counter: impl FnOnce(&Counters) -> &AtomicU64) -> u64 {
+        self.counters()
+            .map(|counters| counter(counters).load(Ordering::Relaxed))
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of successful acquisitions. This is zero unless the
+    /// counters are enabled, see [`Builder::counters`].
+    pub fn acquired(&self) -> u64 {
+        self.load(|counters| &counters.acquired)
+    }
+
+    /// Returns the number of attempts to acquire tokens which failed. This
+    /// includes attempts which failed because the ratelimiter was paused,
+    /// denying all requests, or closed. This is zero unless the counters are
+    /// enabled, see [`Builder::counters`].
+    pub fn denied(&self) -> u64 {
+        self.load(|counters| &counters.denied)
+    }
+
+    /// Returns the total number of tokens handed out by successful
+    /// acquisitions. This is zero unless the counters are enabled, see
+    /// [`Builder::counters`].
+    pub fn issued(&self) -> u64 {
+        self.load(|counters| &counters.issued)
+    }
+
+    /// Returns the number of tokens which have been returned with
+    /// [`Ratelimiter::return_n`]. This is zero unless the counters are
+    /// enabled, see [`Builder::counters`].
+    pub fn returned(&self) -> u64 {
+        self.load(|counters| &counters.returned)
+    }
+
+    /// Returns the number of tokens which have been dropped, broken down by
+    /// cause. See [`Dropped`] for details. This is zero unless the counters are
+    /// enabled, see [`Builder::counters`].
+    ///
+    /// Note: unlike [`Ratelimiter::dropped`], this does not include the
+    /// dropped count restored from a [`State`](crate::State).
+    pub fn dropped_by_cause(&self) -> Dropped {
+        Dropped {
+            overflow: self.load(|counters| &counters.overflow),
+            catch_up: self.load(|counters| &counters.catch_up),
+            expired: self.load(|counters| &counters.expired),
+        }
+    }
+
+    /// Reset all of the counters of the ratelimiter to zero, for example at the
+    /// start of each reporting period. This includes the counters in
+    /// [`Stats`], the dropped tokens by cause, and the early drops. The tokens
+    /// available and the parameters are unchanged.
+    pub fn reset_counters(&self) {
+        self.dropped.store(0, Ordering::Relaxed);
+
+        if let Some(extensions) = self.extensions() {
+            extensions.early_dropped.store(0, Ordering::Relaxed);
+        }
+
+        if let Some(counters) = self.counters() {
+            for counter in [
+                &counters.acquired,
+                &counters.catch_up,
+                &counters.denied,
+                &counters.expired,
+                &counters.issued,
+                &counters.overflow,
+                &counters.returned,
+            ] {
+                counter.store(0, Ordering::Relaxed);
+            }
+
+            self.reset_observed();
+        }
+    }
+
+    /// Capture the counters of the ratelimiter. See [`Stats`] for details.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            acquired: self.acquired(),
+            denied: self.denied(),
+            dropped: self.dropped(),
+            issued: self.issued(),
+            returned: self.returned(),
+        }
+    }
+
+    /// Internal function to count the outcome of an attempt to acquire `n`
+    /// tokens.
+    pub(crate) fn record(
+        &self,
+        n: u64,
+        result: Result<(), TryAcquireError>,
+    ) -> Result<(), TryAcquireError> {
+        // without any optional features there is nothing to record
+        let Some(extensions) = self.extensions() else {
+            return result;
+        };
+
+        if let Some(counters) = &extensions.counters {
+            if result.is_ok() {
+                counters.acquired.fetch_add(1, Ordering::Relaxed);
+                counters.issued.fetch_add(n, Ordering::Relaxed);
+            } else {
+                counters.denied.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if let Err(TryAcquireError::Insufficient(_)) = result {
+            self.offend();
+        }
+
+        #[cfg(feature = "metrics")]
+        self.emit_acquire(n, result.is_ok());
+
+        if let Some(observer) = &extensions.observer {
+            match &result {
+                Ok(()) => observer.on_acquire(n),
+                Err(e) => observer.on_deny(n, e),
+            }
+        }
+
+        if let Err(e) = result {
+            self.send_event(|| Event::Denied {
+                tokens: n,
+                shortfall: match e {
+                    TryAcquireError::Insufficient(_) => n.saturating_sub(self.available()),
+                    _ => 0,
+                },
+            });
+        }
+
+        #[cfg(feature = "histogram")]
+        match result {
+            Ok(()) => {
+                self.record_latency(core::time::Duration::ZERO);
+                self.record_heatmap(Some(core::time::Duration::ZERO));
+            }
+            Err(TryAcquireError::Insufficient(wait)) => {
+                self.record_latency(wait);
+                self.record_heatmap(Some(wait));
+            }
+            Err(_) => self.record_heatmap(None),
+        }
+
+        result
+    }
+
+    /// Internal function to count tokens which were returned.
+    pub(crate) fn record_returned(&self, n: u64) {
+        if let Some(counters) = self.counters() {
+            counters.returned.fetch_add(n, Ordering::Relaxed);
+        }
+
+        #[cfg(feature = "metrics")]
+        self.emit_returned(n);
+    }
+
+    /// Internal function to count tokens which were dropped.
+    pub(crate) fn record_dropped(&self, cause: DropCause, n: u64) {
+        if n == 0 {
+            return;
+        }
+
+        self.dropped.fetch_add(n, Ordering::Relaxed);
+
+        let Some(extensions) = self.extensions() else {
+            return;
+        };
+
+        if let Some(counters) = &extensions.counters {
+            let counter = match cause {
+                DropCause::Overflow => &counters.overflow,
+                DropCause::CatchUp => &counters.catch_up,
+                DropCause::Expired => &counters.expired,
+            };
+
+            counter.fetch_add(n, Ordering::Relaxed);
+        }
+
+        #[cfg(feature = "metrics")]
+        self.emit_dropped(n);
+
+        if let Some(observer) = &extensions.observer {
+            observer.on_drop(n);
+        }
+
+        self.send_event(|| Event::Dropped { tokens: n });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn stats() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .counters(true)
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+        assert_eq!(rl.stats(), Stats::default());
+
+        rl.try_acquire_n(3).unwrap();
+        rl.try_wait().unwrap();
+        assert!(rl.try_acquire_n(7).is_err());
+        // one of the returned tokens does not fit in the bucket
+        rl.return_n(5);
+
+        rl.pause();
+        assert!(rl.try_acquire().is_err());
+
+        assert_eq!(
+            rl.stats(),
+            Stats {
+                acquired: 2,
+                denied: 2,
+                dropped: 1,
+                issued: 4,
+                returned: 5,
+            }
+        );
+    }
+
+    // test that the counters are only kept when they are enabled
+    #[test]
+    fn disabled() {
+        let rl = Ratelimiter::builder(1, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        // a plain ratelimiter does not allocate any optional features
+        assert!(rl.extensions.is_none());
+
+        rl.try_acquire_n(3).unwrap();
+        assert!(rl.try_acquire_n(8).is_err());
+        rl.return_n(5);
+
+        // the total dropped tokens are always counted
+        assert_eq!(
+            rl.stats(),
+            Stats {
+                dropped: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(rl.dropped_by_cause(), Dropped::default());
+    }
+
+    #[test]
+    fn dropped_by_cause() {
+        let rl = Ratelimiter::builder(4, Duration::from_millis(10))
+            .counters(true)
+            .max_tokens(4)
+            .initial_available(3)
+            .build()
+            .unwrap();
+
+        std::thread::sleep(Duration::from_millis(10));
+        rl.try_acquire().unwrap();
+        assert!(rl.dropped_by_cause().overflow >= 3);
+        assert_eq!(rl.dropped_by_cause().total(), rl.dropped());
+
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .counters(true)
+            .max_tokens(100)
+            .warmup(Duration::from_millis(20))
+            .build()
+            .unwrap();
+
+        std::thread::sleep(Duration::from_millis(30));
+        let _ = rl.try_acquire();
+        assert!(rl.dropped_by_cause().catch_up > 10);
+        assert_eq!(rl.dropped_by_cause().overflow, 0);
+
+        rl.reset_counters();
+        assert_eq!(rl.dropped_by_cause(), Dropped::default());
+        assert_eq!(rl.stats(), Stats::default());
+    }
+}
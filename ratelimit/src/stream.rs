@@ -0,0 +1,323 @@
+//! Adapters which pace asynchronous streams with a ratelimiter.
+//!
+//! These are runtime agnostic, using [`futures_timer`] to wait for tokens.
+//! A [`Ticker`] can also be used to drive periodic work from the tokens of a
+//! shared ratelimiter.
+//!
+//! ```
+//! use futures::StreamExt;
+//! use ratelimit::stream::RatelimitStreamExt;
+//! use ratelimit::Ratelimiter;
+//! use std::time::Duration;
+//!
+//! let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+//!     .build()
+//!     .unwrap();
+//!
+//! // each chunk costs one token per byte
+//! let chunks = futures::stream::iter(vec![vec![0_u8; 2], vec![0_u8; 1]])
+//!     .ratelimit_with(&ratelimiter, |chunk| chunk.len() as u64);
+//!
+//! let chunks: Vec<Vec<u8>> = futures::executor::block_on(chunks.collect());
+//! assert_eq!(chunks.len(), 2);
+//! ```
+
+pub use crate::iter::UnitCost;
+
+use crate::{Ratelimiter, TryAcquireError, Waiter};
+use clocksource::precise::Instant;
+use core::borrow::Borrow;
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures_core::Stream;
+
+/// An extension trait which adds ratelimiting adapters to any [`Stream`].
+pub trait RatelimitStreamExt: Stream + Sized {
+    /// Yield items no faster than the rate of the ratelimiter, charging a
+    /// single token for each item.
+    fn ratelimit<L: Borrow<Ratelimiter>>(
+        self,
+        ratelimiter: L,
+    ) -> Ratelimited<Self, L, UnitCost<Self::Item>> {
+        self.ratelimit_with(ratelimiter, one::<Self::Item>)
+    }
+
+    /// Yield items no faster than the rate of the ratelimiter, charging the
+    /// number of tokens returned by the cost function for each item.
+    ///
+    /// Note: costs above the maximum number of tokens in the ratelimiter are
+    /// charged as the maximum, since they could never be acquired otherwise.
+    fn ratelimit_with<L, F>(self, ratelimiter: L, cost: F) -> Ratelimited<Self, L, F>
+    where
+        L: Borrow<Ratelimiter>,
+        F: FnMut(&Self::Item) -> u64,
+    {
+        Ratelimited {
+            cost,
+            pending: None,
+            ratelimiter,
+            stream: self,
+            waiter: Waiter::new(),
+        }
+    }
+}
+
+impl<S: Stream> RatelimitStreamExt for S {}
+
+/// Internal function for the cost of an item when every item is one token.
+fn one<T>(_: &T) -> u64 {
+    1
+}
+
+pin_project_lite::pin_project! {
+    /// A stream which yields the items of the inner stream no faster than the
+    /// rate of a ratelimiter. Created by [`RatelimitStreamExt::ratelimit`] and
+    /// [`RatelimitStreamExt::ratelimit_with`].
+    ///
+    /// The stream ends early if the ratelimiter is closed.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Ratelimited<S: Stream, L, F> {
+        cost: F,
+        pending: Option<(S::Item, u64)>,
+        ratelimiter: L,
+        #[pin]
+        stream: S,
+        waiter: Waiter,
+    }
+}
+
+impl<S: Stream, L, F> Ratelimited<S, L, F> {
+    /// Returns a reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Consumes this adapter, returning the inner stream. An item which was
+    /// waiting for tokens is lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, L, F> Stream for Ratelimited<S, L, F>
+where
+    S: Stream,
+    L: Borrow<Ratelimiter>,
+    F: FnMut(&S::Item) -> u64,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        let cost = match this.pending {
+            Some((_, cost)) => *cost,
+            None => match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(item) => {
+                    let cost = (this.cost)(&item);
+                    *this.pending = Some((item, cost));
+                    cost
+                }
+                None => return Poll::Ready(None),
+            },
+        };
+
+        let ratelimiter: &Ratelimiter = (*this.ratelimiter).borrow();
+
+        if cost != 0 {
+            let cost = cost.min(ratelimiter.max_tokens());
+            if !ready!(poll_wait(ratelimiter, cx, this.waiter, cost)) {
+                *this.pending = None;
+                return Poll::Ready(None);
+            }
+        }
+
+        Poll::Ready(this.pending.take().map(|(item, _)| item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = self.pending.is_some() as usize;
+        let (lower, upper) = self.stream.size_hint();
+
+        (
+            lower.saturating_add(pending),
+            upper.and_then(|upper| upper.checked_add(pending)),
+        )
+    }
+}
+
+/// A single token acquired by a [`Ticker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permit {
+    acquired: Instant,
+}
+
+impl Permit {
+    /// Returns the time at which the token was acquired.
+    pub fn acquired_at(&self) -> Instant {
+        self.acquired
+    }
+}
+
+impl Ratelimiter {
+    /// Returns a stream which yields a [`Permit`] each time a token is
+    /// acquired. See [`Ticker`] for details.
+    pub fn ticker(&self) -> Ticker<&Self> {
+        Ticker::new(self)
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A stream which yields a [`Permit`] each time a single token is acquired
+    /// from a ratelimiter. Created by [`Ratelimiter::ticker`] or, to own the
+    /// ratelimiter (for instance through an `Arc`), by [`Ticker::new`].
+    ///
+    /// This is similar to an interval timer, except that available tokens are
+    /// yielded immediately as a burst and every permit counts against the
+    /// same bucket as other users of the ratelimiter.
+    ///
+    /// The stream ends if the ratelimiter is closed.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Ticker<L> {
+        ratelimiter: L,
+        waiter: Waiter,
+    }
+}
+
+impl<L: Borrow<Ratelimiter>> Ticker<L> {
+    /// Create a ticker which acquires tokens from the provided ratelimiter.
+    pub fn new(ratelimiter: L) -> Self {
+        Self {
+            ratelimiter,
+            waiter: Waiter::new(),
+        }
+    }
+
+    /// Returns the ratelimiter which tokens are acquired from.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+}
+
+impl<L: Borrow<Ratelimiter>> Stream for Ticker<L> {
+    type Item = Permit;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let ratelimiter: &Ratelimiter = (*this.ratelimiter).borrow();
+
+        if !ready!(poll_wait(ratelimiter, cx, this.waiter, 1)) {
+            return Poll::Ready(None);
+        }
+
+        Poll::Ready(Some(Permit {
+            acquired: Instant::now(),
+        }))
+    }
+}
+
+/// Internal function to poll for `n` tokens. A ratelimiter which is denying
+/// requests may resume, so we wait as if it were paused. Returns `false` if
+/// the ratelimiter is closed.
+pub(crate) fn poll_wait(
+    ratelimiter: &Ratelimiter,
+    cx: &mut Context<'_>,
+    waiter: &mut Waiter,
+    n: u64,
+) -> Poll<bool> {
+    loop {
+        match ready!(ratelimiter.poll_acquire(cx, waiter, n)) {
+            Ok(()) => return Poll::Ready(true),
+            Err(TryAcquireError::Closed) => return Poll::Ready(false),
+            Err(_) => waiter.wait(ratelimiter.scaled_interval()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ratelimit() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+
+        let start = Instant::now();
+        let items: Vec<u64> = block_on(
+            futures::stream::iter(0..3)
+                .ratelimit(ratelimiter.clone())
+                .collect(),
+        );
+        assert_eq!(items, vec![0, 1, 2]);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // the stream ends when the ratelimiter is closed
+        ratelimiter.close();
+        let items: Vec<u64> = block_on(
+            futures::stream::iter(0..3)
+                .ratelimit(&*ratelimiter)
+                .collect(),
+        );
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn cost() {
+        let ratelimiter = Ratelimiter::builder(10, Duration::from_secs(60))
+            .max_tokens(10)
+            .initial_available(10)
+            .build()
+            .unwrap();
+
+        // zero cost items are free, and costs above the burst are clamped
+        let mut stream = futures::stream::iter([4, 0, 20, 1]).ratelimit_with(&ratelimiter, |n| *n);
+        assert_eq!(stream.size_hint(), (4, Some(4)));
+
+        block_on(async {
+            assert_eq!(stream.next().await, Some(4));
+            assert_eq!(stream.next().await, Some(0));
+            assert_eq!(ratelimiter.available(), 6);
+        });
+
+        assert!(futures::FutureExt::now_or_never(stream.next()).is_none());
+        assert_eq!(stream.size_hint(), (2, Some(2)));
+    }
+
+    #[test]
+    fn ticker() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .max_tokens(2)
+                .initial_available(2)
+                .build()
+                .unwrap(),
+        );
+
+        // the burst is yielded immediately, then permits follow the rate
+        let start = Instant::now();
+        let permits: Vec<Permit> = block_on(ratelimiter.ticker().take(4).collect());
+        assert_eq!(permits.len(), 4);
+        assert!(permits
+            .windows(2)
+            .all(|p| p[0].acquired_at() <= p[1].acquired_at()));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        // permits share the bucket with other users of the ratelimiter
+        let mut ticker = Ticker::new(ratelimiter.clone());
+        ratelimiter.set_available(1).unwrap();
+        assert!(ratelimiter.try_acquire().is_ok());
+        assert!(futures::FutureExt::now_or_never(ticker.next()).is_none());
+
+        // the ticker ends when the ratelimiter is closed
+        ratelimiter.close();
+        assert_eq!(block_on(ticker.next()), None);
+    }
+}
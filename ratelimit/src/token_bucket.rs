@@ -0,0 +1,189 @@
+use core::time::Duration;
+
+/// A monotonic source of time for a [`TokenBucket`], such as a hardware timer
+/// on targets without `std`.
+///
+/// Closures which return the time elapsed since a fixed point, such as boot,
+/// implement this trait.
+pub trait Clock {
+    /// Returns the time elapsed since a fixed point. The time must never go
+    /// backwards.
+    fn now(&self) -> Duration;
+}
+
+impl<F: Fn() -> Duration> Clock for F {
+    fn now(&self) -> Duration {
+        self()
+    }
+}
+
+/// The core token bucket algorithm of the [`Ratelimiter`], which only
+/// requires `core` and a user-supplied [`Clock`]. This is available without
+/// the default `std` feature, for example to limit the duty cycle of a radio or
+/// an actuator in firmware.
+///
+/// Unlike the `Ratelimiter`, the bucket is not shared between threads and does
+/// not use atomics, so it can be used on targets without 64-bit atomics. Wrap
+/// it in a critical section or mutex to share it.
+///
+/// ```
+/// use core::time::Duration;
+/// use ratelimit::TokenBucket;
+///
+/// // a clock which would read a hardware timer
+/// let clock = || Duration::from_millis(0);
+///
+/// // allows 10 tokens every second, with a burst of 10 tokens
+/// let mut bucket =
+///     TokenBucket::new(clock, 10, Duration::from_secs(1), 10).initial_available(10);
+///
+/// assert!(bucket.try_wait_n(10).is_ok());
+/// assert_eq!(bucket.try_wait(), Err(Duration::from_secs(1)));
+/// ```
+///
+/// [`Ratelimiter`]: crate::Ratelimiter
+pub struct TokenBucket<C> {
+    amount: u64,
+    available: u64,
+    clock: C,
+    interval: u64,
+    max_tokens: u64,
+    refill_at: u64,
+}
+
+impl<C: Clock> TokenBucket<C> {
+    /// Create a bucket which adds `amount` tokens each `interval`, up to
+    /// `max_tokens`. The bucket starts empty.
+    ///
+    /// As there is no error type without `std`, the parameters are clamped
+    /// rather than rejected: the amount and interval are at least one token
+    /// and one nanosecond, and `max_tokens` is at least the amount.
+    pub fn new(clock: C, amount: u64, interval: Duration, max_tokens: u64) -> Self {
+        let amount = amount.max(1);
+        let interval = (interval.as_nanos() as u64).max(1);
+        let refill_at = nanos(clock.now()).saturating_add(interval);
+
+        Self {
+            amount,
+            available: 0,
+            clock,
+            interval,
+            max_tokens: max_tokens.max(amount),
+            refill_at,
+        }
+    }
+
+    /// Set the number of tokens which are initially available, up to
+    /// `max_tokens`.
+    pub fn initial_available(mut self, tokens: u64) -> Self {
+        self.available = tokens.min(self.max_tokens);
+        self
+    }
+
+    /// Returns the number of tokens added each refill interval.
+    pub fn refill_amount(&self) -> u64 {
+        self.amount
+    }
+
+    /// Returns the time between refills.
+    pub fn refill_interval(&self) -> Duration {
+        Duration::from_nanos(self.interval)
+    }
+
+    /// Returns the most tokens which may be available.
+    pub fn max_tokens(&self) -> u64 {
+        self.max_tokens
+    }
+
+    /// Returns the number of tokens currently available, after any refills
+    /// which are due.
+    pub fn available(&mut self) -> u64 {
+        self.refill();
+        self.available
+    }
+
+    /// Non-blocking function to "wait" for `n` tokens. On success, the tokens
+    /// have been acquired. On failure, a `Duration` hinting at when the next
+    /// refill would occur is returned.
+    pub fn try_wait_n(&mut self, n: u64) -> Result<(), Duration> {
+        let now = self.refill();
+
+        if n > self.available {
+            return Err(Duration::from_nanos(self.refill_at.saturating_sub(now)));
+        }
+
+        self.available -= n;
+        Ok(())
+    }
+
+    /// Non-blocking function to "wait" for a single token. See
+    /// [`TokenBucket::try_wait_n`].
+    pub fn try_wait(&mut self) -> Result<(), Duration> {
+        self.try_wait_n(1)
+    }
+
+    /// Internal function to add the tokens for any refill intervals which have
+    /// elapsed. Returns the current time in nanoseconds.
+    fn refill(&mut self) -> u64 {
+        let now = nanos(self.clock.now());
+
+        if now >= self.refill_at {
+            let intervals = (now - self.refill_at) / self.interval + 1;
+
+            self.available = intervals
+                .saturating_mul(self.amount)
+                .saturating_add(self.available)
+                .min(self.max_tokens);
+            self.refill_at = self
+                .refill_at
+                .saturating_add(intervals.saturating_mul(self.interval));
+        }
+
+        now
+    }
+}
+
+/// Internal function to convert a clock reading to nanoseconds.
+fn nanos(time: Duration) -> u64 {
+    time.as_nanos().min(u64::MAX as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn refill() {
+        let time = Cell::new(Duration::ZERO);
+        let mut bucket = TokenBucket::new(|| time.get(), 2, Duration::from_millis(10), 5);
+
+        assert_eq!(bucket.available(), 0);
+        assert_eq!(bucket.try_wait(), Err(Duration::from_millis(10)));
+
+        time.set(Duration::from_millis(4));
+        assert_eq!(bucket.try_wait(), Err(Duration::from_millis(6)));
+
+        // each elapsed interval adds tokens, up to the maximum
+        time.set(Duration::from_millis(25));
+        assert_eq!(bucket.available(), 4);
+        assert!(bucket.try_wait_n(3).is_ok());
+        assert_eq!(bucket.try_wait_n(2), Err(Duration::from_millis(5)));
+
+        time.set(Duration::from_secs(1));
+        assert_eq!(bucket.available(), 5);
+        assert!(bucket.try_wait_n(5).is_ok());
+        assert_eq!(bucket.try_wait(), Err(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn clamped() {
+        let mut bucket =
+            TokenBucket::new(|| Duration::ZERO, 0, Duration::ZERO, 0).initial_available(10);
+
+        assert_eq!(bucket.refill_amount(), 1);
+        assert_eq!(bucket.refill_interval(), Duration::from_nanos(1));
+        assert_eq!(bucket.max_tokens(), 1);
+        assert_eq!(bucket.available(), 1);
+    }
+}
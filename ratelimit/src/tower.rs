@@ -0,0 +1,284 @@
+//! Middleware which applies a ratelimiter to a [`tower`](::tower) service.
+//!
+//! Unlike the ratelimit middleware in `tower` itself, the ratelimiter can be
+//! shared between several services and its rate can be changed at runtime.
+//!
+//! ```
+//! use ratelimit::tower::RateLimitLayer;
+//! use ratelimit::Ratelimiter;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use tower::Layer;
+//!
+//! # struct Svc;
+//! # impl tower::Service<()> for Svc {
+//! #     type Response = ();
+//! #     type Error = std::convert::Infallible;
+//! #     type Future = std::future::Ready<Result<(), Self::Error>>;
+//! #     fn poll_ready(&mut self, _: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+//! #         std::task::Poll::Ready(Ok(()))
+//! #     }
+//! #     fn call(&mut self, _: ()) -> Self::Future { std::future::ready(Ok(())) }
+//! # }
+//! let ratelimiter = Arc::new(Ratelimiter::builder(1, Duration::from_millis(10)).build().unwrap());
+//!
+//! // requests beyond the rate are rejected immediately
+//! let service = RateLimitLayer::shed(ratelimiter.clone()).layer(Svc);
+//!
+//! // the rate can be changed while the service is running
+//! ratelimiter.set_refill_interval(Duration::from_millis(1)).unwrap();
+//! ```
+//!
+//! Each call costs a single token by default. A [`CostExtractor`] can be
+//! provided to charge different requests different amounts, for instance with
+//! [`ItemCost`](crate::ItemCost) for requests which implement
+//! [`Cost`](crate::Cost).
+
+use crate::{CostExtractor, Ratelimiter, UnitCost};
+use ::tower::{BoxError, Layer, Service};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::sync::Arc;
+
+type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+/// A [`Layer`] which wraps services with a [`RateLimit`].
+#[derive(Clone)]
+pub struct RateLimitLayer<C = UnitCost> {
+    cost: C,
+    ratelimiter: Arc<Ratelimiter>,
+    shed: bool,
+}
+
+impl RateLimitLayer {
+    /// Create a layer where each call waits until a token is available.
+    pub fn wait(ratelimiter: Arc<Ratelimiter>) -> Self {
+        Self {
+            cost: UnitCost,
+            ratelimiter,
+            shed: false,
+        }
+    }
+
+    /// Create a layer where each call fails immediately with a
+    /// [`TryAcquireError`](crate::TryAcquireError) if no token is available.
+    pub fn shed(ratelimiter: Arc<Ratelimiter>) -> Self {
+        Self {
+            cost: UnitCost,
+            ratelimiter,
+            shed: true,
+        }
+    }
+}
+
+impl<C> RateLimitLayer<C> {
+    /// Charge each call the number of tokens returned by the cost extractor.
+    /// Costs above the maximum number of tokens are charged as the maximum.
+    pub fn cost_with<T>(self, cost: T) -> RateLimitLayer<T> {
+        RateLimitLayer {
+            cost,
+            ratelimiter: self.ratelimiter,
+            shed: self.shed,
+        }
+    }
+}
+
+impl<S, C: Clone> Layer<S> for RateLimitLayer<C> {
+    type Service = RateLimit<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            cost: self.cost.clone(),
+            inner,
+            ratelimiter: self.ratelimiter.clone(),
+            shed: self.shed,
+        }
+    }
+}
+
+/// A service which charges a ratelimiter for each call before passing it to
+/// the inner service. Each call costs a single token unless a cost extractor
+/// is provided.
+///
+/// In wait mode the call is delayed until a token is available, and fails only
+/// if the ratelimiter denies all requests or is closed. In shed mode the call
+/// fails immediately when there are insufficient tokens. In both cases the
+/// error is a [`TryAcquireError`](crate::TryAcquireError), which can be
+/// recovered by downcasting.
+#[derive(Clone)]
+pub struct RateLimit<S, C = UnitCost> {
+    cost: C,
+    inner: S,
+    ratelimiter: Arc<Ratelimiter>,
+    shed: bool,
+}
+
+impl<S, C> RateLimit<S, C> {
+    /// Returns the ratelimiter which is charged by this service.
+    pub fn ratelimiter(&self) -> &Arc<Ratelimiter> {
+        &self.ratelimiter
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes this service, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, C, R> Service<R> for RateLimit<S, C>
+where
+    S: Service<R> + Clone + Send + 'static,
+    C: CostExtractor<R>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+    R: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let cost = self.ratelimiter.charge(self.cost.cost(&request));
+
+        if self.shed {
+            if let Err(e) = self.ratelimiter.try_acquire_n(cost) {
+                return Box::pin(async move { Err(e.into()) });
+            }
+
+            let future = self.inner.call(request);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        }
+
+        // the inner service has been driven to readiness, so we take it and
+        // leave a clone in its place for the next call
+        let clone = self.inner.clone();
+        let mut inner = core::mem::replace(&mut self.inner, clone);
+        let ratelimiter = self.ratelimiter.clone();
+
+        Box::pin(async move {
+            crate::sleep::acquire_n(&ratelimiter, cost).await?;
+            inner.call(request).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TryAcquireError;
+    use std::convert::Infallible;
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<u64> for Echo {
+        type Response = u64;
+        type Error = Infallible;
+        type Future = core::future::Ready<Result<u64, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: u64) -> Self::Future {
+            core::future::ready(Ok(request))
+        }
+    }
+
+    async fn call<S: Service<u64>>(service: &mut S, request: u64) -> Result<S::Response, S::Error> {
+        core::future::poll_fn(|cx| service.poll_ready(cx)).await?;
+        service.call(request).await
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn shed() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(2)
+                .initial_available(2)
+                .build()
+                .unwrap(),
+        );
+
+        // the ratelimiter is shared by both services
+        let mut a = RateLimitLayer::shed(ratelimiter.clone()).layer(Echo);
+        let mut b = RateLimitLayer::shed(ratelimiter.clone()).layer(Echo);
+
+        runtime().block_on(async {
+            assert_eq!(call(&mut a, 1).await.unwrap(), 1);
+            assert_eq!(call(&mut b, 2).await.unwrap(), 2);
+
+            let error = call(&mut a, 3).await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TryAcquireError>(),
+                Some(TryAcquireError::Insufficient(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn cost() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_secs(60))
+                .max_tokens(10)
+                .initial_available(10)
+                .build()
+                .unwrap(),
+        );
+
+        // each request costs the number of tokens it carries
+        let mut service = RateLimitLayer::shed(ratelimiter.clone())
+            .cost_with(|request: &u64| *request)
+            .layer(Echo);
+
+        runtime().block_on(async {
+            assert_eq!(call(&mut service, 6).await.unwrap(), 6);
+            assert!(call(&mut service, 5).await.is_err());
+            assert_eq!(call(&mut service, 4).await.unwrap(), 4);
+        });
+        assert_eq!(ratelimiter.available(), 0);
+    }
+
+    #[test]
+    fn wait() {
+        let ratelimiter = Arc::new(
+            Ratelimiter::builder(1, Duration::from_millis(10))
+                .build()
+                .unwrap(),
+        );
+        let mut service = RateLimitLayer::wait(ratelimiter.clone()).layer(Echo);
+
+        runtime().block_on(async {
+            let start = Instant::now();
+            for i in 0..3 {
+                assert_eq!(call(&mut service, i).await.unwrap(), i);
+            }
+            assert!(start.elapsed() >= Duration::from_millis(20));
+
+            ratelimiter.close();
+            let error = call(&mut service, 4).await.unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<TryAcquireError>(),
+                Some(TryAcquireError::Closed)
+            ));
+        });
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{Builder, Ratelimiter, MIN_REFILL_INTERVAL_NS};
+use clocksource::precise::Instant;
+use std::sync::OnceLock;
+
+// the number of times the clock is sampled to detect its resolution
+const SAMPLES: usize = 16;
+
+// the most reads of the clock while waiting for it to advance in each sample
+const MAX_SPINS: usize = 1_000_000;
+
+/// The adjustment made to the refill amount and interval by
+/// [`Builder::auto_tune`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Adjustment {
+    /// The refill amount which was requested.
+    pub requested_amount: u64,
+    /// The refill interval which was requested.
+    pub requested_interval: core::time::Duration,
+    /// The refill amount which was selected.
+    pub refill_amount: u64,
+    /// The refill interval which was selected.
+    pub refill_interval: core::time::Duration,
+    /// The resolution of the clock when the adjustment was made.
+    pub clock_resolution: core::time::Duration,
+}
+
+impl Builder {
+    /// Automatically select the refill amount and interval for the clock
+    /// resolution. If the refill interval is shorter than can be reliably
+    /// measured, which is the greater of the detected clock resolution and one
+    /// microsecond, both the refill amount and interval are multiplied so that
+    /// the rate is unchanged but the interval is safe. The max tokens is raised
+    /// to the new refill amount if required.
+    ///
+    /// The adjustment, if any, is reported by [`Ratelimiter::adjustment`].
+    ///
+    /// The default is that the refill amount and interval are used as given.
+    ///
+    /// ```
+    /// use ratelimit::Ratelimiter;
+    /// use std::time::Duration;
+    ///
+    /// let ratelimiter = Ratelimiter::builder(1, Duration::from_nanos(100))
+    ///     .auto_tune(true)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let adjustment = ratelimiter.adjustment().unwrap();
+    /// assert_eq!(adjustment.requested_interval, Duration::from_nanos(100));
+    /// assert!(ratelimiter.refill_interval() >= Duration::from_micros(1));
+    /// assert_eq!(ratelimiter.rate(), 10_000_000.0);
+    /// ```
+    pub fn auto_tune(mut self, enabled: bool) -> Self {
+        self.auto_tune = enabled;
+        self
+    }
+
+    /// Internal function to apply the automatic tuning, returning the
+    /// adjustment if one was made.
+    pub(crate) fn tune(&mut self) -> Option<Adjustment> {
+        let interval = self.refill_interval.as_nanos();
+        if !self.auto_tune || self.refill_amount == 0 || interval == 0 {
+            return None;
+        }
+
+        let resolution = clock_resolution();
+        let safe = resolution.as_nanos().max(MIN_REFILL_INTERVAL_NS as u128);
+        if interval >= safe {
+            return None;
+        }
+
+        let factor = safe.div_ceil(interval);
+
+        // the fraction of a token added each refill is multiplied too, and any
+        // whole tokens are moved into the refill amount
+        let (numerator, denominator) = self.refill_fraction;
+        let fraction = numerator as u128 * factor;
+        let amount = (self.refill_amount as u128 * factor + fraction / denominator as u128)
+            .min(u64::MAX as u128) as u64;
+
+        let adjustment = Adjustment {
+            requested_amount: self.refill_amount,
+            requested_interval: self.refill_interval,
+            refill_amount: amount,
+            refill_interval: core::time::Duration::from_nanos((interval * factor) as u64),
+            clock_resolution: resolution,
+        };
+
+        self.refill_amount = adjustment.refill_amount;
+        self.refill_interval = adjustment.refill_interval;
+        self.refill_fraction = ((fraction % denominator as u128) as u64, denominator);
+        self.max_tokens = self.max_tokens.max(amount);
+
+        Some(adjustment)
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the adjustment made to the refill amount and interval when the
+    /// ratelimiter was constructed, if automatic tuning was enabled and an
+    /// adjustment was required. See [`Builder::auto_tune`].
+    pub fn adjustment(&self) -> Option<Adjustment> {
+        self.extensions()?.adjustment
+    }
+
+    /// Returns the resolution of the clock used by the ratelimiter. This is
+    /// detected by sampling the clock the first time it is required.
+    pub fn clock_resolution() -> core::time::Duration {
+        clock_resolution()
+    }
+}
+
+/// Internal function to return the smallest step of the clock, detecting it
+/// on first use.
+fn clock_resolution() -> core::time::Duration {
+    static RESOLUTION: OnceLock<core::time::Duration> = OnceLock::new();
+
+    *RESOLUTION.get_or_init(|| {
+        let mut resolution = u64::MAX;
+
+        for _ in 0..SAMPLES {
+            let start = Instant::now();
+            let mut now = start;
+
+            for _ in 0..MAX_SPINS {
+                now = Instant::now();
+                if now != start {
+                    break;
+                }
+            }
+
+            let step = now.duration_since(start).as_nanos();
+            if step > 0 {
+                resolution = resolution.min(step);
+            }
+        }
+
+        // a clock which never advanced is assumed to be no finer than the
+        // shortest safe refill interval
+        if resolution == u64::MAX {
+            resolution = MIN_REFILL_INTERVAL_NS;
+        }
+
+        core::time::Duration::from_nanos(resolution)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn auto_tune() {
+        let resolution = Ratelimiter::clock_resolution();
+        assert!(resolution > Duration::ZERO);
+
+        // intervals which are already safe are unchanged
+        let rl = Ratelimiter::builder(1, Duration::from_millis(10))
+            .auto_tune(true)
+            .build()
+            .unwrap();
+        assert!(rl.adjustment().is_none());
+
+        let rl = Ratelimiter::builder(3, Duration::from_nanos(250))
+            .max_tokens(3)
+            .auto_tune(true)
+            .build()
+            .unwrap();
+        let adjustment = rl.adjustment().unwrap();
+        assert_eq!(adjustment.requested_amount, 3);
+        assert_eq!(adjustment.refill_amount, rl.refill_amount());
+        assert!(rl.refill_interval() >= resolution.max(Duration::from_micros(1)));
+        assert_eq!(rl.max_tokens(), rl.refill_amount());
+        assert_eq!(rl.rate(), 12_000_000.0);
+
+        // without tuning, the interval is used as given
+        let rl = Ratelimiter::builder(1, Duration::from_nanos(100))
+            .build()
+            .unwrap();
+        assert!(rl.adjustment().is_none());
+        assert_eq!(rl.refill_interval(), Duration::from_nanos(100));
+    }
+}
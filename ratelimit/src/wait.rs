@@ -0,0 +1,152 @@
+use crate::{Builder, Ratelimiter, TryAcquireError};
+use clocksource::precise::Instant;
+use core::time::Duration;
+
+// the number of times the `Park` strategy yields before parking the thread
+const YIELDS: usize = 16;
+
+/// How a thread which is blocked on a ratelimiter waits between attempts to
+/// acquire tokens. This is used by [`Ratelimiter::wait`] and the other
+/// blocking APIs in this crate.
+///
+/// ```
+/// use ratelimit::{Ratelimiter, WaitStrategy};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_micros(100))
+///     .wait_strategy(WaitStrategy::Spin)
+///     .build()
+///     .unwrap();
+///
+/// // busy-spins until the token is available
+/// ratelimiter.wait().unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Busy-spin on the CPU until the next attempt. This has the lowest
+    /// latency but occupies a core while waiting.
+    Spin,
+    /// Yield to the scheduler until the next attempt, which keeps the thread
+    /// runnable while allowing other threads to use the core.
+    Yield,
+    /// Yield a few times, then park the thread until the next attempt. This
+    /// responds quickly to short waits without occupying a core during long
+    /// waits, and the thread is woken early if tokens are added.
+    Park,
+    /// Sleep until the next attempt.
+    #[default]
+    Sleep,
+}
+
+impl WaitStrategy {
+    /// Internal function to block the calling thread for `delay` using this
+    /// strategy.
+    pub(crate) fn wait(&self, delay: Duration) {
+        let deadline = Instant::now() + delay;
+
+        match self {
+            Self::Spin => {
+                while Instant::now() < deadline {
+                    core::hint::spin_loop();
+                }
+            }
+            Self::Yield => {
+                while Instant::now() < deadline {
+                    std::thread::yield_now();
+                }
+            }
+            Self::Park => {
+                for _ in 0..YIELDS {
+                    if Instant::now() >= deadline {
+                        return;
+                    }
+                    std::thread::yield_now();
+                }
+
+                // parking may wake spuriously, which the caller tolerates by
+                // retrying the acquisition
+                let now = Instant::now();
+                if now < deadline {
+                    std::thread::park_timeout(Duration::from_nanos((deadline - now).as_nanos()));
+                }
+            }
+            Self::Sleep => std::thread::sleep(delay),
+        }
+    }
+}
+
+impl Builder {
+    /// Set how threads which are blocked on the ratelimiter wait between
+    /// attempts to acquire tokens.
+    ///
+    /// The default is [`WaitStrategy::Sleep`].
+    pub fn wait_strategy(mut self, strategy: WaitStrategy) -> Self {
+        self.wait_strategy = strategy;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Returns the strategy used by threads which are blocked on the
+    /// ratelimiter.
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.extensions()
+            .map(|e| e.wait_strategy)
+            .unwrap_or_default()
+    }
+
+    /// Blocking function to wait until `n` tokens have been acquired, waiting
+    /// between attempts with the [`WaitStrategy`] of the ratelimiter. Returns
+    /// an error if the ratelimiter is denying all requests or is closed.
+    pub fn wait_n(&self, n: u64) -> Result<(), TryAcquireError> {
+        crate::sleep::wait_n(self, n)
+    }
+
+    /// Blocking function to wait until a single token has been acquired. See
+    /// [`Ratelimiter::wait_n`].
+    pub fn wait(&self) -> Result<(), TryAcquireError> {
+        self.wait_n(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn strategies() {
+        for strategy in [
+            WaitStrategy::Spin,
+            WaitStrategy::Yield,
+            WaitStrategy::Park,
+            WaitStrategy::Sleep,
+        ] {
+            let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+                .wait_strategy(strategy)
+                .build()
+                .unwrap();
+            assert_eq!(rl.wait_strategy(), strategy);
+
+            let start = Instant::now();
+            for _ in 0..5 {
+                rl.wait().unwrap();
+            }
+            let elapsed = start.elapsed();
+            assert!(
+                elapsed >= Duration::from_millis(4),
+                "{strategy:?}: {elapsed:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn denied() {
+        let rl = Ratelimiter::builder(1, Duration::from_millis(1))
+            .wait_strategy(WaitStrategy::Spin)
+            .build()
+            .unwrap();
+        rl.close();
+        assert_eq!(rl.wait(), Err(TryAcquireError::Closed));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::atomic::Ordering;
+use crate::stats::DropCause;
+use crate::{Builder, Ratelimiter};
+use clocksource::precise::{AtomicInstant, Duration, Instant};
+
+/// The default factor by which the rate is reduced when a ratelimiter with a
+/// warm-up period is cold.
+pub const DEFAULT_COLD_FACTOR: f64 = 3.0;
+
+/// Internal state for a ratelimiter with a warm-up period.
+pub(crate) struct Warmup {
+    period: Duration,
+    cold_factor: f64,
+    // when the current warm-up began
+    start: AtomicInstant,
+    // the most recent acquisition attempt
+    last_active: AtomicInstant,
+}
+
+impl Warmup {
+    pub fn new(period: core::time::Duration, cold_factor: f64, now: Instant) -> Self {
+        Self {
+            period: Duration::from_nanos(period.as_nanos().min(u64::MAX as u128) as u64),
+            cold_factor,
+            start: AtomicInstant::new(now),
+            last_active: AtomicInstant::new(now),
+        }
+    }
+
+    /// Returns the fraction of the configured rate that is in effect at the
+    /// provided time. This rises linearly from `1 / cold_factor` at the start of
+    /// the warm-up to `1.0` at the end of the warm-up period.
+    fn factor(&self, time: Instant) -> f64 {
+        let elapsed = time
+            .checked_duration_since(self.start.load(Ordering::Relaxed))
+            .unwrap_or_default();
+
+        if elapsed >= self.period {
+            return 1.0;
+        }
+
+        let progress = elapsed.as_nanos() as f64 / self.period.as_nanos() as f64;
+        let cold = 1.0 / self.cold_factor;
+
+        cold + (1.0 - cold) * progress
+    }
+}
+
+impl Builder {
+    /// Enable a warm-up period. When the ratelimiter has been idle for at least
+    /// the warm-up period it becomes cold. A cold ratelimiter does not release
+    /// the tokens accumulated while idle as a single burst. Instead, the rate
+    /// starts at a fraction of the configured rate and increases linearly to
+    /// the configured rate over the warm-up period. The ratelimiter also starts
+    /// out cold.
+    ///
+    /// This protects downstream services, such as those with cold caches, from
+    /// being overwhelmed when traffic resumes after an idle period.
+    ///
+    /// By default, a cold ratelimiter runs at one third of the configured rate.
+    /// This can be changed with [`Builder::cold_factor`].
+    pub fn warmup(mut self, period: core::time::Duration) -> Self {
+        self.warmup = Some(period);
+        self
+    }
+
+    /// Set the factor by which the rate is reduced when the ratelimiter is
+    /// cold. For example, a factor of `4.0` means the rate after an idle period
+    /// starts at one quarter of the configured rate. The factor must be at
+    /// least `1.0` and only applies when a warm-up period is set.
+    pub fn cold_factor(mut self, factor: f64) -> Self {
+        self.cold_factor = factor;
+        self
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to track activity for the warm-up, if there is one.
+    /// When the ratelimiter has been idle for the warm-up period, the warm-up
+    /// is restarted and tokens accumulated while idle are discarded.
+    pub(crate) fn update_warmup(&self, time: Instant) {
+        let Some(warmup) = self.extensions().and_then(|e| e.warmup.as_ref()) else {
+            return;
+        };
+
+        let last_active = warmup.last_active.fetch_max(time, Ordering::AcqRel);
+
+        let idle = time.checked_duration_since(last_active).unwrap_or_default();
+
+        if idle < warmup.period {
+            return;
+        }
+
+        // issue any tokens which are due and then discard those in excess of a
+        // single refill so they are not released as a burst
+        let _ = self.refill(time);
+        warmup.start.store(time, Ordering::Release);
+
+        let limit = self.parameters.read().refill_amount;
+        let available = self.available.fetch_min(limit, Ordering::AcqRel);
+        self.record_dropped(DropCause::CatchUp, available.saturating_sub(limit));
+    }
+
+    /// Internal function to return the refill interval adjusted for the
+    /// warm-up, if there is one.
+    pub(crate) fn warmup_interval(&self, time: Instant, interval: Duration) -> Duration {
+        match self.extensions().and_then(|e| e.warmup.as_ref()) {
+            Some(warmup) => {
+                let factor = warmup.factor(time);
+                if factor >= 1.0 {
+                    interval
+                } else {
+                    Duration::from_nanos((interval.as_nanos() as f64 / factor).round() as u64)
+                }
+            }
+            None => interval,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn factor() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+            .max_tokens(100)
+            .warmup(core::time::Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let start = rl.created;
+
+        let interval = Duration::from_millis(1);
+        assert_eq!(
+            rl.warmup_interval(start, interval),
+            Duration::from_millis(3)
+        );
+        assert_eq!(
+            rl.warmup_interval(start + Duration::from_millis(500), interval),
+            Duration::from_micros(1500)
+        );
+        assert_eq!(
+            rl.warmup_interval(start + Duration::from_secs(1), interval),
+            interval
+        );
+    }
+
+    #[test]
+    fn idle() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+            .max_tokens(100)
+            .warmup(core::time::Duration::from_secs(1))
+            .cold_factor(2.0)
+            .build()
+            .unwrap();
+        let start = rl.created;
+
+        // the ratelimiter warms up while active
+        rl.update_warmup(start + Duration::from_millis(500));
+        rl.update_warmup(start + Duration::from_millis(1000));
+        rl.update_warmup(start + Duration::from_millis(1500));
+        let _ = rl.refill(start + Duration::from_millis(1500));
+        assert!(rl.available() > 1);
+        rl.set_available(0).unwrap();
+
+        // after an idle period, the tokens which accumulated are not released
+        // as a burst and the rate drops
+        let time = start + Duration::from_secs(10);
+        rl.update_warmup(time);
+        assert_eq!(rl.available(), 1);
+        assert_eq!(
+            rl.warmup_interval(time, Duration::from_millis(1)),
+            Duration::from_millis(2)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(
+            Ratelimiter::builder(1, core::time::Duration::from_millis(1))
+                .warmup(core::time::Duration::from_secs(1))
+                .cold_factor(0.5)
+                .build()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{Error, Ratelimiter, TryAcquireError, Waiter};
+use core::borrow::Borrow;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures_timer::Delay;
+use parking_lot::Mutex;
+
+/// Internal type which holds the scheduling state and the stats for a class.
+#[derive(Debug)]
+struct Class {
+    // the number of tokens acquired by this class
+    acquired: u64,
+    // the virtual time at which this class was last served
+    finish: f64,
+    // the number of callers in this class which are waiting
+    waiting: usize,
+    weight: f64,
+}
+
+/// A scheduler which shares the tokens of a ratelimiter between several
+/// traffic classes by weighted fair queuing.
+///
+/// When callers from several classes are waiting, each class receives a share
+/// of the tokens proportional to its weight, rather than the tokens going to
+/// whichever caller polls fastest. A class which is not waiting does not
+/// accumulate credit, so an idle class cannot burst past the others when it
+/// becomes active. Without contention, a class may use all the tokens.
+///
+/// ```
+/// use ratelimit::{Ratelimiter, WeightedFairQueue};
+/// use std::time::Duration;
+///
+/// let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+///     .build()
+///     .unwrap();
+///
+/// // interactive traffic gets 3/4 of the tokens when contended
+/// let wfq = WeightedFairQueue::new(&ratelimiter, &[3.0, 1.0]).unwrap();
+///
+/// futures::executor::block_on(async {
+///     wfq.acquire(0, 1).await.unwrap();
+///     wfq.acquire(1, 1).await.unwrap();
+/// });
+///
+/// assert_eq!(wfq.acquired(0), 1);
+/// ```
+pub struct WeightedFairQueue<L> {
+    classes: Mutex<Vec<Class>>,
+    ratelimiter: L,
+}
+
+impl<L: Borrow<Ratelimiter>> WeightedFairQueue<L> {
+    /// Create a scheduler with a class for each of the provided weights.
+    /// Returns an error unless there is at least one class and all the weights
+    /// are finite and greater than zero.
+    pub fn new(ratelimiter: L, weights: &[f64]) -> Result<Self, Error> {
+        if weights.is_empty() || !weights.iter().all(|w| w.is_finite() && *w > 0.0) {
+            return Err(Error::InvalidWeight);
+        }
+
+        let classes = weights
+            .iter()
+            .map(|weight| Class {
+                acquired: 0,
+                finish: 0.0,
+                waiting: 0,
+                weight: *weight,
+            })
+            .collect();
+
+        Ok(Self {
+            classes: Mutex::new(classes),
+            ratelimiter,
+        })
+    }
+
+    /// Returns the ratelimiter which tokens are acquired from.
+    pub fn ratelimiter(&self) -> &Ratelimiter {
+        self.ratelimiter.borrow()
+    }
+
+    /// Returns the number of classes.
+    pub fn classes(&self) -> usize {
+        self.classes.lock().len()
+    }
+
+    /// Wait until `n` tokens have been acquired on behalf of a class. A class
+    /// beyond those which are configured is treated as the last class.
+    ///
+    /// Returns an error if the ratelimiter is denying all requests or is
+    /// closed.
+    pub async fn acquire(&self, class: usize, n: u64) -> Result<(), TryAcquireError> {
+        let class = class.min(self.classes() - 1);
+        let _active = Active::new(self, class);
+
+        let mut turn = None;
+        let mut waiter = Waiter::new();
+
+        poll_fn(|cx| self.poll_acquire(cx, &mut turn, &mut waiter, class, n)).await
+    }
+
+    /// Returns the number of tokens acquired by a class.
+    pub fn acquired(&self, class: usize) -> u64 {
+        self.classes
+            .lock()
+            .get(class)
+            .map(|class| class.acquired)
+            .unwrap_or(0)
+    }
+
+    /// Returns the fraction of all the tokens acquired through this scheduler
+    /// which were acquired by a class. This can be used to verify that the
+    /// classes receive their configured shares under contention.
+    pub fn share(&self, class: usize) -> f64 {
+        let classes = self.classes.lock();
+        let total: u64 = classes.iter().map(|class| class.acquired).sum();
+
+        match classes.get(class) {
+            Some(class) if total > 0 => class.acquired as f64 / total as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Internal function to poll for the tokens once it is the turn of the
+    /// class. The turn timer is a fallback in case no other caller is served,
+    /// since the task is otherwise woken whenever tokens are granted.
+    fn poll_acquire(
+        &self,
+        cx: &mut Context<'_>,
+        turn: &mut Option<Delay>,
+        waiter: &mut Waiter,
+        class: usize,
+        n: u64,
+    ) -> Poll<Result<(), TryAcquireError>> {
+        let ratelimiter = self.ratelimiter.borrow();
+
+        loop {
+            if !self.is_turn(class) {
+                ratelimiter.register_waker(cx.waker());
+
+                // the turn may have passed before the waker was registered
+                if !self.is_turn(class) {
+                    let delay =
+                        turn.get_or_insert_with(|| Delay::new(ratelimiter.scaled_interval()));
+                    ready!(Pin::new(delay).poll(cx));
+                    *turn = None;
+                    continue;
+                }
+            }
+
+            ready!(ratelimiter.poll_acquire(cx, waiter, n))?;
+
+            let mut classes = self.classes.lock();
+            let served = &mut classes[class];
+            served.acquired += n;
+            served.finish += n as f64 / served.weight;
+            drop(classes);
+
+            // the next class may now have its turn
+            ratelimiter.wakers.wake_all();
+
+            return Poll::Ready(Ok(()));
+        }
+    }
+
+    /// Internal function which returns `true` if the class has the earliest
+    /// virtual time of all the classes which are waiting.
+    fn is_turn(&self, class: usize) -> bool {
+        let classes = self.classes.lock();
+        let finish = classes[class].finish;
+
+        classes.iter().enumerate().all(|(index, other)| {
+            other.waiting == 0
+                || other.finish > finish
+                || (other.finish == finish && index >= class)
+        })
+    }
+}
+
+/// Internal guard which marks a caller as waiting within a class.
+struct Active<'a, L> {
+    class: usize,
+    wfq: &'a WeightedFairQueue<L>,
+}
+
+impl<'a, L> Active<'a, L> {
+    fn new(wfq: &'a WeightedFairQueue<L>, class: usize) -> Self {
+        let mut classes = wfq.classes.lock();
+
+        // a class which becomes active starts from the earliest virtual time
+        // of the other active classes, so that it can't use credit from while
+        // it was idle
+        if classes[class].waiting == 0 {
+            let start = classes
+                .iter()
+                .filter(|other| other.waiting > 0)
+                .map(|other| other.finish)
+                .min_by(f64::total_cmp);
+
+            if let Some(start) = start {
+                classes[class].finish = classes[class].finish.max(start);
+            }
+        }
+
+        classes[class].waiting += 1;
+
+        Self { class, wfq }
+    }
+}
+
+impl<L> Drop for Active<'_, L> {
+    fn drop(&mut self) {
+        self.wfq.classes.lock()[self.class].waiting -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    #[test]
+    fn shares() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let wfq = WeightedFairQueue::new(&ratelimiter, &[3.0, 1.0]).unwrap();
+        assert_eq!(wfq.classes(), 2);
+
+        let total = Cell::new(0);
+        let run = |class| {
+            let wfq = &wfq;
+            let total = &total;
+            async move {
+                while total.get() < 40 {
+                    wfq.acquire(class, 1).await.unwrap();
+                    total.set(total.get() + 1);
+                }
+            }
+        };
+
+        block_on(async { futures::join!(run(0), run(1)) });
+
+        assert_eq!(wfq.acquired(0) + wfq.acquired(1), total.get());
+        assert!((0.65..=0.85).contains(&wfq.share(0)), "{}", wfq.share(0));
+
+        ratelimiter.close();
+        assert_eq!(block_on(wfq.acquire(5, 1)), Err(TryAcquireError::Closed));
+    }
+
+    #[test]
+    fn invalid() {
+        let ratelimiter = Ratelimiter::builder(1, Duration::from_millis(1))
+            .build()
+            .unwrap();
+
+        assert!(WeightedFairQueue::new(&ratelimiter, &[]).is_err());
+        assert!(WeightedFairQueue::new(&ratelimiter, &[1.0, 0.0]).is_err());
+        assert!(WeightedFairQueue::new(&ratelimiter, &[1.0, f64::NAN]).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::Ratelimiter;
+use clocksource::precise::Instant;
+use core::task::Waker;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+// the number of slots in the wheel, which must be a power of two
+const SLOTS: usize = 64;
+
+/// Internal type which coalesces the timers of the tasks waiting on a
+/// ratelimiter. Deadlines are rounded up to refill boundaries, so that the
+/// tasks waiting for the same refill share an entry in the wheel. Only the
+/// first task to wait for a deadline, the leader, holds a runtime timer. When
+/// the timer fires, the leader wakes every task waiting for that deadline as a
+/// batch.
+///
+/// Deadlines are hashed into a fixed number of slots, and each entry records
+/// its exact deadline so that deadlines which share a slot are kept apart.
+pub(crate) struct Wheel {
+    slots: Vec<Mutex<Vec<Entry>>>,
+}
+
+/// Internal type which holds the tasks waiting for a single deadline.
+struct Entry {
+    deadline: u64,
+    // a task holds a timer for this deadline
+    armed: bool,
+    wakers: Vec<Waker>,
+}
+
+impl Wheel {
+    /// Internal function to create an empty wheel.
+    pub(crate) fn new() -> Self {
+        Self {
+            slots: (0..SLOTS).map(|_| Mutex::new(Vec::new())).collect(),
+        }
+    }
+
+    /// Add a task which is waiting for the deadline, in nanoseconds since the
+    /// ratelimiter was created. Returns `true` if the task is the leader and
+    /// must hold a timer for the deadline.
+    pub(crate) fn insert(&self, deadline: u64, waker: &Waker) -> bool {
+        let mut slot = self.slot(deadline).lock();
+
+        let entry = match slot.iter_mut().position(|e| e.deadline == deadline) {
+            Some(index) => &mut slot[index],
+            None => {
+                slot.push(Entry {
+                    deadline,
+                    armed: false,
+                    wakers: Vec::new(),
+                });
+                slot.last_mut().unwrap()
+            }
+        };
+
+        if !entry.wakers.iter().any(|w| w.will_wake(waker)) {
+            entry.wakers.push(waker.clone());
+        }
+
+        !core::mem::replace(&mut entry.armed, true)
+    }
+
+    /// Remove the entry for the deadline, waking all of its tasks. This is
+    /// used both when the timer for the deadline fires and when the leader
+    /// stops waiting, in which case one of the woken tasks will take over the
+    /// timer if it still needs to wait.
+    pub(crate) fn wake(&self, deadline: u64) {
+        let entry = {
+            let mut slot = self.slot(deadline).lock();
+            match slot.iter().position(|e| e.deadline == deadline) {
+                Some(index) => slot.swap_remove(index),
+                None => return,
+            }
+        };
+
+        for waker in entry.wakers {
+            waker.wake();
+        }
+    }
+
+    /// Returns the number of bytes allocated for the slots and the entries of
+    /// the wheel.
+    pub(crate) fn heap_size(&self) -> usize {
+        let entries: usize = self
+            .slots
+            .iter()
+            .map(|slot| {
+                let slot = slot.lock();
+                slot.capacity() * core::mem::size_of::<Entry>()
+                    + slot
+                        .iter()
+                        .map(|e| e.wakers.capacity() * core::mem::size_of::<Waker>())
+                        .sum::<usize>()
+            })
+            .sum();
+
+        self.slots.capacity() * core::mem::size_of::<Mutex<Vec<Entry>>>() + entries
+    }
+
+    /// Returns the number of tasks waiting in the wheel.
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.slots
+            .iter()
+            .map(|slot| slot.lock().iter().map(|e| e.wakers.len()).sum::<usize>())
+            .sum()
+    }
+
+    /// Internal function to return the slot for a deadline.
+    fn slot(&self, deadline: u64) -> &Mutex<Vec<Entry>> {
+        // deadlines are spaced by the refill interval, so they are mixed
+        // before selecting a slot to spread them across the wheel
+        let hash = deadline.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        &self.slots[(hash >> (64 - SLOTS.trailing_zeros())) as usize]
+    }
+}
+
+impl core::fmt::Debug for Wheel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Wheel").finish_non_exhaustive()
+    }
+}
+
+impl Ratelimiter {
+    /// Internal function to return the timer wheel, which is created when a
+    /// task first has to wait so that ratelimiters which are never awaited
+    /// don't allocate one.
+    pub(crate) fn wheel(&self) -> &Arc<Wheel> {
+        self.wheel.get_or_init(|| Arc::new(Wheel::new()))
+    }
+
+    /// Internal function to return the deadline for a wait of `delay`,
+    /// rounded up to the next refill boundary, in nanoseconds since the
+    /// ratelimiter was created.
+    pub(crate) fn wheel_deadline(&self, delay: core::time::Duration) -> u64 {
+        let now = Instant::now();
+        let refill_at = self.next_refill();
+        let interval = self.scaled_interval().as_nanos().max(1) as u64;
+
+        let deadline = now + delay;
+        let deadline = if deadline <= refill_at {
+            refill_at
+        } else {
+            let intervals = deadline
+                .duration_since(refill_at)
+                .as_nanos()
+                .div_ceil(interval);
+            refill_at
+                + clocksource::precise::Duration::from_nanos(intervals.saturating_mul(interval))
+        };
+
+        deadline.duration_since(self.created).as_nanos()
+    }
+
+    /// Internal function to return the time until a deadline from the wheel.
+    pub(crate) fn until_deadline(&self, deadline: u64) -> core::time::Duration {
+        let deadline = self.created + clocksource::precise::Duration::from_nanos(deadline);
+        core::time::Duration::from_nanos(
+            deadline
+                .checked_duration_since(Instant::now())
+                .map(|d| d.as_nanos())
+                .unwrap_or(0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Waiter;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Wake};
+
+    struct Counter(AtomicUsize);
+
+    impl Wake for Counter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn coalesce() {
+        let wheel = Wheel::new();
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+
+        let wakers: Vec<Waker> = (0..10)
+            .map(|_| Waker::from(Arc::new(Counter(AtomicUsize::new(0)))))
+            .collect();
+
+        // only the first task for a deadline holds a timer
+        assert!(wheel.insert(1_000, &wakers[0]));
+        for waker in &wakers[1..] {
+            assert!(!wheel.insert(1_000, waker));
+        }
+
+        // duplicate registrations are only held once
+        let waker = Waker::from(counter.clone());
+        assert!(wheel.insert(2_000, &waker));
+        assert!(!wheel.insert(2_000, &waker));
+        assert_eq!(wheel.len(), 11);
+
+        // the batch for a deadline is woken at once
+        wheel.wake(2_000);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+        assert_eq!(wheel.len(), 10);
+
+        wheel.wake(1_000);
+        assert_eq!(wheel.len(), 0);
+
+        // a later task for a woken deadline becomes the leader
+        assert!(wheel.insert(1_000, &waker));
+    }
+
+    #[test]
+    fn lazy() {
+        let rl = Ratelimiter::builder(1, core::time::Duration::from_secs(60))
+            .initial_available(1)
+            .build()
+            .unwrap();
+
+        // acquisitions which don't wait never create the wheel
+        rl.try_acquire().unwrap();
+        assert!(rl.wheel.get().is_none());
+
+        let waker = Waker::from(Arc::new(Counter(AtomicUsize::new(0))));
+        let mut cx = Context::from_waker(&waker);
+        let mut waiter = Waiter::new();
+        assert!(rl.poll_acquire(&mut cx, &mut waiter, 1).is_pending());
+        assert_eq!(rl.wheel.get().map(|wheel| wheel.len()), Some(1));
+    }
+}